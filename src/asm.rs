@@ -1,133 +1,966 @@
 use crate::err::B::X;
+use crate::err::B::Y;
 use crate::err::LlccB;
-use crate::file_io::Dest;
-use crate::file_io::DestKind;
+use crate::err::LlccError;
+use crate::ir::Module;
+use crate::ir::lower::ENTRY;
+use crate::ir::lower::lower_function;
+use crate::ir::ssa;
+use crate::obj::Object;
+use crate::opt::OptLevel;
+use crate::opt::optimize_module;
+use crate::orchestrator::file_manage::Dest;
+use crate::orchestrator::file_manage::DestKind;
+use crate::register::Shift as RegShift;
 use crate::register::*;
+use crate::target;
+use crate::target::CallingConvention;
+use crate::target::RegisterFile;
+use crate::target::Syscall;
+use crate::target::Triple;
 use core::str;
 use std::ffi::OsStr;
+use std::fmt::Display;
 use std::fs;
 use std::io::Write;
-use std::iter::Peekable;
 use std::path::PathBuf;
 use std::process::Command;
 use std::process::ExitStatus;
-use std::str::Chars;
-
-/// syscall number of exit
-const EXIT: u16 = 93;
+use table::Form;
+use table::Opcode;
 
 macro_rules! ret_val {
 	($value:expr) => {
-		Instruction::Mov { target: Register::X8, value: $value, }
+		Instruction::Mov { target: Register::X8.into(), value: $value, }
 	};
 }
 
-pub struct Assembler {
-	dest: Dest,
+mod decode;
+mod emulate;
+mod encode;
+mod parse;
+mod regalloc;
+mod riscv64;
+mod select;
+mod table;
+mod wasm;
+mod x86_64;
+
+pub use decode::objdump;
+pub use emulate::emulate;
+pub use riscv64::Riscv64;
+pub use wasm::wat_str;
+pub use x86_64::X86_64;
+
+/// instruction set to generate code for, named like the first part of a
+/// target triple
+#[derive(
+	Debug, Clone, Copy, PartialEq, Eq, strum::Display, strum::EnumString,
+)]
+pub enum Arch {
+	#[strum(to_string = "aarch64", serialize = "arm64")]
+	Aarch64,
+	#[strum(to_string = "x86_64", serialize = "x86-64", serialize = "amd64")]
+	X86_64,
+	#[strum(to_string = "riscv64", serialize = "riscv64gc")]
+	Riscv64,
 }
 
-impl Assembler {}
+impl Default for Arch {
+	/// the host, so that the output runs without emulation
+	fn default() -> Self {
+		if cfg!(target_arch = "x86_64") {
+			Self::X86_64
+		} else if cfg!(target_arch = "riscv64") {
+			Self::Riscv64
+		} else {
+			Self::Aarch64
+		}
+	}
+}
 
-pub fn asm_str(src: impl Into<String,>,) -> LlccB<impl Into<String,>,> {
-	use Instruction::*;
-	use Register::*;
+/// aarch64 Linux with AAPCS64
+pub struct Aarch64 {
+	pub triple: Triple,
+}
 
-	let chars = src.into();
-	let mut chars = chars.chars().peekable();
-	let mut inst_list = vec![
-		Section(SectionKind::Text,),
-		Global(&["_start",],),
-		Symbol("_start",),
-	];
-
-	let first_num = parse_number(&mut chars,)?;
-	inst_list.push(Mov {
-		target: X0,
-		value:  RegisterOrImmediate::try_from(first_num,)?,
-	},);
-
-	while let Some(c,) = chars.next() {
-		match c {
-			'+' => {
-				let num = parse_number(&mut chars,)?;
-				inst_list.push(Add {
-					target: X0,
-					lhs:    X0,
-					rhs:    RegisterOrImmediate::try_from(num,)?,
-				},);
-			},
-			'-' => {
-				let num = parse_number(&mut chars,)?;
-				inst_list.push(Sub {
-					target: X0,
-					lhs:    X0,
-					rhs:    RegisterOrImmediate::try_from(num,)?,
-				},);
-			},
-			a => todo!("unexpected char `{a}`"),
+impl target::Target for Aarch64 {
+	fn triple(&self,) -> &Triple {
+		&self.triple
+	}
+
+	fn registers(&self,) -> RegisterFile {
+		let names = |class| {
+			(0..32)
+				.filter_map(|i| Register::new(class, i,).ok(),)
+				.map(|r| r.to_string(),)
+				.collect()
+		};
+		RegisterFile {
+			general:       names(RegisterClass::Gpr64,),
+			float:         names(RegisterClass::Fp { bytes: 8, },),
+			stack_pointer: Register::Sp.to_string(),
+			frame_pointer: Register::X29.to_string(),
+		}
+	}
+
+	fn calling_convention(&self,) -> CallingConvention {
+		let registers = self.registers();
+		let with_role = |names: &[String], roles: &[AbiRole]| {
+			names
+				.iter()
+				.filter(|n| {
+					n.parse::<Register>()
+						.is_ok_and(|r| roles.contains(&r.role(),),)
+				},)
+				.cloned()
+				.collect::<Vec<_,>>()
+		};
+		let saved = [AbiRole::CalleeSaved, AbiRole::FramePointer,];
+		let mut callee_saved = with_role(&registers.general, &saved,);
+		callee_saved.extend(with_role(&registers.float, &saved,),);
+		let mut temporaries =
+			with_role(&registers.general, &[AbiRole::Scratch,],);
+		temporaries.extend(with_role(&registers.float, &[AbiRole::Scratch,],),);
+		CallingConvention {
+			int_args: with_role(&registers.general, &[AbiRole::Argument,],),
+			float_args: with_role(&registers.float, &[AbiRole::Argument,],),
+			int_ret: Register::X0.to_string(),
+			float_ret: Register::D0.to_string(),
+			callee_saved,
+			temporaries,
 		}
 	}
 
-	inst_list.push(ret_val!(RegisterOrImmediate::try_from(EXIT as i32)?),);
-	inst_list.push(Svc { syscall: EXIT, },);
-	X(ReadableAsm::from_instructions(inst_list,),)
+	fn syscall(&self, call: Syscall,) -> u16 {
+		call.generic()
+	}
+
+	fn print(&self, module: &Module,) -> LlccB<String,> {
+		let inst_list = select::select_module(module, self,)?;
+		X(String::from(ReadableAsm::from_instructions(inst_list,),),)
+	}
+
+	fn object(&self, module: &Module,) -> Option<LlccB<Object,>,> {
+		Some(match select::select_module(module, self,) {
+			X(insts,) => encode::encode(&insts,),
+			Y(e,) => Y(e,),
+		},)
+	}
+}
+
+pub fn asm_str(
+	src: impl Into<String,>,
+	level: OptLevel,
+	target: &dyn target::Target,
+) -> LlccB<impl Into<String,>,> {
+	target.print(&module_of(src.into(), ENTRY, level,)?,)
+}
+
+/// # Return
+///
+/// returns the object for `src`, or `None` if `target` needs an external
+/// assembler
+pub fn object_of(
+	src: impl Into<String,>,
+	level: OptLevel,
+	target: &dyn target::Target,
+) -> LlccB<Option<Object,>,> {
+	function_object_of(src, ENTRY, level, target,)
+}
+
+/// `object_of` with `src` as the function `int name(void)` that returns
+/// the value, for objects linked into other programs
+pub fn function_object_of(
+	src: impl Into<String,>,
+	name: &str,
+	level: OptLevel,
+	target: &dyn target::Target,
+) -> LlccB<Option<Object,>,> {
+	match target.object(&module_of(src.into(), name, level,)?,) {
+		Some(obj,) => X(Some(obj?,),),
+		None => X(None,),
+	}
+}
+
+/// assembles hand-written aarch64 assembly in the GNU syntax `asm_str`
+/// prints, checking each instruction like selected code
+pub fn assemble_str(src: &str,) -> LlccB<Object,> {
+	encode::encode(&parse::parse(src,)?,)
 }
 
-#[cfg(target_arch = "aarch64")]
-enum Instruction<'a,> {
+fn module_of(src: String, name: &str, level: OptLevel,) -> LlccB<Module,> {
+	let func = lower_function(&src, name,)?;
+	let mut module = Module { functions: vec![func], };
+	optimize_module(&mut module, level,);
+	for func in module.functions.iter_mut() {
+		ssa::out_of_ssa(func,);
+	}
+	X(module,)
+}
+
+/// `R` is `regalloc::Reg` until registers are allocated. variants taking an
+/// `op` cover every opcode of one `table::Form`
+#[derive(Debug, PartialEq,)]
+enum Instruction<'a, R = Register,>
+where R: Display
+{
 	Section(SectionKind,),
-	Global(&'a [&'a str],),
+	Global(&'a str,),
 	Symbol(&'a str,),
+	Label(Target<'a,>,),
 	Svc {
 		/// this number is ignored on aarch64 linux
 		syscall: u16,
 	},
+	/// alias of `orr` for registers and of `movz` for immediates
 	Mov {
-		target: Register,
-		value:  RegisterOrImmediate<12, false,>,
+		target: R,
+		value:  RegisterOrImmediate<12, false, R,>,
+	},
+	Wide {
+		op:     Opcode,
+		target: R,
+		imm:    Immediate<16, false,>,
+	},
+	Arith {
+		op:     Opcode,
+		target: R,
+		lhs:    R,
+		rhs:    RegisterOrImmediate<12, false, R,>,
+	},
+	Compare {
+		op:  Opcode,
+		lhs: R,
+		rhs: RegisterOrImmediate<12, false, R,>,
+	},
+	Logic {
+		op:     Opcode,
+		target: R,
+		lhs:    R,
+		rhs:    LogicOperand<R,>,
+	},
+	Test {
+		op:  Opcode,
+		lhs: R,
+		rhs: LogicOperand<R,>,
+	},
+	Shift {
+		op:     Opcode,
+		target: R,
+		lhs:    R,
+		rhs:    RegisterOrImmediate<6, false, R,>,
+	},
+	Binary {
+		op:     Opcode,
+		target: R,
+		lhs:    R,
+		rhs:    R,
+	},
+	/// `target = acc + lhs * rhs` or `target = acc - lhs * rhs`
+	MulAdd {
+		op:     Opcode,
+		target: R,
+		lhs:    R,
+		rhs:    R,
+		acc:    R,
 	},
-	Add {
-		target: Register,
-		lhs:    Register,
-		rhs:    RegisterOrImmediate<12, false,>,
+	/// `table::Form::Unary` and `table::Form::Extend`
+	Unary {
+		op:     Opcode,
+		target: R,
+		source: R,
 	},
-	Sub {
-		target: Register,
-		lhs:    Register,
-		rhs:    RegisterOrImmediate<12, false,>,
+	Select {
+		op:     Opcode,
+		target: R,
+		lhs:    R,
+		rhs:    R,
+		cond:   Cond,
 	},
+	/// sets `target` to 1 if `cond` holds, to 0 otherwise
+	Cset {
+		target: R,
+		cond:   Cond,
+	},
+	Load {
+		op:     Opcode,
+		target: R,
+		addr:   Address<R,>,
+	},
+	Store {
+		op:     Opcode,
+		source: R,
+		addr:   Address<R,>,
+	},
+	Pair {
+		op:     Opcode,
+		first:  R,
+		second: R,
+		addr:   Address<R,>,
+	},
+	PcRel {
+		op:     Opcode,
+		target: R,
+		label:  Target<'a,>,
+	},
+	CompareBranch {
+		op:     Opcode,
+		reg:    R,
+		target: Target<'a,>,
+	},
+	/// branches on bit `bit` of `reg` being clear or set
+	TestBranch {
+		op:     Opcode,
+		reg:    R,
+		bit:    u8,
+		target: Target<'a,>,
+	},
+	B(Target<'a,>,),
+	BCond {
+		cond:   Cond,
+		target: Target<'a,>,
+	},
+	Bl(&'a str,),
+	Blr(R,),
+	Br(R,),
+	Ret,
+	/// sets up the frame. replaced once the frame size is known
+	Prologue,
+	/// tears down the frame set up by `Prologue`
+	Epilogue,
 }
 
-impl<'a,> From<Instruction<'a,>,> for String {
-	fn from(val: Instruction<'a,>,) -> Self {
-		const SEPARATOR: &str = ", ";
+/// second operand of logical instructions
+type LogicOperand<R,> = RegisterOrImmediate<64, false, R, BitmaskImmediate,>;
+
+impl<'a, R: Display + Copy,> Instruction<'a, R,> {
+	fn map_registers<S: Display,>(
+		self,
+		mut f: impl FnMut(R,) -> S,
+	) -> Instruction<'a, S,> {
 		use Instruction::*;
-		let mut val = match val {
-			Section(section_kind,) => {
-				let kind: String = section_kind.into();
-				format!(".{kind}")
+		match self {
+			Section(kind,) => Section(kind,),
+			Global(s,) => Global(s,),
+			Symbol(s,) => Symbol(s,),
+			Label(t,) => Label(t,),
+			Svc { syscall, } => Svc { syscall, },
+			Mov { target, value, } => {
+				Mov { target: f(target,), value: value.map_register(&mut f,), }
 			},
-			Global(items,) => {
-				let items = items.join(" ",);
-				format!(".global {items}")
+			Wide { op, target, imm, } => Wide { op, target: f(target,), imm, },
+			Arith { op, target, lhs, rhs, } => Arith {
+				op,
+				target: f(target,),
+				lhs: f(lhs,),
+				rhs: rhs.map_register(&mut f,),
 			},
-			Symbol(s,) => format!("{s}:"),
-			Svc { syscall, } => format!("svc #{syscall}"),
-			Mov { target, value, } => {
-				[format!("mov {}", target), value.to_string(),].join(SEPARATOR,)
+			Compare { op, lhs, rhs, } => {
+				Compare { op, lhs: f(lhs,), rhs: rhs.map_register(&mut f,), }
+			},
+			Logic { op, target, lhs, rhs, } => Logic {
+				op,
+				target: f(target,),
+				lhs: f(lhs,),
+				rhs: rhs.map_register(&mut f,),
+			},
+			Test { op, lhs, rhs, } => {
+				Test { op, lhs: f(lhs,), rhs: rhs.map_register(&mut f,), }
+			},
+			Shift { op, target, lhs, rhs, } => Shift {
+				op,
+				target: f(target,),
+				lhs: f(lhs,),
+				rhs: rhs.map_register(&mut f,),
+			},
+			Binary { op, target, lhs, rhs, } => {
+				Binary { op, target: f(target,), lhs: f(lhs,), rhs: f(rhs,), }
+			},
+			MulAdd { op, target, lhs, rhs, acc, } => MulAdd {
+				op,
+				target: f(target,),
+				lhs: f(lhs,),
+				rhs: f(rhs,),
+				acc: f(acc,),
+			},
+			Unary { op, target, source, } => {
+				Unary { op, target: f(target,), source: f(source,), }
+			},
+			Select { op, target, lhs, rhs, cond, } => Select {
+				op,
+				target: f(target,),
+				lhs: f(lhs,),
+				rhs: f(rhs,),
+				cond,
+			},
+			Cset { target, cond, } => Cset { target: f(target,), cond, },
+			Load { op, target, addr, } => Load {
+				op,
+				target: f(target,),
+				addr: addr.map_register(&mut f,),
+			},
+			Store { op, source, addr, } => Store {
+				op,
+				source: f(source,),
+				addr: addr.map_register(&mut f,),
+			},
+			Pair { op, first, second, addr, } => Pair {
+				op,
+				first: f(first,),
+				second: f(second,),
+				addr: addr.map_register(&mut f,),
+			},
+			PcRel { op, target, label, } => {
+				PcRel { op, target: f(target,), label, }
+			},
+			CompareBranch { op, reg, target, } => {
+				CompareBranch { op, reg: f(reg,), target, }
+			},
+			TestBranch { op, reg, bit, target, } => {
+				TestBranch { op, reg: f(reg,), bit, target, }
+			},
+			B(t,) => B(t,),
+			BCond { cond, target, } => BCond { cond, target, },
+			Bl(s,) => Bl(s,),
+			Blr(r,) => Blr(f(r,),),
+			Br(r,) => Br(f(r,),),
+			Ret => Ret,
+			Prologue => Prologue,
+			Epilogue => Epilogue,
+		}
+	}
+
+	/// registers read by `self`
+	fn uses(&self,) -> Vec<R,> {
+		use Instruction::*;
+		match self {
+			Mov { value, .. } => {
+				value.register().copied().into_iter().collect()
+			},
+			Wide { op: Opcode::Movk, target, .. } => vec![*target],
+			Arith { lhs, rhs, .. } | Compare { lhs, rhs, .. } => {
+				std::iter::once(*lhs,).chain(rhs.register().copied(),).collect()
+			},
+			Logic { lhs, rhs, .. } | Test { lhs, rhs, .. } => {
+				std::iter::once(*lhs,).chain(rhs.register().copied(),).collect()
+			},
+			Shift { lhs, rhs, .. } => {
+				std::iter::once(*lhs,).chain(rhs.register().copied(),).collect()
+			},
+			Binary { lhs, rhs, .. } | Select { lhs, rhs, .. } => {
+				vec![*lhs, *rhs]
+			},
+			MulAdd { lhs, rhs, acc, .. } => vec![*lhs, *rhs, *acc],
+			Unary { source, .. } => vec![*source],
+			Load { addr, .. } => addr.registers(),
+			Store { source, addr, .. } => {
+				std::iter::once(*source,).chain(addr.registers(),).collect()
+			},
+			Pair { op: Opcode::Stp, first, second, addr, } => {
+				[*first, *second,]
+					.into_iter()
+					.chain(addr.registers(),)
+					.collect()
+			},
+			Pair { addr, .. } => addr.registers(),
+			CompareBranch { reg, .. }
+			| TestBranch { reg, .. }
+			| Blr(reg,)
+			| Br(reg,) => vec![*reg],
+			_ => vec![],
+		}
+	}
+
+	/// registers written by `self`
+	fn defs(&self,) -> Vec<R,> {
+		use Instruction::*;
+		let mut defs = match self {
+			Mov { target, .. }
+			| Wide { target, .. }
+			| Arith { target, .. }
+			| Logic { target, .. }
+			| Shift { target, .. }
+			| Binary { target, .. }
+			| MulAdd { target, .. }
+			| Unary { target, .. }
+			| Select { target, .. }
+			| Cset { target, .. }
+			| Load { target, .. }
+			| PcRel { target, .. } => vec![*target],
+			Pair { op: Opcode::Ldp, first, second, .. } => {
+				vec![*first, *second]
+			},
+			_ => vec![],
+		};
+		if let Load { addr, .. } | Store { addr, .. } | Pair { addr, .. } = self
+			&& addr.writes_back()
+		{
+			defs.push(addr.base(),);
+		}
+		defs
+	}
+
+	/// the opcode `self` is encoded as, `None` for directives, `mov` and
+	/// the frame pseudo instructions
+	fn opcode(&self,) -> Option<Opcode,> {
+		use Instruction::*;
+		Some(match self {
+			Wide { op, .. }
+			| Arith { op, .. }
+			| Compare { op, .. }
+			| Logic { op, .. }
+			| Test { op, .. }
+			| Shift { op, .. }
+			| Binary { op, .. }
+			| MulAdd { op, .. }
+			| Unary { op, .. }
+			| Select { op, .. }
+			| Load { op, .. }
+			| Store { op, .. }
+			| Pair { op, .. }
+			| PcRel { op, .. }
+			| CompareBranch { op, .. }
+			| TestBranch { op, .. } => *op,
+			Svc { .. } => Opcode::Svc,
+			Cset { .. } => Opcode::Cset,
+			B(_,) => Opcode::B,
+			BCond { .. } => Opcode::BCond,
+			Bl(_,) => Opcode::Bl,
+			Blr(_,) => Opcode::Blr,
+			Br(_,) => Opcode::Br,
+			Ret => Opcode::Ret,
+			Section(_,)
+			| Global(_,)
+			| Symbol(_,)
+			| Label(_,)
+			| Mov { .. }
+			| Prologue
+			| Epilogue => return None,
+		},)
+	}
+
+	/// whether `self` calls a function, which clobbers caller saved
+	/// registers
+	fn is_call(&self,) -> bool {
+		matches!(self, Instruction::Bl(_,) | Instruction::Blr(_,))
+	}
+}
+
+impl<R: GeneralRegister,> Instruction<'_, R,> {
+	/// register operands, each paired with the register file its position
+	/// takes
+	fn slots(&self,) -> Vec<(R, Operand,),> {
+		use Instruction::*;
+		let rhs_slot = |r: Option<&R,>| r.map(|r| (*r, Operand::Gpr,),);
+		let addr_slots = |addr: &Address<R,>| {
+			addr.registers().into_iter().enumerate().map(|(i, r,)| {
+				(r, if i == 0 { Operand::Sp } else { Operand::Gpr },)
+			},)
+		};
+		let sp_if = |sp: bool| if sp { Operand::Sp } else { Operand::Gpr };
+		match self {
+			Mov { target, value, } => match value.register() {
+				// moves from or to `sp` are an `add #0`
+				Some(r,) => {
+					let sp = sp_if(target.is_sp() || r.is_sp(),);
+					vec![(*target, sp,), (*r, sp,)]
+				},
+				None => vec![(*target, Operand::Gpr,)],
+			},
+			Arith { op, target, lhs, rhs, } => {
+				let extended = !matches!(rhs, RegisterOrImmediate::Shifted(_,));
+				let flags = matches!(op, Opcode::Adds | Opcode::Subs);
+				[
+					(*target, sp_if(extended && !flags,),),
+					(*lhs, sp_if(extended,),),
+				]
+				.into_iter()
+				.chain(rhs_slot(rhs.register(),),)
+				.collect()
 			},
-			Add { target, lhs, rhs, } => {
-				[format!("add {}", target,), lhs.to_string(), rhs.to_string(),]
-					.join(SEPARATOR,)
+			Compare { op: Opcode::Fcmp, lhs, rhs, } => std::iter::once(*lhs,)
+				.chain(rhs.register().copied(),)
+				.map(|r| (r, Operand::Fp,),)
+				.collect(),
+			Compare { lhs, rhs, .. } => {
+				let extended = !matches!(rhs, RegisterOrImmediate::Shifted(_,));
+				std::iter::once((*lhs, sp_if(extended,),),)
+					.chain(rhs_slot(rhs.register(),),)
+					.collect()
 			},
-			Sub { target, lhs, rhs, } => {
-				[format!("sub {}", target), lhs.to_string(), rhs.to_string(),]
-					.join(SEPARATOR,)
+			Logic { op, target, lhs, rhs, } => {
+				let imm = matches!(rhs, RegisterOrImmediate::Immediate(_,));
+				[
+					(*target, sp_if(imm && *op != Opcode::Ands,),),
+					(*lhs, Operand::Gpr,),
+				]
+				.into_iter()
+				.chain(rhs_slot(rhs.register(),),)
+				.collect()
+			},
+			Test { lhs, rhs, .. } => std::iter::once((*lhs, Operand::Gpr,),)
+				.chain(rhs_slot(rhs.register(),),)
+				.collect(),
+			Shift { target, lhs, rhs, .. } => {
+				[(*target, Operand::Gpr,), (*lhs, Operand::Gpr,),]
+					.into_iter()
+					.chain(rhs_slot(rhs.register(),),)
+					.collect()
+			},
+			Binary { op, target, lhs, rhs, } => {
+				let file = match table::form(*op,) {
+					Form::FpBinary => Operand::Fp,
+					_ => Operand::Gpr,
+				};
+				[*target, *lhs, *rhs,]
+					.into_iter()
+					.map(|r| (r, file,),)
+					.collect()
+			},
+			Unary { op, target, source, } => {
+				let (t, s,) = match table::form(*op,) {
+					Form::FpUnary | Form::FpConvert => {
+						(Operand::Fp, Operand::Fp,)
+					},
+					Form::FpMove => (Operand::Any, Operand::Any,),
+					Form::IntToFp => (Operand::Fp, Operand::Gpr,),
+					Form::FpToInt => (Operand::Gpr, Operand::Fp,),
+					_ => (Operand::Gpr, Operand::Gpr,),
+				};
+				vec![(*target, t,), (*source, s,)]
+			},
+			Load { target: reg, addr, .. }
+			| Store { source: reg, addr, .. } => std::iter::once((*reg, Operand::Any,),)
+				.chain(addr_slots(addr,),)
+				.collect(),
+			Pair { first, second, addr, .. } => {
+				[(*first, Operand::Any,), (*second, Operand::Any,),]
+					.into_iter()
+					.chain(addr_slots(addr,),)
+					.collect()
+			},
+			_ => self
+				.uses()
+				.into_iter()
+				.chain(self.defs(),)
+				.map(|r| (r, Operand::Gpr,),)
+				.collect(),
+		}
+	}
+
+	/// whether `self` assembles: its opcode belongs to the variant and the
+	/// widths of its operands agree
+	fn check(&self,) -> Result<(), LlccError,> {
+		use Instruction::*;
+		let invalid = || LlccError::unsupported(format!("`{self}`"),);
+		for (r, operand,) in self.slots() {
+			let scalar =
+				r.is_gpr() || matches!(r.class(), RegisterClass::Fp { .. });
+			let fits = match operand {
+				Operand::Gpr => r.is_gpr() && !r.is_sp(),
+				Operand::Sp => r.is_gpr() && !r.is_zero(),
+				Operand::Fp => scalar && !r.is_gpr(),
+				Operand::Any => scalar && !r.is_sp(),
+			};
+			if !fits {
+				return Err(LlccError::unsupported(format!(
+					"`{r}` in `{self}`"
+				),),);
+			}
+		}
+		let same = |regs: &[R]| {
+			regs.windows(2,).all(|w| w[0].class() == w[1].class(),)
+		};
+		let arith_rhs =
+			|target: R, rhs: &RegisterOrImmediate<12, false, R,>| match rhs {
+				RegisterOrImmediate::Register(r,) => {
+					r.is_64() == target.is_64()
+				},
+				RegisterOrImmediate::Shifted(s,) => {
+					s.reg().is_64() == target.is_64()
+						&& s.shift() != RegShift::Ror
+				},
+				RegisterOrImmediate::Extended(e,) => {
+					target.is_64() || !e.extend().reads_64()
+				},
+				RegisterOrImmediate::Immediate(_,) => true,
+			};
+		let logic_rhs = |target: R, rhs: &LogicOperand<R,>| match rhs {
+			RegisterOrImmediate::Register(r,) => r.is_64() == target.is_64(),
+			RegisterOrImmediate::Shifted(s,) => {
+				s.reg().is_64() == target.is_64()
+			},
+			RegisterOrImmediate::Extended(_,) => false,
+			RegisterOrImmediate::Immediate(i,) => {
+				target.is_64() || (i.encoding().0 == 0 && i.value() >> 32 == 0)
 			},
 		};
+		let form = self.opcode().map(table::form,);
+
+		let valid = match (self, form,) {
+			(Mov { target, value, }, _,) => {
+				value.register().is_none_or(|r| r.is_64() == target.is_64(),)
+			},
+			(Wide { target, imm, .. }, Some(Form::Wide,),) => {
+				imm.shift() % 16 == 0
+					&& imm.shift() < if target.is_64() { 64 } else { 32 }
+			},
+			(Arith { target, lhs, rhs, .. }, Some(Form::Arith,),) => {
+				same(&[*target, *lhs,],) && arith_rhs(*target, rhs,)
+			},
+			(Compare { lhs, rhs, .. }, Some(Form::Compare,),) => {
+				arith_rhs(*lhs, rhs,)
+			},
+			(Compare { lhs, rhs, .. }, Some(Form::FpCompare,),) => {
+				rhs.register().is_some_and(|r| same(&[*lhs, *r,],),)
+			},
+			(Logic { target, lhs, rhs, .. }, Some(Form::Logic,),) => {
+				same(&[*target, *lhs,],) && logic_rhs(*target, rhs,)
+			},
+			(Test { lhs, rhs, .. }, Some(Form::Test,),) => {
+				logic_rhs(*lhs, rhs,)
+			},
+			(Shift { target, lhs, rhs, .. }, Some(Form::Shift,),) => {
+				let bits = if target.is_64() { 64 } else { 32 };
+				same(&[*target, *lhs,],)
+					&& match rhs {
+						RegisterOrImmediate::Register(r,) => {
+							r.is_64() == target.is_64()
+						},
+						RegisterOrImmediate::Immediate(i,) => i.value() < bits,
+						_ => false,
+					}
+			},
+			(
+				Binary { target, lhs, rhs, .. },
+				Some(Form::Binary | Form::FpBinary,),
+			) => same(&[*target, *lhs, *rhs,],),
+			(MulAdd { target, lhs, rhs, acc, .. }, Some(Form::MulAdd,),) => {
+				same(&[*target, *lhs, *rhs, *acc,],)
+			},
+			(
+				Unary { target, source, .. },
+				Some(Form::Unary | Form::FpUnary,),
+			) => same(&[*target, *source,],),
+			(Unary { target, source, .. }, Some(Form::FpMove,),) => {
+				target.class().bytes() == source.class().bytes()
+			},
+			(Unary { target, source, .. }, Some(Form::FpConvert,),) => {
+				!same(&[*target, *source,],)
+			},
+			(Unary { .. }, Some(Form::IntToFp | Form::FpToInt,),) => true,
+			(Unary { op, target, source, }, Some(Form::Extend { .. },),) => {
+				!source.is_64()
+					&& match op {
+						Opcode::Sxtw => target.is_64(),
+						Opcode::Uxtb | Opcode::Uxth => !target.is_64(),
+						_ => true,
+					}
+			},
+			(Select { target, lhs, rhs, .. }, Some(Form::Select,),) => {
+				same(&[*target, *lhs, *rhs,],)
+			},
+			(Cset { .. }, Some(Form::SetCond,),) => true,
+			(
+				Load { target, addr, .. },
+				Some(form @ Form::Load { bytes, signed, },),
+			) => {
+				let target_fits = match (bytes, signed,) {
+					(None, _,) => true,
+					(Some(4,), true,) => target.is_64(),
+					(Some(_,), _,) if !target.is_gpr() => false,
+					(Some(_,), false,) => !target.is_64(),
+					(Some(_,), true,) => true,
+				};
+				target_fits
+					&& addr
+						.check(form.access(target.class().bytes(),).unwrap(),)
+						.is_ok()
+			},
+			(
+				Store { source, addr, .. },
+				Some(form @ Form::Store { bytes, },),
+			) => {
+				(bytes.is_none() || source.class() == RegisterClass::Gpr32)
+					&& addr
+						.check(form.access(source.class().bytes(),).unwrap(),)
+						.is_ok()
+			},
+			(Pair { first, second, addr, .. }, Some(Form::Pair,),) => {
+				same(&[*first, *second,],)
+					&& addr
+						.check(
+							Form::Pair.access(first.class().bytes(),).unwrap(),
+						)
+						.is_ok()
+			},
+			(PcRel { target, .. }, Some(Form::PcRel,),) => target.is_64(),
+			(CompareBranch { .. }, Some(Form::CompareBranch,),) => true,
+			(TestBranch { reg, bit, .. }, Some(Form::TestBranch,),) => {
+				*bit < if reg.is_64() { 64 } else { 32 }
+			},
+			(B(_,) | Bl(_,), Some(Form::Branch,),)
+			| (BCond { .. }, Some(Form::CondBranch,),)
+			| (Ret, Some(Form::Return,),)
+			| (Svc { .. }, Some(Form::Exception,),) => true,
+			(Blr(r,) | Br(r,), Some(Form::BranchReg,),) => r.is_64(),
+			(_, Some(_,),) => false,
+			(_, None,) => true,
+		};
+		if valid { Ok((),) } else { Err(invalid(),) }
+	}
+}
+
+/// register file an operand position takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+enum Operand {
+	/// register 31 is the zero register
+	Gpr,
+	/// register 31 is `sp`
+	Sp,
+	/// scalar float register
+	Fp,
+	/// `fmov` and the data registers of loads and stores take either file
+	Any,
+}
+
+/// destination of a branch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash,)]
+enum Target<'a,> {
+	Symbol(&'a str,),
+	/// basic block `block` of function `func`
+	Block {
+		func:  &'a str,
+		block: u32,
+	},
+}
+
+impl Display for Target<'_,> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		match self {
+			Self::Symbol(s,) => write!(f, "{s}"),
+			Self::Block { func, block, } => write!(f, ".L{func}_{block}"),
+		}
+	}
+}
+
+/// condition codes on the flags set by `cmp`, `cmn`, `tst` and `ands`
+#[derive(
+	Debug, Clone, Copy, PartialEq, Eq, strum::Display, strum::EnumString,
+)]
+#[strum(serialize_all = "lowercase")]
+enum Cond {
+	Eq,
+	Ne,
+	Hs,
+	Lo,
+	Mi,
+	Pl,
+	Vs,
+	Vc,
+	Hi,
+	Ls,
+	Ge,
+	Lt,
+	Gt,
+	Le,
+}
 
-		val.push('\n',);
-		val
+impl Cond {
+	/// the condition holding exactly when `self` does not
+	fn invert(self,) -> Self {
+		match self {
+			Self::Eq => Self::Ne,
+			Self::Ne => Self::Eq,
+			Self::Hs => Self::Lo,
+			Self::Lo => Self::Hs,
+			Self::Mi => Self::Pl,
+			Self::Pl => Self::Mi,
+			Self::Vs => Self::Vc,
+			Self::Vc => Self::Vs,
+			Self::Hi => Self::Ls,
+			Self::Ls => Self::Hi,
+			Self::Ge => Self::Lt,
+			Self::Lt => Self::Ge,
+			Self::Gt => Self::Le,
+			Self::Le => Self::Gt,
+		}
+	}
+}
+
+impl<R: Display,> Display for Instruction<'_, R,> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		use Instruction::*;
+		match self {
+			Section(section_kind,) => {
+				let kind: String = (*section_kind).into();
+				write!(f, ".{kind}")
+			},
+			Global(s,) => write!(f, ".global {s}"),
+			Symbol(s,) => write!(f, "{s}:"),
+			Label(t,) => write!(f, "{t}:"),
+			Svc { syscall, } => write!(f, "{} #{syscall}", Opcode::Svc),
+			Mov { target, value, } => write!(f, "mov {target}, {value}"),
+			Wide { op, target, imm, } => write!(f, "{op} {target}, #{imm}"),
+			Arith { op, target, lhs, rhs, } => {
+				write!(f, "{op} {target}, {lhs}, {rhs}")
+			},
+			Compare { op, lhs, rhs, } => write!(f, "{op} {lhs}, {rhs}"),
+			Logic { op, target, lhs, rhs, } => {
+				write!(f, "{op} {target}, {lhs}, {rhs}")
+			},
+			Test { op, lhs, rhs, } => write!(f, "{op} {lhs}, {rhs}"),
+			Shift { op, target, lhs, rhs, } => {
+				write!(f, "{op} {target}, {lhs}, {rhs}")
+			},
+			Binary { op, target, lhs, rhs, } => {
+				write!(f, "{op} {target}, {lhs}, {rhs}")
+			},
+			MulAdd { op, target, lhs, rhs, acc, } => {
+				write!(f, "{op} {target}, {lhs}, {rhs}, {acc}")
+			},
+			Unary { op, target, source, } => {
+				write!(f, "{op} {target}, {source}")
+			},
+			Select { op, target, lhs, rhs, cond, } => {
+				write!(f, "{op} {target}, {lhs}, {rhs}, {cond}")
+			},
+			Cset { target, cond, } => {
+				write!(f, "{} {target}, {cond}", Opcode::Cset)
+			},
+			Load { op, target, addr, } => write!(f, "{op} {target}, {addr}"),
+			Store { op, source, addr, } => write!(f, "{op} {source}, {addr}"),
+			Pair { op, first, second, addr, } => {
+				write!(f, "{op} {first}, {second}, {addr}")
+			},
+			PcRel { op, target, label, } => write!(f, "{op} {target}, {label}"),
+			CompareBranch { op, reg, target, } => {
+				write!(f, "{op} {reg}, {target}")
+			},
+			TestBranch { op, reg, bit, target, } => {
+				write!(f, "{op} {reg}, #{bit}, {target}")
+			},
+			B(target,) => write!(f, "{} {target}", Opcode::B),
+			BCond { cond, target, } => {
+				write!(f, "{}{cond} {target}", Opcode::BCond)
+			},
+			Bl(label,) => write!(f, "{} {label}", Opcode::Bl),
+			Blr(reg,) => write!(f, "{} {reg}", Opcode::Blr),
+			Br(reg,) => write!(f, "{} {reg}", Opcode::Br),
+			Ret => write!(f, "{}", Opcode::Ret),
+			Prologue | Epilogue => {
+				unreachable!(
+					"frame pseudo instructions are expanded by regalloc"
+				)
+			},
+		}
+	}
+}
+
+impl<'a, R: Display,> From<Instruction<'a, R,>,> for String {
+	fn from(val: Instruction<'a, R,>,) -> Self {
+		format!("{val}\n")
 	}
 }
 
@@ -152,6 +985,7 @@ impl<'a,> From<ReadableAsm<'a,>,> for String {
 	}
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
 enum SectionKind {
 	Text,
 }
@@ -165,23 +999,6 @@ impl From<SectionKind,> for String {
 	}
 }
 
-fn parse_number(chars: &mut Peekable<Chars,>,) -> LlccB<i32,> {
-	let mut num = "".to_string();
-	while let Some(c,) = chars.peek() {
-		if c.is_numeric() {
-			num.push(*c,);
-		} else {
-			let num = num.parse()?;
-			return X(num,);
-		}
-
-		chars.next();
-	}
-
-	let num = num.parse()?;
-	X(num,)
-}
-
 /// # Return
 ///
 /// returns path to generated assembly file
@@ -216,3 +1033,101 @@ pub fn clear_out(dest: &Dest,) -> LlccB<(),> {
 
 	X((),)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use Instruction::*;
+	use Register::*;
+
+	#[test]
+	fn forms_print_and_check() {
+		let cases: Vec<(Instruction<'_,>, &str,),> = vec![
+			(
+				Test {
+					op:  Opcode::Tst,
+					lhs: W1,
+					rhs: RegisterOrImmediate::Immediate(
+						BitmaskImmediate::new(0xff, false,).unwrap(),
+					),
+				},
+				"tst w1, #0xff",
+			),
+			(
+				Select {
+					op:     Opcode::Csel,
+					target: X0,
+					lhs:    X1,
+					rhs:    X2,
+					cond:   Cond::Lt,
+				},
+				"csel x0, x1, x2, lt",
+			),
+			(
+				Load {
+					op:     Opcode::Ldrsw,
+					target: X0,
+					addr:   Address::offset(X1, 8, Access::Single(4,),)
+						.unwrap(),
+				},
+				"ldrsw x0, [x1, #8]",
+			),
+			(
+				PcRel {
+					op:     Opcode::Adrp,
+					target: X0,
+					label:  Target::Symbol("g",),
+				},
+				"adrp x0, g",
+			),
+			(Br(X16,), "br x16",),
+			(
+				TestBranch {
+					op:     Opcode::Tbnz,
+					reg:    W3,
+					bit:    31,
+					target: Target::Symbol("g",),
+				},
+				"tbnz w3, #31, g",
+			),
+			(Cset { target: W0, cond: Cond::Hi, }, "cset w0, hi",),
+		];
+		for (inst, text,) in cases {
+			assert!(inst.check().is_ok(), "{inst}");
+			assert_eq!(inst.to_string(), text);
+		}
+
+		let mixed =
+			Binary { op: Opcode::Mul, target: X0, lhs: W1, rhs: X2, };
+		assert!(mixed.check().is_err());
+		let narrow = Load {
+			op:     Opcode::Ldrsw,
+			target: W0,
+			addr:   Address::offset(X1, 0, Access::Single(4,),).unwrap(),
+		};
+		assert!(narrow.check().is_err());
+
+		let stack = |lhs, rhs| Arith {
+			op: Opcode::Add,
+			target: Sp,
+			lhs,
+			rhs: RegisterOrImmediate::Register(rhs,),
+		};
+		assert!(stack(Sp, X1,).check().is_ok());
+		assert!(stack(Xzr, X1,).check().is_err());
+		assert!(stack(X0, Sp,).check().is_err());
+		let zero =
+			Binary { op: Opcode::Mul, target: X0, lhs: Sp, rhs: X1, };
+		assert!(zero.check().is_err());
+		let fp =
+			Binary { op: Opcode::Mul, target: X0, lhs: D1, rhs: X1, };
+		assert!(fp.check().is_err());
+		let wide_bit = TestBranch {
+			op:     Opcode::Tbz,
+			reg:    W3,
+			bit:    32,
+			target: Target::Symbol("g",),
+		};
+		assert!(wide_bit.check().is_err());
+	}
+}
@@ -0,0 +1,961 @@
+//! disassembler for aarch64
+//!
+//! decodes machine words back into `Instruction`s, the inverse of
+//! `encode`. aliases come back as the forms the backend emits, so that
+//! `orr x0, xzr, x1` reads as `mov x0, x1` and `csinc w0, wzr, wzr, ne` as
+//! `cset w0, eq`. words outside of what the backend emits are not decoded.
+//! `objdump` prints whole ELF files with it, which is enough to inspect
+//! binaries on hosts without binutils for the target
+
+use super::Cond;
+use super::Instruction;
+use super::Instruction::*;
+use super::Target;
+use super::table;
+use super::table::Form;
+use super::table::Opcode;
+use crate::err::B::X;
+use crate::err::LlccB;
+use crate::obj::RelocKind;
+use crate::obj::elf;
+use crate::register::Access;
+use crate::register::Address;
+use crate::register::BitmaskImmediate;
+use crate::register::Extend;
+use crate::register::ExtendedRegister;
+use crate::register::Immediate;
+use crate::register::Register;
+use crate::register::RegisterClass;
+use crate::register::RegisterOrImmediate;
+use crate::register::Shift as RegShift;
+use crate::register::ShiftedRegister;
+use std::collections::BTreeMap;
+
+/// the `len` bits of `word` starting at bit `low`
+fn bits(word: u32, low: u32, len: u32,) -> u32 {
+	word >> low & ((1 << len) - 1)
+}
+
+/// `bits` read as a two's complement number
+fn signed(word: u32, low: u32, len: u32,) -> i64 {
+	((word << (32 - low - len)) as i32 >> (32 - len)) as i64
+}
+
+fn flag(word: u32, bit: u32,) -> bool {
+	word >> bit & 1 == 1
+}
+
+/// general register `n`. 31 is `sp` if `sp`, the zero register otherwise
+fn gpr(n: u32, is_64: bool, sp: bool,) -> Register {
+	match (n, is_64, sp,) {
+		(31, true, true,) => Register::Sp,
+		(31, false, true,) => Register::Wsp,
+		(31, true, false,) => Register::Xzr,
+		(31, false, false,) => Register::Wzr,
+		_ => Register::gpr(n as u8, is_64,).expect("registers below 31 exist",),
+	}
+}
+
+/// float register `n` of the `ftype` field
+fn fp(n: u32, ftype: u32,) -> Option<Register,> {
+	let bytes = match ftype {
+		0b00 => 4,
+		0b01 => 8,
+		0b11 => 2,
+		_ => return None,
+	};
+	Register::new(RegisterClass::Fp { bytes, }, n as u8,).ok()
+}
+
+fn cond(n: u32,) -> Option<Cond,> {
+	[
+		Cond::Eq,
+		Cond::Ne,
+		Cond::Hs,
+		Cond::Lo,
+		Cond::Mi,
+		Cond::Pl,
+		Cond::Vs,
+		Cond::Vc,
+		Cond::Hi,
+		Cond::Ls,
+		Cond::Ge,
+		Cond::Lt,
+		Cond::Gt,
+		Cond::Le,
+	]
+	.get(n as usize,)
+	.copied()
+}
+
+fn shift(n: u32,) -> Option<RegShift,> {
+	[RegShift::Lsl, RegShift::Lsr, RegShift::Asr, RegShift::Ror,]
+		.get(n as usize,)
+		.copied()
+}
+
+/// `option` field of extended registers
+fn extend(n: u32,) -> Extend {
+	[
+		Extend::Uxtb,
+		Extend::Uxth,
+		Extend::Uxtw,
+		Extend::Uxtx,
+		Extend::Sxtb,
+		Extend::Sxth,
+		Extend::Sxtw,
+		Extend::Sxtx,
+	][n as usize & 0b111]
+}
+
+/// decodes the word at `pc`. `name` labels the destinations of branches
+/// and `adr`, which are given as addresses
+pub(super) fn decode<'a,>(
+	word: u32,
+	pc: u64,
+	mut name: impl FnMut(u64,) -> &'a str,
+) -> Option<Instruction<'a,>,> {
+	let sf = flag(word, 31,);
+	let (rd, rn, rm,) =
+		(bits(word, 0, 5,), bits(word, 5, 5,), bits(word, 16, 5,),);
+	let mut target =
+		|offset: i64| Target::Symbol(name(pc.wrapping_add_signed(offset,),),);
+	Some(match word {
+		0xd65f_03c0 => Ret,
+		_ if word & 0xffe0_001f == 0xd400_0001 => {
+			Svc { syscall: bits(word, 5, 16,) as u16, }
+		},
+		_ if word & 0xffff_fc1f == 0xd63f_0000 => Blr(gpr(rn, true, false,),),
+		_ if word & 0xffff_fc1f == 0xd61f_0000 => Br(gpr(rn, true, false,),),
+		_ if word & 0x7c00_0000 == 0x1400_0000 => {
+			match target(signed(word, 0, 26,) * 4,) {
+				Target::Symbol(callee,) if sf => Bl(callee,),
+				to => B(to,),
+			}
+		},
+		_ if word & 0xff00_0010 == 0x5400_0000 => BCond {
+			cond:   cond(bits(word, 0, 4,),)?,
+			target: target(signed(word, 5, 19,) * 4,),
+		},
+		_ if word & 0x7e00_0000 == 0x3400_0000 => CompareBranch {
+			op:     if flag(word, 24,) { Opcode::Cbnz } else { Opcode::Cbz },
+			reg:    gpr(rd, sf, false,),
+			target: target(signed(word, 5, 19,) * 4,),
+		},
+		_ if word & 0x7e00_0000 == 0x3600_0000 => TestBranch {
+			op:     if flag(word, 24,) { Opcode::Tbnz } else { Opcode::Tbz },
+			reg:    gpr(rd, sf, false,),
+			bit:    (bits(word, 31, 1,) << 5 | bits(word, 19, 5,)) as u8,
+			target: target(signed(word, 5, 14,) * 4,),
+		},
+		_ if word & 0x1f00_0000 == 0x1000_0000 => {
+			let imm = signed(word, 5, 19,) << 2 | bits(word, 29, 2,) as i64;
+			let (op, label,) = if sf {
+				let page = (pc & !0xfff) as i64 - pc as i64;
+				(Opcode::Adrp, target(page + (imm << 12),),)
+			} else {
+				(Opcode::Adr, target(imm,),)
+			};
+			PcRel { op, target: gpr(rd, true, false,), label, }
+		},
+		_ if word & 0x1f80_0000 == 0x1280_0000 => wide(word,)?,
+		_ if word & 0x1f80_0000 == 0x1100_0000 => {
+			let lhs = gpr(rn, sf, true,);
+			let target = gpr(rd, sf, !flag(word, 29,),);
+			let imm = bits(word, 10, 12,) as i64;
+			let shift = if flag(word, 22,) { 12 } else { 0 };
+			if word & 0x7f80_0000 == 0x1100_0000
+				&& imm == 0 && shift == 0
+				&& (lhs.is_sp() || target.is_sp())
+			{
+				return Some(Mov {
+					target,
+					value: RegisterOrImmediate::Register(lhs,),
+				},);
+			}
+			let rhs = Immediate::with_shift(imm, shift,).ok()?;
+			arith(word, target, lhs, RegisterOrImmediate::Immediate(rhs,),)
+		},
+		_ if word & 0x1f20_0000 == 0x0b00_0000 => {
+			let (kind, amount,) = (bits(word, 22, 2,), bits(word, 10, 6,),);
+			let rm = gpr(rm, sf, false,);
+			let rhs = match (kind, amount,) {
+				(0, 0,) => RegisterOrImmediate::Register(rm,),
+				_ => RegisterOrImmediate::Shifted(
+					ShiftedRegister::new(rm, shift(kind,)?, amount as u8,)
+						.ok()?,
+				),
+			};
+			let target = gpr(rd, sf, false,);
+			// `sub target, zr, source`
+			if word & 0x7fe0_fc00 == 0x4b00_0000 && rn == 31 {
+				return Some(Unary { op: Opcode::Neg, target, source: rm, },);
+			}
+			arith(word, target, gpr(rn, sf, false,), rhs,)
+		},
+		_ if word & 0x1fe0_0000 == 0x0b20_0000 => {
+			let (option, amount,) = (bits(word, 13, 3,), bits(word, 10, 3,),);
+			let lhs = gpr(rn, sf, true,);
+			let target = gpr(rd, sf, !flag(word, 29,),);
+			// the encoder extends plain registers next to `sp`
+			let rhs = if (lhs.is_sp() || target.is_sp())
+				&& option == if sf { 0b011 } else { 0b010 }
+				&& amount == 0
+			{
+				RegisterOrImmediate::Register(gpr(rm, sf, false,),)
+			} else {
+				let e = extend(option,);
+				RegisterOrImmediate::Extended(
+					ExtendedRegister::new(
+						gpr(rm, e.reads_64(), false,),
+						e,
+						amount as u8,
+					)
+					.ok()?,
+				)
+			};
+			arith(word, target, lhs, rhs,)
+		},
+		_ if word & 0x1f80_0000 == 0x1200_0000 => {
+			let opc = bits(word, 29, 2,);
+			let imm = BitmaskImmediate::decode(
+				flag(word, 22,) as u8,
+				bits(word, 16, 6,) as u8,
+				bits(word, 10, 6,) as u8,
+				sf,
+			)?;
+			// fields above the element size are ignored, but never set
+			if imm.encoding()
+				!= (
+					flag(word, 22,) as u8,
+					bits(word, 16, 6,) as u8,
+					bits(word, 10, 6,) as u8,
+				) {
+				return None;
+			}
+			let lhs = gpr(rn, sf, false,);
+			let rhs = RegisterOrImmediate::Immediate(imm,);
+			if opc == 0b11 && rd == 31 {
+				return Some(Test { op: Opcode::Tst, lhs, rhs, },);
+			}
+			let op = [Opcode::And, Opcode::Orr, Opcode::Eor, Opcode::Ands,]
+				[opc as usize];
+			Logic { op, target: gpr(rd, sf, opc != 0b11,), lhs, rhs, }
+		},
+		_ if word & 0x1f00_0000 == 0x0a00_0000 => logic(word,)?,
+		_ if word & 0x7fe0_0000 == 0x1ac0_0000 => {
+			let (target, lhs, rhs,) = (
+				gpr(rd, sf, false,),
+				gpr(rn, sf, false,),
+				gpr(rm, sf, false,),
+			);
+			match bits(word, 10, 6,) {
+				0b000010 => Binary { op: Opcode::Udiv, target, lhs, rhs, },
+				0b000011 => Binary { op: Opcode::Sdiv, target, lhs, rhs, },
+				op2 @ 0b001000..=0b001011 => Shift {
+					op: [Opcode::Lsl, Opcode::Lsr, Opcode::Asr, Opcode::Ror,]
+						[op2 as usize & 0b11],
+					target,
+					lhs,
+					rhs: RegisterOrImmediate::Register(rhs,),
+				},
+				_ => return None,
+			}
+		},
+		_ if word & 0x7f00_0000 == 0x1b00_0000 => {
+			let (target, lhs, rhs,) = (
+				gpr(rd, sf, false,),
+				gpr(rn, sf, false,),
+				gpr(rm, sf, false,),
+			);
+			let ra = bits(word, 10, 5,);
+			let acc = gpr(ra, sf, false,);
+			match (bits(word, 21, 3,), flag(word, 15,), ra,) {
+				(0b000, false, 31,) => {
+					Binary { op: Opcode::Mul, target, lhs, rhs, }
+				},
+				(0b000, o0, _,) => MulAdd {
+					op: if o0 { Opcode::Msub } else { Opcode::Madd },
+					target,
+					lhs,
+					rhs,
+					acc,
+				},
+				(0b010, false, 31,) if sf => {
+					Binary { op: Opcode::Smulh, target, lhs, rhs, }
+				},
+				(0b110, false, 31,) if sf => {
+					Binary { op: Opcode::Umulh, target, lhs, rhs, }
+				},
+				_ => return None,
+			}
+		},
+		_ if word & 0x1f80_0000 == 0x1300_0000 => bitfield(word,)?,
+		// `extr` of a register with itself
+		_ if word & 0x7fa0_0000 == 0x1380_0000
+			&& flag(word, 22,) == sf
+			&& rn == rm =>
+		{
+			Shift {
+				op:     Opcode::Ror,
+				target: gpr(rd, sf, false,),
+				lhs:    gpr(rn, sf, false,),
+				rhs:    RegisterOrImmediate::Immediate(
+					Immediate::try_from(bits(word, 10, 6,) as i64,).ok()?,
+				),
+			}
+		},
+		_ if word & 0x3fe0_0800 == 0x1a80_0000 => {
+			let (target, lhs, rhs,) = (
+				gpr(rd, sf, false,),
+				gpr(rn, sf, false,),
+				gpr(rm, sf, false,),
+			);
+			let c = cond(bits(word, 12, 4,),)?;
+			let op = match (flag(word, 30,), flag(word, 10,),) {
+				(false, false,) => Opcode::Csel,
+				(false, true,) => Opcode::Csinc,
+				(true, false,) => Opcode::Csinv,
+				(true, true,) => Opcode::Csneg,
+			};
+			if op == Opcode::Csinc && rn == 31 && rm == 31 {
+				return Some(Cset { target, cond: c.invert(), },);
+			}
+			Select { op, target, lhs, rhs, cond: c, }
+		},
+		_ if word & 0x3b00_0000 == 0x3900_0000
+			|| word & 0x3b20_0400 == 0x3800_0400
+			|| word & 0x3b20_0c00 == 0x3820_0800 =>
+		{
+			single(word,)?
+		},
+		_ if word & 0x3a00_0000 == 0x2800_0000 => pair(word,)?,
+		_ if word & 0x7f20_0000 == 0x1e20_0000 => float(word,)?,
+		_ => return None,
+	},)
+}
+
+/// `add`, `adds`, `sub` or `subs`, which are `cmp` and `cmn` when they
+/// discard the result
+fn arith<'a,>(
+	word: u32,
+	target: Register,
+	lhs: Register,
+	rhs: RegisterOrImmediate<12, false,>,
+) -> Instruction<'a,> {
+	let op = [Opcode::Add, Opcode::Adds, Opcode::Sub, Opcode::Subs,]
+		[bits(word, 29, 2,) as usize];
+	match op {
+		Opcode::Adds if target.is_zero() => {
+			Compare { op: Opcode::Cmn, lhs, rhs, }
+		},
+		Opcode::Subs if target.is_zero() => {
+			Compare { op: Opcode::Cmp, lhs, rhs, }
+		},
+		_ => Arith { op, target, lhs, rhs, },
+	}
+}
+
+/// `movn`, `movz` and `movk`. `movz` of a 12 bit value is `mov`
+fn wide<'a,>(word: u32,) -> Option<Instruction<'a,>,> {
+	let sf = flag(word, 31,);
+	let target = gpr(bits(word, 0, 5,), sf, false,);
+	let (hw, imm,) = (bits(word, 21, 2,), bits(word, 5, 16,) as i64,);
+	if !sf && hw > 1 {
+		return None;
+	}
+	let op = match bits(word, 29, 2,) {
+		0b00 => Opcode::Movn,
+		0b10 if hw == 0 && imm < 1 << 12 => {
+			let value = RegisterOrImmediate::Immediate(
+				Immediate::try_from(imm,).ok()?,
+			);
+			return Some(Mov { target, value, },);
+		},
+		0b10 => Opcode::Movz,
+		0b11 => Opcode::Movk,
+		_ => return None,
+	};
+	let imm = Immediate::with_shift(imm, hw as u8 * 16,).ok()?;
+	Some(Wide { op, target, imm, },)
+}
+
+/// logical instructions on shifted registers, and `mov`, `mvn` and `tst`
+/// built from them
+fn logic<'a,>(word: u32,) -> Option<Instruction<'a,>,> {
+	let sf = flag(word, 31,);
+	let (rd, rn,) = (bits(word, 0, 5,), bits(word, 5, 5,),);
+	let (target, lhs, rm,) = (
+		gpr(rd, sf, false,),
+		gpr(rn, sf, false,),
+		gpr(bits(word, 16, 5,), sf, false,),
+	);
+	let (kind, amount,) = (bits(word, 22, 2,), bits(word, 10, 6,),);
+	let plain = kind == 0 && amount == 0;
+	let rhs = if plain {
+		RegisterOrImmediate::Register(rm,)
+	} else {
+		RegisterOrImmediate::Shifted(
+			ShiftedRegister::new(rm, shift(kind,)?, amount as u8,).ok()?,
+		)
+	};
+	let op = match (bits(word, 29, 2,), flag(word, 21,),) {
+		(0b01, false,) if rn == 31 && plain => {
+			let value = RegisterOrImmediate::Register(rm,);
+			return Some(Mov { target, value, },);
+		},
+		(0b01, true,) if rn == 31 && plain => {
+			return Some(Unary { op: Opcode::Mvn, target, source: rm, },);
+		},
+		(0b11, false,) if rd == 31 => {
+			return Some(Test { op: Opcode::Tst, lhs, rhs, },);
+		},
+		(0b00, false,) => Opcode::And,
+		(0b01, false,) => Opcode::Orr,
+		(0b10, false,) => Opcode::Eor,
+		(0b11, false,) => Opcode::Ands,
+		(0b00, true,) => Opcode::Bic,
+		_ => return None,
+	};
+	Some(Logic { op, target, lhs, rhs, },)
+}
+
+/// `sbfm` and `ubfm` as the shifts and extensions the encoder builds
+/// from them
+fn bitfield<'a,>(word: u32,) -> Option<Instruction<'a,>,> {
+	let sf = flag(word, 31,);
+	if flag(word, 22,) != sf {
+		return None;
+	}
+	let size = if sf { 64 } else { 32 };
+	let (immr, imms,) = (bits(word, 16, 6,), bits(word, 10, 6,),);
+	if immr >= size || imms >= size {
+		return None;
+	}
+	let target = gpr(bits(word, 0, 5,), sf, false,);
+	let lhs = gpr(bits(word, 5, 5,), sf, false,);
+	let shift = |op, amount: u32| {
+		Some(Shift {
+			op,
+			target,
+			lhs,
+			rhs: RegisterOrImmediate::Immediate(
+				Immediate::try_from(amount as i64,).ok()?,
+			),
+		},)
+	};
+	// extensions read the low bits of a `w` register
+	let source = gpr(bits(word, 5, 5,), false, false,);
+	let op = match (bits(word, 29, 2,), immr, imms,) {
+		(0b10, ..,) if imms == size - 1 => return shift(Opcode::Lsr, immr,),
+		(0b10, ..,) if imms + 1 == immr => {
+			return shift(Opcode::Lsl, size - 1 - imms,);
+		},
+		(0b00, ..,) if imms == size - 1 => return shift(Opcode::Asr, immr,),
+		(0b10, 0, 7,) => Opcode::Uxtb,
+		(0b10, 0, 15,) => Opcode::Uxth,
+		(0b00, 0, 7,) => Opcode::Sxtb,
+		(0b00, 0, 15,) => Opcode::Sxth,
+		(0b00, 0, 31,) => Opcode::Sxtw,
+		_ => return None,
+	};
+	Some(Unary { op, target, source, },)
+}
+
+/// loads and stores of a single register
+fn single<'a,>(word: u32,) -> Option<Instruction<'a,>,> {
+	let (size, opc,) = (bits(word, 30, 2,), bits(word, 22, 2,),);
+	let rt = bits(word, 0, 5,);
+	let (op, reg, bytes,) = if flag(word, 26,) {
+		// `q` registers use size 0 with the high bit of `opc` set
+		let bytes = match (opc >> 1, size,) {
+			(0, _,) => 1 << size,
+			(_, 0,) => 16,
+			_ => return None,
+		};
+		let op = if opc & 1 == 1 { Opcode::Ldr } else { Opcode::Str };
+		(
+			op,
+			Register::new(RegisterClass::Fp { bytes, }, rt as u8,).ok()?,
+			bytes,
+		)
+	} else {
+		let op = match (opc, size,) {
+			(0b00, 0,) => Opcode::Strb,
+			(0b00, 1,) => Opcode::Strh,
+			(0b00, _,) => Opcode::Str,
+			(0b01, 0,) => Opcode::Ldrb,
+			(0b01, 1,) => Opcode::Ldrh,
+			(0b01, _,) => Opcode::Ldr,
+			(0b10 | 0b11, 0,) => Opcode::Ldrsb,
+			(0b10 | 0b11, 1,) => Opcode::Ldrsh,
+			(0b10, 2,) => Opcode::Ldrsw,
+			_ => return None,
+		};
+		(op, gpr(rt, size == 3 || opc == 0b10, false,), 1 << size,)
+	};
+
+	let base = gpr(bits(word, 5, 5,), true, true,);
+	let access = Access::Single(bytes,);
+	let addr = if flag(word, 24,) {
+		let offset = bits(word, 10, 12,) as i64 * bytes as i64;
+		Address::offset(base, offset, access,)
+	} else {
+		match bits(word, 10, 2,) {
+			0b11 => Address::pre_index(base, signed(word, 12, 9,), access,),
+			0b01 => Address::post_index(base, signed(word, 12, 9,), access,),
+			// the shift of byte accesses is 0 either way, the encoder
+			// leaves `S` clear
+			_ if bytes == 1 && flag(word, 12,) => return None,
+			_ => {
+				let e = extend(bits(word, 13, 3,),);
+				let index = gpr(bits(word, 16, 5,), e.reads_64(), false,);
+				Address::register(base, index, e, flag(word, 12,), access,)
+			},
+		}
+	}
+	.ok()?;
+	Some(match table::form(op,) {
+		Form::Load { .. } => Load { op, target: reg, addr, },
+		_ => Store { op, source: reg, addr, },
+	},)
+}
+
+/// `ldp` and `stp`
+fn pair<'a,>(word: u32,) -> Option<Instruction<'a,>,> {
+	let class = match (flag(word, 26,), bits(word, 30, 2,),) {
+		(false, 0b00,) => RegisterClass::Gpr32,
+		(false, 0b10,) => RegisterClass::Gpr64,
+		(true, 0b00,) => RegisterClass::Fp { bytes: 4, },
+		(true, 0b01,) => RegisterClass::Fp { bytes: 8, },
+		(true, 0b10,) => RegisterClass::Fp { bytes: 16, },
+		_ => return None,
+	};
+	let bytes = class.bytes();
+	let reg = |n| match class {
+		RegisterClass::Gpr64 | RegisterClass::Gpr32 => {
+			Some(gpr(n, class == RegisterClass::Gpr64, false,),)
+		},
+		_ => Register::new(class, n as u8,).ok(),
+	};
+	let (first, second,) =
+		(reg(bits(word, 0, 5,),)?, reg(bits(word, 10, 5,),)?,);
+	let base = gpr(bits(word, 5, 5,), true, true,);
+	let offset = signed(word, 15, 7,) * bytes as i64;
+	let access = Access::Pair(bytes,);
+	let addr = match bits(word, 23, 2,) {
+		0b01 => Address::post_index(base, offset, access,),
+		0b10 => Address::offset(base, offset, access,),
+		0b11 => Address::pre_index(base, offset, access,),
+		_ => return None,
+	}
+	.ok()?;
+	let op = if flag(word, 22,) { Opcode::Ldp } else { Opcode::Stp };
+	Some(Pair { op, first, second, addr, },)
+}
+
+/// scalar float arithmetic, comparisons and conversions
+fn float<'a,>(word: u32,) -> Option<Instruction<'a,>,> {
+	let sf = flag(word, 31,);
+	let ftype = bits(word, 22, 2,);
+	let (rd, rn, rm,) =
+		(bits(word, 0, 5,), bits(word, 5, 5,), bits(word, 16, 5,),);
+	Some(match word {
+		_ if word & 0xff20_fc1f == 0x1e20_2000 => Compare {
+			op:  Opcode::Fcmp,
+			lhs: fp(rn, ftype,)?,
+			rhs: RegisterOrImmediate::Register(fp(rm, ftype,)?,),
+		},
+		_ if word & 0xff20_0c00 == 0x1e20_0800 => {
+			let op = match bits(word, 12, 4,) {
+				0b0000 => Opcode::Fmul,
+				0b0001 => Opcode::Fdiv,
+				0b0010 => Opcode::Fadd,
+				0b0011 => Opcode::Fsub,
+				_ => return None,
+			};
+			Binary {
+				op,
+				target: fp(rd, ftype,)?,
+				lhs: fp(rn, ftype,)?,
+				rhs: fp(rm, ftype,)?,
+			}
+		},
+		_ if word & 0xff20_7c00 == 0x1e20_4000 => {
+			let (op, to,) = match bits(word, 15, 6,) {
+				0b000000 => (Opcode::Fmov, ftype,),
+				0b000001 => (Opcode::Fabs, ftype,),
+				0b000010 => (Opcode::Fneg, ftype,),
+				0b000011 => (Opcode::Fsqrt, ftype,),
+				// `opc` is the `ftype` of the target
+				opc @ 0b000100..=0b000111 => (Opcode::Fcvt, opc & 0b11,),
+				_ => return None,
+			};
+			Unary { op, target: fp(rd, to,)?, source: fp(rn, ftype,)?, }
+		},
+		_ if word & 0x7f20_fc00 == 0x1e20_0000 => {
+			let (op, target, source,) = match bits(word, 16, 5,) {
+				0b00010 => {
+					(Opcode::Scvtf, fp(rd, ftype,)?, gpr(rn, sf, false,),)
+				},
+				0b00011 => {
+					(Opcode::Ucvtf, fp(rd, ftype,)?, gpr(rn, sf, false,),)
+				},
+				0b00110 => {
+					(Opcode::Fmov, gpr(rd, sf, false,), fp(rn, ftype,)?,)
+				},
+				0b00111 => {
+					(Opcode::Fmov, fp(rd, ftype,)?, gpr(rn, sf, false,),)
+				},
+				0b11000 => {
+					(Opcode::Fcvtzs, gpr(rd, sf, false,), fp(rn, ftype,)?,)
+				},
+				0b11001 => {
+					(Opcode::Fcvtzu, gpr(rd, sf, false,), fp(rn, ftype,)?,)
+				},
+				_ => return None,
+			};
+			Unary { op, target, source, }
+		},
+		_ => return None,
+	},)
+}
+
+/// prints the sections, symbols and disassembled code of the ELF object or
+/// executable `bytes`. relocations are listed below the instruction they
+/// patch, words that do not decode are shown as `.inst`
+pub fn objdump(bytes: &[u8],) -> LlccB<String,> {
+	let kind = elf::file_kind(bytes,)?;
+	let headers = elf::section_headers(bytes, kind,)?;
+	let symbols = elf::symbols(bytes, &headers,)?;
+	let mut out = format!(
+		"file format elf64-littleaarch64, {}\n",
+		match kind {
+			elf::ET_REL => "relocatable",
+			elf::ET_EXEC => "executable",
+			_ => "unknown type",
+		}
+	);
+	out += "\nSections:\n";
+	out += "Idx Name                Size             Address          Flags\n";
+	for (i, (name, header,),) in headers.iter().enumerate().skip(1,) {
+		let flags: String = [
+			(elf::SHF_WRITE, 'W',),
+			(elf::SHF_ALLOC, 'A',),
+			(elf::SHF_EXECINSTR, 'X',),
+		]
+		.into_iter()
+		.filter(|(flag, _,)| header.flags & flag != 0,)
+		.map(|(_, c,)| c,)
+		.collect();
+		let line = format!(
+			"{i:3} {name:20}{:016x} {:016x} {flags}",
+			header.size, header.addr
+		);
+		out += line.trim_end();
+		out += "\n";
+	}
+
+	out += "\nSymbols:\n";
+	out +=
+		"Value            Bind   Type    Section      Size             Name\n";
+	for symbol in symbols.iter().skip(1,) {
+		let kind = match symbol.kind() {
+			elf::STT_NOTYPE => "notype",
+			elf::STT_OBJECT => "object",
+			elf::STT_FUNC => "func",
+			elf::STT_SECTION => "section",
+			elf::STT_FILE => "file",
+			_ => "other",
+		};
+		let section = match symbol.shndx {
+			elf::SHN_UNDEF => "*und*",
+			elf::SHN_ABS => "*abs*",
+			elf::SHN_COMMON => "*com*",
+			shndx => headers.get(shndx as usize,).map_or("?", |(n, _,)| n,),
+		};
+		// section symbols are named after their section
+		let name = match symbol.kind() {
+			elf::STT_SECTION => section,
+			_ => &symbol.name,
+		};
+		out += &format!(
+			"{:016x} {:6} {kind:7} {section:12} {:016x} {name}\n",
+			symbol.value,
+			format!("{:?}", symbol.binding()).to_lowercase(),
+			symbol.size,
+		);
+	}
+
+	for (i, (name, header,),) in headers.iter().enumerate() {
+		if header.flags & elf::SHF_EXECINSTR == 0
+			|| header.kind != elf::SHT_PROGBITS
+		{
+			continue;
+		}
+		out += &format!("\nDisassembly of section {name}:\n");
+
+		// sections of objects are at 0, so that symbol values and the
+		// offsets of relocations are addresses as in executables
+		let mut labels = BTreeMap::new();
+		for symbol in &symbols {
+			let named =
+				!symbol.name.is_empty() && !symbol.name.starts_with('$',);
+			if symbol.shndx as usize == i
+				&& named && symbol.kind() != elf::STT_SECTION
+			{
+				labels.entry(symbol.value,).or_insert(symbol.name.as_str(),);
+			}
+		}
+
+		let mut relocations = BTreeMap::<u64, Vec<String,>,>::new();
+		for (_, rela,) in headers
+			.iter()
+			.filter(|(_, h,)| h.kind == elf::SHT_RELA && h.info as usize == i,)
+		{
+			for rela in elf::relas(bytes, rela,)? {
+				let symbol = match symbols.get(rela.symbol,) {
+					Some(s,) if s.kind() == elf::STT_SECTION => headers
+						.get(s.shndx as usize,)
+						.map_or("?", |(n, _,)| n.as_str(),),
+					Some(s,) => s.name.as_str(),
+					None => "?",
+				};
+				let kind = match RelocKind::from_elf(rela.kind,) {
+					Some(kind,) => kind.name().to_string(),
+					None => format!("R_AARCH64_{}", rela.kind),
+				};
+				let addend = match rela.addend {
+					0 => String::new(),
+					a if a < 0 => format!("-{:#x}", a.unsigned_abs()),
+					a => format!("+{a:#x}"),
+				};
+				let at = header.addr + rela.offset;
+				relocations
+					.entry(at,)
+					.or_default()
+					.push(format!("{at:8x}: {kind}\t{symbol}{addend}"),);
+			}
+		}
+
+		let words: Vec<(u64, u32,),> = header
+			.data(bytes,)?
+			.chunks_exact(4,)
+			.enumerate()
+			.map(|(n, w,)| {
+				let word = u32::from_le_bytes(w.try_into().unwrap(),);
+				(header.addr + n as u64 * 4, word,)
+			},)
+			.collect();
+		// destinations in the section are named after the closest label
+		// before them
+		let mut destinations = BTreeMap::new();
+		for (pc, word,) in &words {
+			decode(*word, *pc, |to| {
+				let inside =
+					(header.addr..header.addr + header.size).contains(&to,);
+				let name = match labels.range(..=to,).next_back() {
+					Some((at, label,),) if *at == to => label.to_string(),
+					Some((at, label,),) if inside => {
+						format!("{label}+{:#x}", to - at)
+					},
+					_ => format!("{to:#x}"),
+				};
+				destinations.insert(to, name,);
+				""
+			},);
+		}
+		for (pc, word,) in words {
+			if let Some(label,) = labels.get(&pc,) {
+				out += &format!("\n{pc:016x} <{label}>:\n");
+			}
+			let inst = decode(word, pc, |to| {
+				destinations.get(&to,).map_or("?", String::as_str,)
+			},);
+			let text = match inst {
+				Some(inst,) => inst.to_string(),
+				None => format!(".inst {word:#010x}"),
+			};
+			out += &format!("{pc:8x}:\t{word:08x}\t{text}\n");
+			for relocation in relocations.get(&pc,).into_iter().flatten() {
+				out += &format!("\t\t{relocation}\n");
+			}
+		}
+	}
+	X(out,)
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+	use super::super::encode::encode;
+	use super::*;
+	use crate::err::Container;
+	use crate::obj::Binding;
+	use crate::obj::Object;
+	use crate::obj::SectionKind;
+	use crate::obj::Symbol;
+	use quickcheck_macros::quickcheck;
+
+	/// fixed bits of each decoded form other than branches
+	pub(in crate::asm) const FORMS: [(u32, u32,); 20] = [
+		(0xffe0_001f, 0xd400_0001,),
+		(0x1f80_0000, 0x1280_0000,),
+		(0x1f80_0000, 0x1100_0000,),
+		(0x1f20_0000, 0x0b00_0000,),
+		(0x1fe0_0000, 0x0b20_0000,),
+		(0x1f80_0000, 0x1200_0000,),
+		(0x1f00_0000, 0x0a00_0000,),
+		(0x7fe0_0000, 0x1ac0_0000,),
+		(0x7f00_0000, 0x1b00_0000,),
+		(0x1f80_0000, 0x1300_0000,),
+		(0x7fa0_0000, 0x1380_0000,),
+		(0x3fe0_0800, 0x1a80_0000,),
+		(0x3b00_0000, 0x3900_0000,),
+		(0x3b20_0400, 0x3800_0400,),
+		(0x3b20_0c00, 0x3820_0800,),
+		(0x3a00_0000, 0x2800_0000,),
+		(0xff20_fc1f, 0x1e20_2000,),
+		(0xff20_0c00, 0x1e20_0800,),
+		(0xff20_7c00, 0x1e20_4000,),
+		(0x7f20_fc00, 0x1e20_0000,),
+	];
+
+	fn encoded(inst: Instruction<'_,>,) -> Vec<u8,> {
+		encode(&[inst,],)
+			.unwrap()
+			.section(SectionKind::Text,)
+			.unwrap()
+			.data
+			.clone()
+	}
+
+	/// the encoder is the oracle: whatever decodes has to encode back to
+	/// the same word
+	#[quickcheck]
+	fn decoded_words_encode_back(form: u8, word: u32,) -> bool {
+		let (mask, fixed,) = FORMS[form as usize % FORMS.len()];
+		let word = word & !mask | fixed;
+		match decode(word, 0, |_| "",) {
+			Some(inst,) => encoded(inst,) == word.to_le_bytes(),
+			None => true,
+		}
+	}
+
+	/// as printed by GNU `objdump`, up to immediates in decimal
+	#[test]
+	fn known_words() {
+		let cases = [
+			(0xd65f03c0, "ret",),
+			(0xd4000ba1, "svc #93",),
+			(0xaa0103e0, "mov x0, x1",),
+			(0x910003bf, "mov sp, x29",),
+			(0x52800540, "mov w0, #42",),
+			(0xf2a24681, "movk x1, #4660, lsl #16",),
+			(0xd14007ff, "sub sp, sp, #1, lsl #12",),
+			(0x8b22c020, "add x0, x1, w2, sxtw",),
+			(0x8b2163e0, "add x0, sp, x1",),
+			(0xf100041f, "cmp x0, #1",),
+			(0x4b0103e0, "neg w0, w1",),
+			(0x8a220020, "bic x0, x1, x2",),
+			(0x92401c20, "and x0, x1, #0xff",),
+			(0x7200001f, "tst w0, #0x1",),
+			(0xd37cec20, "lsl x0, x1, #4",),
+			(0x13812020, "ror w0, w1, #8",),
+			(0x93407c20, "sxtw x0, w1",),
+			(0x9b028c20, "msub x0, x1, x2, x3",),
+			(0x9a82b020, "csel x0, x1, x2, lt",),
+			(0x1a9f17e0, "cset w0, eq",),
+			(0xf94007e0, "ldr x0, [sp, #8]",),
+			(0xb8627820, "ldr w0, [x1, x2, lsl #2]",),
+			(0x38401420, "ldrb w0, [x1], #1",),
+			(0xa9bf7bfd, "stp x29, x30, [sp, #-16]!",),
+			(0x6d4127e8, "ldp d8, d9, [sp, #16]",),
+			(0x1e612000, "fcmp d0, d1",),
+			(0x1e22c020, "fcvt d0, s1",),
+			(0x9e670020, "fmov d0, x1",),
+			(0xd63f0200, "blr x16",),
+		];
+		for (word, text,) in cases {
+			let inst = decode(word, 0, |_| "",);
+			assert_eq!(
+				inst.map(|i| i.to_string()).as_deref(),
+				Some(text),
+				"{word:#010x}"
+			);
+		}
+		// `ldur` is never emitted
+		assert!(decode(0xf85f83a0, 0, |_| "",).is_none());
+	}
+
+	/// a call, a branch back and a load of a global, as object and as
+	/// executable
+	fn sample() -> Object {
+		let mut obj = Object::default();
+		let text = obj.section_mut(SectionKind::Text,);
+		for word in [
+			0x94000000u32, // bl g
+			0x90000000,    // adrp x0, n
+			0x54ffffc1,    // b.ne _start
+			0xd4000ba1,    // svc #93
+			0xd65f03c0,    // g: ret
+		] {
+			text.push(&word.to_le_bytes(),);
+		}
+		obj.section_mut(SectionKind::Bss,).size = 8;
+		for (name, section, offset,) in [
+			("_start", SectionKind::Text, 0,),
+			("g", SectionKind::Text, 16,),
+			("n", SectionKind::Bss, 0,),
+		] {
+			obj.symbols.push(Symbol {
+				name: name.to_string(),
+				section: Some(section,),
+				offset,
+				binding: Binding::Global,
+			},);
+		}
+		for (offset, kind, symbol,) in
+			[(0, RelocKind::Call26, "g",), (4, RelocKind::AdrPrelPgHi21, "n",),]
+		{
+			obj.relocations.push(crate::obj::Relocation {
+				section: SectionKind::Text,
+				offset,
+				kind,
+				symbol: symbol.to_string(),
+				addend: 0,
+			},);
+		}
+		obj
+	}
+
+	#[test]
+	fn dumps_objects_and_executables() {
+		let obj = objdump(&elf::write(&sample(),).unwrap(),).unwrap();
+		for line in [
+			"  1 .text               0000000000000014 0000000000000000 AX",
+			"0000000000000010 global func    .text        0000000000000000 g",
+			"0000000000000000 <_start>:",
+			"       0:\t94000000\tbl _start",
+			"\t\t       0: R_AARCH64_CALL26\tg",
+			"\t\t       4: R_AARCH64_ADR_PREL_PG_HI21\tn",
+			"       8:\t54ffffc1\tb.ne _start",
+			"      10:\td65f03c0\tret",
+		] {
+			assert!(obj.lines().any(|l| l == line,), "{line}\n{obj}");
+		}
+
+		let exe =
+			objdump(&crate::obj::link::link(&[sample(),],).unwrap(),).unwrap();
+		assert!(
+			exe.starts_with("file format elf64-littleaarch64, executable",)
+		);
+		assert!(!exe.contains("R_AARCH64",));
+		for line in [
+			"  4000b0:\t94000004\tbl g",
+			"  4000b4:\t90000080\tadrp x0, 0x410000",
+			"00000000004000c0 <g>:",
+		] {
+			assert!(exe.lines().any(|l| l == line,), "{line}\n{exe}");
+		}
+	}
+}
@@ -0,0 +1,953 @@
+//! user mode emulator for aarch64 Linux
+//!
+//! runs static executables on any host by interpreting the words `decode`
+//! understands, which covers everything the backend emits. memory is a
+//! sparse set of pages filled from the `PT_LOAD` segments, with a stack
+//! below `STACK_TOP`. of the system calls only those llcc programs make are
+//! served: `exit`, `write`, `read`, `brk` and anonymous `mmap`. everything
+//! else fails with `ENOSYS`. like a process on a real machine, a program
+//! that touches unmapped memory is killed by `SIGSEGV` and one running into
+//! a word the decoder rejects by `SIGILL`
+
+use super::Cond;
+use super::Instruction::*;
+use super::decode::decode;
+use super::table;
+use super::table::Form;
+use super::table::Opcode;
+use crate::err::B::X;
+use crate::err::LlccB;
+use crate::obj::elf;
+use crate::register::Address;
+use crate::register::AddressMode;
+use crate::register::Extend;
+use crate::register::Register;
+use crate::register::RegisterClass;
+use crate::register::RegisterOrImmediate;
+use crate::register::Shift as RegShift;
+use crate::target::Syscall;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::io::Read;
+use std::io::Write;
+use std::os::unix::process::ExitStatusExt as _;
+use std::process::ExitStatus;
+
+/// granule of the sparse memory
+const PAGE: u64 = 0x1000;
+/// the stack grows down from here
+const STACK_TOP: u64 = 0x7fff_ffff_0000;
+const STACK_SIZE: u64 = 8 << 20;
+/// `mmap` hands out addresses upwards from here
+const MMAP_BASE: u64 = 0x10_0000_0000;
+
+const SIGILL: i32 = 4;
+const SIGSEGV: i32 = 11;
+
+const EBADF: i64 = 9;
+const EIO: i64 = 5;
+const EFAULT: i64 = 14;
+const EINVAL: i64 = 22;
+const ENOSYS: i64 = 38;
+
+const EXIT_GROUP: u64 = 94;
+/// longest `read` or `write`, longer ones are cut short as Linux may do
+const IO_MAX: u64 = 1 << 20;
+const MAP_ANONYMOUS: u64 = 0x20;
+
+/// runs the aarch64 executable `exe` to its end. the program reads `stdin`
+/// and writes `stdout`, what it writes to `stderr` goes to that of the host
+///
+/// # Return
+///
+/// returns how the program ended, as `front::exec` would
+pub fn emulate(
+	exe: &[u8],
+	stdin: &mut dyn Read,
+	stdout: &mut dyn Write,
+) -> LlccB<ExitStatus,> {
+	let mut machine = Machine::new(exe, stdin, stdout,)?;
+	let status = loop {
+		if let Err(stop,) = machine.step() {
+			break stop;
+		}
+	};
+	X(match status {
+		Stop::Exit(code,) => ExitStatus::from_raw((code & 0xff) << 8,),
+		Stop::Signal(signal,) => ExitStatus::from_raw(signal,),
+	},)
+}
+
+/// why the program ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+enum Stop {
+	Exit(i32,),
+	Signal(i32,),
+}
+
+/// zero filled pages, only those mapped can be accessed
+#[derive(Default,)]
+struct Memory {
+	pages: BTreeMap<u64, Box<[u8; PAGE as usize],>,>,
+}
+
+impl Memory {
+	/// maps the pages covering `len` bytes from `addr`
+	fn map(&mut self, addr: u64, len: u64,) {
+		let end = addr.saturating_add(len,).next_multiple_of(PAGE,);
+		for page in (addr / PAGE * PAGE..end).step_by(PAGE as usize,) {
+			self.pages
+				.entry(page,)
+				.or_insert_with(|| Box::new([0; PAGE as usize],),);
+		}
+	}
+
+	fn read(&self, addr: u64, out: &mut [u8],) -> Result<(), Stop,> {
+		for (i, byte,) in out.iter_mut().enumerate() {
+			let at = addr.wrapping_add(i as u64,);
+			let page = self
+				.pages
+				.get(&(at / PAGE * PAGE),)
+				.ok_or(Stop::Signal(SIGSEGV,),)?;
+			*byte = page[(at % PAGE) as usize];
+		}
+		Ok((),)
+	}
+
+	fn write(&mut self, addr: u64, data: &[u8],) -> Result<(), Stop,> {
+		for (i, byte,) in data.iter().enumerate() {
+			let at = addr.wrapping_add(i as u64,);
+			let page = self
+				.pages
+				.get_mut(&(at / PAGE * PAGE),)
+				.ok_or(Stop::Signal(SIGSEGV,),)?;
+			page[(at % PAGE) as usize] = *byte;
+		}
+		Ok((),)
+	}
+}
+
+/// the `NZCV` condition flags
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq,)]
+struct Flags {
+	n: bool,
+	z: bool,
+	c: bool,
+	v: bool,
+}
+
+impl Flags {
+	/// flags of a logical result, which clear `c` and `v`
+	fn of(result: u64, bits: u32,) -> Self {
+		Self {
+			n: result >> (bits - 1) & 1 == 1,
+			z: result == 0,
+			..Self::default()
+		}
+	}
+
+	fn holds(self, cond: Cond,) -> bool {
+		let Self { n, z, c, v, } = self;
+		match cond {
+			Cond::Eq => z,
+			Cond::Ne => !z,
+			Cond::Hs => c,
+			Cond::Lo => !c,
+			Cond::Mi => n,
+			Cond::Pl => !n,
+			Cond::Vs => v,
+			Cond::Vc => !v,
+			Cond::Hi => c && !z,
+			Cond::Ls => !c || z,
+			Cond::Ge => n == v,
+			Cond::Lt => n != v,
+			Cond::Gt => !z && n == v,
+			Cond::Le => z || n != v,
+		}
+	}
+}
+
+struct Machine<'io,> {
+	x:      [u64; 31],
+	sp:     u64,
+	/// vector registers, of which the scalar float views are the low bits
+	v:      [u128; 32],
+	pc:     u64,
+	flags:  Flags,
+	memory: Memory,
+	/// start and current end of the heap
+	brk:    (u64, u64,),
+	/// where the next `mmap` goes
+	mmap:   u64,
+	stdin:  &'io mut dyn Read,
+	stdout: &'io mut dyn Write,
+}
+
+/// all bits below `bits`
+fn mask(bits: u32,) -> u64 {
+	u64::MAX >> (64 - bits)
+}
+
+/// `value` of `bits` bits read as a two's complement number
+fn sign_extend(value: u64, bits: u32,) -> i64 {
+	((value << (64 - bits)) as i64) >> (64 - bits)
+}
+
+fn shift(value: u64, shift: RegShift, amount: u32, bits: u32,) -> u64 {
+	let value = value & mask(bits,);
+	let amount = amount % bits;
+	let shifted = match shift {
+		RegShift::Lsl => value << amount,
+		RegShift::Lsr => value >> amount,
+		RegShift::Asr => (sign_extend(value, bits,) >> amount) as u64,
+		RegShift::Ror if amount == 0 => value,
+		RegShift::Ror => value >> amount | value << (bits - amount),
+	};
+	shifted & mask(bits,)
+}
+
+fn extend(value: u64, extend: Extend,) -> u64 {
+	match extend {
+		Extend::Uxtb => value as u8 as u64,
+		Extend::Uxth => value as u16 as u64,
+		Extend::Uxtw => value as u32 as u64,
+		Extend::Uxtx | Extend::Sxtx => value,
+		Extend::Sxtb => value as i8 as u64,
+		Extend::Sxth => value as i16 as u64,
+		Extend::Sxtw => value as i32 as u64,
+	}
+}
+
+/// `x + y + carry` of `bits` bits with the flags it sets
+fn add_with_carry(x: u64, y: u64, carry: bool, bits: u32,) -> (u64, Flags,) {
+	let (x, y,) = (x & mask(bits,), y & mask(bits,),);
+	let unsigned = x as u128 + y as u128 + carry as u128;
+	let result = unsigned as u64 & mask(bits,);
+	let signed = sign_extend(x, bits,) as i128
+		+ sign_extend(y, bits,) as i128
+		+ carry as i128;
+	let flags = Flags {
+		c: unsigned > mask(bits,) as u128,
+		v: signed != sign_extend(result, bits,) as i128,
+		..Flags::of(result, bits,)
+	};
+	(result, flags,)
+}
+
+/// the float of `bytes` bytes in the low bits of `value`. `f32` widens
+/// exactly, and rounding a sum, product, quotient or root of two widened
+/// `f32`s back to `f32` gives the correctly rounded `f32` result
+fn float(value: u64, bytes: u8,) -> Result<f64, Stop,> {
+	match bytes {
+		4 => Ok(f32::from_bits(value as u32,) as f64,),
+		8 => Ok(f64::from_bits(value,),),
+		_ => Err(Stop::Signal(SIGILL,),),
+	}
+}
+
+fn float_bits(value: f64, bytes: u8,) -> Result<u64, Stop,> {
+	match bytes {
+		4 => Ok((value as f32).to_bits() as u64,),
+		8 => Ok(value.to_bits(),),
+		_ => Err(Stop::Signal(SIGILL,),),
+	}
+}
+
+impl<'io,> Machine<'io,> {
+	/// maps the segments of `exe` and sets up an empty `argv` and
+	/// environment on the stack
+	fn new(
+		exe: &[u8],
+		stdin: &'io mut dyn Read,
+		stdout: &'io mut dyn Write,
+	) -> LlccB<Self,> {
+		let (entry, headers,) = elf::program_headers(exe,)?;
+		let mut memory = Memory::default();
+		let mut end = 0;
+		for header in headers.iter().filter(|h| h.kind == elf::PT_LOAD,) {
+			memory.map(header.vaddr, header.memsz,);
+			memory
+				.write(header.vaddr, header.data(exe,)?,)
+				.expect("segments are mapped",);
+			end = end.max(header.vaddr + header.memsz,);
+		}
+		memory.map(STACK_TOP - STACK_SIZE, STACK_SIZE,);
+		let brk = end.next_multiple_of(PAGE,);
+		X(Self {
+			x: [0; 31],
+			// `argc`, the ends of `argv` and the environment and `AT_NULL`
+			sp: STACK_TOP - 32,
+			v: [0; 32],
+			pc: entry,
+			flags: Flags::default(),
+			memory,
+			brk: (brk, brk,),
+			mmap: MMAP_BASE,
+			stdin,
+			stdout,
+		},)
+	}
+
+	/// the low bits of `reg`, zero for the zero registers
+	fn get(&self, reg: Register,) -> u64 {
+		let class = reg.class();
+		let value = match reg.index() {
+			_ if reg.is_sp() => self.sp,
+			None => 0,
+			Some(i,) if class.is_gpr() => self.x[i as usize],
+			Some(i,) => self.v[i as usize] as u64,
+		};
+		value & mask(class.bytes().min(8,) as u32 * 8,)
+	}
+
+	/// writes `value` to `reg`, clearing the bits above it
+	fn set(&mut self, reg: Register, value: u64,) {
+		self.set_wide(reg, value as u128,);
+	}
+
+	fn get_wide(&self, reg: Register,) -> u128 {
+		match (reg.class(), reg.index(),) {
+			(
+				RegisterClass::Fp { bytes: 16, } | RegisterClass::Simd,
+				Some(i,),
+			) => self.v[i as usize],
+			_ => self.get(reg,) as u128,
+		}
+	}
+
+	fn set_wide(&mut self, reg: Register, value: u128,) {
+		let class = reg.class();
+		let value = value & (u128::MAX >> (128 - class.bytes() as u32 * 8));
+		match reg.index() {
+			_ if reg.is_sp() => self.sp = value as u64,
+			None => (),
+			Some(i,) if class.is_gpr() => self.x[i as usize] = value as u64,
+			Some(i,) => self.v[i as usize] = value,
+		}
+	}
+
+	fn operand<const N: u8, I: Display,>(
+		&self,
+		rhs: &RegisterOrImmediate<N, false, Register, I,>,
+		bits: u32,
+		imm: impl FnOnce(&I,) -> u64,
+	) -> u64 {
+		let value = match rhs {
+			RegisterOrImmediate::Register(r,) => self.get(*r,),
+			RegisterOrImmediate::Immediate(i,) => imm(i,),
+			RegisterOrImmediate::Shifted(s,) => {
+				shift(self.get(*s.reg(),), s.shift(), s.amount() as u32, bits,)
+			},
+			RegisterOrImmediate::Extended(e,) => {
+				extend(self.get(*e.reg(),), e.extend(),) << e.amount()
+			},
+		};
+		value & mask(bits,)
+	}
+
+	/// the address accessed, after writing back the base of indexed modes
+	fn address(&mut self, addr: &Address,) -> u64 {
+		let base = self.get(addr.base(),);
+		match *addr.mode() {
+			AddressMode::Offset(offset,) => base.wrapping_add_signed(offset,),
+			AddressMode::PreIndex(offset,) => {
+				let at = base.wrapping_add_signed(offset,);
+				self.set(addr.base(), at,);
+				at
+			},
+			AddressMode::PostIndex(offset,) => {
+				self.set(addr.base(), base.wrapping_add_signed(offset,),);
+				base
+			},
+			AddressMode::Register { index, extend: e, amount, } => {
+				base.wrapping_add(extend(self.get(index,), e,) << amount,)
+			},
+		}
+	}
+
+	fn load(&self, at: u64, bytes: u8,) -> Result<u128, Stop,> {
+		let mut buf = [0; 16];
+		self.memory.read(at, &mut buf[..bytes as usize],)?;
+		Ok(u128::from_le_bytes(buf,),)
+	}
+
+	fn store(&mut self, at: u64, value: u128, bytes: u8,) -> Result<(), Stop,> {
+		self.memory.write(at, &value.to_le_bytes()[..bytes as usize],)
+	}
+
+	/// runs the instruction at `pc`
+	fn step(&mut self,) -> Result<(), Stop,> {
+		let mut word = [0; 4];
+		self.memory.read(self.pc, &mut word,)?;
+		// branches name their destination, of which there is at most one
+		let mut dest = 0;
+		let inst = decode(u32::from_le_bytes(word,), self.pc, |to| {
+			dest = to;
+			""
+		},)
+		.ok_or(Stop::Signal(SIGILL,),)?;
+		let next = self.pc.wrapping_add(4,);
+		self.pc = next;
+
+		match inst {
+			Svc { .. } => self.syscall()?,
+			Mov { target, value, } => {
+				let bits = target.class().bytes() as u32 * 8;
+				let value = self.operand(&value, bits, |i| i.value() as u64,);
+				self.set(target, value,);
+			},
+			Wide { op, target, imm, } => {
+				let value = imm.value() as u64;
+				let value = match op {
+					Opcode::Movn => !value,
+					Opcode::Movk => {
+						self.get(target,) & !(0xffff << imm.shift()) | value
+					},
+					_ => value,
+				};
+				self.set(target, value,);
+			},
+			Arith { op, target, lhs, rhs, } => {
+				let bits = target.class().bytes() as u32 * 8;
+				let rhs = self.operand(&rhs, bits, |i| i.value() as u64,);
+				let (result, flags,) = match op {
+					Opcode::Add | Opcode::Adds => {
+						add_with_carry(self.get(lhs,), rhs, false, bits,)
+					},
+					_ => add_with_carry(self.get(lhs,), !rhs, true, bits,),
+				};
+				if matches!(op, Opcode::Adds | Opcode::Subs) {
+					self.flags = flags;
+				}
+				self.set(target, result,);
+			},
+			Compare { op: Opcode::Fcmp, lhs, rhs, } => {
+				let bytes = lhs.class().bytes();
+				let rhs = rhs.register().map_or(0, |r| self.get(*r,),);
+				let (a, b,) =
+					(float(self.get(lhs,), bytes,)?, float(rhs, bytes,)?,);
+				let (n, z, c, v,) = match a.partial_cmp(&b,) {
+					Some(Ordering::Less,) => (true, false, false, false,),
+					Some(Ordering::Equal,) => (false, true, true, false,),
+					Some(Ordering::Greater,) => (false, false, true, false,),
+					None => (false, false, true, true,),
+				};
+				self.flags = Flags { n, z, c, v, };
+			},
+			Compare { op, lhs, rhs, } => {
+				let bits = lhs.class().bytes() as u32 * 8;
+				let rhs = self.operand(&rhs, bits, |i| i.value() as u64,);
+				self.flags = match op {
+					Opcode::Cmn => {
+						add_with_carry(self.get(lhs,), rhs, false, bits,).1
+					},
+					_ => add_with_carry(self.get(lhs,), !rhs, true, bits,).1,
+				};
+			},
+			Logic { op, target, lhs, rhs, } => {
+				let bits = target.class().bytes() as u32 * 8;
+				let rhs = self.operand(&rhs, bits, |i| i.value(),);
+				let lhs = self.get(lhs,);
+				let result = match op {
+					Opcode::And | Opcode::Ands => lhs & rhs,
+					Opcode::Orr => lhs | rhs,
+					Opcode::Eor => lhs ^ rhs,
+					_ => lhs & !rhs & mask(bits,),
+				};
+				if op == Opcode::Ands {
+					self.flags = Flags::of(result, bits,);
+				}
+				self.set(target, result,);
+			},
+			Test { lhs, rhs, .. } => {
+				let bits = lhs.class().bytes() as u32 * 8;
+				let rhs = self.operand(&rhs, bits, |i| i.value(),);
+				self.flags = Flags::of(self.get(lhs,) & rhs, bits,);
+			},
+			Shift { op, target, lhs, rhs, } => {
+				let bits = target.class().bytes() as u32 * 8;
+				let amount = self.operand(&rhs, 64, |i| i.value() as u64,);
+				let kind = match op {
+					Opcode::Lsl => RegShift::Lsl,
+					Opcode::Lsr => RegShift::Lsr,
+					Opcode::Asr => RegShift::Asr,
+					_ => RegShift::Ror,
+				};
+				let amount = (amount % bits as u64) as u32;
+				self.set(target, shift(self.get(lhs,), kind, amount, bits,),);
+			},
+			Binary { op, target, lhs, rhs, } => {
+				let result =
+					self.binary(op, target, self.get(lhs,), self.get(rhs,),)?;
+				self.set(target, result,);
+			},
+			MulAdd { op, target, lhs, rhs, acc, } => {
+				let product = self.get(lhs,).wrapping_mul(self.get(rhs,),);
+				let acc = self.get(acc,);
+				let result = match op {
+					Opcode::Msub => acc.wrapping_sub(product,),
+					_ => acc.wrapping_add(product,),
+				};
+				self.set(target, result,);
+			},
+			Unary { op, target, source, } => {
+				let result = self.unary(op, target, source,)?;
+				self.set(target, result,);
+			},
+			Select { op, target, lhs, rhs, cond, } => {
+				let result = if self.flags.holds(cond,) {
+					self.get(lhs,)
+				} else {
+					let rhs = self.get(rhs,);
+					match op {
+						Opcode::Csinc => rhs.wrapping_add(1,),
+						Opcode::Csinv => !rhs,
+						Opcode::Csneg => rhs.wrapping_neg(),
+						_ => rhs,
+					}
+				};
+				self.set(target, result,);
+			},
+			Cset { target, cond, } => {
+				self.set(target, self.flags.holds(cond,) as u64,);
+			},
+			Load { op, target, addr, } => {
+				let Form::Load { bytes, signed, } = table::form(op,) else {
+					unreachable!("loads have the load form")
+				};
+				let bytes = bytes.unwrap_or(target.class().bytes(),);
+				let at = self.address(&addr,);
+				let value = self.load(at, bytes,)?;
+				let value = if signed {
+					sign_extend(value as u64, bytes as u32 * 8,) as u64 as u128
+				} else {
+					value
+				};
+				self.set_wide(target, value,);
+			},
+			Store { op, source, addr, } => {
+				let Form::Store { bytes, } = table::form(op,) else {
+					unreachable!("stores have the store form")
+				};
+				let bytes = bytes.unwrap_or(source.class().bytes(),);
+				let value = self.get_wide(source,);
+				let at = self.address(&addr,);
+				self.store(at, value, bytes,)?;
+			},
+			Pair { op, first, second, addr, } => {
+				let bytes = first.class().bytes();
+				let values = (self.get_wide(first,), self.get_wide(second,),);
+				let at = self.address(&addr,);
+				let second_at = at.wrapping_add(bytes as u64,);
+				if op == Opcode::Ldp {
+					let (a, b,) = (
+						self.load(at, bytes,)?,
+						self.load(second_at, bytes,)?,
+					);
+					self.set_wide(first, a,);
+					self.set_wide(second, b,);
+				} else {
+					self.store(at, values.0, bytes,)?;
+					self.store(second_at, values.1, bytes,)?;
+				}
+			},
+			PcRel { target, .. } => self.set(target, dest,),
+			CompareBranch { op, reg, .. } => {
+				if (self.get(reg,) == 0) == (op == Opcode::Cbz) {
+					self.pc = dest;
+				}
+			},
+			TestBranch { op, reg, bit, .. } => {
+				if (self.get(reg,) >> bit & 1 == 0) == (op == Opcode::Tbz) {
+					self.pc = dest;
+				}
+			},
+			B(_,) => self.pc = dest,
+			BCond { cond, .. } => {
+				if self.flags.holds(cond,) {
+					self.pc = dest;
+				}
+			},
+			Bl(_,) => {
+				self.x[30] = next;
+				self.pc = dest;
+			},
+			Blr(reg,) => {
+				self.pc = self.get(reg,);
+				self.x[30] = next;
+			},
+			Br(reg,) => self.pc = self.get(reg,),
+			Ret => self.pc = self.x[30],
+			Section(_,) | Global(_,) | Symbol(_,) | Label(_,) | Prologue
+			| Epilogue => {
+				unreachable!("the decoder makes machine instructions only")
+			},
+		}
+		Ok((),)
+	}
+
+	fn binary(
+		&self,
+		op: Opcode,
+		target: Register,
+		lhs: u64,
+		rhs: u64,
+	) -> Result<u64, Stop,> {
+		let bits = target.class().bytes() as u32 * 8;
+		let fp = |f: fn(f64, f64,) -> f64| {
+			let bytes = target.class().bytes();
+			float_bits(f(float(lhs, bytes,)?, float(rhs, bytes,)?,), bytes,)
+		};
+		Ok(match op {
+			Opcode::Mul => lhs.wrapping_mul(rhs,),
+			Opcode::Smulh => {
+				((lhs as i64 as i128 * rhs as i64 as i128) >> 64) as u64
+			},
+			Opcode::Umulh => ((lhs as u128 * rhs as u128) >> 64) as u64,
+			// division by zero gives zero
+			Opcode::Sdiv if rhs == 0 => 0,
+			Opcode::Sdiv => sign_extend(lhs, bits,)
+				.wrapping_div(sign_extend(rhs, bits,),) as u64,
+			Opcode::Udiv => lhs.checked_div(rhs,).unwrap_or(0,),
+			Opcode::Fadd => fp(|a, b| a + b,)?,
+			Opcode::Fsub => fp(|a, b| a - b,)?,
+			Opcode::Fmul => fp(|a, b| a * b,)?,
+			_ => fp(|a, b| a / b,)?,
+		},)
+	}
+
+	fn unary(
+		&self,
+		op: Opcode,
+		target: Register,
+		source: Register,
+	) -> Result<u64, Stop,> {
+		let value = self.get(source,);
+		let (to, from,) = (target.class().bytes(), source.class().bytes(),);
+		Ok(match op {
+			Opcode::Neg => value.wrapping_neg(),
+			Opcode::Mvn => !value,
+			Opcode::Sxtb => extend(value, Extend::Sxtb,),
+			Opcode::Sxth => extend(value, Extend::Sxth,),
+			Opcode::Sxtw => extend(value, Extend::Sxtw,),
+			Opcode::Uxtb => extend(value, Extend::Uxtb,),
+			Opcode::Uxth => extend(value, Extend::Uxth,),
+			Opcode::Fneg => value ^ 1 << (from as u32 * 8 - 1),
+			Opcode::Fabs => value & !(1 << (from as u32 * 8 - 1)),
+			Opcode::Fsqrt => float_bits(float(value, from,)?.sqrt(), to,)?,
+			Opcode::Fcvt => float_bits(float(value, from,)?, to,)?,
+			// through `f64` integers would be rounded twice on the way to
+			// `f32`
+			Opcode::Scvtf | Opcode::Ucvtf => {
+				let value = if op == Opcode::Scvtf {
+					sign_extend(value, from as u32 * 8,) as i128
+				} else {
+					value as i128
+				};
+				match to {
+					4 => (value as f32).to_bits() as u64,
+					8 => (value as f64).to_bits(),
+					_ => return Err(Stop::Signal(SIGILL,),),
+				}
+			},
+			// conversions to integers round towards zero and saturate,
+			// which is what `as` does
+			Opcode::Fcvtzs => {
+				let value = float(value, from,)?;
+				if to == 8 { value as i64 as u64 } else { value as i32 as u64 }
+			},
+			Opcode::Fcvtzu => {
+				let value = float(value, from,)?;
+				if to == 8 { value as u64 } else { value as u32 as u64 }
+			},
+			// `fmov` copies the bits
+			_ => value,
+		},)
+	}
+
+	/// serves the system call numbered by `x8`, with the arguments in
+	/// `x0` to `x5` and the result or a negated `errno` in `x0`
+	fn syscall(&mut self,) -> Result<(), Stop,> {
+		let [a0, a1, a2, a3, a4,] = [0, 1, 2, 3, 4,].map(|i| self.x[i],);
+		let number = self.x[8];
+		let result = match number {
+			_ if number == Syscall::Exit.generic() as u64
+				|| number == EXIT_GROUP =>
+			{
+				return Err(Stop::Exit(a0 as i32,),);
+			},
+			_ if number == Syscall::Write.generic() as u64 => {
+				let mut buf = vec![0; a2.min(IO_MAX,) as usize];
+				match self.memory.read(a1, &mut buf,) {
+					Err(_,) => -EFAULT,
+					Ok((),) => {
+						let written = match a0 {
+							1 => self.stdout.write_all(&buf,),
+							2 => std::io::stderr().write_all(&buf,),
+							_ => return self.ret(-EBADF,),
+						};
+						match written {
+							Ok((),) => buf.len() as i64,
+							Err(_,) => -EIO,
+						}
+					},
+				}
+			},
+			_ if number == Syscall::Read.generic() as u64 => {
+				if a0 != 0 {
+					return self.ret(-EBADF,);
+				}
+				let mut buf = vec![0; a2.min(IO_MAX,) as usize];
+				match self.stdin.read(&mut buf,) {
+					Err(_,) => -EIO,
+					Ok(n,) => match self.memory.write(a1, &buf[..n],) {
+						Ok((),) => n as i64,
+						Err(_,) => -EFAULT,
+					},
+				}
+			},
+			// the heap only grows, and a failed request leaves it as it is
+			_ if number == Syscall::Brk.generic() as u64 => {
+				let (start, end,) = self.brk;
+				if a0 > end {
+					self.memory.map(end, a0 - end,);
+				}
+				if a0 >= start {
+					self.brk.1 = a0.max(end,);
+				}
+				self.brk.1 as i64
+			},
+			_ if number == Syscall::Mmap.generic() as u64 => {
+				if a1 == 0 || a3 & MAP_ANONYMOUS == 0 || a4 as i64 != -1 {
+					-EINVAL
+				} else {
+					let at = self.mmap;
+					self.memory.map(at, a1,);
+					self.mmap = at + a1.next_multiple_of(PAGE,);
+					at as i64
+				}
+			},
+			_ => -ENOSYS,
+		};
+		self.ret(result,)
+	}
+
+	fn ret(&mut self, result: i64,) -> Result<(), Stop,> {
+		self.x[0] = result as u64;
+		Ok((),)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::Aarch64;
+	use super::super::assemble_str;
+	use super::super::object_of;
+	use super::*;
+	use crate::err::Container;
+	use crate::ir::BinOp;
+	use crate::ir::FunctionBuilder;
+	use crate::ir::Module;
+	use crate::ir::Type;
+	use crate::ir::lower;
+	use crate::obj::link;
+	use crate::opt::OptLevel;
+	use crate::opt::optimize_module;
+	use crate::target::Target as _;
+	use quickcheck_macros::quickcheck;
+
+	/// runs `exe` with `input` on `stdin`, returning the exit code and what
+	/// it wrote
+	fn run(exe: &[u8], input: &[u8],) -> (ExitStatus, Vec<u8,>,) {
+		let mut out = vec![];
+		let status = emulate(exe, &mut &input[..], &mut out,).unwrap();
+		(status, out,)
+	}
+
+	fn compiled(src: &str, level: OptLevel,) -> Vec<u8,> {
+		let target = Aarch64 { triple: "aarch64-linux-gnu".parse().unwrap(), };
+		let obj = object_of(src, level, &target,).unwrap().unwrap();
+		link::link(&[obj,],).unwrap()
+	}
+
+	#[quickcheck]
+	fn exits_with_the_result(a: u8, b: u8, c: u8, optimize: bool,) -> bool {
+		let level = if optimize { OptLevel::O2 } else { OptLevel::O0 };
+		let exe = compiled(&format!("{a}+{b}-{c}"), level,);
+		let expected = (a as i32 + b as i32 - c as i32) & 0xff;
+		run(&exe, b"",).0.code() == Some(expected,)
+	}
+
+	/// `-a` is a `long` once `a` does not fit in an `int`
+	#[quickcheck]
+	fn negative_and_long_literals(a: u32, b: u8, optimize: bool,) -> bool {
+		let level = if optimize { OptLevel::O2 } else { OptLevel::O0 };
+		let exe = compiled(&format!("-{a} + {b}"), level,);
+		let expected = (b as i64 - a as i64) as i32 & 0xff;
+		run(&exe, b"",).0.code() == Some(expected,)
+	}
+
+	/// `_start` calls `g` at -O1 instead of branching to it without a frame
+	#[test]
+	fn entry_returns_through_calls() {
+		let mut fb = FunctionBuilder::new("g", &[], Some(Type::I32,),);
+		let v = fb.iconst(Type::I32, 42,);
+		fb.ret(Some(v,),);
+		let g = fb.finish();
+		let mut fb = FunctionBuilder::new(lower::ENTRY, &[], Some(Type::I32,),);
+		let r = fb.call("g", &[], Some(Type::I32,),);
+		fb.ret(Some(r,),);
+		let mut module = Module { functions: vec![fb.finish(), g], };
+		optimize_module(&mut module, OptLevel::O1,);
+		let target = Aarch64 { triple: "aarch64-linux-gnu".parse().unwrap(), };
+		let obj = target.object(&module,).unwrap().unwrap();
+		let exe = link::link(&[obj,],).unwrap();
+		assert_eq!(run(&exe, b"",).0.code(), Some(42));
+	}
+
+	/// `int a = 40, b = 2; int *p = &a; *p += b; return a;`
+	#[test]
+	fn stack_slots_live_in_the_frame() {
+		let mut fb = FunctionBuilder::new(lower::ENTRY, &[], Some(Type::I32,),);
+		let (a, b,) = (fb.slot(Type::I32,), fb.slot(Type::I32,),);
+		let forty = fb.iconst(Type::I32, 40,);
+		fb.store_slot(a, forty,);
+		let two = fb.iconst(Type::I32, 2,);
+		fb.store_slot(b, two,);
+		let p = fb.slot_addr(a,);
+		let v = fb.load(Type::I32, p,);
+		let b = fb.load_slot(b,);
+		let sum = fb.binary(BinOp::Add, v, b,);
+		fb.store(p, sum,);
+		let r = fb.load_slot(a,);
+		fb.ret(Some(r,),);
+		let module = Module { functions: vec![fb.finish()], };
+		let target = Aarch64 { triple: "aarch64-linux-gnu".parse().unwrap(), };
+		let obj = target.object(&module,).unwrap().unwrap();
+		let exe = link::link(&[obj,],).unwrap();
+		assert_eq!(run(&exe, b"",).0.code(), Some(42));
+	}
+
+	/// frames beyond what `stp` can allocate, and beyond a 12 bit `sub`
+	#[test]
+	fn large_frames() {
+		for n in [100, 600,] {
+			let mut fb =
+				FunctionBuilder::new(lower::ENTRY, &[], Some(Type::I32,),);
+			let slots: Vec<_,> = (0..n).map(|_| fb.slot(Type::I32,),).collect();
+			for (i, slot,) in slots.iter().enumerate() {
+				let v = fb.iconst(Type::I32, i as i64 % 50,);
+				fb.store_slot(*slot, v,);
+			}
+			let first = fb.load_slot(slots[40],);
+			let last = fb.load_slot(slots[n - 1],);
+			let sum = fb.binary(BinOp::Add, first, last,);
+			fb.ret(Some(sum,),);
+			let module = Module { functions: vec![fb.finish()], };
+			let target =
+				Aarch64 { triple: "aarch64-linux-gnu".parse().unwrap(), };
+			let obj = target.object(&module,).unwrap().unwrap();
+			let exe = link::link(&[obj,],).unwrap();
+			let expected = 40 + (n as i32 - 1) % 50;
+			assert_eq!(run(&exe, b"",).0.code(), Some(expected), "{n} slots");
+		}
+	}
+
+	#[test]
+	fn converts_floats() {
+		let exe = compiled("2.5+1.75f-1", OptLevel::O0,);
+		assert_eq!(run(&exe, b"",).0.code(), Some(3));
+	}
+
+	/// reads a digit, writes `hi` from the stack, takes memory from `brk`
+	/// and `mmap` and counts down from the digit through both
+	#[test]
+	fn serves_system_calls() {
+		let src = "	.text
+			.global _start
+		_start:
+			sub sp, sp, #16
+			mov x0, #0
+			mov x1, sp
+			mov x2, #1
+			mov x8, #63
+			svc #0
+			ldrb w19, [sp]
+			sub w19, w19, #48
+			movz w3, #26984
+			strh w3, [sp, #2]
+			mov x0, #1
+			add x1, sp, #2
+			mov x2, #2
+			mov x8, #64
+			svc #0
+			mov x0, #0
+			mov x8, #214
+			svc #0
+			add x0, x0, #4095
+			mov x8, #214
+			svc #0
+			sub x20, x0, #8
+			mov x0, #0
+			movz x1, #4096
+			mov x2, #3
+			mov x3, #34
+			movn x4, #0
+			mov x5, #0
+			mov x8, #222
+			svc #0
+			mov x21, x0
+			str xzr, [x20]
+		.L_start_1:
+			ldr x3, [x20]
+			add x3, x3, #2
+			str x3, [x20]
+			str w19, [x21, #8]!
+			subs w19, w19, #1
+			b.ne .L_start_1
+			ldr x0, [x20]
+			mov x8, #93
+			svc #0
+		";
+		let exe = link::link(&[assemble_str(src,).unwrap(),],).unwrap();
+		let (status, out,) = run(&exe, b"5",);
+		assert_eq!(out, b"hi");
+		assert_eq!(status.code(), Some(10));
+	}
+
+	/// 5 has bit 1 clear and bit 2 set
+	#[test]
+	fn tests_single_bits() {
+		let src = "	.text
+			.global _start
+		_start:
+			mov x1, #5
+			mov x0, #1
+			tbnz x1, #1, .L_start_2
+			tbz w1, #2, .L_start_2
+			tbz x1, #1, .L_start_1
+			mov x0, #2
+		.L_start_1:
+			add x0, x0, #41
+		.L_start_2:
+			mov x8, #93
+			svc #0
+		";
+		let exe = link::link(&[assemble_str(src,).unwrap(),],).unwrap();
+		assert_eq!(run(&exe, b"",).0.code(), Some(42));
+	}
+
+	#[test]
+	fn faults_like_a_process() {
+		let src = "	.text
+			.global _start
+		_start:
+			mov x0, #16
+			ldr x0, [x0]
+			ret
+		";
+		let exe = link::link(&[assemble_str(src,).unwrap(),],).unwrap();
+		assert_eq!(run(&exe, b"",).0.signal(), Some(SIGSEGV));
+	}
+}
@@ -0,0 +1,1112 @@
+//! integrated assembler for aarch64
+//!
+//! encodes allocated `Instruction`s into machine words, so that objects can
+//! be produced without binutils for the target. a first pass assigns every
+//! label its offset, the second encodes. branches to local labels are
+//! resolved here, references to symbols other objects may define are left
+//! to the linker as relocations
+//!
+//! encodings follow the Arm Architecture Reference Manual for A-profile,
+//! section C4 "A64 Instruction Set Encoding". the fixed bits of every
+//! opcode come from `table::encoding`, operand fields are added here
+
+use super::Cond;
+use super::Instruction;
+use super::Instruction::*;
+use super::Target;
+use super::table;
+use super::table::Form;
+use super::table::Opcode;
+use crate::err::B::X;
+use crate::err::B::Y;
+use crate::err::LlccB;
+use crate::err::LlccError;
+use crate::obj::Binding;
+use crate::obj::Object;
+use crate::obj::RelocKind;
+use crate::obj::Relocation;
+use crate::obj::SectionKind;
+use crate::register::Address;
+use crate::register::AddressMode;
+use crate::register::BitmaskImmediate;
+use crate::register::Extend;
+use crate::register::GeneralRegister;
+use crate::register::Register;
+use crate::register::RegisterClass;
+use crate::register::RegisterOrImmediate;
+use crate::register::Shift as RegShift;
+use std::collections::HashMap;
+
+/// register number in an encoding. `sp` and the zero registers are 31
+fn reg(r: Register,) -> u32 {
+	r.index().map(u32::from,).unwrap_or(31,)
+}
+
+/// `sf` bit selecting 64 bit operation
+fn sf(r: Register,) -> u32 {
+	r.is_64() as u32
+}
+
+/// `ftype` field of scalar float instructions
+fn ftype(r: Register,) -> u32 {
+	match r.class() {
+		RegisterClass::Fp { bytes: 2, } => 0b11,
+		RegisterClass::Fp { bytes: 8, } => 0b01,
+		_ => 0b00,
+	}
+}
+
+fn cond(c: Cond,) -> u32 {
+	match c {
+		Cond::Eq => 0,
+		Cond::Ne => 1,
+		Cond::Hs => 2,
+		Cond::Lo => 3,
+		Cond::Mi => 4,
+		Cond::Pl => 5,
+		Cond::Vs => 6,
+		Cond::Vc => 7,
+		Cond::Hi => 8,
+		Cond::Ls => 9,
+		Cond::Ge => 10,
+		Cond::Lt => 11,
+		Cond::Gt => 12,
+		Cond::Le => 13,
+	}
+}
+
+fn shift(s: RegShift,) -> u32 {
+	match s {
+		RegShift::Lsl => 0,
+		RegShift::Lsr => 1,
+		RegShift::Asr => 2,
+		RegShift::Ror => 3,
+	}
+}
+
+/// `option` field of extended registers
+fn extend(e: Extend,) -> u32 {
+	match e {
+		Extend::Uxtb => 0,
+		Extend::Uxth => 1,
+		Extend::Uxtw => 2,
+		Extend::Uxtx => 3,
+		Extend::Sxtb => 4,
+		Extend::Sxth => 5,
+		Extend::Sxtw => 6,
+		Extend::Sxtx => 7,
+	}
+}
+
+/// the low `bits` of `value`, which has to fit them as a signed number
+fn signed(value: i64, bits: u8,) -> LlccB<u32,> {
+	if !(-(1 << (bits - 1))..1 << (bits - 1)).contains(&value,) {
+		return Y(LlccError::mismatch_imm(value, bits, true,),);
+	}
+	X((value as u32) & ((1 << bits) - 1),)
+}
+
+/// `rd = rn <op> rm` with `op` in bits 31:21 and 15:10
+fn rrr(base: u32, rd: Register, rn: Register, rm: Register,) -> u32 {
+	base | reg(rm,) << 16 | reg(rn,) << 5 | reg(rd,)
+}
+
+/// `add`, `adds`, `sub` and `subs`, with `op` and `S` taken from `base`.
+/// `rd` is `None` for `cmp` and `cmn`, which discard the result
+fn arith(
+	base: u32,
+	rd: Option<Register,>,
+	rn: Register,
+	rhs: &RegisterOrImmediate<12, false,>,
+) -> LlccB<u32,> {
+	let sp = rn.is_sp() || rd.is_some_and(|r| r.is_sp(),);
+	let rd = rd.map(reg,).unwrap_or(31,);
+	let head = sf(rn,) << 31 | base & 0x6000_0000 | reg(rn,) << 5 | rd;
+	X(match rhs {
+		RegisterOrImmediate::Immediate(i,) => {
+			head | 0x1100_0000
+				| ((i.shift() == 12) as u32) << 22
+				| (i.field() as u32) << 10
+		},
+		// `sp` is only accepted by the extended form
+		RegisterOrImmediate::Register(rm,) if sp => {
+			let option = if rn.is_64() { Extend::Uxtx } else { Extend::Uxtw };
+			head | 0x0b20_0000 | reg(*rm,) << 16 | extend(option,) << 13
+		},
+		RegisterOrImmediate::Register(rm,) => {
+			head | 0x0b00_0000 | reg(*rm,) << 16
+		},
+		RegisterOrImmediate::Shifted(sr,) => {
+			head | 0x0b00_0000
+				| shift(sr.shift(),) << 22
+				| reg(*sr.reg(),) << 16
+				| (sr.amount() as u32) << 10
+		},
+		RegisterOrImmediate::Extended(er,) => {
+			head | 0x0b20_0000
+				| reg(*er.reg(),) << 16
+				| extend(er.extend(),) << 13
+				| (er.amount() as u32) << 10
+		},
+	},)
+}
+
+/// `and`, `orr`, `eor` and `ands`, with `opc` taken from `base`. `bic` is
+/// `and` with `N` set, which inverts the operand
+fn logic(
+	base: u32,
+	rd: u32,
+	rn: Register,
+	rhs: &RegisterOrImmediate<64, false, Register, BitmaskImmediate,>,
+) -> LlccB<u32,> {
+	let invert = base & 1 << 21 != 0;
+	let head = sf(rn,) << 31 | base & 0x6000_0000 | reg(rn,) << 5 | rd;
+	X(match rhs {
+		RegisterOrImmediate::Immediate(i,) => {
+			let i = if invert {
+				BitmaskImmediate::new(!i.value(), rn.is_64(),)?
+			} else {
+				*i
+			};
+			let (n, immr, imms,) = i.encoding();
+			head | 0x1200_0000
+				| (n as u32) << 22
+				| (immr as u32) << 16
+				| (imms as u32) << 10
+		},
+		RegisterOrImmediate::Register(rm,) => {
+			head | 0x0a00_0000 | (invert as u32) << 21 | reg(*rm,) << 16
+		},
+		RegisterOrImmediate::Shifted(sr,) => {
+			head | 0x0a00_0000
+				| shift(sr.shift(),) << 22
+				| (invert as u32) << 21
+				| reg(*sr.reg(),) << 16
+				| (sr.amount() as u32) << 10
+		},
+		RegisterOrImmediate::Extended(_,) => {
+			return Y(LlccError::unsupported("extended register in logic",),);
+		},
+	},)
+}
+
+/// `ubfm` or `sbfm`, which implement shifts by constants and extensions
+fn bitfield(
+	signed: bool,
+	rd: Register,
+	rn: Register,
+	immr: u32,
+	imms: u32,
+) -> u32 {
+	// the extensions are bitfield moves with a fixed `imms`
+	let extend = if signed { Opcode::Sxtb } else { Opcode::Uxtb };
+	let base = table::encoding(extend,) & !(0x3f << 10);
+	base | sf(rd,) << 31
+		| sf(rd,) << 22
+		| immr << 16
+		| imms << 10
+		| reg(rn,) << 5
+		| reg(rd,)
+}
+
+/// `size` and `opc` fields of a single load or store, and whether it uses
+/// the vector register file. the table holds them for a `w` register
+fn single(op: Opcode, r: Register,) -> LlccB<(u32, u32, u32,),> {
+	let bytes = match table::form(op,) {
+		Form::Load { bytes, .. } | Form::Store { bytes, } => bytes,
+		_ => {
+			return Y(LlccError::unsupported(format!(
+				"`{op}` as load or store"
+			),),);
+		},
+	};
+	let base = table::encoding(op,);
+	let (size, opc,) = (base >> 30, base >> 22 & 0b11,);
+	let width = r.class().bytes();
+	let size = if bytes.is_none() { width.trailing_zeros() } else { size };
+	if !r.is_gpr() {
+		// `q` registers use size 0 with the high bit of `opc` set
+		let opc = opc | if width == 16 { 0b10 } else { 0 };
+		return X((size & 0b11, opc, 1,),);
+	}
+	// signed loads into `x` registers clear the low bit of `opc`
+	let opc = if opc == 0b11 && r.is_64() { 0b10 } else { opc };
+	X((size, opc, 0,),)
+}
+
+/// the address operand of a single load or store of `bytes`
+fn single_address(addr: &Address, bytes: u32,) -> LlccB<u32,> {
+	let rn = reg(addr.base(),) << 5;
+	X(match *addr.mode() {
+		AddressMode::Offset(o,) => {
+			0x3900_0000 | ((o / bytes as i64) as u32) << 10 | rn
+		},
+		AddressMode::PreIndex(o,) => 0x3800_0c00 | signed(o, 9,)? << 12 | rn,
+		AddressMode::PostIndex(o,) => 0x3800_0400 | signed(o, 9,)? << 12 | rn,
+		AddressMode::Register { index, extend: e, amount, } => {
+			0x3820_0800
+				| reg(index,) << 16
+				| extend(e,) << 13
+				| ((amount != 0) as u32) << 12
+				| rn
+		},
+	},)
+}
+
+/// encodes `insts` into the `.text` of a new object. `Global` marks a
+/// symbol as visible to other objects, symbols referenced but not defined
+/// become undefined globals
+pub(super) fn encode(insts: &[Instruction<'_,>],) -> LlccB<Object,> {
+	let mut encoder = Encoder {
+		obj:    Object::default(),
+		labels: HashMap::new(),
+		pc:     0,
+	};
+	encoder.obj.section_mut(SectionKind::Text,);
+	for inst in insts {
+		match inst {
+			Global(name,) => {
+				encoder.obj.symbol_mut(name,).binding = Binding::Global
+			},
+			Symbol(name,) | Label(Target::Symbol(name,),) => {
+				let offset = encoder.pc;
+				let symbol = encoder.obj.symbol_mut(name,);
+				symbol.section = Some(SectionKind::Text,);
+				symbol.offset = offset;
+			},
+			Label(target,) => {
+				encoder.labels.insert(*target, encoder.pc,);
+			},
+			Section(_,) => (),
+			_ => encoder.pc += 4,
+		}
+	}
+
+	encoder.pc = 0;
+	for inst in insts {
+		if matches!(inst, Section(_,) | Global(_,) | Symbol(_,) | Label(_,)) {
+			continue;
+		}
+		let word = encoder.inst(inst,)?;
+		encoder.obj.section_mut(SectionKind::Text,).push(&word.to_le_bytes(),);
+		encoder.pc += 4;
+	}
+
+	let mut obj = encoder.obj;
+	for symbol in obj.symbols.iter_mut() {
+		if symbol.section.is_none() {
+			symbol.binding = Binding::Global;
+		}
+	}
+	X(obj,)
+}
+
+struct Encoder<'a,> {
+	obj:    Object,
+	labels: HashMap<Target<'a,>, u64,>,
+	/// offset of the instruction being encoded
+	pc:     u64,
+}
+
+impl<'a,> Encoder<'a,> {
+	/// word offset from the current instruction to `target`, or 0 with a
+	/// relocation of `kind` if only the linker knows it
+	fn branch(
+		&mut self,
+		target: Target<'a,>,
+		kind: RelocKind,
+		bits: u8,
+	) -> LlccB<u32,> {
+		let resolved = match target {
+			Target::Block { .. } => match self.labels.get(&target,) {
+				Some(offset,) => Some(*offset,),
+				None => {
+					return Y(LlccError::unsupported(format!(
+						"undefined label `{target}`"
+					),),);
+				},
+			},
+			Target::Symbol(name,) => self
+				.obj
+				.symbol(name,)
+				.filter(|s| s.binding == Binding::Local && s.section.is_some(),)
+				.map(|s| s.offset,),
+		};
+		match resolved {
+			Some(offset,) => {
+				signed((offset as i64 - self.pc as i64) / 4, bits,)
+			},
+			None => {
+				let Target::Symbol(name,) = target else { unreachable!() };
+				self.relocate(name, kind,);
+				X(0,)
+			},
+		}
+	}
+
+	fn relocate(&mut self, symbol: &str, kind: RelocKind,) {
+		self.obj.symbol_mut(symbol,);
+		self.obj.relocations.push(Relocation {
+			section: SectionKind::Text,
+			offset: self.pc,
+			kind,
+			symbol: symbol.to_string(),
+			addend: 0,
+		},);
+	}
+
+	fn inst(&mut self, inst: &Instruction<'a,>,) -> LlccB<u32,> {
+		let invalid = || LlccError::unsupported(format!("`{inst}`"),);
+		X(match inst {
+			Svc { syscall, } => {
+				table::encoding(Opcode::Svc,) | (*syscall as u32) << 5
+			},
+			Mov { target, value: RegisterOrImmediate::Register(source,), } => {
+				// `add target, source, #0` and `orr target, zr, source`
+				if target.is_sp() || source.is_sp() {
+					0x1100_0000
+						| sf(*target,) << 31
+						| reg(*source,) << 5
+						| reg(*target,)
+				} else {
+					table::encoding(Opcode::Orr,)
+						| sf(*target,) << 31
+						| 31 << 5 | reg(*source,) << 16
+						| reg(*target,)
+				}
+			},
+			Mov { target, value: RegisterOrImmediate::Immediate(i,), } => {
+				let value = i.value();
+				if value >> 16 != 0 {
+					return Y(LlccError::mismatch_imm(value, 16, false,),);
+				}
+				table::encoding(Opcode::Movz,)
+					| sf(*target,) << 31
+					| (value as u32) << 5
+					| reg(*target,)
+			},
+			Mov { .. } => return Y(invalid(),),
+			Wide { op, target, imm, } => {
+				table::encoding(*op,)
+					| sf(*target,) << 31
+					| (imm.shift() as u32 / 16) << 21
+					| (imm.field() as u32) << 5
+					| reg(*target,)
+			},
+			Arith { op, target, lhs, rhs, } => {
+				arith(table::encoding(*op,), Some(*target,), *lhs, rhs,)?
+			},
+			Compare {
+				op: Opcode::Fcmp,
+				lhs,
+				rhs: RegisterOrImmediate::Register(rhs,),
+			} => {
+				table::encoding(Opcode::Fcmp,)
+					| ftype(*lhs,) << 22
+					| reg(*rhs,) << 16
+					| reg(*lhs,) << 5
+			},
+			Compare { op, lhs, rhs, } => {
+				arith(table::encoding(*op,), None, *lhs, rhs,)?
+			},
+			Logic { op, target, lhs, rhs, } => {
+				logic(table::encoding(*op,), reg(*target,), *lhs, rhs,)?
+			},
+			Test { op, lhs, rhs, } => {
+				logic(table::encoding(*op,), 31, *lhs, rhs,)?
+			},
+			Shift {
+				op,
+				target,
+				lhs,
+				rhs: RegisterOrImmediate::Register(rhs,),
+			} => {
+				rrr(table::encoding(*op,), *target, *lhs, *rhs,)
+					| sf(*target,) << 31
+			},
+			Shift {
+				op,
+				target,
+				lhs,
+				rhs: RegisterOrImmediate::Immediate(i,),
+			} => {
+				let bits = if target.is_64() { 64 } else { 32 };
+				let amount = i.value() as u32;
+				match op {
+					Opcode::Lsl => bitfield(
+						false,
+						*target,
+						*lhs,
+						(bits - amount) % bits,
+						bits - 1 - amount,
+					),
+					Opcode::Lsr => {
+						bitfield(false, *target, *lhs, amount, bits - 1,)
+					},
+					Opcode::Asr => {
+						bitfield(true, *target, *lhs, amount, bits - 1,)
+					},
+					// `extr` of a register with itself
+					_ => {
+						rrr(0x1380_0000 | amount << 10, *target, *lhs, *lhs,)
+							| sf(*target,) << 31 | sf(*target,) << 22
+					},
+				}
+			},
+			Shift { .. } => return Y(invalid(),),
+			Binary { op, target, lhs, rhs, } => {
+				let base = table::encoding(*op,);
+				let width = if target.is_gpr() {
+					sf(*target,) << 31
+				} else {
+					ftype(*target,) << 22
+				};
+				rrr(base, *target, *lhs, *rhs,) | width
+			},
+			MulAdd { op, target, lhs, rhs, acc, } => {
+				rrr(
+					table::encoding(*op,) | reg(*acc,) << 10,
+					*target,
+					*lhs,
+					*rhs,
+				) | sf(*target,) << 31
+			},
+			Unary { op, target, source, } => {
+				self.unary(*op, *target, *source,)?
+			},
+			Select { op, target, lhs, rhs, cond: c, } => {
+				let base = table::encoding(*op,);
+				rrr(base | cond(*c,) << 12, *target, *lhs, *rhs,)
+					| sf(*target,) << 31
+			},
+			// `csinc target, zr, zr, !cond`
+			Cset { target, cond: c, } => {
+				table::encoding(Opcode::Cset,)
+					| sf(*target,) << 31
+					| cond(c.invert(),) << 12
+					| reg(*target,)
+			},
+			Load { op, target: r, addr, } | Store { op, source: r, addr, } => {
+				let (size, opc, v,) = single(*op, *r,)?;
+				// `q` registers have size 0 but scale offsets by 16
+				let bytes =
+					if v == 1 && opc & 0b10 != 0 { 16 } else { 1 << size };
+				size << 30
+					| v << 26 | opc << 22
+					| single_address(addr, bytes,)?
+					| reg(*r,)
+			},
+			Pair { op, first, second, addr, } => {
+				let bytes = first.class().bytes() as i64;
+				let opc = match (first.is_gpr(), bytes,) {
+					(true, 4,) | (false, 4,) => 0b00,
+					(false, 8,) => 0b01,
+					_ => 0b10,
+				};
+				let (mode, offset,) = match *addr.mode() {
+					AddressMode::Offset(o,) => (0b010, o,),
+					AddressMode::PreIndex(o,) => (0b011, o,),
+					AddressMode::PostIndex(o,) => (0b001, o,),
+					AddressMode::Register { .. } => return Y(invalid(),),
+				};
+				// the table holds the signed offset form of `w` registers
+				table::encoding(*op,) & !(0b111 << 23)
+					| opc << 30 | (!first.is_gpr() as u32) << 26
+					| mode << 23 | signed(offset / bytes, 7,)? << 15
+					| reg(*second,) << 10
+					| reg(addr.base(),) << 5
+					| reg(*first,)
+			},
+			PcRel { op, target, label, } => {
+				let kind = match op {
+					Opcode::Adr => RelocKind::AdrPrelLo21,
+					_ => RelocKind::AdrPrelPgHi21,
+				};
+				let offset = match (op, label,) {
+					(Opcode::Adr, Target::Block { .. },) => {
+						let Some(to,) = self.labels.get(label,) else {
+							return Y(invalid(),);
+						};
+						signed(*to as i64 - self.pc as i64, 21,)?
+					},
+					(_, Target::Symbol(name,),) => {
+						self.relocate(name, kind,);
+						0
+					},
+					// pages of local labels depend on where the section lands
+					_ => return Y(invalid(),),
+				};
+				table::encoding(*op,)
+					| (offset & 0b11) << 29
+					| (offset >> 2) << 5
+					| reg(*target,)
+			},
+			CompareBranch { op, reg: r, target, } => {
+				let imm = self.branch(*target, RelocKind::CondBr19, 19,)?;
+				table::encoding(*op,) | sf(*r,) << 31 | imm << 5 | reg(*r,)
+			},
+			// bit 5 of `bit` goes to `b5`, the rest to `b40`
+			TestBranch { op, reg: r, bit, target, } => {
+				let imm = self.branch(*target, RelocKind::TstBr14, 14,)?;
+				let bit = *bit as u32;
+				table::encoding(*op,)
+					| (bit >> 5) << 31
+					| (bit & 0x1f) << 19
+					| imm << 5 | reg(*r,)
+			},
+			B(target,) => {
+				table::encoding(Opcode::B,)
+					| self.branch(*target, RelocKind::Jump26, 26,)?
+			},
+			BCond { cond: c, target, } => {
+				table::encoding(Opcode::BCond,)
+					| self.branch(*target, RelocKind::CondBr19, 19,)? << 5
+					| cond(*c,)
+			},
+			Bl(name,) => {
+				table::encoding(Opcode::Bl,)
+					| self.branch(
+						Target::Symbol(name,),
+						RelocKind::Call26,
+						26,
+					)?
+			},
+			Blr(r,) => table::encoding(Opcode::Blr,) | reg(*r,) << 5,
+			Br(r,) => table::encoding(Opcode::Br,) | reg(*r,) << 5,
+			Ret => table::encoding(Opcode::Ret,),
+			Section(_,) | Global(_,) | Symbol(_,) | Label(_,) | Prologue
+			| Epilogue => {
+				return Y(invalid(),);
+			},
+		},)
+	}
+
+	fn unary(
+		&self,
+		op: Opcode,
+		target: Register,
+		source: Register,
+	) -> LlccB<u32,> {
+		let (rd, rn,) = (reg(target,), reg(source,),);
+		let base = table::encoding(op,);
+		let fp_bits = |base: u32| base | ftype(source,) << 22 | rn << 5 | rd;
+		X(match op {
+			// `sbfm` and `ubfm` with `sf` and `N` from the target
+			Opcode::Sxtb
+			| Opcode::Sxth
+			| Opcode::Sxtw
+			| Opcode::Uxtb
+			| Opcode::Uxth => {
+				base | sf(target,) << 31 | sf(target,) << 22 | rn << 5 | rd
+			},
+			// `sub target, zr, source` and `orn target, zr, source`
+			Opcode::Neg | Opcode::Mvn => {
+				base | sf(target,) << 31 | rn << 16 | rd
+			},
+			Opcode::Fneg | Opcode::Fabs | Opcode::Fsqrt => fp_bits(base,),
+			Opcode::Fmov => {
+				// the moves from and to general registers are their own
+				// encodings
+				match (target.is_gpr(), source.is_gpr(),) {
+					(false, false,) => fp_bits(base,),
+					(false, true,) => {
+						0x1e27_0000
+							| sf(source,) << 31 | ftype(target,) << 22
+							| rn << 5 | rd
+					},
+					(true, false,) => {
+						0x1e26_0000
+							| sf(target,) << 31 | ftype(source,) << 22
+							| rn << 5 | rd
+					},
+					(true, true,) => {
+						return Y(LlccError::unsupported(
+							"`fmov` between general registers",
+						),);
+					},
+				}
+			},
+			// `opc` is the `ftype` of the target
+			Opcode::Fcvt => fp_bits(base | ftype(target,) << 15,),
+			Opcode::Scvtf | Opcode::Ucvtf => {
+				base | sf(source,) << 31 | ftype(target,) << 22 | rn << 5 | rd
+			},
+			Opcode::Fcvtzs | Opcode::Fcvtzu => {
+				fp_bits(base,) | sf(target,) << 31
+			},
+			_ => return Y(LlccError::unsupported(format!("`{op}` as unary"),),),
+		},)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::err::Container;
+	use crate::register::ExtendedRegister;
+	use crate::register::Immediate;
+	use crate::register::ShiftedRegister;
+	use Register::*;
+
+	fn word(inst: Instruction<'_,>,) -> u32 {
+		let text = inst.to_string();
+		let obj = encode(&[inst,],).unwrap();
+		let data = &obj.section(SectionKind::Text,).unwrap().data;
+		assert_eq!(data.len(), 4, "{text}");
+		u32::from_le_bytes(data[..4].try_into().unwrap(),)
+	}
+
+	fn imm<const N: u8,>(v: i64,) -> RegisterOrImmediate<N, false,> {
+		RegisterOrImmediate::Immediate(Immediate::try_from(v,).unwrap(),)
+	}
+
+	/// encodings as listed in the Arm ARM and printed by GNU `as`
+	#[test]
+	fn known_encodings() {
+		let single = |b| crate::register::Access::Single(b,);
+		let cases: Vec<(Instruction<'_,>, u32,),> = vec![
+			(Ret, 0xd65f03c0,),
+			(Svc { syscall: 0, }, 0xd4000001,),
+			(
+				Mov { target: X0, value: RegisterOrImmediate::Register(X1,), },
+				0xaa0103e0,
+			),
+			(
+				Mov { target: Sp, value: RegisterOrImmediate::Register(X29,), },
+				0x910003bf,
+			),
+			(Mov { target: W0, value: imm(42,), }, 0x52800540,),
+			(
+				Wide {
+					op:     Opcode::Movk,
+					target: X1,
+					imm:    Immediate::with_shift(0x1234, 16,).unwrap(),
+				},
+				0xf2a24681,
+			),
+			(
+				Arith {
+					op:     Opcode::Add,
+					target: X0,
+					lhs:    X1,
+					rhs:    imm(16,),
+				},
+				0x91004020,
+			),
+			(
+				Arith {
+					op:     Opcode::Sub,
+					target: Sp,
+					lhs:    Sp,
+					rhs:    RegisterOrImmediate::Immediate(
+						Immediate::with_shift(1, 12,).unwrap(),
+					),
+				},
+				0xd14007ff,
+			),
+			(
+				Arith {
+					op:     Opcode::Subs,
+					target: W2,
+					lhs:    W3,
+					rhs:    RegisterOrImmediate::Register(W4,),
+				},
+				0x6b040062,
+			),
+			(
+				Arith {
+					op:     Opcode::Add,
+					target: X0,
+					lhs:    X1,
+					rhs:    RegisterOrImmediate::Shifted(
+						ShiftedRegister::new(X2, RegShift::Lsl, 3,).unwrap(),
+					),
+				},
+				0x8b020c20,
+			),
+			(
+				Arith {
+					op:     Opcode::Add,
+					target: X0,
+					lhs:    X1,
+					rhs:    RegisterOrImmediate::Extended(
+						ExtendedRegister::new(W2, Extend::Sxtw, 0,).unwrap(),
+					),
+				},
+				0x8b22c020,
+			),
+			(
+				Arith {
+					op:     Opcode::Add,
+					target: X0,
+					lhs:    Sp,
+					rhs:    RegisterOrImmediate::Register(X1,),
+				},
+				0x8b2163e0,
+			),
+			(Compare { op: Opcode::Cmp, lhs: X0, rhs: imm(1,), }, 0xf100041f,),
+			(
+				Compare {
+					op:  Opcode::Cmn,
+					lhs: W0,
+					rhs: RegisterOrImmediate::Register(W1,),
+				},
+				0x2b01001f,
+			),
+			(
+				Compare {
+					op:  Opcode::Fcmp,
+					lhs: D0,
+					rhs: RegisterOrImmediate::Register(D1,),
+				},
+				0x1e612000,
+			),
+			(
+				Logic {
+					op:     Opcode::And,
+					target: X0,
+					lhs:    X1,
+					rhs:    RegisterOrImmediate::Immediate(
+						BitmaskImmediate::new(0xff, true,).unwrap(),
+					),
+				},
+				0x92401c20,
+			),
+			(
+				Logic {
+					op:     Opcode::Eor,
+					target: W0,
+					lhs:    W1,
+					rhs:    RegisterOrImmediate::Register(W2,),
+				},
+				0x4a020020,
+			),
+			(
+				Logic {
+					op:     Opcode::Bic,
+					target: X0,
+					lhs:    X1,
+					rhs:    RegisterOrImmediate::Register(X2,),
+				},
+				0x8a220020,
+			),
+			(
+				Test {
+					op:  Opcode::Tst,
+					lhs: W0,
+					rhs: RegisterOrImmediate::Immediate(
+						BitmaskImmediate::new(1, false,).unwrap(),
+					),
+				},
+				0x7200001f,
+			),
+			(
+				Shift {
+					op:     Opcode::Lsl,
+					target: X0,
+					lhs:    X1,
+					rhs:    imm(4,),
+				},
+				0xd37cec20,
+			),
+			(
+				Shift {
+					op:     Opcode::Lsr,
+					target: W0,
+					lhs:    W1,
+					rhs:    imm(3,),
+				},
+				0x53037c20,
+			),
+			(
+				Shift {
+					op:     Opcode::Asr,
+					target: X0,
+					lhs:    X1,
+					rhs:    RegisterOrImmediate::Register(X2,),
+				},
+				0x9ac22820,
+			),
+			(
+				Shift {
+					op:     Opcode::Ror,
+					target: W0,
+					lhs:    W1,
+					rhs:    imm(8,),
+				},
+				0x13812020,
+			),
+			(
+				Binary {
+					op:     Opcode::Mul,
+					target: X0,
+					lhs:    X1,
+					rhs:    X2,
+				},
+				0x9b027c20,
+			),
+			(
+				Binary {
+					op:     Opcode::Sdiv,
+					target: W0,
+					lhs:    W1,
+					rhs:    W2,
+				},
+				0x1ac20c20,
+			),
+			(
+				Binary {
+					op:     Opcode::Udiv,
+					target: X0,
+					lhs:    X1,
+					rhs:    X2,
+				},
+				0x9ac20820,
+			),
+			(
+				Binary {
+					op:     Opcode::Smulh,
+					target: X0,
+					lhs:    X1,
+					rhs:    X2,
+				},
+				0x9b427c20,
+			),
+			(
+				Binary {
+					op:     Opcode::Fadd,
+					target: S0,
+					lhs:    S1,
+					rhs:    S2,
+				},
+				0x1e222820,
+			),
+			(
+				Binary {
+					op:     Opcode::Fdiv,
+					target: D0,
+					lhs:    D1,
+					rhs:    D2,
+				},
+				0x1e621820,
+			),
+			(
+				MulAdd {
+					op:     Opcode::Msub,
+					target: X0,
+					lhs:    X1,
+					rhs:    X2,
+					acc:    X3,
+				},
+				0x9b028c20,
+			),
+			(Unary { op: Opcode::Neg, target: W0, source: W1, }, 0x4b0103e0,),
+			(Unary { op: Opcode::Mvn, target: X0, source: X1, }, 0xaa2103e0,),
+			(Unary { op: Opcode::Sxtb, target: X0, source: W1, }, 0x93401c20,),
+			(Unary { op: Opcode::Sxtw, target: X0, source: W1, }, 0x93407c20,),
+			(Unary { op: Opcode::Uxth, target: W0, source: W1, }, 0x53003c20,),
+			(Unary { op: Opcode::Fneg, target: D0, source: D1, }, 0x1e614020,),
+			(Unary { op: Opcode::Fmov, target: D0, source: X1, }, 0x9e670020,),
+			(Unary { op: Opcode::Fmov, target: W0, source: S1, }, 0x1e260020,),
+			(Unary { op: Opcode::Fmov, target: S0, source: S1, }, 0x1e204020,),
+			(Unary { op: Opcode::Fcvt, target: D0, source: S1, }, 0x1e22c020,),
+			(Unary { op: Opcode::Fcvt, target: S0, source: D1, }, 0x1e624020,),
+			(Unary { op: Opcode::Scvtf, target: D0, source: X1, }, 0x9e620020,),
+			(Unary { op: Opcode::Ucvtf, target: S0, source: W1, }, 0x1e230020,),
+			(Unary { op: Opcode::Fcvtzs, target: W0, source: D1, }, 0x1e780020,),
+			(Unary { op: Opcode::Fcvtzu, target: X0, source: S1, }, 0x9e390020,),
+			(
+				Select {
+					op:     Opcode::Csel,
+					target: X0,
+					lhs:    X1,
+					rhs:    X2,
+					cond:   Cond::Lt,
+				},
+				0x9a82b020,
+			),
+			(Cset { target: W0, cond: Cond::Eq, }, 0x1a9f17e0,),
+			(
+				Load {
+					op:     Opcode::Ldr,
+					target: X0,
+					addr:   Address::offset(Sp, 8, single(8,),).unwrap(),
+				},
+				0xf94007e0,
+			),
+			(
+				Load {
+					op:     Opcode::Ldrsw,
+					target: X0,
+					addr:   Address::offset(X1, 4, single(4,),).unwrap(),
+				},
+				0xb9800420,
+			),
+			(
+				Load {
+					op:     Opcode::Ldrb,
+					target: W0,
+					addr:   Address::post_index(X1, 1, single(1,),).unwrap(),
+				},
+				0x38401420,
+			),
+			(
+				Load {
+					op:     Opcode::Ldrsh,
+					target: W0,
+					addr:   Address::offset(X1, 0, single(2,),).unwrap(),
+				},
+				0x79c00020,
+			),
+			(
+				Load {
+					op:     Opcode::Ldr,
+					target: D0,
+					addr:   Address::offset(X29, 16, single(8,),).unwrap(),
+				},
+				0xfd400ba0,
+			),
+			(
+				Load {
+					op:     Opcode::Ldr,
+					target: W0,
+					addr:   Address::register(
+						X1,
+						X2,
+						Extend::Uxtx,
+						true,
+						single(4,),
+					)
+					.unwrap(),
+				},
+				0xb8627820,
+			),
+			(
+				Store {
+					op:     Opcode::Str,
+					source: W0,
+					addr:   Address::pre_index(Sp, -16, single(4,),).unwrap(),
+				},
+				0xb81f0fe0,
+			),
+			(
+				Store {
+					op:     Opcode::Strh,
+					source: W0,
+					addr:   Address::offset(X1, 2, single(2,),).unwrap(),
+				},
+				0x79000420,
+			),
+			(
+				Store {
+					op:     Opcode::Str,
+					source: S0,
+					addr:   Address::offset(X1, 4, single(4,),).unwrap(),
+				},
+				0xbd000420,
+			),
+			(
+				Pair {
+					op:     Opcode::Stp,
+					first:  X29,
+					second: X30,
+					addr:   Address::pre_index(
+						Sp,
+						-16,
+						crate::register::Access::Pair(8,),
+					)
+					.unwrap(),
+				},
+				0xa9bf7bfd,
+			),
+			(
+				Pair {
+					op:     Opcode::Ldp,
+					first:  D8,
+					second: D9,
+					addr:   Address::offset(
+						Sp,
+						16,
+						crate::register::Access::Pair(8,),
+					)
+					.unwrap(),
+				},
+				0x6d4127e8,
+			),
+			(Blr(X16,), 0xd63f0200,),
+			(Br(X17,), 0xd61f0220,),
+		];
+		for (inst, expected,) in cases {
+			let text = inst.to_string();
+			assert_eq!(word(inst,), expected, "{text}: {:#010x}", expected);
+		}
+	}
+
+	#[test]
+	fn modules_encode_through_the_target() {
+		use crate::target::Target;
+
+		let triple = "aarch64".parse().unwrap();
+		let module = crate::ir::Module {
+			functions: vec![crate::ir::lower::lower_expr("1+2",).unwrap()],
+		};
+		let obj = super::super::Aarch64 { triple, }
+			.object(&module,)
+			.unwrap()
+			.unwrap();
+		let start = obj.symbol("_start",).unwrap();
+		assert_eq!((start.offset, start.binding,), (0, Binding::Global,));
+		let text = &obj.section(SectionKind::Text,).unwrap().data;
+		// `_start` ends in `svc #93`, the exit call
+		assert_eq!(text[text.len() - 4..], 0xd4000ba1u32.to_le_bytes());
+
+		let x86: crate::target::Triple = "x86_64".parse().unwrap();
+		assert!(x86.target().object(&module,).is_none());
+	}
+
+	#[test]
+	fn branches_resolve_or_relocate() {
+		let top = Target::Block { func: "f", block: 0, };
+		let end = Target::Block { func: "f", block: 1, };
+		let insts = vec![
+			Section(super::super::SectionKind::Text,),
+			Global("f",),
+			Symbol("f",),
+			Label(top,),
+			CompareBranch { op: Opcode::Cbz, reg: W0, target: end, },
+			Bl("g",),
+			BCond { cond: Cond::Ne, target: top, },
+			B(Target::Symbol("h",),),
+			Label(end,),
+			Ret,
+		];
+		let obj = encode(&insts,).unwrap();
+		let text = &obj.section(SectionKind::Text,).unwrap().data;
+		let words: Vec<u32,> = text
+			.chunks(4,)
+			.map(|c| u32::from_le_bytes(c.try_into().unwrap(),),)
+			.collect();
+		// cbz w0, +16; bl g; b.ne -8; b h; ret
+		assert_eq!(
+			words,
+			[0x34000080, 0x94000000, 0x54ffffc1, 0x14000000, 0xd65f03c0]
+		);
+
+		let f = obj.symbol("f",).unwrap();
+		assert_eq!(
+			(f.section, f.offset, f.binding,),
+			(Some(SectionKind::Text,), 0, Binding::Global,)
+		);
+		let g = obj.symbol("g",).unwrap();
+		assert_eq!((g.section, g.binding,), (None, Binding::Global,));
+		let relocs: Vec<_,> = obj
+			.relocations
+			.iter()
+			.map(|r| (r.offset, r.kind, r.symbol.as_str(),),)
+			.collect();
+		assert_eq!(
+			relocs,
+			[(4, RelocKind::Call26, "g",), (12, RelocKind::Jump26, "h",)]
+		);
+	}
+}
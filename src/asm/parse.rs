@@ -0,0 +1,479 @@
+//! parser for aarch64 assembly text
+//!
+//! reads the subset of GNU syntax the printer writes back into
+//! `Instruction`s: one directive, label or instruction per line, with `//`
+//! comments. every instruction is held to `Instruction::check`, so that
+//! hand-written files are validated like selected code before they are
+//! encoded
+
+use super::Cond;
+use super::Instruction;
+use super::Instruction::*;
+use super::SectionKind;
+use super::Target;
+use super::table;
+use super::table::Form;
+use super::table::Opcode;
+use crate::err::B::X;
+use crate::err::B::Y;
+use crate::err::LlccB;
+use crate::err::LlccError;
+use crate::register::Access;
+use crate::register::Address;
+use crate::register::BitmaskImmediate;
+use crate::register::Extend;
+use crate::register::ExtendedRegister;
+use crate::register::Immediate;
+use crate::register::Register;
+use crate::register::RegisterOrImmediate;
+use crate::register::Shift as RegShift;
+use crate::register::ShiftedRegister;
+use std::fmt::Display;
+
+/// parses `src`, naming the offending line on errors
+pub(super) fn parse(src: &str,) -> LlccB<Vec<Instruction<'_,>,>,> {
+	let mut insts = vec![];
+	for (n, line,) in src.lines().enumerate() {
+		let line = line.split("//",).next().unwrap_or_default().trim();
+		if line.is_empty() {
+			continue;
+		}
+		let Some(inst,) = statement(line,) else {
+			return Y(LlccError::syntax(format!(
+				"line {}: cannot parse `{line}`",
+				n + 1
+			),),);
+		};
+		if let Err(e,) = inst.check() {
+			return Y(LlccError::syntax(format!("line {}: {e}", n + 1),),);
+		}
+		insts.push(inst,);
+	}
+	X(insts,)
+}
+
+fn statement(line: &str,) -> Option<Instruction<'_,>,> {
+	if let Some(label,) = line.strip_suffix(':',) {
+		return Some(match target(label,)? {
+			Target::Symbol(name,) => Symbol(name,),
+			block => Label(block,),
+		},);
+	}
+	let (mnemonic, rest,) =
+		line.split_once(char::is_whitespace,).unwrap_or((line, "",),);
+	let ops = operands(rest.trim(),);
+	let reg = |i: usize| register(ops.get(i,)?,);
+	let label = |i: usize| target(ops.get(i,)?,);
+
+	Some(match (mnemonic, ops.as_slice(),) {
+		(".text", [],) => Section(SectionKind::Text,),
+		(".global" | ".globl", [name,],) => Global(symbol(name,)?,),
+		("mov", [_, rest @ ..,],) => Mov {
+			target: reg(0,)?,
+			value:  operand(rest, |value, shift| {
+				Immediate::with_shift(value, shift,).ok()
+			},)?,
+		},
+		(_, [_,],) if mnemonic.starts_with("b.",) => BCond {
+			cond:   mnemonic["b.".len()..].parse::<Cond>().ok()?,
+			target: label(0,)?,
+		},
+		_ => return instruction(mnemonic.parse().ok()?, &ops,),
+	},)
+}
+
+/// instructions named by their opcode, with operands after `table::form`
+fn instruction<'a,>(op: Opcode, ops: &[&'a str],) -> Option<Instruction<'a,>,> {
+	let reg = |i: usize| register(ops.get(i,)?,);
+	let arith = |rest: &[&str]| {
+		operand(rest, |value, shift| Immediate::with_shift(value, shift,).ok(),)
+	};
+	// bitmask immediates are never shifted
+	let logic = |rest: &[&str], is_64: bool| {
+		operand(rest, |value, shift| {
+			(shift == 0)
+				.then(|| BitmaskImmediate::new(value as u64, is_64,).ok(),)?
+		},)
+	};
+	let form = table::form(op,);
+	Some(match (form, ops,) {
+		(Form::Wide, [_, rest @ ..,],) => {
+			let (value, shift,) = shifted(rest,)?;
+			Wide {
+				op,
+				target: reg(0,)?,
+				imm: Immediate::with_shift(value, shift,).ok()?,
+			}
+		},
+		(Form::Arith, [_, _, rest @ ..,],) => {
+			Arith { op, target: reg(0,)?, lhs: reg(1,)?, rhs: arith(rest,)?, }
+		},
+		(Form::Compare, [_, rest @ ..,],) => {
+			Compare { op, lhs: reg(0,)?, rhs: arith(rest,)?, }
+		},
+		(Form::FpCompare, [_, _,],) => Compare {
+			op,
+			lhs: reg(0,)?,
+			rhs: RegisterOrImmediate::Register(reg(1,)?,),
+		},
+		(Form::Logic, [_, _, rest @ ..,],) => {
+			let target = reg(0,)?;
+			Logic {
+				op,
+				target,
+				lhs: reg(1,)?,
+				rhs: logic(rest, target.is_64(),)?,
+			}
+		},
+		(Form::Test, [_, rest @ ..,],) => {
+			let lhs = reg(0,)?;
+			Test { op, lhs, rhs: logic(rest, lhs.is_64(),)?, }
+		},
+		(Form::Shift, [_, _, rest @ ..,],) => Shift {
+			op,
+			target: reg(0,)?,
+			lhs: reg(1,)?,
+			rhs: operand(rest, |value, shift| {
+				(shift == 0).then(|| Immediate::try_from(value,).ok(),)?
+			},)?,
+		},
+		(Form::Binary | Form::FpBinary, [_, _, _,],) => {
+			Binary { op, target: reg(0,)?, lhs: reg(1,)?, rhs: reg(2,)?, }
+		},
+		(Form::MulAdd, [_, _, _, _,],) => MulAdd {
+			op,
+			target: reg(0,)?,
+			lhs: reg(1,)?,
+			rhs: reg(2,)?,
+			acc: reg(3,)?,
+		},
+		(
+			Form::Unary
+			| Form::Extend { .. }
+			| Form::FpUnary
+			| Form::FpMove
+			| Form::FpConvert
+			| Form::IntToFp
+			| Form::FpToInt,
+			[_, _,],
+		) => Unary { op, target: reg(0,)?, source: reg(1,)?, },
+		(Form::Select, [_, _, _, c,],) => Select {
+			op,
+			target: reg(0,)?,
+			lhs: reg(1,)?,
+			rhs: reg(2,)?,
+			cond: c.parse().ok()?,
+		},
+		(Form::Load { .. }, [_, addr @ ..,],) => {
+			let target = reg(0,)?;
+			let access = form.access(target.class().bytes(),)?;
+			Load { op, target, addr: address(addr, access,)?, }
+		},
+		(Form::Store { .. }, [_, addr @ ..,],) => {
+			let source = reg(0,)?;
+			let access = form.access(source.class().bytes(),)?;
+			Store { op, source, addr: address(addr, access,)?, }
+		},
+		(Form::Pair, [_, _, addr @ ..,],) => {
+			let first = reg(0,)?;
+			let access = form.access(first.class().bytes(),)?;
+			Pair { op, first, second: reg(1,)?, addr: address(addr, access,)?, }
+		},
+		(Form::PcRel, [_, label,],) => {
+			PcRel { op, target: reg(0,)?, label: target(label,)?, }
+		},
+		(Form::CompareBranch, [_, label,],) => {
+			CompareBranch { op, reg: reg(0,)?, target: target(label,)?, }
+		},
+		(Form::TestBranch, [_, bit, label,],) => TestBranch {
+			op,
+			reg: reg(0,)?,
+			bit: immediate(bit,)?.try_into().ok()?,
+			target: target(label,)?,
+		},
+		(Form::SetCond, [_, c,],) => {
+			Cset { target: reg(0,)?, cond: c.parse().ok()?, }
+		},
+		(Form::Branch, [label,],) if op == Opcode::Bl => Bl(symbol(label,)?,),
+		(Form::Branch, [label,],) => B(target(label,)?,),
+		(Form::BranchReg, [_,],) if op == Opcode::Blr => Blr(reg(0,)?,),
+		(Form::BranchReg, [_,],) => Br(reg(0,)?,),
+		(Form::Return, [],) => Ret,
+		(Form::Exception, [number,],) => {
+			Svc { syscall: immediate(number,)?.try_into().ok()?, }
+		},
+		_ => return None,
+	},)
+}
+
+/// splits at the commas outside of brackets
+fn operands(text: &str,) -> Vec<&str,> {
+	if text.is_empty() {
+		return vec![];
+	}
+	let mut ops = vec![];
+	let (mut depth, mut start,) = (0, 0,);
+	for (i, c,) in text.char_indices() {
+		match c {
+			'[' => depth += 1,
+			']' => depth -= 1,
+			',' if depth == 0 => {
+				ops.push(text[start..i].trim(),);
+				start = i + 1;
+			},
+			_ => (),
+		}
+	}
+	ops.push(text[start..].trim(),);
+	ops
+}
+
+fn register(text: &str,) -> Option<Register,> {
+	text.parse().ok()
+}
+
+/// `#value` in decimal or `0x` hexadecimal
+fn immediate(text: &str,) -> Option<i64,> {
+	let text = text.strip_prefix('#',)?;
+	let (negative, digits,) = match text.strip_prefix('-',) {
+		Some(digits,) => (true, digits,),
+		None => (false, text,),
+	};
+	let value = match digits.strip_prefix("0x",) {
+		Some(hex,) => u64::from_str_radix(hex, 16,).ok()? as i64,
+		None => digits.parse().ok()?,
+	};
+	Some(if negative { value.wrapping_neg() } else { value },)
+}
+
+/// `#value`, optionally followed by `lsl #shift`
+fn shifted(ops: &[&str],) -> Option<(i64, u8,),> {
+	match ops {
+		[value,] => Some((immediate(value,)?, 0,),),
+		[value, shift,] => Some((
+			immediate(value,)?,
+			immediate(shift.strip_prefix("lsl ",)?,)?.try_into().ok()?,
+		),),
+		_ => None,
+	}
+}
+
+/// a register, optionally shifted or extended, or an immediate `imm` makes
+/// from the value and its shift
+fn operand<const N: u8, I: Display,>(
+	ops: &[&str],
+	imm: impl FnOnce(i64, u8,) -> Option<I,>,
+) -> Option<RegisterOrImmediate<N, false, Register, I,>,> {
+	if ops.first()?.starts_with('#',) {
+		let (value, shift,) = shifted(ops,)?;
+		return Some(RegisterOrImmediate::Immediate(imm(value, shift,)?,),);
+	}
+	let (reg, modifier,) = match ops {
+		[reg,] => return Some(RegisterOrImmediate::Register(register(reg,)?,),),
+		[reg, modifier,] => (register(reg,)?, *modifier,),
+		_ => return None,
+	};
+	let (name, amount,) = match modifier.split_once(' ',) {
+		Some((name, amount,),) => (name, immediate(amount,)?.try_into().ok()?,),
+		None => (modifier, 0,),
+	};
+	Some(match name.parse::<RegShift>() {
+		Ok(shift,) => RegisterOrImmediate::Shifted(
+			ShiftedRegister::new(reg, shift, amount,).ok()?,
+		),
+		Err(_,) => RegisterOrImmediate::Extended(
+			ExtendedRegister::new(reg, name.parse().ok()?, amount,).ok()?,
+		),
+	},)
+}
+
+/// `[base]`, `[base, #offset]`, `[base, #offset]!`, `[base], #offset` or
+/// `[base, index]` with an optional extension or shift
+fn address(ops: &[&str], access: Access,) -> Option<Address,> {
+	let (inner, post,) = match ops {
+		[inner,] => (*inner, None,),
+		[inner, offset,] => (*inner, Some(immediate(offset,)?,),),
+		_ => return None,
+	};
+	let (inner, pre,) = match inner.strip_suffix('!',) {
+		Some(inner,) => (inner, true,),
+		None => (inner, false,),
+	};
+	let parts = operands(inner.strip_prefix('[',)?.strip_suffix(']',)?,);
+	let base = register(parts.first()?,)?;
+	let addr = match (&parts[1..], post, pre,) {
+		([], None, false,) => Address::offset(base, 0, access,),
+		([], Some(offset,), false,) => {
+			Address::post_index(base, offset, access,)
+		},
+		([offset,], None, _,) if offset.starts_with('#',) => {
+			let offset = immediate(offset,)?;
+			if pre {
+				Address::pre_index(base, offset, access,)
+			} else {
+				Address::offset(base, offset, access,)
+			}
+		},
+		([index, modifier @ ..,], None, false,) => {
+			let index = register(index,)?;
+			let (extend, amount,) = match modifier {
+				[] => (Extend::Uxtx, 0,),
+				[modifier,] => match modifier.split_once(' ',) {
+					Some(("lsl", amount,),) => {
+						(Extend::Uxtx, immediate(amount,)?,)
+					},
+					Some((extend, amount,),) => {
+						(extend.parse().ok()?, immediate(amount,)?,)
+					},
+					None => (modifier.parse().ok()?, 0,),
+				},
+				_ => return None,
+			};
+			// the index is either scaled by the access size or not at all
+			let (Access::Single(size,) | Access::Pair(size,)) = access;
+			let scaled = amount == size.trailing_zeros() as i64;
+			if !scaled && amount != 0 {
+				return None;
+			}
+			Address::register(base, index, extend, scaled, access,)
+		},
+		_ => return None,
+	};
+	addr.ok()
+}
+
+/// symbols and the `.L{func}_{block}` labels of basic blocks
+fn target(text: &str,) -> Option<Target<'_,>,> {
+	let block = text
+		.strip_prefix(".L",)
+		.and_then(|label| label.rsplit_once('_',),)
+		.and_then(|(func, block,)| Some((func, block.parse().ok()?,),),);
+	match block {
+		Some((func, block,),) => Some(Target::Block { func, block, },),
+		None => Some(Target::Symbol(symbol(text,)?,),),
+	}
+}
+
+fn symbol(text: &str,) -> Option<&str,> {
+	let valid =
+		|c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$');
+	let starts_well = text.chars().next().is_some_and(|c| !c.is_ascii_digit(),);
+	(starts_well && text.chars().all(valid,)).then_some(text,)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::Aarch64;
+	use super::super::ReadableAsm;
+	use super::super::asm_str;
+	use super::super::assemble_str;
+	use super::super::decode::decode;
+	use super::super::decode::tests::FORMS;
+	use super::*;
+	use crate::err::Container;
+	use crate::obj::SectionKind;
+	use crate::opt::OptLevel;
+	use quickcheck_macros::quickcheck;
+
+	/// fixed bits of `b`, `b.cond`, `cbz` and `adr`
+	const BRANCHES: [(u32, u32,); 4] = [
+		(0x7c00_0000, 0x1400_0000,),
+		(0xff00_0010, 0x5400_0000,),
+		(0x7e00_0000, 0x3400_0000,),
+		(0x1f00_0000, 0x1000_0000,),
+	];
+
+	/// the decoder makes arbitrary instructions, which have to parse back
+	/// from what the printer writes
+	#[quickcheck]
+	fn printed_instructions_parse_back(form: u8, word: u32,) -> bool {
+		let forms = FORMS.len() + BRANCHES.len();
+		let (mask, fixed,) = FORMS
+			.iter()
+			.chain(&BRANCHES,)
+			.nth(form as usize % forms,)
+			.copied()
+			.expect("form in range",);
+		let word = word & !mask | fixed;
+		match decode(word, 0, |_| "f",) {
+			Some(inst,) if inst.check().is_ok() => {
+				let text = inst.to_string();
+				parse(&text,).unwrap() == [inst,]
+			},
+			_ => true,
+		}
+	}
+
+	#[test]
+	fn readable_asm_round_trips() {
+		let target = Aarch64 { triple: "aarch64-linux-gnu".parse().unwrap(), };
+		for src in ["1+2+3-4", "1.5+2.25f-1",] {
+			let printed: String =
+				asm_str(src, OptLevel::O0, &target,).unwrap().into();
+			let parsed = parse(&printed,).unwrap();
+			let reprinted =
+				String::from(ReadableAsm::from_instructions(parsed,),);
+			assert_eq!(reprinted, printed);
+		}
+	}
+
+	#[test]
+	fn hand_written_asm_encodes_like_llvm_mc() {
+		let src = "	.text
+			.global _start
+		_start:
+			mov x0, #3 // count
+			mov x1, #0
+		.L_start_1:
+			add x1, x1, x0, lsl #1
+			subs x0, x0, #1
+			b.ne .L_start_1
+			tbnz x1, #33, .L_start_1
+			tbz w2, #3, .L_start_1
+			cset w3, hi
+			ldr w2, [sp, x1, lsl #2]
+			stp x29, x30, [sp, #-16]!
+			and w0, w1, #0xff
+			mov x8, #93
+			svc #0
+		";
+		// as `llvm-mc -triple=aarch64-linux-gnu` assembles it
+		let theirs: Vec<u8,> = [
+			0xd280_0060u32,
+			0xd280_0001,
+			0x8b00_0421,
+			0xf100_0400,
+			0x54ff_ffc1,
+			0xb70f_ffa1,
+			0x361f_ff82,
+			0x1a9f_97e3,
+			0xb861_7be2,
+			0xa9bf_7bfd,
+			0x1200_1c20,
+			0xd280_0ba8,
+			0xd400_0001,
+		]
+		.iter()
+		.flat_map(|word| word.to_le_bytes(),)
+		.collect();
+		let obj = assemble_str(src,).unwrap();
+		assert_eq!(obj.section(SectionKind::Text,).unwrap().data, theirs);
+	}
+
+	#[test]
+	fn rejects_malformed_and_invalid_lines() {
+		for src in [
+			"mov x0, x1, x2",
+			"ldr x0, [x1, x2, lsl #2]",
+			"add x0, w1, #1",
+			"b.xx .Lf_1",
+			"ldr x0, [x1, #3]",
+			"frobnicate x0",
+		] {
+			let Y(e,) = parse(&format!("ret\n{src}\n"),) else {
+				panic!("`{src}` parsed");
+			};
+			assert!(e.to_string().contains("line 2",), "{e}");
+		}
+	}
+}
@@ -0,0 +1,636 @@
+//! linear scan register allocation
+//!
+//! instruction selection writes virtual registers. each of them gets one
+//! live interval over the instruction list, computed from liveness on the
+//! branches between labels. intervals are assigned in order of their start.
+//! an interval crossing a call only gets callee saved registers, others
+//! prefer caller saved ones. when no register is free, the interval ending
+//! last is spilled to a frame slot and every access to it goes through
+//! one of `x15`-`x17`, or `v29`-`v31` for floats. the general purpose and
+//! the float registers are allocated independently
+//!
+//! the frame is laid out afterwards: `x29` and `x30`, the stack slots of
+//! the IR, the callee saved registers in use, then the spill slots
+
+use super::Instruction;
+use super::Instruction::*;
+use super::Target;
+use super::select::ARITH_SHIFTS;
+use super::table::Opcode;
+use crate::err::B::X;
+use crate::err::LlccB;
+use crate::err::LlccError;
+use crate::register::Access;
+use crate::register::Address;
+use crate::register::GeneralRegister;
+use crate::register::Immediate;
+use crate::register::Register;
+use crate::register::RegisterClass;
+use crate::register::RegisterOrImmediate;
+use crate::target;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+/// allocatable registers of one register file
+struct File {
+	/// caller saved registers which do not pass arguments
+	caller_saved:  Vec<u8,>,
+	callee_saved:  Vec<u8,>,
+	/// holds spilled registers while an instruction reads them. an
+	/// instruction reads up to three registers
+	spill_scratch: [u8; 3],
+	/// view registers are saved, spilled and reloaded in
+	slot_view:     RegisterClass,
+}
+
+/// what allocation takes from the target: the registers of both files and
+/// the alignment of frames
+pub(super) struct Abi {
+	gpr:         File,
+	fpr:         File,
+	stack_align: usize,
+}
+
+impl Abi {
+	/// the temporaries of the calling convention are caller saved, except
+	/// for the spill scratch registers. the frame pointer is saved by the
+	/// frame itself. only the low 64 bits of `v8`-`v15` are callee saved,
+	/// which is all a scalar needs
+	pub(super) fn of(target: &dyn target::Target,) -> Self {
+		let cc = target.calling_convention();
+		let frame_pointer = target.registers().frame_pointer;
+		let indices = |names: &[String], gpr: bool| -> Vec<u8,> {
+			names
+				.iter()
+				.filter(|n| **n != frame_pointer,)
+				.filter_map(|n| n.parse::<Register>().ok(),)
+				.filter(|r| r.is_gpr() == gpr,)
+				.filter_map(|r| r.index(),)
+				.collect()
+		};
+		let file = |gpr: bool, spill_scratch: [u8; 3], slot_view| File {
+			caller_saved: indices(&cc.temporaries, gpr,)
+				.into_iter()
+				.filter(|r| !spill_scratch.contains(r,),)
+				.collect(),
+			callee_saved: indices(&cc.callee_saved, gpr,),
+			spill_scratch,
+			slot_view,
+		};
+		Self {
+			gpr:         file(true, [15, 16, 17,], RegisterClass::Gpr64,),
+			fpr:         file(
+				false,
+				[29, 30, 31,],
+				RegisterClass::Fp { bytes: 8, },
+			),
+			stack_align: target.data_layout().stack_align as usize,
+		}
+	}
+
+	fn file(&self, class: RegisterClass,) -> &File {
+		if class.is_gpr() { &self.gpr } else { &self.fpr }
+	}
+}
+
+/// the largest frame a pre-indexed `stp` can allocate. larger frames are
+/// allocated by a separate `sub sp, sp, #size`
+const MAX_FRAME: usize = 504;
+
+/// `class` picks the view of the register, such as `x` or `w`. all views
+/// are the same register
+#[derive(Debug, Clone, Copy,)]
+pub(super) struct VReg {
+	pub index: u32,
+	pub class: RegisterClass,
+}
+
+impl VReg {
+	pub fn is_64(&self,) -> bool {
+		self.class == RegisterClass::Gpr64
+	}
+}
+
+impl PartialEq for VReg {
+	fn eq(&self, other: &Self,) -> bool {
+		self.index == other.index
+	}
+}
+
+impl Eq for VReg {}
+
+impl Hash for VReg {
+	fn hash<H: Hasher,>(&self, state: &mut H,) {
+		self.index.hash(state,);
+	}
+}
+
+impl PartialOrd for VReg {
+	fn partial_cmp(&self, other: &Self,) -> Option<Ordering,> {
+		Some(self.cmp(other,),)
+	}
+}
+
+impl Ord for VReg {
+	fn cmp(&self, other: &Self,) -> Ordering {
+		self.index.cmp(&other.index,)
+	}
+}
+
+impl Display for VReg {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		write!(f, "%v{}", self.index)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash,)]
+pub(super) enum Reg {
+	Virt(VReg,),
+	/// fixed by the calling convention
+	Phys(Register,),
+}
+
+impl From<Register,> for Reg {
+	fn from(value: Register,) -> Self {
+		Self::Phys(value,)
+	}
+}
+
+impl From<VReg,> for Reg {
+	fn from(value: VReg,) -> Self {
+		Self::Virt(value,)
+	}
+}
+
+impl GeneralRegister for Reg {
+	fn class(&self,) -> RegisterClass {
+		match self {
+			Self::Virt(v,) => v.class,
+			Self::Phys(r,) => r.class(),
+		}
+	}
+
+	fn is_sp(&self,) -> bool {
+		matches!(self, Self::Phys(r) if r.is_sp())
+	}
+
+	fn is_zero(&self,) -> bool {
+		matches!(self, Self::Phys(r) if r.is_zero())
+	}
+}
+
+impl Display for Reg {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		match self {
+			Self::Virt(v,) => write!(f, "{v}"),
+			Self::Phys(r,) => write!(f, "{r}"),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+enum Location {
+	Reg(u8,),
+	Slot(usize,),
+}
+
+#[derive(Debug,)]
+struct Interval {
+	vreg:         VReg,
+	start:        usize,
+	end:          usize,
+	crosses_call: bool,
+}
+
+fn virt(regs: Vec<Reg,>,) -> impl Iterator<Item = VReg,> {
+	regs.into_iter().filter_map(|r| match r {
+		Reg::Virt(v,) => Some(v,),
+		Reg::Phys(_,) => None,
+	},)
+}
+
+fn phys(index: u8, class: RegisterClass,) -> Register {
+	Register::new(class, index,).expect("allocatable register",)
+}
+
+/// `[x29, #offset]`, frame slots are 8 bytes
+fn frame_slot(offset: i64,) -> Result<Address, LlccError,> {
+	Address::offset(Register::X29, offset, Access::Single(8,),)
+}
+
+/// `op` is `add` or `sub` of `bytes` to `sp`, in two steps if `bytes` does
+/// not fit in 12 bits
+fn adjust_sp(op: Opcode, bytes: usize,) -> LlccB<Vec<Instruction<'static,>,>,> {
+	let mut out = vec![];
+	for part in [bytes & !0xfff, bytes & 0xfff,] {
+		if part == 0 {
+			continue;
+		}
+		out.push(Arith {
+			op,
+			target: Register::Sp,
+			lhs: Register::Sp,
+			rhs: RegisterOrImmediate::Immediate(Immediate::shifted(
+				part as i64,
+				&ARITH_SHIFTS,
+			)?,),
+		},);
+	}
+	X(out,)
+}
+
+/// offset of IR stack slot `slot` from `x29`. every slot takes 8 bytes,
+/// which fits all types
+pub(super) fn local_offset(slot: usize,) -> i64 {
+	16 + 8 * slot as i64
+}
+
+/// uses of instruction `i` are at `2 * i`, its definitions at `2 * i + 1`.
+/// a register read for the last time may thus be written by the same
+/// instruction
+fn intervals(insts: &[Instruction<'_, Reg,>],) -> Vec<Interval,> {
+	let mut starts = vec![0];
+	for (i, inst,) in
+		insts.iter().enumerate().take(insts.len().saturating_sub(1,),)
+	{
+		if matches!(
+			inst,
+			B(_,)
+				| BCond { .. }
+				| CompareBranch { .. }
+				| TestBranch { .. }
+				| Br(_,) | Ret
+		) || matches!(insts[i + 1], Label(_,))
+		{
+			starts.push(i + 1,);
+		}
+	}
+	let ranges: Vec<(usize, usize,),> = starts
+		.iter()
+		.enumerate()
+		.map(|(k, s,)| {
+			(*s, starts.get(k + 1,).copied().unwrap_or(insts.len(),),)
+		},)
+		.collect();
+	let block_of: HashMap<Target<'_,>, usize,> = ranges
+		.iter()
+		.enumerate()
+		.filter_map(|(k, (s, _,),)| match insts[*s] {
+			Label(t,) => Some((t, k,),),
+			_ => None,
+		},)
+		.collect();
+	let succs: Vec<Vec<usize,>,> = ranges
+		.iter()
+		.enumerate()
+		.map(|(k, (_, e,),)| {
+			let fallthrough = (k + 1 < ranges.len()).then_some(k + 1,);
+			match insts[e - 1] {
+				B(t,) => block_of.get(&t,).copied().into_iter().collect(),
+				BCond { target, .. }
+				| CompareBranch { target, .. }
+				| TestBranch { target, .. } => block_of
+					.get(&target,)
+					.copied()
+					.into_iter()
+					.chain(fallthrough,)
+					.collect(),
+				Br(_,) | Ret => vec![],
+				_ => fallthrough.into_iter().collect(),
+			}
+		},)
+		.collect();
+
+	// registers read before being written and registers written per block
+	let (mut gen_, mut kill,) = (vec![], vec![],);
+	for (s, e,) in ranges.iter() {
+		let (mut g, mut k,) = (HashSet::new(), HashSet::new(),);
+		for inst in insts[*s..*e].iter() {
+			g.extend(virt(inst.uses(),).filter(|v| !k.contains(v,),),);
+			k.extend(virt(inst.defs(),),);
+		}
+		gen_.push(g,);
+		kill.push(k,);
+	}
+	let mut live_in: Vec<HashSet<VReg,>,> = vec![HashSet::new(); ranges.len()];
+	let mut live_out = live_in.clone();
+	let mut changed = true;
+	while changed {
+		changed = false;
+		for k in (0..ranges.len()).rev() {
+			let out: HashSet<VReg,> = succs[k]
+				.iter()
+				.flat_map(|s| live_in[*s].iter().copied(),)
+				.collect();
+			let mut inn = gen_[k].clone();
+			inn.extend(out.difference(&kill[k],),);
+			if inn != live_in[k] || out != live_out[k] {
+				changed = true;
+				live_in[k] = inn;
+				live_out[k] = out;
+			}
+		}
+	}
+
+	let mut bounds: HashMap<VReg, (usize, usize,),> = HashMap::new();
+	let mut extend = |v: VReg, at: usize| {
+		let b = bounds.entry(v,).or_insert((at, at,),);
+		b.0 = b.0.min(at,);
+		b.1 = b.1.max(at,);
+	};
+	for (k, (s, e,),) in ranges.iter().enumerate() {
+		live_in[k].iter().for_each(|v| extend(*v, 2 * s,),);
+		live_out[k].iter().for_each(|v| extend(*v, 2 * e - 1,),);
+		for (i, inst,) in insts.iter().enumerate().take(*e,).skip(*s,) {
+			virt(inst.uses(),).for_each(|v| extend(v, 2 * i,),);
+			virt(inst.defs(),).for_each(|v| extend(v, 2 * i + 1,),);
+		}
+	}
+
+	let calls: Vec<usize,> = insts
+		.iter()
+		.enumerate()
+		.filter_map(|(i, inst,)| inst.is_call().then_some(i,),)
+		.collect();
+	let mut intervals: Vec<Interval,> = bounds
+		.into_iter()
+		.map(|(vreg, (start, end,),)| Interval {
+			vreg,
+			start,
+			end,
+			crosses_call: calls
+				.iter()
+				.any(|c| start < 2 * c && end > 2 * c + 1,),
+		},)
+		.collect();
+	intervals.sort_by_key(|iv| (iv.start, iv.vreg,),);
+	intervals
+}
+
+/// assigns the registers of `file` to `intervals`, adding spill slots
+/// after the first `slots`
+///
+/// # Return
+///
+/// returns the location of every virtual register and the number of spill
+/// slots
+fn scan<'i,>(
+	intervals: impl Iterator<Item = &'i Interval,>,
+	file: &File,
+	mut slots: usize,
+) -> (HashMap<VReg, Location,>, usize,) {
+	let mut free_caller: BTreeSet<u8,> =
+		file.caller_saved.iter().copied().collect();
+	let mut free_callee: BTreeSet<u8,> =
+		file.callee_saved.iter().copied().collect();
+	let mut active: Vec<(usize, VReg, u8,),> = vec![];
+	let mut locations = HashMap::new();
+	for iv in intervals {
+		for (_, _, r,) in active.extract_if(.., |(end, ..,)| *end < iv.start,) {
+			if file.callee_saved.contains(&r,) {
+				free_callee.insert(r,);
+			} else {
+				free_caller.insert(r,);
+			}
+		}
+
+		let reg = if iv.crosses_call {
+			free_callee.pop_first()
+		} else {
+			free_caller.pop_first().or_else(|| free_callee.pop_first(),)
+		};
+		if let Some(r,) = reg {
+			locations.insert(iv.vreg, Location::Reg(r,),);
+			active.push((iv.end, iv.vreg, r,),);
+			continue;
+		}
+
+		let victim = active
+			.iter()
+			.enumerate()
+			.filter(|(_, (_, _, r,),)| {
+				!iv.crosses_call || file.callee_saved.contains(r,)
+			},)
+			.max_by_key(|(_, (end, ..,),)| *end,)
+			.map(|(k, _,)| k,);
+		match victim {
+			Some(k,) if active[k].0 > iv.end => {
+				let (_, spilled, r,) = active[k];
+				locations.insert(spilled, Location::Slot(slots,),);
+				locations.insert(iv.vreg, Location::Reg(r,),);
+				active[k] = (iv.end, iv.vreg, r,);
+			},
+			_ => {
+				locations.insert(iv.vreg, Location::Slot(slots,),);
+			},
+		}
+		slots += 1;
+	}
+	(locations, slots,)
+}
+
+/// replaces virtual registers of a single function by physical ones and
+/// expands `Prologue` and `Epilogue`. the frame holds `locals` IR stack
+/// slots
+pub(super) fn allocate<'a,>(
+	insts: Vec<Instruction<'a, Reg,>,>,
+	locals: usize,
+	abi: &Abi,
+) -> LlccB<Vec<Instruction<'a,>,>,> {
+	let intervals = intervals(&insts,);
+	let (mut locations, slots,) = scan(
+		intervals.iter().filter(|iv| iv.vreg.class.is_gpr(),),
+		&abi.gpr,
+		0,
+	);
+	let (fp_locations, slots,) = scan(
+		intervals.iter().filter(|iv| !iv.vreg.class.is_gpr(),),
+		&abi.fpr,
+		slots,
+	);
+	locations.extend(fp_locations,);
+	// general purpose registers first
+	let callee_saved: Vec<Register,> = locations
+		.iter()
+		.filter_map(|(v, l,)| match l {
+			Location::Reg(r,)
+				if abi.file(v.class,).callee_saved.contains(r,) =>
+			{
+				Some((!v.class.is_gpr(), *r,),)
+			},
+			_ => None,
+		},)
+		.collect::<BTreeSet<_,>>()
+		.into_iter()
+		.map(|(is_fp, r,)| {
+			phys(r, if is_fp { abi.fpr.slot_view } else { abi.gpr.slot_view },)
+		},)
+		.collect();
+	let has_call = insts.iter().any(Instruction::is_call,);
+	// registers ever viewed as `x`, whose upper half matters
+	let wide: HashSet<VReg,> = insts
+		.iter()
+		.flat_map(|i| virt(i.uses(),).chain(virt(i.defs(),),),)
+		.filter(|v| v.is_64(),)
+		.collect();
+	let needs_frame =
+		has_call || locals > 0 || slots > 0 || !callee_saved.is_empty();
+	let frame_size = (16 + 8 * (locals + callee_saved.len() + slots))
+		.next_multiple_of(abi.stack_align,);
+	let saved_at = |k: usize| local_offset(locals + k,);
+	let slot_at = |slot: usize| saved_at(callee_saved.len() + slot,);
+
+	let mut out = vec![];
+	for inst in insts {
+		match inst {
+			Prologue => {
+				if frame_size > MAX_FRAME {
+					out.extend(adjust_sp(Opcode::Sub, frame_size,)?,);
+					out.push(Pair {
+						op:     Opcode::Stp,
+						first:  Register::X29,
+						second: Register::X30,
+						addr:   Address::offset(
+							Register::Sp,
+							0,
+							Access::Pair(8,),
+						)?,
+					},);
+				} else if needs_frame {
+					out.push(Pair {
+						op:     Opcode::Stp,
+						first:  Register::X29,
+						second: Register::X30,
+						addr:   Address::pre_index(
+							Register::Sp,
+							-(frame_size as i64),
+							Access::Pair(8,),
+						)?,
+					},);
+				}
+				if needs_frame {
+					out.push(Mov {
+						target: Register::X29,
+						value:  RegisterOrImmediate::Register(Register::Sp,),
+					},);
+				}
+				for (k, r,) in callee_saved.iter().enumerate() {
+					out.push(Store {
+						op:     Opcode::Str,
+						source: *r,
+						addr:   frame_slot(saved_at(k,),)?,
+					},);
+				}
+				continue;
+			},
+			Epilogue => {
+				for (k, r,) in callee_saved.iter().enumerate() {
+					out.push(Load {
+						op:     Opcode::Ldr,
+						target: *r,
+						addr:   frame_slot(saved_at(k,),)?,
+					},);
+				}
+				if frame_size > MAX_FRAME {
+					out.push(Pair {
+						op:     Opcode::Ldp,
+						first:  Register::X29,
+						second: Register::X30,
+						addr:   Address::offset(
+							Register::Sp,
+							0,
+							Access::Pair(8,),
+						)?,
+					},);
+					out.extend(adjust_sp(Opcode::Add, frame_size,)?,);
+				} else if needs_frame {
+					out.push(Pair {
+						op:     Opcode::Ldp,
+						first:  Register::X29,
+						second: Register::X30,
+						addr:   Address::post_index(
+							Register::Sp,
+							frame_size as i64,
+							Access::Pair(8,),
+						)?,
+					},);
+				}
+				continue;
+			},
+			_ => (),
+		}
+
+		// spilled registers live in scratch registers around `inst`
+		let mut scratch: HashMap<VReg, u8,> = HashMap::new();
+		for v in virt(inst.uses(),) {
+			if let Location::Slot(slot,) = locations[&v]
+				&& !scratch.contains_key(&v,)
+			{
+				let file = abi.file(v.class,);
+				let r = file.spill_scratch[scratch
+					.keys()
+					.filter(|s| s.class.is_gpr() == v.class.is_gpr(),)
+					.count()];
+				scratch.insert(v, r,);
+				out.push(Load {
+					op:     Opcode::Ldr,
+					target: phys(r, file.slot_view,),
+					addr:   frame_slot(slot_at(slot,),)?,
+				},);
+			}
+		}
+		let mut stores = vec![];
+		// the two definitions of `ldp` need two scratch registers
+		let mut spilled_defs: Vec<VReg,> = vec![];
+		for v in virt(inst.defs(),) {
+			if let Location::Slot(slot,) = locations[&v] {
+				let file = abi.file(v.class,);
+				let n = spilled_defs
+					.iter()
+					.filter(|d| d.class.is_gpr() == v.class.is_gpr(),)
+					.count();
+				spilled_defs.push(v,);
+				let r = *scratch.entry(v,).or_insert(file.spill_scratch[n],);
+				stores.push(Store {
+					op:     Opcode::Str,
+					source: phys(r, file.slot_view,),
+					addr:   frame_slot(slot_at(slot,),)?,
+				},);
+			}
+		}
+		// a copy into the same register only matters when it clears the
+		// upper half of a register viewed as `x` elsewhere
+		let redundant = match inst {
+			Mov {
+				target: Reg::Virt(t,),
+				value: RegisterOrImmediate::Register(Reg::Virt(_,),),
+			} => t.is_64() || !wide.contains(&t,),
+			Unary { op: Opcode::Fmov, target, source, } => {
+				target.class() == source.class()
+			},
+			_ => false,
+		};
+		let inst = inst.map_registers(|r| match r {
+			Reg::Phys(p,) => p,
+			Reg::Virt(v,) => match locations[&v] {
+				Location::Reg(n,) => phys(n, v.class,),
+				Location::Slot(_,) => phys(scratch[&v], v.class,),
+			},
+		},);
+		if let Mov { target, value: RegisterOrImmediate::Register(source,), }
+		| Unary { target, source, .. } = inst
+			&& redundant
+			&& target == source
+		{
+			continue;
+		}
+		out.push(inst,);
+		out.extend(stores,);
+	}
+	X(out,)
+}
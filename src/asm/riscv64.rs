@@ -0,0 +1,1105 @@
+//! instruction selection from `ir` for riscv64 Linux (RV64GC, LP64D)
+//!
+//! like the x86_64 backend, every ir value and every stack slot owns an 8
+//! byte home below the frame pointer `s0`. operands are loaded into `t0` and
+//! `t1`, or `ft0` and `ft1` for floats, and the result is stored back.
+//! integers are kept sign extended to 64 bits, which is also how LP64 passes
+//! them, floats keep their bit pattern in the low bytes
+
+use super::SectionKind;
+use super::Target;
+use crate::err::B::X;
+use crate::err::B::Y;
+use crate::err::LlccB;
+use crate::err::LlccError;
+use crate::ir::BinOp;
+use crate::ir::Block;
+use crate::ir::CastOp;
+use crate::ir::CmpOp;
+use crate::ir::Function;
+use crate::ir::Inst;
+use crate::ir::InstKind;
+use crate::ir::Linkage;
+use crate::ir::Module;
+use crate::ir::Slot;
+use crate::ir::Terminator;
+use crate::ir::Type;
+use crate::ir::UnOp;
+use crate::ir::Value;
+use crate::ir::cfg::Cfg;
+use crate::ir::lower;
+use crate::opt::fold::normalize;
+use crate::target;
+use crate::target::CallingConvention;
+use crate::target::RegisterFile;
+use crate::target::Syscall;
+use crate::target::Triple;
+use std::fmt::Display;
+
+/// LP64D passes the first eight integer arguments in `a0`-`a7` and the first
+/// eight float arguments in `fa0`-`fa7`
+const ARG_REGS: u8 = 8;
+
+/// `ra` and the caller's `s0` are saved right below the frame pointer
+const SAVED: i64 = 16;
+
+/// integer registers by their ABI names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display,)]
+#[strum(serialize_all = "lowercase")]
+pub enum Gpr {
+	Zero,
+	Ra,
+	Sp,
+	Gp,
+	Tp,
+	T0,
+	T1,
+	T2,
+	/// frame pointer
+	S0,
+	S1,
+	A0,
+	A1,
+	A2,
+	A3,
+	A4,
+	A5,
+	A6,
+	A7,
+	S2,
+	S3,
+	S4,
+	S5,
+	S6,
+	S7,
+	S8,
+	S9,
+	S10,
+	S11,
+	T3,
+	T4,
+	T5,
+	T6,
+}
+
+/// float registers by their ABI names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display,)]
+#[strum(serialize_all = "lowercase")]
+pub enum Fpr {
+	Ft0,
+	Ft1,
+	Ft2,
+	Ft3,
+	Ft4,
+	Ft5,
+	Ft6,
+	Ft7,
+	Fs0,
+	Fs1,
+	Fa0,
+	Fa1,
+	Fa2,
+	Fa3,
+	Fa4,
+	Fa5,
+	Fa6,
+	Fa7,
+	Fs2,
+	Fs3,
+	Fs4,
+	Fs5,
+	Fs6,
+	Fs7,
+	Fs8,
+	Fs9,
+	Fs10,
+	Fs11,
+	Ft8,
+	Ft9,
+	Ft10,
+	Ft11,
+}
+
+impl TryFrom<u8,> for Gpr {
+	type Error = LlccError;
+
+	/// register `x{n}`
+	fn try_from(n: u8,) -> Result<Self, Self::Error,> {
+		use Gpr::*;
+		const ALL: [Gpr; 32] = [
+			Zero, Ra, Sp, Gp, Tp, T0, T1, T2, S0, S1, A0, A1, A2, A3, A4, A5,
+			A6, A7, S2, S3, S4, S5, S6, S7, S8, S9, S10, S11, T3, T4, T5, T6,
+		];
+		ALL.get(n as usize,)
+			.copied()
+			.ok_or(LlccError::mismatch_imm(n as i64, 5, false,),)
+	}
+}
+
+impl TryFrom<u8,> for Fpr {
+	type Error = LlccError;
+
+	/// register `f{n}`
+	fn try_from(n: u8,) -> Result<Self, Self::Error,> {
+		use Fpr::*;
+		const ALL: [Fpr; 32] = [
+			Ft0, Ft1, Ft2, Ft3, Ft4, Ft5, Ft6, Ft7, Fs0, Fs1, Fa0, Fa1, Fa2,
+			Fa3, Fa4, Fa5, Fa6, Fa7, Fs2, Fs3, Fs4, Fs5, Fs6, Fs7, Fs8, Fs9,
+			Fs10, Fs11, Ft8, Ft9, Ft10, Ft11,
+		];
+		ALL.get(n as usize,)
+			.copied()
+			.ok_or(LlccError::mismatch_imm(n as i64, 5, false,),)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub enum Reg {
+	X(Gpr,),
+	F(Fpr,),
+}
+
+impl Display for Reg {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		match self {
+			Self::X(r,) => write!(f, "{r}"),
+			Self::F(r,) => write!(f, "{r}"),
+		}
+	}
+}
+
+impl From<Gpr,> for Reg {
+	fn from(r: Gpr,) -> Self {
+		Self::X(r,)
+	}
+}
+
+impl From<Fpr,> for Reg {
+	fn from(r: Fpr,) -> Self {
+		Self::F(r,)
+	}
+}
+
+/// `offset(base)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub struct Address {
+	offset: i16,
+	base:   Gpr,
+}
+
+impl Display for Address {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		write!(f, "{}({})", self.offset, self.base)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display,)]
+#[strum(serialize_all = "lowercase")]
+pub enum Opcode {
+	Add,
+	Sub,
+	Mul,
+	Div,
+	Divu,
+	Rem,
+	Remu,
+	And,
+	Or,
+	Xor,
+	Sll,
+	Srl,
+	Sra,
+	Slt,
+	Sltu,
+	Addi,
+	Andi,
+	Xori,
+	Slli,
+	Srli,
+	Srai,
+	Mv,
+	Neg,
+	Not,
+	Seqz,
+	Snez,
+	#[strum(serialize = "sext.w")]
+	SextW,
+	Lb,
+	Lh,
+	Lw,
+	Ld,
+	Sb,
+	Sh,
+	Sw,
+	Sd,
+	Flw,
+	Fld,
+	Fsw,
+	Fsd,
+	#[strum(serialize = "fadd.s")]
+	FaddS,
+	#[strum(serialize = "fadd.d")]
+	FaddD,
+	#[strum(serialize = "fsub.s")]
+	FsubS,
+	#[strum(serialize = "fsub.d")]
+	FsubD,
+	#[strum(serialize = "fmul.s")]
+	FmulS,
+	#[strum(serialize = "fmul.d")]
+	FmulD,
+	#[strum(serialize = "fdiv.s")]
+	FdivS,
+	#[strum(serialize = "fdiv.d")]
+	FdivD,
+	#[strum(serialize = "fneg.s")]
+	FnegS,
+	#[strum(serialize = "fneg.d")]
+	FnegD,
+	#[strum(serialize = "feq.s")]
+	FeqS,
+	#[strum(serialize = "feq.d")]
+	FeqD,
+	#[strum(serialize = "flt.s")]
+	FltS,
+	#[strum(serialize = "flt.d")]
+	FltD,
+	#[strum(serialize = "fle.s")]
+	FleS,
+	#[strum(serialize = "fle.d")]
+	FleD,
+	#[strum(serialize = "fcvt.s.l")]
+	FcvtSL,
+	#[strum(serialize = "fcvt.d.l")]
+	FcvtDL,
+	#[strum(serialize = "fcvt.s.lu")]
+	FcvtSLu,
+	#[strum(serialize = "fcvt.d.lu")]
+	FcvtDLu,
+	#[strum(serialize = "fcvt.l.s")]
+	FcvtLS,
+	#[strum(serialize = "fcvt.l.d")]
+	FcvtLD,
+	#[strum(serialize = "fcvt.lu.s")]
+	FcvtLuS,
+	#[strum(serialize = "fcvt.lu.d")]
+	FcvtLuD,
+	#[strum(serialize = "fcvt.d.s")]
+	FcvtDS,
+	#[strum(serialize = "fcvt.s.d")]
+	FcvtSD,
+	Beqz,
+	Bnez,
+}
+
+pub enum Instruction<'a,> {
+	Section(SectionKind,),
+	Global(&'a str,),
+	Symbol(&'a str,),
+	Label(Target<'a,>,),
+	/// loads any 64 bit constant, expanded by the assembler
+	Li {
+		target: Gpr,
+		imm:    i64,
+	},
+	/// `op target, lhs, rhs` on registers of one file
+	Binary {
+		op:     Opcode,
+		target: Reg,
+		lhs:    Reg,
+		rhs:    Reg,
+	},
+	/// `op target, source, imm` with a 12 bit `imm`
+	Immediate {
+		op:     Opcode,
+		target: Gpr,
+		source: Gpr,
+		imm:    i16,
+	},
+	/// `op target, source`, also between register files
+	Unary {
+		op:     Opcode,
+		target: Reg,
+		source: Reg,
+	},
+	/// `op target, source, rtz`, a float to integer conversion rounding
+	/// toward zero as C does
+	Truncate {
+		op:     Opcode,
+		target: Gpr,
+		source: Fpr,
+	},
+	Load {
+		op:     Opcode,
+		target: Reg,
+		addr:   Address,
+	},
+	Store {
+		op:     Opcode,
+		source: Reg,
+		addr:   Address,
+	},
+	Branch {
+		op:     Opcode,
+		reg:    Gpr,
+		target: Target<'a,>,
+	},
+	J(Target<'a,>,),
+	Call(&'a str,),
+	Ret,
+	Ecall,
+}
+
+impl Display for Instruction<'_,> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		use Instruction::*;
+		match self {
+			Section(section_kind,) => {
+				let kind: String = (*section_kind).into();
+				write!(f, ".{kind}")
+			},
+			Global(s,) => write!(f, ".global {s}"),
+			Symbol(s,) => write!(f, "{s}:"),
+			Label(t,) => write!(f, "{t}:"),
+			Li { target, imm, } => write!(f, "li {target}, {imm}"),
+			Binary { op, target, lhs, rhs, } => {
+				write!(f, "{op} {target}, {lhs}, {rhs}")
+			},
+			Immediate { op, target, source, imm, } => {
+				write!(f, "{op} {target}, {source}, {imm}")
+			},
+			Unary { op, target, source, } => {
+				write!(f, "{op} {target}, {source}")
+			},
+			Truncate { op, target, source, } => {
+				write!(f, "{op} {target}, {source}, rtz")
+			},
+			Load { op, target, addr, } => write!(f, "{op} {target}, {addr}"),
+			Store { op, source, addr, } => write!(f, "{op} {source}, {addr}"),
+			Branch { op, reg, target, } => write!(f, "{op} {reg}, {target}"),
+			J(target,) => write!(f, "j {target}"),
+			Call(s,) => write!(f, "call {s}"),
+			Ret => write!(f, "ret"),
+			Ecall => write!(f, "ecall"),
+		}
+	}
+}
+
+/// riscv64 Linux with the LP64D ABI
+pub struct Riscv64 {
+	pub triple: Triple,
+}
+
+impl target::Target for Riscv64 {
+	fn triple(&self,) -> &Triple {
+		&self.triple
+	}
+
+	fn registers(&self,) -> RegisterFile {
+		RegisterFile {
+			general:       (0..32)
+				.filter_map(|n| Gpr::try_from(n,).ok(),)
+				.map(|r| r.to_string(),)
+				.collect(),
+			float:         (0..32)
+				.filter_map(|n| Fpr::try_from(n,).ok(),)
+				.map(|r| r.to_string(),)
+				.collect(),
+			stack_pointer: Gpr::Sp.to_string(),
+			frame_pointer: Gpr::S0.to_string(),
+		}
+	}
+
+	fn calling_convention(&self,) -> CallingConvention {
+		let registers = self.registers();
+		let named = |names: &[String], prefix: &str| {
+			names
+				.iter()
+				.filter(|n| {
+					n.strip_prefix(prefix,)
+						.is_some_and(|i| i.parse::<u8>().is_ok(),)
+				},)
+				.cloned()
+				.collect::<Vec<_,>>()
+		};
+		let mut callee_saved = named(&registers.general, "s",);
+		callee_saved.extend(named(&registers.float, "fs",),);
+		let mut temporaries = named(&registers.general, "t",);
+		temporaries.extend(named(&registers.float, "ft",),);
+		CallingConvention {
+			int_args: named(&registers.general, "a",),
+			float_args: named(&registers.float, "fa",),
+			int_ret: Gpr::A0.to_string(),
+			float_ret: Fpr::Fa0.to_string(),
+			callee_saved,
+			temporaries,
+		}
+	}
+
+	fn syscall(&self, call: Syscall,) -> u16 {
+		call.generic()
+	}
+
+	fn print(&self, module: &Module,) -> LlccB<String,> {
+		let inst_list = select_module(module, self,)?;
+		X(inst_list.into_iter().map(|inst| format!("{inst}\n"),).collect(),)
+	}
+}
+
+fn select_module<'m,>(
+	module: &'m Module,
+	target: &dyn target::Target,
+) -> LlccB<Vec<Instruction<'m,>,>,> {
+	let mut inst_list = vec![Instruction::Section(SectionKind::Text,)];
+	for func in module.functions.iter() {
+		inst_list.extend(select_function(func, target,)?,);
+	}
+	X(inst_list,)
+}
+
+/// picks the single or the double precision form of a float operation
+fn float_op(ty: Type, single: Opcode, double: Opcode,) -> Opcode {
+	if ty == Type::F32 { single } else { double }
+}
+
+struct Selector<'f,> {
+	func:        &'f Function,
+	/// number of the exit system call, which ends `_start`
+	exit:        i64,
+	stack_align: i64,
+	insts:       Vec<Instruction<'f,>,>,
+}
+
+impl<'f,> Selector<'f,> {
+	fn ty(&self, v: Value,) -> Type {
+		self.func.ty(v,)
+	}
+
+	/// offset of the home of the `n`th value or slot from `s0`
+	fn offset(&self, n: usize,) -> i64 {
+		-SAVED - 8 * (n as i64 + 1)
+	}
+
+	/// bytes below `s0`, keeping `sp` aligned
+	fn frame_size(&self,) -> i64 {
+		let homes = self.func.value_types.len() + self.func.slots.len();
+		(SAVED + homes as i64 * 8 + self.stack_align - 1) & -self.stack_align
+	}
+
+	/// `s0 + offset`, through `t2` if the offset exceeds 12 bits
+	fn addr(&mut self, offset: i64,) -> Address {
+		if let Ok(offset,) = i16::try_from(offset,)
+			&& (-2048..2048).contains(&offset,)
+		{
+			return Address { offset, base: Gpr::S0, };
+		}
+		self.insts.push(Instruction::Li { target: Gpr::T2, imm: offset, },);
+		self.binary(Opcode::Add, Gpr::T2, Gpr::S0, Gpr::T2,);
+		Address { offset: 0, base: Gpr::T2, }
+	}
+
+	fn home(&mut self, v: Value,) -> Address {
+		self.addr(self.offset(v.index(),),)
+	}
+
+	/// slots live below the homes of the values
+	fn slot(&mut self, s: Slot,) -> Address {
+		self.addr(self.offset(self.func.value_types.len() + s.index(),),)
+	}
+
+	fn label(&self, b: Block,) -> Target<'f,> {
+		Target::Block { func: &self.func.name, block: b.0, }
+	}
+
+	fn binary(
+		&mut self,
+		op: Opcode,
+		target: impl Into<Reg,>,
+		lhs: impl Into<Reg,>,
+		rhs: impl Into<Reg,>,
+	) {
+		self.insts.push(Instruction::Binary {
+			op,
+			target: target.into(),
+			lhs: lhs.into(),
+			rhs: rhs.into(),
+		},);
+	}
+
+	fn immediate(&mut self, op: Opcode, target: Gpr, source: Gpr, imm: i16,) {
+		self.insts.push(Instruction::Immediate { op, target, source, imm, },);
+	}
+
+	fn unary(
+		&mut self,
+		op: Opcode,
+		target: impl Into<Reg,>,
+		source: impl Into<Reg,>,
+	) {
+		self.insts.push(Instruction::Unary {
+			op,
+			target: target.into(),
+			source: source.into(),
+		},);
+	}
+
+	/// `sp = source + imm` for any `imm`
+	fn adjust_sp(&mut self, source: Gpr, imm: i64,) {
+		match i16::try_from(imm,) {
+			Ok(imm,) if (-2048..2048).contains(&imm,) => {
+				self.immediate(Opcode::Addi, Gpr::Sp, source, imm,)
+			},
+			_ => {
+				self.insts.push(Instruction::Li { target: Gpr::T0, imm, },);
+				self.binary(Opcode::Add, Gpr::Sp, source, Gpr::T0,);
+			},
+		}
+	}
+
+	/// loads the home of `v` into an integer or a float register
+	fn load_home(&mut self, v: Value, target: impl Into<Reg,>,) {
+		let target = target.into();
+		let op = match (target, self.ty(v,),) {
+			(Reg::F(_,), Type::F32,) => Opcode::Flw,
+			(Reg::F(_,), _,) => Opcode::Fld,
+			(Reg::X(_,), _,) => Opcode::Ld,
+		};
+		let addr = self.home(v,);
+		self.insts.push(Instruction::Load { op, target, addr, },);
+	}
+
+	fn store_home(&mut self, source: impl Into<Reg,>, v: Value,) {
+		let source = source.into();
+		let op = match (source, self.ty(v,),) {
+			(Reg::F(_,), Type::F32,) => Opcode::Fsw,
+			(Reg::F(_,), _,) => Opcode::Fsd,
+			(Reg::X(_,), _,) => Opcode::Sd,
+		};
+		let addr = self.home(v,);
+		self.insts.push(Instruction::Store { op, source, addr, },);
+	}
+
+	/// sign extends `t0` from the width of `ty`
+	fn normalize(&mut self, ty: Type,) {
+		let shift = match ty {
+			Type::I32 => {
+				return self.unary(Opcode::SextW, Gpr::T0, Gpr::T0,);
+			},
+			Type::I8 | Type::I16 => 64 - ty.bits() as i16,
+			_ => return,
+		};
+		self.immediate(Opcode::Slli, Gpr::T0, Gpr::T0, shift,);
+		self.immediate(Opcode::Srai, Gpr::T0, Gpr::T0, shift,);
+	}
+
+	/// zero extends `reg` from the width of `ty`
+	fn zext(&mut self, reg: Gpr, ty: Type,) {
+		let shift = match ty {
+			Type::I8 => return self.immediate(Opcode::Andi, reg, reg, 0xff,),
+			Type::I16 | Type::I32 => 64 - ty.bits() as i16,
+			_ => return,
+		};
+		self.immediate(Opcode::Slli, reg, reg, shift,);
+		self.immediate(Opcode::Srli, reg, reg, shift,);
+	}
+
+	/// width of a load or store of a value of type `ty` between memory and
+	/// `t0`
+	fn access(&self, ty: Type,) -> (Opcode, Opcode,) {
+		match ty.bytes() {
+			1 => (Opcode::Lb, Opcode::Sb,),
+			2 => (Opcode::Lh, Opcode::Sh,),
+			4 => (Opcode::Lw, Opcode::Sw,),
+			_ => (Opcode::Ld, Opcode::Sd,),
+		}
+	}
+
+	fn int_binary(&mut self, op: BinOp, ty: Type,) {
+		let op = match op {
+			BinOp::Add => Opcode::Add,
+			BinOp::Sub => Opcode::Sub,
+			BinOp::Mul => Opcode::Mul,
+			BinOp::SDiv => Opcode::Div,
+			BinOp::SRem => Opcode::Rem,
+			BinOp::And => Opcode::And,
+			BinOp::Or => Opcode::Or,
+			BinOp::Xor => Opcode::Xor,
+			BinOp::Shl => Opcode::Sll,
+			BinOp::AShr => Opcode::Sra,
+			BinOp::UDiv | BinOp::URem => {
+				self.zext(Gpr::T0, ty,);
+				self.zext(Gpr::T1, ty,);
+				if op == BinOp::UDiv { Opcode::Divu } else { Opcode::Remu }
+			},
+			BinOp::LShr => {
+				self.zext(Gpr::T0, ty,);
+				Opcode::Srl
+			},
+			BinOp::FAdd | BinOp::FSub | BinOp::FMul | BinOp::FDiv => {
+				unreachable!("float operations are selected by `inst`")
+			},
+		};
+		self.binary(op, Gpr::T0, Gpr::T0, Gpr::T1,);
+		self.normalize(ty,);
+	}
+
+	/// compares `lhs` and `rhs` and leaves 0 or 1 in `t0`
+	fn cmp(&mut self, op: CmpOp, lhs: Value, rhs: Value,) {
+		let ty = self.ty(lhs,);
+		let (t0, t1,) = (Gpr::T0, Gpr::T1,);
+		if ty.is_float() {
+			let (ft0, ft1,) = (Fpr::Ft0, Fpr::Ft1,);
+			self.load_home(lhs, ft0,);
+			self.load_home(rhs, ft1,);
+			let eq = float_op(ty, Opcode::FeqS, Opcode::FeqD,);
+			let lt = float_op(ty, Opcode::FltS, Opcode::FltD,);
+			let le = float_op(ty, Opcode::FleS, Opcode::FleD,);
+			// every comparison is false for NaN operands, so `fne` is the
+			// negated `feq`
+			match op {
+				CmpOp::FEq | CmpOp::FNe => self.binary(eq, t0, ft0, ft1,),
+				CmpOp::FLt => self.binary(lt, t0, ft0, ft1,),
+				CmpOp::FLe => self.binary(le, t0, ft0, ft1,),
+				CmpOp::FGt => self.binary(lt, t0, ft1, ft0,),
+				_ => self.binary(le, t0, ft1, ft0,),
+			}
+			if op == CmpOp::FNe {
+				self.immediate(Opcode::Xori, t0, t0, 1,);
+			}
+			return;
+		}
+
+		self.load_home(lhs, t0,);
+		self.load_home(rhs, t1,);
+		match op {
+			CmpOp::Eq | CmpOp::Ne => {
+				self.binary(Opcode::Xor, t0, t0, t1,);
+				let op =
+					if op == CmpOp::Eq { Opcode::Seqz } else { Opcode::Snez };
+				self.unary(op, t0, t0,);
+			},
+			_ => {
+				let unsigned = matches!(
+					op,
+					CmpOp::ULt | CmpOp::ULe | CmpOp::UGt | CmpOp::UGe
+				);
+				let slt = if unsigned { Opcode::Sltu } else { Opcode::Slt };
+				// `a > b` is `b < a`, and `a <= b` is `!(b < a)`
+				let (swap, negate,) = match op {
+					CmpOp::SLt | CmpOp::ULt => (false, false,),
+					CmpOp::SGt | CmpOp::UGt => (true, false,),
+					CmpOp::SLe | CmpOp::ULe => (true, true,),
+					_ => (false, true,),
+				};
+				if swap {
+					self.binary(slt, t0, t1, t0,);
+				} else {
+					self.binary(slt, t0, t0, t1,);
+				}
+				if negate {
+					self.immediate(Opcode::Xori, t0, t0, 1,);
+				}
+			},
+		}
+	}
+
+	/// converts `source` and stores the result to the home of `dest`
+	fn cast(&mut self, op: CastOp, dest: Value, source: Value,) {
+		let (from, to,) = (self.ty(source,), self.ty(dest,),);
+		let (t0, ft0,) = (Gpr::T0, Fpr::Ft0,);
+		match op {
+			CastOp::SExt | CastOp::Trunc | CastOp::ZExt => {
+				self.load_home(source, t0,);
+				if op == CastOp::ZExt {
+					self.zext(t0, from,);
+				}
+				self.normalize(to,);
+				self.store_home(t0, dest,);
+			},
+			CastOp::SIToFP | CastOp::UIToFP => {
+				self.load_home(source, t0,);
+				let op = if op == CastOp::SIToFP {
+					float_op(to, Opcode::FcvtSL, Opcode::FcvtDL,)
+				} else {
+					self.zext(t0, from,);
+					float_op(to, Opcode::FcvtSLu, Opcode::FcvtDLu,)
+				};
+				self.unary(op, ft0, t0,);
+				self.store_home(ft0, dest,);
+			},
+			CastOp::FPToSI | CastOp::FPToUI => {
+				self.load_home(source, ft0,);
+				let op = if op == CastOp::FPToSI {
+					float_op(from, Opcode::FcvtLS, Opcode::FcvtLD,)
+				} else {
+					float_op(from, Opcode::FcvtLuS, Opcode::FcvtLuD,)
+				};
+				self.insts.push(Instruction::Truncate {
+					op,
+					target: t0,
+					source: ft0,
+				},);
+				self.normalize(to,);
+				self.store_home(t0, dest,);
+			},
+			CastOp::FPExt | CastOp::FPTrunc => {
+				self.load_home(source, ft0,);
+				let op = if op == CastOp::FPExt {
+					Opcode::FcvtDS
+				} else {
+					Opcode::FcvtSD
+				};
+				self.unary(op, ft0, ft0,);
+				self.store_home(ft0, dest,);
+			},
+		}
+	}
+
+	/// moves `args` into the argument registers
+	fn args(&mut self, args: &[Value],) -> LlccB<(),> {
+		let (mut ints, mut floats,) = (0, 0,);
+		for a in args {
+			let n =
+				if self.ty(*a,).is_float() { &mut floats } else { &mut ints };
+			if *n == ARG_REGS {
+				return Y(LlccError::unsupported(
+					"arguments passed on the stack",
+				),);
+			}
+			let target: Reg = if self.ty(*a,).is_float() {
+				Fpr::try_from(Fpr::Fa0 as u8 + *n,)?.into()
+			} else {
+				Gpr::try_from(Gpr::A0 as u8 + *n,)?.into()
+			};
+			*n += 1;
+			self.load_home(*a, target,);
+		}
+		X((),)
+	}
+
+	fn inst(&mut self, inst: &'f Inst,) -> LlccB<(),> {
+		let (t0, t1,) = (Gpr::T0, Gpr::T1,);
+		let Some(dest,) = inst.dest else {
+			match &inst.kind {
+				InstKind::Call { callee, args, .. } => {
+					self.args(args,)?;
+					self.insts.push(Instruction::Call(callee,),);
+				},
+				InstKind::Store { addr, value, } => {
+					self.load_home(*addr, t1,);
+					self.load_home(*value, t0,);
+					let (_, op,) = self.access(self.ty(*value,),);
+					let addr = Address { offset: 0, base: t1, };
+					self.insts.push(Instruction::Store {
+						op,
+						source: t0.into(),
+						addr,
+					},);
+				},
+				InstKind::StackStore(slot, value,) => {
+					self.load_home(*value, t0,);
+					let (_, op,) = self.access(self.ty(*value,),);
+					let addr = self.slot(*slot,);
+					self.insts.push(Instruction::Store {
+						op,
+						source: t0.into(),
+						addr,
+					},);
+				},
+				_ => (),
+			}
+			return X((),);
+		};
+
+		let ty = self.ty(dest,);
+		match &inst.kind {
+			InstKind::Const(c,) => {
+				self.insts.push(Instruction::Li {
+					target: t0,
+					imm:    normalize(ty, *c,),
+				},);
+			},
+			InstKind::Copy(v,) => self.load_home(*v, t0,),
+			InstKind::Binary(op, lhs, rhs,) if ty.is_float() => {
+				let (ft0, ft1,) = (Fpr::Ft0, Fpr::Ft1,);
+				self.load_home(*lhs, ft0,);
+				self.load_home(*rhs, ft1,);
+				let op = match op {
+					BinOp::FAdd => float_op(ty, Opcode::FaddS, Opcode::FaddD,),
+					BinOp::FSub => float_op(ty, Opcode::FsubS, Opcode::FsubD,),
+					BinOp::FMul => float_op(ty, Opcode::FmulS, Opcode::FmulD,),
+					_ => float_op(ty, Opcode::FdivS, Opcode::FdivD,),
+				};
+				self.binary(op, ft0, ft0, ft1,);
+				self.store_home(ft0, dest,);
+				return X((),);
+			},
+			InstKind::Binary(op, lhs, rhs,) => {
+				self.load_home(*lhs, t0,);
+				self.load_home(*rhs, t1,);
+				self.int_binary(*op, ty,);
+			},
+			InstKind::Unary(UnOp::FNeg, v,) => {
+				self.load_home(*v, Fpr::Ft0,);
+				let op = float_op(ty, Opcode::FnegS, Opcode::FnegD,);
+				self.unary(op, Fpr::Ft0, Fpr::Ft0,);
+				self.store_home(Fpr::Ft0, dest,);
+				return X((),);
+			},
+			InstKind::Unary(op, v,) => {
+				self.load_home(*v, t0,);
+				let op =
+					if *op == UnOp::Neg { Opcode::Neg } else { Opcode::Not };
+				self.unary(op, t0, t0,);
+				self.normalize(ty,);
+			},
+			InstKind::Cmp(op, lhs, rhs,) => self.cmp(*op, *lhs, *rhs,),
+			InstKind::Cast(op, v,) => {
+				self.cast(*op, dest, *v,);
+				return X((),);
+			},
+			InstKind::Call { callee, args, .. } => {
+				self.args(args,)?;
+				self.insts.push(Instruction::Call(callee,),);
+				if ty.is_float() {
+					self.store_home(Fpr::Fa0, dest,);
+					return X((),);
+				}
+				self.unary(Opcode::Mv, t0, Gpr::A0,);
+				self.normalize(ty,);
+			},
+			InstKind::StackLoad(slot,) => {
+				let (op, _,) = self.access(ty,);
+				let addr = self.slot(*slot,);
+				self.insts.push(Instruction::Load {
+					op,
+					target: t0.into(),
+					addr,
+				},);
+			},
+			InstKind::StackAddr(slot,) => {
+				let offset =
+					self.offset(self.func.value_types.len() + slot.index(),);
+				self.insts.push(Instruction::Li { target: t0, imm: offset, },);
+				self.binary(Opcode::Add, t0, Gpr::S0, t0,);
+			},
+			InstKind::Load(addr,) => {
+				self.load_home(*addr, t1,);
+				let (op, _,) = self.access(ty,);
+				let addr = Address { offset: 0, base: t1, };
+				self.insts.push(Instruction::Load {
+					op,
+					target: t0.into(),
+					addr,
+				},);
+			},
+			kind => {
+				return Y(LlccError::unsupported(format!(
+					"`{kind}` in riscv64 backend"
+				),),);
+			},
+		}
+		self.store_home(t0, dest,);
+		X((),)
+	}
+
+	fn term(&mut self, term: &Terminator, next: Option<Block,>,) -> LlccB<(),> {
+		match *term {
+			Terminator::Jump(to,) => {
+				if next != Some(to,) {
+					self.insts.push(Instruction::J(self.label(to,),),);
+				}
+			},
+			Terminator::Branch { cond, then, els, } => {
+				self.load_home(cond, Gpr::T0,);
+				let (op, taken, fallthrough,) = if next == Some(then,) {
+					(Opcode::Beqz, els, then,)
+				} else {
+					(Opcode::Bnez, then, els,)
+				};
+				self.insts.push(Instruction::Branch {
+					op,
+					reg: Gpr::T0,
+					target: self.label(taken,),
+				},);
+				if next != Some(fallthrough,) {
+					self.insts.push(Instruction::J(self.label(fallthrough,),),);
+				}
+			},
+			Terminator::Return(v,) => {
+				match v {
+					Some(v,) if self.ty(v,).is_float() => {
+						self.load_home(v, Fpr::Fa0,)
+					},
+					Some(v,) => self.load_home(v, Gpr::A0,),
+					None => self
+						.insts
+						.push(Instruction::Li { target: Gpr::A0, imm: 0, },),
+				}
+				if self.func.name == lower::ENTRY {
+					// `_start` has nowhere to return to
+					self.insts.push(Instruction::Li {
+						target: Gpr::A7,
+						imm:    self.exit,
+					},);
+					self.insts.push(Instruction::Ecall,);
+				} else {
+					self.immediate(
+						Opcode::Addi,
+						Gpr::Sp,
+						Gpr::S0,
+						-SAVED as i16,
+					);
+					for (reg, offset,) in [(Gpr::Ra, 8,), (Gpr::S0, 0,),] {
+						self.insts.push(Instruction::Load {
+							op:     Opcode::Ld,
+							target: reg.into(),
+							addr:   Address { offset, base: Gpr::Sp, },
+						},);
+					}
+					self.immediate(
+						Opcode::Addi,
+						Gpr::Sp,
+						Gpr::Sp,
+						SAVED as i16,
+					);
+					self.insts.push(Instruction::Ret,);
+				}
+			},
+			Terminator::Unreachable => (),
+		}
+		X((),)
+	}
+}
+
+fn select_function<'f,>(
+	func: &'f Function,
+	target: &dyn target::Target,
+) -> LlccB<Vec<Instruction<'f,>,>,> {
+	let cfg = Cfg::new(func,);
+	let layout = cfg.rpo();
+	let mut s = Selector {
+		func,
+		exit: target.syscall(Syscall::Exit,) as i64,
+		stack_align: target.data_layout().stack_align as i64,
+		insts: vec![],
+	};
+
+	if func.linkage == Linkage::External {
+		s.insts.push(Instruction::Global(&func.name,),);
+	}
+	s.insts.push(Instruction::Symbol(&func.name,),);
+	s.immediate(Opcode::Addi, Gpr::Sp, Gpr::Sp, -SAVED as i16,);
+	for (reg, offset,) in [(Gpr::Ra, 8,), (Gpr::S0, 0,),] {
+		s.insts.push(Instruction::Store {
+			op:     Opcode::Sd,
+			source: reg.into(),
+			addr:   Address { offset, base: Gpr::Sp, },
+		},);
+	}
+	s.immediate(Opcode::Addi, Gpr::S0, Gpr::Sp, SAVED as i16,);
+	s.adjust_sp(Gpr::Sp, SAVED - s.frame_size(),);
+
+	let (mut ints, mut floats,) = (0, 0,);
+	for p in func.params.iter() {
+		let ty = s.ty(*p,);
+		let n = if ty.is_float() { &mut floats } else { &mut ints };
+		if *n == ARG_REGS {
+			return Y(
+				LlccError::unsupported("parameters passed on the stack",),
+			);
+		}
+		if ty.is_float() {
+			let source = Fpr::try_from(Fpr::Fa0 as u8 + *n,)?;
+			*n += 1;
+			s.store_home(source, *p,);
+		} else {
+			let source = Gpr::try_from(Gpr::A0 as u8 + *n,)?;
+			*n += 1;
+			s.unary(Opcode::Mv, Gpr::T0, source,);
+			s.normalize(ty,);
+			s.store_home(Gpr::T0, *p,);
+		}
+	}
+
+	for (n, b,) in layout.iter().enumerate() {
+		let block = func.block(*b,);
+		if *b != Block::ENTRY {
+			s.insts.push(Instruction::Label(s.label(*b,),),);
+		}
+		for inst in block.insts.iter() {
+			s.inst(inst,)?;
+		}
+		s.term(&block.term, layout.get(n + 1,).copied(),)?;
+	}
+	X(s.insts,)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::err::Container;
+	use crate::ir::FunctionBuilder;
+
+	fn lines(func: &Function,) -> Vec<String,> {
+		select_function(
+			func,
+			&Riscv64 { triple: "riscv64-linux-gnu".parse().unwrap(), },
+		)
+		.unwrap()
+		.into_iter()
+		.map(|i| i.to_string(),)
+		.collect()
+	}
+
+	#[test]
+	fn registers_and_mnemonics() {
+		assert_eq!(Gpr::try_from(8,).unwrap(), Gpr::S0);
+		assert_eq!(Gpr::try_from(31,).unwrap().to_string(), "t6");
+		assert_eq!(Fpr::try_from(10,).unwrap().to_string(), "fa0");
+		assert!(Fpr::try_from(32,).is_err());
+		assert_eq!(Opcode::FcvtLuD.to_string(), "fcvt.lu.d");
+		assert_eq!(Opcode::SextW.to_string(), "sext.w");
+	}
+
+	#[test]
+	fn calls_follow_lp64d() {
+		// long f(int x, float y) { return g(y, x) <= 3; }
+		let mut fb = FunctionBuilder::new(
+			"f",
+			&[Type::I32, Type::F32,],
+			Some(Type::I64,),
+		);
+		let (x, y,) = (fb.param(0,), fb.param(1,),);
+		let r = fb.call("g", &[y, x,], Some(Type::I64,),);
+		let three = fb.iconst(Type::I64, 3,);
+		let c = fb.cmp(CmpOp::SLe, r, three,);
+		let c = fb.cast(CastOp::SExt, c, Type::I64,);
+		fb.ret(Some(c,),);
+		let asm = lines(&fb.finish(),);
+		let has = |line: &str| asm.contains(&line.to_string(),);
+		assert!(has("sd ra, 8(sp)",), "{asm:?}");
+		assert!(has("mv t0, a0",), "{asm:?}");
+		assert!(has("sext.w t0, t0",), "{asm:?}");
+		assert!(has("fsw fa0, -32(s0)",), "{asm:?}");
+		assert!(has("flw fa0, -32(s0)",), "{asm:?}");
+		assert!(has("ld a0, -24(s0)",), "{asm:?}");
+		assert!(has("call g",), "{asm:?}");
+		assert!(has("slt t0, t1, t0",), "{asm:?}");
+		assert!(has("xori t0, t0, 1",), "{asm:?}");
+		assert_eq!(asm.last().unwrap(), "ret");
+	}
+
+	#[test]
+	fn start_exits_through_ecall() {
+		let target = Riscv64 { triple: "riscv64-linux-gnu".parse().unwrap(), };
+		let text: String =
+			crate::asm::asm_str("2.5+1", crate::opt::OptLevel::O0, &target,)
+				.unwrap()
+				.into();
+		let asm: Vec<&str,> = text.lines().collect();
+		assert!(asm.contains(&"fcvt.d.l ft0, t0"), "{asm:?}");
+		assert!(asm.contains(&"fcvt.l.d t0, ft0, rtz"), "{asm:?}");
+		assert_eq!(asm[asm.len() - 2..], ["li a7, 93", "ecall"]);
+	}
+}
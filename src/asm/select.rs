@@ -0,0 +1,1096 @@
+//! instruction selection from `ir` for aarch64
+//!
+//! every ir value gets its own virtual register, which is mapped onto a
+//! machine register by `regalloc`. blocks are laid out in reverse
+//! postorder, so that a jump to the next block falls through
+//!
+//! `i8` and `i16` values live sign extended in `w` registers. results that
+//! may leave their range are extended again, and unsigned operations work
+//! on zero extended copies. `f32` and `f64` values live in `s` and `d`
+//! registers
+
+use super::Cond;
+use super::Instruction;
+use super::Instruction::*;
+use super::SectionKind;
+use super::Target;
+use super::regalloc;
+use super::regalloc::Reg;
+use super::regalloc::VReg;
+use super::table::Opcode;
+use crate::err::B::X;
+use crate::err::B::Y;
+use crate::err::LlccB;
+use crate::err::LlccError;
+use crate::ir::BinOp;
+use crate::ir::Block;
+use crate::ir::CastOp;
+use crate::ir::CmpOp;
+use crate::ir::Function;
+use crate::ir::Inst;
+use crate::ir::InstKind;
+use crate::ir::Linkage;
+use crate::ir::Module;
+use crate::ir::Slot;
+use crate::ir::Terminator;
+use crate::ir::Type;
+use crate::ir::UnOp;
+use crate::ir::Value;
+use crate::ir::cfg::Cfg;
+use crate::ir::lower;
+use crate::register::Access;
+use crate::register::Address;
+use crate::register::BitmaskImmediate;
+use crate::register::GeneralRegister;
+use crate::register::Immediate;
+use crate::register::Register;
+use crate::register::RegisterClass;
+use crate::register::RegisterOrImmediate;
+use crate::target;
+use crate::target::Syscall;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/// AAPCS64 passes the first eight integer arguments in `x0`-`x7` and the
+/// first eight float arguments in `v0`-`v7`
+const ARG_REGS: usize = 8;
+
+/// `add`, `sub` and `cmp` take 12 bit immediates, optionally `lsl #12`
+pub(super) const ARITH_SHIFTS: [u8; 2] = [0, 12,];
+
+pub(super) fn select_module<'m,>(
+	module: &'m Module,
+	target: &dyn target::Target,
+) -> LlccB<Vec<Instruction<'m,>,>,> {
+	let abi = regalloc::Abi::of(target,);
+	let mut inst_list = vec![Section(SectionKind::Text,)];
+	for func in module.functions.iter() {
+		inst_list.extend(regalloc::allocate(
+			select_function(func, target,)?,
+			func.slots.len(),
+			&abi,
+		)?,);
+	}
+	X(inst_list,)
+}
+
+fn cond(op: CmpOp,) -> Cond {
+	match op {
+		CmpOp::Eq => Cond::Eq,
+		CmpOp::Ne => Cond::Ne,
+		CmpOp::SLt => Cond::Lt,
+		CmpOp::SLe => Cond::Le,
+		CmpOp::SGt => Cond::Gt,
+		CmpOp::SGe => Cond::Ge,
+		CmpOp::ULt => Cond::Lo,
+		CmpOp::ULe => Cond::Ls,
+		CmpOp::UGt => Cond::Hi,
+		CmpOp::UGe => Cond::Hs,
+		// `fcmp` sets `nzcv` to `0011` for unordered operands, which fails
+		// all but `ne` of these
+		CmpOp::FEq => Cond::Eq,
+		CmpOp::FNe => Cond::Ne,
+		CmpOp::FLt => Cond::Mi,
+		CmpOp::FLe => Cond::Ls,
+		CmpOp::FGt => Cond::Gt,
+		CmpOp::FGe => Cond::Ge,
+	}
+}
+
+fn class(ty: Type,) -> RegisterClass {
+	match ty {
+		Type::I8 | Type::I16 | Type::I32 => RegisterClass::Gpr32,
+		Type::I64 => RegisterClass::Gpr64,
+		Type::F32 => RegisterClass::Fp { bytes: 4, },
+		Type::F64 => RegisterClass::Fp { bytes: 8, },
+	}
+}
+
+/// loads `value` into `target` with `movz` or `movn` followed by a `movk`
+/// for every remaining 16 bit chunk
+fn materialize<'a,>(
+	target: Reg,
+	value: i64,
+	is_64: bool,
+) -> LlccB<Vec<Instruction<'a, Reg,>,>,> {
+	let value = if is_64 { value as u64 } else { value as u32 as u64 };
+	if value < 1 << 12 {
+		return X(vec![Mov {
+			target,
+			value: RegisterOrImmediate::try_from(value as i64,)?,
+		}],);
+	}
+
+	let chunks: Vec<i64,> = (0..if is_64 { 4 } else { 2 })
+		.map(|k| (value >> (16 * k)) as i64 & 0xffff,)
+		.collect();
+	let count = |c: i64| chunks.iter().filter(|h| **h == c,).count();
+	// starting from all ones saves a `movk` for every `0xffff` chunk
+	let inverted = count(0xffff,) > count(0,);
+	let skip = if inverted { 0xffff } else { 0 };
+
+	let mut insts = vec![];
+	for (k, chunk,) in chunks.iter().enumerate() {
+		if *chunk == skip {
+			continue;
+		}
+		let shift = 16 * k as u8;
+		let (op, field,) = match (insts.is_empty(), inverted,) {
+			(true, true,) => (Opcode::Movn, !chunk & 0xffff,),
+			(true, false,) => (Opcode::Movz, *chunk,),
+			(false, _,) => (Opcode::Movk, *chunk,),
+		};
+		insts.push(Wide {
+			op,
+			target,
+			imm: Immediate::with_shift(field, shift,)?,
+		},);
+	}
+	if insts.is_empty() {
+		// every chunk is `0xffff`
+		insts.push(Wide {
+			op: Opcode::Movn,
+			target,
+			imm: Immediate::try_from(0,)?,
+		},);
+	}
+	X(insts,)
+}
+
+struct Selector<'f,> {
+	func:      &'f Function,
+	consts:    HashMap<Value, i64,>,
+	/// comparisons only used by the branch right after them. they set the
+	/// flags for a conditional branch instead of a register
+	fused:     HashMap<Value, CmpOp,>,
+	needs_reg: HashSet<Value,>,
+	next_temp: u32,
+	/// number of the exit system call, which ends `_start`
+	exit:      u16,
+	insts:     Vec<Instruction<'f, Reg,>,>,
+}
+
+impl<'f,> Selector<'f,> {
+	fn new(func: &'f Function, layout: &[Block], exit: u16,) -> Self {
+		let uses = func.use_counts();
+		let mut consts = HashMap::new();
+		let mut fused = HashMap::new();
+		for b in layout {
+			let block = func.block(*b,);
+			for inst in block.insts.iter() {
+				if let (Some(dest,), InstKind::Const(c,),) =
+					(inst.dest, &inst.kind,)
+				{
+					consts.insert(dest, *c,);
+				}
+			}
+			if let Terminator::Branch { cond, .. } = block.term
+				&& let Some(last,) = block.insts.last()
+				&& let InstKind::Cmp(op, ..,) = last.kind
+				&& last.dest == Some(cond,)
+				&& uses[cond.index()] == 1
+			{
+				fused.insert(cond, op,);
+			}
+		}
+
+		let mut selector = Self {
+			func,
+			consts,
+			fused,
+			needs_reg: HashSet::new(),
+			next_temp: func.value_types.len() as u32,
+			exit,
+			insts: vec![],
+		};
+		// constants only used as immediate operand never occupy a register
+		for b in layout {
+			let block = func.block(*b,);
+			for inst in block.insts.iter() {
+				for (n, op,) in inst.kind.operands().into_iter().enumerate() {
+					if !selector.is_immediate(&inst.kind, n, op,) {
+						selector.needs_reg.insert(op,);
+					}
+				}
+			}
+			for op in block.term.operands() {
+				if !selector.fused.contains_key(&op,) {
+					selector.needs_reg.insert(op,);
+				}
+			}
+		}
+		selector
+	}
+
+	fn ty(&self, v: Value,) -> Type {
+		self.func.ty(v,)
+	}
+
+	fn reg(&self, v: Value,) -> Reg {
+		Reg::from(VReg { index: v.0, class: class(self.ty(v,),), },)
+	}
+
+	/// integer `v` viewed as an `x` or a `w` register
+	fn view(&self, v: Value, is_64: bool,) -> Reg {
+		let class =
+			if is_64 { RegisterClass::Gpr64 } else { RegisterClass::Gpr32 };
+		Reg::from(VReg { index: v.0, class, },)
+	}
+
+	fn temp(&mut self, class: RegisterClass,) -> Reg {
+		self.next_temp += 1;
+		Reg::from(VReg { index: self.next_temp - 1, class, },)
+	}
+
+	/// registers passing `values` in order, integers and floats counted
+	/// apart
+	fn args(&self, values: &[Value],) -> LlccB<Vec<Reg,>,> {
+		let (mut ints, mut floats,) = (0, 0,);
+		let mut regs = vec![];
+		for v in values {
+			let class = class(self.ty(*v,),);
+			let n = if class.is_gpr() { &mut ints } else { &mut floats };
+			if *n == ARG_REGS {
+				return Y(LlccError::unsupported(
+					"arguments passed on the stack",
+				),);
+			}
+			regs.push(Register::new(class, *n as u8,)?.into(),);
+			*n += 1;
+		}
+		X(regs,)
+	}
+
+	/// copies between registers of one class
+	fn copy(&mut self, target: Reg, source: Reg,) {
+		self.insts.push(
+			if target.is_gpr() {
+				Mov { target, value: RegisterOrImmediate::Register(source,), }
+			} else {
+				Unary { op: Opcode::Fmov, target, source, }
+			},
+		);
+	}
+
+	fn label(&self, b: Block,) -> Target<'f,> {
+		Target::Block { func: &self.func.name, block: b.0, }
+	}
+
+	fn arith_imm(
+		&self,
+		v: Value,
+		negate: bool,
+	) -> Option<Immediate<12, false,>,> {
+		let c = *self.consts.get(&v,)?;
+		let c = if negate { c.checked_neg()? } else { c };
+		Immediate::shifted(c, &ARITH_SHIFTS,).ok()
+	}
+
+	fn bitmask(&self, v: Value,) -> Option<BitmaskImmediate,> {
+		let c = *self.consts.get(&v,)?;
+		BitmaskImmediate::new(c as u64, self.ty(v,) == Type::I64,).ok()
+	}
+
+	fn shift_amount(&self, v: Value,) -> Option<Immediate<6, false,>,> {
+		let c = *self.consts.get(&v,)?;
+		if c >= self.ty(v,).bits() as i64 {
+			return None;
+		}
+		Immediate::try_from(c,).ok()
+	}
+
+	/// whether operand `n` of `kind`, `v`, is encoded in the instruction
+	fn is_immediate(&self, kind: &InstKind, n: usize, v: Value,) -> bool {
+		n == 1
+			&& !self.ty(v,).is_float()
+			&& match kind {
+				InstKind::Binary(BinOp::Add | BinOp::Sub, ..,)
+				| InstKind::Cmp(..,) => self
+					.arith_imm(v, false,)
+					.or(self.arith_imm(v, true,),)
+					.is_some(),
+				InstKind::Binary(BinOp::And | BinOp::Or | BinOp::Xor, ..,) => {
+					self.bitmask(v,).is_some()
+				},
+				InstKind::Binary(
+					BinOp::Shl | BinOp::LShr | BinOp::AShr,
+					..,
+				) => self.shift_amount(v,).is_some(),
+				_ => false,
+			}
+	}
+
+	/// sign extends `target` again if `ty` is narrower than a register
+	fn narrow(&mut self, target: Reg, ty: Type,) {
+		let op = match ty {
+			Type::I8 => Opcode::Sxtb,
+			Type::I16 => Opcode::Sxth,
+			_ => return,
+		};
+		self.insts.push(Unary { op, target, source: target, },);
+	}
+
+	/// `v` zero extended to a full register
+	fn zext(&mut self, v: Value,) -> Reg {
+		let op = match self.ty(v,) {
+			Type::I8 => Opcode::Uxtb,
+			Type::I16 => Opcode::Uxth,
+			_ => return self.reg(v,),
+		};
+		let target = self.temp(RegisterClass::Gpr32,);
+		self.insts.push(Unary { op, target, source: self.reg(v,), },);
+		target
+	}
+
+	fn binary(
+		&mut self,
+		op: BinOp,
+		target: Reg,
+		lhs: Value,
+		rhs: Value,
+	) -> LlccB<(),> {
+		let ty = self.ty(lhs,);
+		let (l, r,) = (self.reg(lhs,), self.reg(rhs,),);
+		match op {
+			BinOp::FAdd | BinOp::FSub | BinOp::FMul | BinOp::FDiv => {
+				let op = match op {
+					BinOp::FAdd => Opcode::Fadd,
+					BinOp::FSub => Opcode::Fsub,
+					BinOp::FMul => Opcode::Fmul,
+					_ => Opcode::Fdiv,
+				};
+				self.insts.push(Binary { op, target, lhs: l, rhs: r, },);
+			},
+			BinOp::Add | BinOp::Sub => {
+				// a negative immediate flips the operation
+				let (is_add, rhs,) = match (
+					self.arith_imm(rhs, false,),
+					self.arith_imm(rhs, true,),
+				) {
+					(Some(i,), _,) => {
+						(op == BinOp::Add, RegisterOrImmediate::Immediate(i,),)
+					},
+					(None, Some(i,),) => {
+						(op == BinOp::Sub, RegisterOrImmediate::Immediate(i,),)
+					},
+					(None, None,) => {
+						(op == BinOp::Add, RegisterOrImmediate::Register(r,),)
+					},
+				};
+				let op = if is_add { Opcode::Add } else { Opcode::Sub };
+				self.insts.push(Arith { op, target, lhs: l, rhs, },);
+			},
+			BinOp::Mul | BinOp::SDiv => {
+				let op =
+					if op == BinOp::Mul { Opcode::Mul } else { Opcode::Sdiv };
+				self.insts.push(Binary { op, target, lhs: l, rhs: r, },);
+			},
+			BinOp::UDiv => {
+				let (l, r,) = (self.zext(lhs,), self.zext(rhs,),);
+				self.insts.push(Binary {
+					op: Opcode::Udiv,
+					target,
+					lhs: l,
+					rhs: r,
+				},);
+			},
+			BinOp::SRem | BinOp::URem => {
+				// `lhs - lhs / rhs * rhs`
+				let (div, l, r,) = if op == BinOp::SRem {
+					(Opcode::Sdiv, l, r,)
+				} else {
+					(Opcode::Udiv, self.zext(lhs,), self.zext(rhs,),)
+				};
+				let quotient = self.temp(class(ty,),);
+				self.insts.push(Binary {
+					op:     div,
+					target: quotient,
+					lhs:    l,
+					rhs:    r,
+				},);
+				self.insts.push(MulAdd {
+					op: Opcode::Msub,
+					target,
+					lhs: quotient,
+					rhs: r,
+					acc: l,
+				},);
+			},
+			BinOp::And | BinOp::Or | BinOp::Xor => {
+				let op = match op {
+					BinOp::And => Opcode::And,
+					BinOp::Or => Opcode::Orr,
+					_ => Opcode::Eor,
+				};
+				let rhs = match self.bitmask(rhs,) {
+					Some(i,) => RegisterOrImmediate::Immediate(i,),
+					None => RegisterOrImmediate::Register(r,),
+				};
+				self.insts.push(Logic { op, target, lhs: l, rhs, },);
+			},
+			BinOp::Shl | BinOp::LShr | BinOp::AShr => {
+				let (op, l,) = match op {
+					BinOp::Shl => (Opcode::Lsl, l,),
+					BinOp::LShr => (Opcode::Lsr, self.zext(lhs,),),
+					_ => (Opcode::Asr, l,),
+				};
+				let rhs = match self.shift_amount(rhs,) {
+					Some(i,) => RegisterOrImmediate::Immediate(i,),
+					None => RegisterOrImmediate::Register(r,),
+				};
+				self.insts.push(Shift { op, target, lhs: l, rhs, },);
+			},
+		}
+		self.narrow(target, ty,);
+		X((),)
+	}
+
+	fn cast(&mut self, op: CastOp, dest: Value, source: Value,) {
+		let (from, to,) = (self.ty(source,), self.ty(dest,),);
+		let op = match op {
+			CastOp::SIToFP => Opcode::Scvtf,
+			CastOp::UIToFP => Opcode::Ucvtf,
+			CastOp::FPToSI => Opcode::Fcvtzs,
+			CastOp::FPToUI => Opcode::Fcvtzu,
+			CastOp::FPExt | CastOp::FPTrunc => Opcode::Fcvt,
+			_ => return self.int_cast(op, dest, source,),
+		};
+		let source = match op {
+			Opcode::Ucvtf => self.zext(source,),
+			_ => self.reg(source,),
+		};
+		// conversions to narrow integers go through `w` registers
+		let target = match to {
+			Type::I8 | Type::I16 => self.view(dest, false,),
+			_ => self.reg(dest,),
+		};
+		self.insts.push(Unary { op, target, source, },);
+		if from.is_float() {
+			self.narrow(target, to,);
+		}
+	}
+
+	fn int_cast(&mut self, op: CastOp, dest: Value, source: Value,) {
+		let (from, to,) = (self.ty(source,), self.ty(dest,),);
+		let source = self.view(source, false,);
+		let extend = |signed: bool| match (from, signed,) {
+			(Type::I8, true,) => Some(Opcode::Sxtb,),
+			(Type::I16, true,) => Some(Opcode::Sxth,),
+			(Type::I32, true,) => Some(Opcode::Sxtw,),
+			(Type::I8, false,) => Some(Opcode::Uxtb,),
+			(Type::I16, false,) => Some(Opcode::Uxth,),
+			_ => None,
+		};
+		let (op, target,) = match op {
+			// narrow values are sign extended already
+			CastOp::SExt if to != Type::I64 => (None, self.view(dest, false,),),
+			CastOp::SExt => (extend(true,), self.reg(dest,),),
+			// writing a `w` register clears the upper half
+			CastOp::ZExt => (extend(false,), self.view(dest, false,),),
+			_ => {
+				let op = match to {
+					Type::I8 => Some(Opcode::Sxtb,),
+					Type::I16 => Some(Opcode::Sxth,),
+					_ => None,
+				};
+				(op, self.view(dest, false,),)
+			},
+		};
+		self.insts.push(match op {
+			Some(op,) => Unary { op, target, source, },
+			None => {
+				Mov { target, value: RegisterOrImmediate::Register(source,), }
+			},
+		},);
+	}
+
+	fn access(&self, v: Value,) -> (Access, Opcode, Opcode,) {
+		match self.ty(v,) {
+			Type::I8 => (Access::Single(1,), Opcode::Ldrsb, Opcode::Strb,),
+			Type::I16 => (Access::Single(2,), Opcode::Ldrsh, Opcode::Strh,),
+			ty => {
+				(Access::Single(ty.bytes() as u8,), Opcode::Ldr, Opcode::Str,)
+			},
+		}
+	}
+
+	/// stores `value` to `addr`, which is accessed like `value`
+	fn store(&mut self, value: Value, addr: Address<Reg,>,) {
+		let (access, _, op,) = self.access(value,);
+		let source = if self.ty(value,).is_float() {
+			self.reg(value,)
+		} else {
+			self.view(value, access == Access::Single(8,),)
+		};
+		self.insts.push(Store { op, source, addr, },);
+	}
+
+	/// `[x29, #offset]` of `slot`
+	fn slot(&self, slot: Slot, access: Access,) -> LlccB<Address<Reg,>,> {
+		X(Address::offset(
+			Register::X29.into(),
+			regalloc::local_offset(slot.index(),),
+			access,
+		)?,)
+	}
+
+	/// # Return
+	///
+	/// returns whether the rest of the block is skipped after a tail call
+	fn inst(&mut self, inst: &'f Inst,) -> LlccB<bool,> {
+		match &inst.kind {
+			InstKind::Call { callee, args, tail, } => {
+				for (target, a,) in self.args(args,)?.into_iter().zip(args,) {
+					self.copy(target, self.reg(*a,),);
+				}
+				if *tail && self.func.name == lower::ENTRY {
+					return Y(LlccError::unsupported(format!(
+						"tail call from `{}`, which has no caller",
+						lower::ENTRY
+					),),);
+				}
+				if *tail {
+					// the callee returns straight to our caller
+					self.insts.push(Epilogue,);
+					self.insts.push(B(Target::Symbol(callee,),),);
+					return X(true,);
+				}
+				self.insts.push(Bl(callee,),);
+				if let Some(dest,) =
+					inst.dest.filter(|d| self.needs_reg.contains(d,),)
+				{
+					self.copy(self.reg(dest,), self.args(&[dest,],)?[0],);
+				}
+				return X(false,);
+			},
+			InstKind::Store { addr, value, } => {
+				let (access, ..,) = self.access(*value,);
+				let addr = Address::offset(self.reg(*addr,), 0, access,)?;
+				self.store(*value, addr,);
+				return X(false,);
+			},
+			InstKind::StackStore(slot, value,) => {
+				let (access, ..,) = self.access(*value,);
+				let addr = self.slot(*slot, access,)?;
+				self.store(*value, addr,);
+				return X(false,);
+			},
+			_ => (),
+		}
+
+		let Some(dest,) = inst.dest else {
+			return Y(LlccError::unsupported(format!(
+				"`{}` in aarch64 backend",
+				inst.kind
+			),),);
+		};
+		if !self.needs_reg.contains(&dest,) && !self.fused.contains_key(&dest,)
+		{
+			return X(false,);
+		}
+		let target = self.reg(dest,);
+		match &inst.kind {
+			InstKind::Const(c,) if self.ty(dest,).is_float() => {
+				// the bit pattern goes through a general purpose register
+				let is_64 = self.ty(dest,) == Type::F64;
+				let source = match (*c, is_64,) {
+					(0, true,) => Register::Xzr.into(),
+					(0, false,) => Register::Wzr.into(),
+					_ => {
+						let bits = self.temp(class(
+							if is_64 { Type::I64 } else { Type::I32 },
+						),);
+						self.insts.extend(materialize(bits, *c, is_64,)?,);
+						bits
+					},
+				};
+				self.insts.push(Unary { op: Opcode::Fmov, target, source, },);
+			},
+			InstKind::Const(c,) => {
+				let is_64 = self.ty(dest,) == Type::I64;
+				self.insts.extend(materialize(target, *c, is_64,)?,);
+			},
+			InstKind::Copy(v,) => self.copy(target, self.reg(*v,),),
+			InstKind::Binary(op, lhs, rhs,) => {
+				self.binary(*op, target, *lhs, *rhs,)?
+			},
+			InstKind::Unary(op, v,) => {
+				let op = match op {
+					UnOp::Neg => Opcode::Neg,
+					UnOp::Not => Opcode::Mvn,
+					UnOp::FNeg => Opcode::Fneg,
+				};
+				self.insts.push(Unary { op, target, source: self.reg(*v,), },);
+				self.narrow(target, self.ty(dest,),);
+			},
+			InstKind::Cmp(op, lhs, rhs,) => {
+				let (op_code, rhs,) = match (
+					self.arith_imm(*rhs, false,),
+					self.arith_imm(*rhs, true,),
+				) {
+					_ if self.ty(*lhs,).is_float() => (
+						Opcode::Fcmp,
+						RegisterOrImmediate::Register(self.reg(*rhs,),),
+					),
+					(Some(i,), _,) => {
+						(Opcode::Cmp, RegisterOrImmediate::Immediate(i,),)
+					},
+					(None, Some(i,),) => {
+						(Opcode::Cmn, RegisterOrImmediate::Immediate(i,),)
+					},
+					(None, None,) => (
+						Opcode::Cmp,
+						RegisterOrImmediate::Register(self.reg(*rhs,),),
+					),
+				};
+				self.insts.push(Compare {
+					op: op_code,
+					lhs: self.reg(*lhs,),
+					rhs,
+				},);
+				if !self.fused.contains_key(&dest,) {
+					self.insts.push(Cset { target, cond: cond(*op,), },);
+				}
+			},
+			InstKind::Cast(op, v,) => self.cast(*op, dest, *v,),
+			InstKind::Load(addr,) => {
+				let (access, op, _,) = self.access(dest,);
+				let addr = Address::offset(self.reg(*addr,), 0, access,)?;
+				self.insts.push(Load { op, target, addr, },);
+			},
+			InstKind::StackLoad(slot,) => {
+				let (access, op, _,) = self.access(dest,);
+				let addr = self.slot(*slot, access,)?;
+				self.insts.push(Load { op, target, addr, },);
+			},
+			InstKind::StackAddr(slot,) => {
+				self.insts.push(Arith {
+					op: Opcode::Add,
+					target,
+					lhs: Register::X29.into(),
+					rhs: RegisterOrImmediate::try_from(
+						regalloc::local_offset(slot.index(),),
+					)?,
+				},);
+			},
+			kind => {
+				return Y(LlccError::unsupported(format!(
+					"`{kind}` in aarch64 backend"
+				),),);
+			},
+		}
+		X(false,)
+	}
+
+	fn term(&mut self, term: &Terminator, next: Option<Block,>,) -> LlccB<(),> {
+		match *term {
+			Terminator::Jump(to,) => {
+				if next != Some(to,) {
+					self.insts.push(B(self.label(to,),),);
+				}
+			},
+			Terminator::Branch { cond: c, then, els, } => {
+				// branch away on the condition which skips the next block
+				let (taken, holds,) = if next == Some(then,) {
+					(els, false,)
+				} else {
+					(then, true,)
+				};
+				self.insts.push(match self.fused.get(&c,) {
+					Some(op,) => {
+						let cond = if holds {
+							cond(*op,)
+						} else {
+							cond(*op,).invert()
+						};
+						BCond { cond, target: self.label(taken,), }
+					},
+					None => CompareBranch {
+						op:     if holds { Opcode::Cbnz } else { Opcode::Cbz },
+						reg:    self.reg(c,),
+						target: self.label(taken,),
+					},
+				},);
+				let fallthrough = if holds { els } else { then };
+				if next != Some(fallthrough,) {
+					self.insts.push(B(self.label(fallthrough,),),);
+				}
+			},
+			Terminator::Return(v,) => {
+				match v {
+					Some(v,) => self.copy(self.args(&[v,],)?[0], self.reg(v,),),
+					None => self.insts.push(Mov {
+						target: Register::W0.into(),
+						value:  RegisterOrImmediate::try_from(0,)?,
+					},),
+				}
+				if self.func.name == lower::ENTRY {
+					// `_start` has nowhere to return to
+					self.insts.push(ret_val!(RegisterOrImmediate::try_from(
+						self.exit as i64
+					)?),);
+					self.insts.push(Svc { syscall: self.exit, },);
+				} else {
+					self.insts.push(Epilogue,);
+					self.insts.push(Ret,);
+				}
+			},
+			Terminator::Unreachable => (),
+		}
+		X((),)
+	}
+}
+
+fn select_function<'f,>(
+	func: &'f Function,
+	target: &dyn target::Target,
+) -> LlccB<Vec<Instruction<'f, Reg,>,>,> {
+	let cfg = Cfg::new(func,);
+	let layout = cfg.rpo();
+	let mut s = Selector::new(func, layout, target.syscall(Syscall::Exit,),);
+
+	if func.linkage == Linkage::External {
+		s.insts.push(Global(&func.name,),);
+	}
+	s.insts.push(Symbol(&func.name,),);
+	s.insts.push(Prologue,);
+	for (source, p,) in s.args(&func.params,)?.into_iter().zip(&func.params,) {
+		if s.needs_reg.contains(p,) {
+			s.copy(s.reg(*p,), source,);
+		}
+	}
+
+	for (n, b,) in layout.iter().enumerate() {
+		let block = func.block(*b,);
+		if *b != Block::ENTRY {
+			s.insts.push(Label(s.label(*b,),),);
+		}
+		let mut tail = false;
+		for inst in block.insts.iter() {
+			tail = s.inst(inst,)?;
+			if tail {
+				break;
+			}
+		}
+		if !tail {
+			s.term(&block.term, layout.get(n + 1,).copied(),)?;
+		}
+	}
+
+	for inst in s.insts.iter() {
+		inst.check()?;
+	}
+	X(s.insts,)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::Aarch64;
+	use super::*;
+	use crate::err::Container;
+	use crate::ir::FunctionBuilder;
+	use quickcheck_macros::quickcheck;
+
+	fn target() -> Aarch64 {
+		Aarch64 { triple: "aarch64-linux-gnu".parse().unwrap(), }
+	}
+
+	fn lines(func: &Function,) -> Vec<String,> {
+		regalloc::allocate(
+			select_function(func, &target(),).unwrap(),
+			func.slots.len(),
+			&regalloc::Abi::of(&target(),),
+		)
+		.unwrap()
+		.into_iter()
+		.map(|i| String::from(i,).trim().to_string(),)
+		.collect()
+	}
+
+	#[test]
+	fn frame_only_around_plain_calls() {
+		// int f(int x) { return g(x) + 1; }
+		let mut fb =
+			FunctionBuilder::new("f", &[Type::I32,], Some(Type::I32,),);
+		let x = fb.param(0,);
+		let r = fb.call("g", &[x,], Some(Type::I32,),);
+		let one = fb.iconst(Type::I32, 1,);
+		let sum = fb.binary(BinOp::Add, r, one,);
+		fb.ret(Some(sum,),);
+		let asm = lines(&fb.finish(),);
+		assert!(
+			asm.contains(&"stp x29, x30, [sp, #-16]!".to_string()),
+			"{asm:?}"
+		);
+		assert!(asm.contains(&"bl g".to_string()), "{asm:?}");
+		assert_eq!(asm.last().unwrap(), "ret");
+
+		// int h(int x) { return g(x); }
+		let mut fb =
+			FunctionBuilder::new("h", &[Type::I32,], Some(Type::I32,),);
+		let x = fb.param(0,);
+		let r = fb.call("g", &[x,], Some(Type::I32,),);
+		fb.ret(Some(r,),);
+		let mut h = fb.finish();
+		crate::opt::tail::mark(&mut h,);
+		let asm = lines(&h,);
+		assert!(!asm.iter().any(|l| l.starts_with("stp",)), "{asm:?}");
+		assert_eq!(asm.last().unwrap(), "b g");
+
+		// `_start` has no caller for `g` to return to
+		h.name = lower::ENTRY.to_string();
+		let Y(_,) = select_function(&h, &target(),) else {
+			panic!("tail call from `_start`");
+		};
+	}
+
+	#[test]
+	fn values_across_calls_get_callee_saved() {
+		// int f(int x) { return g(x) + x; }
+		let mut fb =
+			FunctionBuilder::new("f", &[Type::I32,], Some(Type::I32,),);
+		let x = fb.param(0,);
+		let r = fb.call("g", &[x,], Some(Type::I32,),);
+		let sum = fb.binary(BinOp::Add, r, x,);
+		fb.ret(Some(sum,),);
+		let asm = lines(&fb.finish(),);
+		assert!(asm.contains(&"str x19, [x29, #16]".to_string()), "{asm:?}");
+		assert!(asm.contains(&"mov w19, w0".to_string()), "{asm:?}");
+		assert!(asm.contains(&"ldr x19, [x29, #16]".to_string()), "{asm:?}");
+	}
+
+	#[test]
+	fn pressure_spills_to_frame() {
+		// twenty values derived from `p` are all live at once
+		let mut fb =
+			FunctionBuilder::new("f", &[Type::I64,], Some(Type::I64,),);
+		let p = fb.param(0,);
+		let values: Vec<Value,> = (1..=20)
+			.map(|i| {
+				let c = fb.iconst(Type::I64, i,);
+				fb.binary(BinOp::Add, p, c,)
+			},)
+			.collect();
+		let sum = values.iter().fold(p, |s, v| fb.binary(BinOp::Add, s, *v,),);
+		fb.ret(Some(sum,),);
+		let asm = lines(&fb.finish(),);
+		assert!(!asm.iter().any(|l| l.contains("%v",)), "{asm:?}");
+		assert!(asm.iter().any(|l| l.starts_with("str x15, [x29",)), "{asm:?}");
+		assert!(asm.iter().any(|l| l.starts_with("ldr x15, [x29",)), "{asm:?}");
+	}
+
+	#[test]
+	fn spilled_pair_loads_get_two_scratch_registers() {
+		let x =
+			|index| Reg::from(VReg { index, class: RegisterClass::Gpr64, },);
+		let mut insts = vec![Prologue];
+		// more values than registers, all live to the end
+		for i in 0..20 {
+			insts.push(Mov {
+				target: x(i,),
+				value:  RegisterOrImmediate::try_from(i as i64,).unwrap(),
+			},);
+		}
+		insts.push(Pair {
+			op:     Opcode::Ldp,
+			first:  x(20,),
+			second: x(21,),
+			addr:   Address::offset(x(0,), 0, Access::Pair(8,),).unwrap(),
+		},);
+		for i in 1..22 {
+			insts.push(Arith {
+				op:     Opcode::Add,
+				target: x(0,),
+				lhs:    x(0,),
+				rhs:    RegisterOrImmediate::Register(x(i,),),
+			},);
+		}
+		insts.extend([Epilogue, Ret,],);
+		let asm: Vec<String,> =
+			regalloc::allocate(insts, 0, &regalloc::Abi::of(&target(),),)
+				.unwrap()
+				.into_iter()
+				.map(|i| String::from(i,).trim().to_string(),)
+				.collect();
+		let ldp = asm.iter().find(|l| l.starts_with("ldp x",),).unwrap();
+		assert!(ldp.starts_with("ldp x15, x16,",), "{asm:?}");
+	}
+
+	#[test]
+	fn loops_branch_to_labels() {
+		// int f(int n) { int i = 0; while (i < n) i++; return i; }
+		let mut fb =
+			FunctionBuilder::new("f", &[Type::I32,], Some(Type::I32,),);
+		let n = fb.param(0,);
+		let header = fb.create_block();
+		let body = fb.create_block();
+		let exit = fb.create_block();
+		let zero = fb.iconst(Type::I32, 0,);
+		let one = fb.iconst(Type::I32, 1,);
+		fb.jump(header,);
+		fb.switch_to(header,);
+		let i = fb.push(Some(Type::I32,), InstKind::Phi(vec![],),);
+		let c = fb.cmp(CmpOp::SLt, i, n,);
+		fb.branch(c, body, exit,);
+		fb.switch_to(body,);
+		let next = fb.binary(BinOp::Add, i, one,);
+		fb.jump(header,);
+		fb.switch_to(exit,);
+		fb.ret(Some(i,),);
+		let mut func = fb.finish();
+		func.block_mut(header,).insts[0].kind =
+			InstKind::Phi(vec![(Block::ENTRY, zero,), (body, next,)],);
+		crate::ir::ssa::out_of_ssa(&mut func,);
+
+		let asm = lines(&func,);
+		assert!(asm.contains(&".Lf_1:".to_string()), "{asm:?}");
+		// the comparison feeds the branch through the flags
+		assert!(!asm.iter().any(|l| l.starts_with("cset",)), "{asm:?}");
+		assert!(asm.iter().any(|l| l.starts_with("cmp w",)), "{asm:?}");
+		assert!(asm.iter().any(|l| l.starts_with("b.lt .Lf_",)), "{asm:?}");
+		assert!(asm.contains(&"b .Lf_1".to_string()), "{asm:?}");
+		assert!(!asm.iter().any(|l| l.starts_with("stp",)), "{asm:?}");
+	}
+
+	/// runs a constant materialization sequence
+	fn evaluate(insts: &[Instruction<'_, Reg,>],) -> u64 {
+		insts.iter().fold(0, |acc, inst| match inst {
+			Mov { value: RegisterOrImmediate::Immediate(i,), .. } => {
+				i.value() as u64
+			},
+			Wide { op: Opcode::Movz, imm, .. } => imm.value() as u64,
+			Wide { op: Opcode::Movn, imm, .. } => !(imm.value() as u64),
+			Wide { op: Opcode::Movk, imm, .. } => {
+				acc & !(0xffff << imm.shift()) | imm.value() as u64
+			},
+			_ => panic!("not a constant"),
+		},)
+	}
+
+	#[quickcheck]
+	fn materialize_any_constant(value: i64, is_64: bool,) -> bool {
+		let class =
+			if is_64 { RegisterClass::Gpr64 } else { RegisterClass::Gpr32 };
+		let target = Reg::from(VReg { index: 0, class, },);
+		let insts = materialize(target, value, is_64,).unwrap();
+		let mask = if is_64 { u64::MAX } else { u32::MAX as u64 };
+		insts.len() <= if is_64 { 4 } else { 2 }
+			&& evaluate(&insts,) & mask == value as u64 & mask
+	}
+
+	#[test]
+	fn negative_and_shifted_addends() {
+		// long f(long x) { return (x - -3) + 0x5000 + -1; }
+		let mut fb =
+			FunctionBuilder::new("f", &[Type::I64,], Some(Type::I64,),);
+		let x = fb.param(0,);
+		let minus_three = fb.iconst(Type::I64, -3,);
+		let big = fb.iconst(Type::I64, 0x5000,);
+		let minus_one = fb.iconst(Type::I64, -1,);
+		let a = fb.binary(BinOp::Sub, x, minus_three,);
+		let b = fb.binary(BinOp::Add, a, big,);
+		let c = fb.binary(BinOp::Add, b, minus_one,);
+		fb.ret(Some(c,),);
+		let asm = lines(&fb.finish(),);
+		assert!(asm.contains(&"add x9, x9, #3".to_string()), "{asm:?}");
+		assert!(
+			asm.contains(&"add x9, x9, #5, lsl #12".to_string()),
+			"{asm:?}"
+		);
+		assert!(asm.contains(&"sub x9, x9, #1".to_string()), "{asm:?}");
+		assert!(!asm.iter().any(|l| l.starts_with("mov",) && l.contains('#',)));
+	}
+
+	#[test]
+	fn integer_operations() {
+		// int f(int x, int y, char c) {
+		//   return (x % y & 0xff0) + (c >> 1) + (x < 10);
+		// }
+		let mut fb = FunctionBuilder::new(
+			"f",
+			&[Type::I32, Type::I32, Type::I8,],
+			Some(Type::I32,),
+		);
+		let (x, y, c,) = (fb.param(0,), fb.param(1,), fb.param(2,),);
+		let mask = fb.iconst(Type::I32, 0xff0,);
+		let one = fb.iconst(Type::I8, 1,);
+		let ten = fb.iconst(Type::I32, 10,);
+		let rem = fb.binary(BinOp::SRem, x, y,);
+		let masked = fb.binary(BinOp::And, rem, mask,);
+		let half = fb.binary(BinOp::LShr, c, one,);
+		let wide = fb.cast(CastOp::SExt, half, Type::I32,);
+		let sum = fb.binary(BinOp::Add, masked, wide,);
+		let less = fb.cmp(CmpOp::SLt, x, ten,);
+		let total = fb.binary(BinOp::Add, sum, less,);
+		fb.ret(Some(total,),);
+
+		let asm = lines(&fb.finish(),);
+		let has = |prefix: &str| asm.iter().any(|l| l.starts_with(prefix,),);
+		assert!(has("sdiv w",), "{asm:?}");
+		assert!(has("msub w",), "{asm:?}");
+		assert!(
+			asm.iter()
+				.any(|l| l.starts_with("and w",) && l.ends_with("#0xff0",))
+		);
+		assert!(has("uxtb w",), "{asm:?}");
+		assert!(
+			asm.iter().any(|l| l.starts_with("lsr w",) && l.ends_with("#1",))
+		);
+		assert!(has("sxtb w",), "{asm:?}");
+		assert!(has("cset w",), "{asm:?}");
+	}
+
+	#[test]
+	fn floats_use_vector_registers() {
+		// double f(int n, double x, float y) {
+		//   double z = g(x) * 1.5;
+		//   return z < y ? z + n : 0.0;
+		// }
+		let mut fb = FunctionBuilder::new(
+			"f",
+			&[Type::I32, Type::F64, Type::F32,],
+			Some(Type::F64,),
+		);
+		let (n, x, y,) = (fb.param(0,), fb.param(1,), fb.param(2,),);
+		let less = fb.create_block();
+		let other = fb.create_block();
+		let g = fb.call("g", &[x,], Some(Type::F64,),);
+		let half = fb.fconst(Type::F64, 1.5,);
+		let z = fb.binary(BinOp::FMul, g, half,);
+		let wide = fb.cast(CastOp::FPExt, y, Type::F64,);
+		let c = fb.cmp(CmpOp::FLt, z, wide,);
+		fb.branch(c, less, other,);
+		fb.switch_to(less,);
+		let n = fb.cast(CastOp::SIToFP, n, Type::F64,);
+		let sum = fb.binary(BinOp::FAdd, z, n,);
+		fb.ret(Some(sum,),);
+		fb.switch_to(other,);
+		let zero = fb.fconst(Type::F64, 0.0,);
+		fb.ret(Some(zero,),);
+
+		let asm = lines(&fb.finish(),);
+		let has = |line: &str| asm.contains(&line.to_string(),);
+		// `n` and `y` live across the call in callee saved registers
+		assert!(has("str x19, [x29, #16]",), "{asm:?}");
+		assert!(has("str d8, [x29, #24]",), "{asm:?}");
+		assert!(has("fmov s8, s1",), "{asm:?}");
+		assert!(has("bl g",), "{asm:?}");
+		assert!(asm.iter().any(|l| l.starts_with("fmul d",)), "{asm:?}");
+		assert!(
+			asm.iter().any(|l| l.starts_with("fcvt d",) && l.ends_with("s8",))
+		);
+		assert!(asm.iter().any(|l| l.starts_with("fcmp d",)), "{asm:?}");
+		assert!(
+			asm.iter()
+				.any(|l| l.starts_with("b.pl",) || l.starts_with("b.mi",))
+		);
+		assert!(
+			asm.iter()
+				.any(|l| l.starts_with("scvtf d",) && l.ends_with("w19",))
+		);
+		assert!(
+			asm.iter()
+				.any(|l| l.starts_with("fmov d",) && l.ends_with(", xzr",))
+		);
+	}
+}
@@ -0,0 +1,288 @@
+//! the instruction table
+//!
+//! every mnemonic of the backend is listed once together with the operand
+//! form it takes and its base encoding. `Instruction` is grouped by form,
+//! the printer and `Instruction::check` look the form of an opcode up here
+//! and the encoder its fixed bits
+
+use crate::register::Access;
+
+#[derive(
+	Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display, strum::EnumString,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum Opcode {
+	Movz,
+	Movk,
+	Movn,
+	Add,
+	Adds,
+	Sub,
+	Subs,
+	Cmp,
+	Cmn,
+	And,
+	Ands,
+	Orr,
+	Eor,
+	Bic,
+	Tst,
+	Lsl,
+	Lsr,
+	Asr,
+	Ror,
+	Mul,
+	Smulh,
+	Umulh,
+	Sdiv,
+	Udiv,
+	Madd,
+	Msub,
+	Neg,
+	Mvn,
+	Sxtb,
+	Sxth,
+	Sxtw,
+	Uxtb,
+	Uxth,
+	Csel,
+	Csinc,
+	Csinv,
+	Csneg,
+	Cset,
+	Ldr,
+	Ldrb,
+	Ldrh,
+	Ldrsb,
+	Ldrsh,
+	Ldrsw,
+	Str,
+	Strb,
+	Strh,
+	Ldp,
+	Stp,
+	Adr,
+	Adrp,
+	Cbz,
+	Cbnz,
+	Tbz,
+	Tbnz,
+	B,
+	#[strum(serialize = "b.")]
+	BCond,
+	Bl,
+	Blr,
+	Br,
+	Ret,
+	Svc,
+	Fadd,
+	Fsub,
+	Fmul,
+	Fdiv,
+	Fneg,
+	Fabs,
+	Fsqrt,
+	Fmov,
+	Fcvt,
+	Scvtf,
+	Ucvtf,
+	Fcvtzs,
+	Fcvtzu,
+	Fcmp,
+}
+
+/// shape of the operands of an opcode
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub enum Form {
+	/// `op target, #imm16, lsl #shift`
+	Wide,
+	/// `op target, lhs, rhs` with an arithmetic `rhs`
+	Arith,
+	/// `op lhs, rhs` with an arithmetic `rhs`
+	Compare,
+	/// `op target, lhs, rhs` with a logical `rhs`
+	Logic,
+	/// `op lhs, rhs` with a logical `rhs`
+	Test,
+	/// `op target, lhs, rhs` with a register or an amount as `rhs`
+	Shift,
+	/// `op target, lhs, rhs`, registers only
+	Binary,
+	/// `op target, lhs, rhs, acc`
+	MulAdd,
+	/// `op target, source` of equal width
+	Unary,
+	/// `op target, source` reading the low `bytes` of `source`
+	Extend { bytes: u8, },
+	/// `op target, lhs, rhs, cond`
+	Select,
+	/// `op target, cond`
+	SetCond,
+	/// `op target, addr` loading `bytes`, the width of `target` if `None`
+	Load { bytes: Option<u8,>, signed: bool, },
+	/// `op source, addr` storing `bytes`, the width of `source` if `None`
+	Store { bytes: Option<u8,>, },
+	/// `op first, second, addr`
+	Pair,
+	/// `op target, label`
+	PcRel,
+	/// `op reg, label`
+	CompareBranch,
+	/// `op reg, #bit, label`
+	TestBranch,
+	/// `op label`
+	Branch,
+	/// `b.<cond> label`
+	CondBranch,
+	/// `op reg`
+	BranchReg,
+	/// `op` without operands
+	Return,
+	/// `op #imm16`
+	Exception,
+	/// `op target, lhs, rhs` on floats of one width
+	FpBinary,
+	/// `op target, source` on floats of one width
+	FpUnary,
+	/// `op target, source` copying bits between registers of equal size
+	FpMove,
+	/// `op target, source` between float widths
+	FpConvert,
+	/// `op target, source` from an integer to a float
+	IntToFp,
+	/// `op target, source` from a float to an integer
+	FpToInt,
+	/// `op lhs, rhs` on floats of one width
+	FpCompare,
+}
+
+impl Form {
+	/// access made by a load or store of a register of `width` bytes
+	pub fn access(self, width: u8,) -> Option<Access,> {
+		match self {
+			Self::Load { bytes, .. } | Self::Store { bytes, } => {
+				Some(Access::Single(bytes.unwrap_or(width,),),)
+			},
+			Self::Pair => Some(Access::Pair(width,),),
+			_ => None,
+		}
+	}
+}
+
+/// every opcode with its form and base encoding: the word with all operand
+/// fields zero, of the 32 bit variant where the width is a field, and of the
+/// register form where an immediate one exists. loads and stores take the
+/// unsigned offset form
+const TABLE: &[(Opcode, Form, u32,)] = {
+	use Form::*;
+	use Opcode as O;
+	&[
+		(O::Movz, Wide, 0x5280_0000,),
+		(O::Movk, Wide, 0x7280_0000,),
+		(O::Movn, Wide, 0x1280_0000,),
+		(O::Add, Arith, 0x0b00_0000,),
+		(O::Adds, Arith, 0x2b00_0000,),
+		(O::Sub, Arith, 0x4b00_0000,),
+		(O::Subs, Arith, 0x6b00_0000,),
+		(O::Cmp, Compare, 0x6b00_001f,),
+		(O::Cmn, Compare, 0x2b00_001f,),
+		(O::And, Logic, 0x0a00_0000,),
+		(O::Ands, Logic, 0x6a00_0000,),
+		(O::Orr, Logic, 0x2a00_0000,),
+		(O::Eor, Logic, 0x4a00_0000,),
+		(O::Bic, Logic, 0x0a20_0000,),
+		(O::Tst, Test, 0x6a00_001f,),
+		(O::Lsl, Shift, 0x1ac0_2000,),
+		(O::Lsr, Shift, 0x1ac0_2400,),
+		(O::Asr, Shift, 0x1ac0_2800,),
+		(O::Ror, Shift, 0x1ac0_2c00,),
+		(O::Mul, Binary, 0x1b00_7c00,),
+		(O::Smulh, Binary, 0x9b40_7c00,),
+		(O::Umulh, Binary, 0x9bc0_7c00,),
+		(O::Sdiv, Binary, 0x1ac0_0c00,),
+		(O::Udiv, Binary, 0x1ac0_0800,),
+		(O::Madd, MulAdd, 0x1b00_0000,),
+		(O::Msub, MulAdd, 0x1b00_8000,),
+		(O::Neg, Unary, 0x4b00_03e0,),
+		(O::Mvn, Unary, 0x2a20_03e0,),
+		(O::Sxtb, Extend { bytes: 1, }, 0x1300_1c00,),
+		(O::Sxth, Extend { bytes: 2, }, 0x1300_3c00,),
+		(O::Sxtw, Extend { bytes: 4, }, 0x1300_7c00,),
+		(O::Uxtb, Extend { bytes: 1, }, 0x5300_1c00,),
+		(O::Uxth, Extend { bytes: 2, }, 0x5300_3c00,),
+		(O::Csel, Select, 0x1a80_0000,),
+		(O::Csinc, Select, 0x1a80_0400,),
+		(O::Csinv, Select, 0x5a80_0000,),
+		(O::Csneg, Select, 0x5a80_0400,),
+		(O::Cset, SetCond, 0x1a9f_07e0,),
+		(O::Ldr, Load { bytes: None, signed: false, }, 0xb940_0000,),
+		(O::Ldrb, Load { bytes: Some(1,), signed: false, }, 0x3940_0000,),
+		(O::Ldrh, Load { bytes: Some(2,), signed: false, }, 0x7940_0000,),
+		(O::Ldrsb, Load { bytes: Some(1,), signed: true, }, 0x39c0_0000,),
+		(O::Ldrsh, Load { bytes: Some(2,), signed: true, }, 0x79c0_0000,),
+		(O::Ldrsw, Load { bytes: Some(4,), signed: true, }, 0xb980_0000,),
+		(O::Str, Store { bytes: None, }, 0xb900_0000,),
+		(O::Strb, Store { bytes: Some(1,), }, 0x3900_0000,),
+		(O::Strh, Store { bytes: Some(2,), }, 0x7900_0000,),
+		(O::Ldp, Pair, 0x2940_0000,),
+		(O::Stp, Pair, 0x2900_0000,),
+		(O::Adr, PcRel, 0x1000_0000,),
+		(O::Adrp, PcRel, 0x9000_0000,),
+		(O::Cbz, CompareBranch, 0x3400_0000,),
+		(O::Cbnz, CompareBranch, 0x3500_0000,),
+		(O::Tbz, TestBranch, 0x3600_0000,),
+		(O::Tbnz, TestBranch, 0x3700_0000,),
+		(O::B, Branch, 0x1400_0000,),
+		(O::BCond, CondBranch, 0x5400_0000,),
+		(O::Bl, Branch, 0x9400_0000,),
+		(O::Blr, BranchReg, 0xd63f_0000,),
+		(O::Br, BranchReg, 0xd61f_0000,),
+		(O::Ret, Return, 0xd65f_03c0,),
+		(O::Svc, Exception, 0xd400_0001,),
+		(O::Fadd, FpBinary, 0x1e20_2800,),
+		(O::Fsub, FpBinary, 0x1e20_3800,),
+		(O::Fmul, FpBinary, 0x1e20_0800,),
+		(O::Fdiv, FpBinary, 0x1e20_1800,),
+		(O::Fneg, FpUnary, 0x1e21_4000,),
+		(O::Fabs, FpUnary, 0x1e20_c000,),
+		(O::Fsqrt, FpUnary, 0x1e21_c000,),
+		(O::Fmov, FpMove, 0x1e20_4000,),
+		(O::Fcvt, FpConvert, 0x1e22_4000,),
+		(O::Scvtf, IntToFp, 0x1e22_0000,),
+		(O::Ucvtf, IntToFp, 0x1e23_0000,),
+		(O::Fcvtzs, FpToInt, 0x1e38_0000,),
+		(O::Fcvtzu, FpToInt, 0x1e39_0000,),
+		(O::Fcmp, FpCompare, 0x1e20_2000,),
+	]
+};
+
+fn entry(op: Opcode,) -> &'static (Opcode, Form, u32,) {
+	TABLE
+		.iter()
+		.find(|(o, ..,)| *o == op,)
+		.expect("every opcode is in the table",)
+}
+
+pub fn form(op: Opcode,) -> Form {
+	entry(op,).1
+}
+
+pub fn encoding(op: Opcode,) -> u32 {
+	entry(op,).2
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn every_opcode_once() {
+		for (op, _, base,) in TABLE {
+			assert_eq!(TABLE.iter().filter(|(o, ..,)| o == op,).count(), 1);
+			assert_eq!(TABLE.iter().filter(|(.., b,)| b == base,).count(), 1);
+			let parsed: Opcode = op.to_string().parse().unwrap();
+			assert_eq!(parsed, *op);
+		}
+		assert_eq!(TABLE.len(), Opcode::Fcmp as usize + 1);
+	}
+}
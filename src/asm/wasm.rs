@@ -0,0 +1,813 @@
+//! WebAssembly text backend
+//!
+//! every function becomes a wasm function, exported unless it is `static`,
+//! and every value a local. stack slots live in linear memory below
+//! `$sp`, a global moved down by each frame.
+//!
+//! wasm has no `goto`, so the blocks are arranged with the algorithm of
+//! Ramsey, "Beyond Relooper". it walks the dominator tree: a loop header
+//! opens a `loop` continued by its back edges, and a block with several
+//! forward predecessors is placed after a `block` that the predecessors
+//! leave with `br`. any other block has a single predecessor and is
+//! emitted in place of the branch to it. this covers every reducible
+//! control flow graph, which is all that C without `goto` produces
+
+use crate::err::B::X;
+use crate::err::B::Y;
+use crate::err::LlccB;
+use crate::err::LlccError;
+use crate::ir::BinOp;
+use crate::ir::Block;
+use crate::ir::CastOp;
+use crate::ir::CmpOp;
+use crate::ir::Function;
+use crate::ir::InstKind;
+use crate::ir::Linkage;
+use crate::ir::Module;
+use crate::ir::Terminator;
+use crate::ir::Type;
+use crate::ir::UnOp;
+use crate::ir::Value;
+use crate::ir::cfg::Cfg;
+use crate::ir::dom::DomTree;
+use crate::ir::lower::lower_expr;
+use crate::ir::ssa;
+use crate::opt::OptLevel;
+use crate::opt::optimize_module;
+
+/// size of the linear memory in 64KiB pages. the stack starts at its end
+const PAGES: u32 = 2;
+const STACK_TOP: u32 = PAGES * 0x10000;
+
+/// frames are aligned like the stack of the native targets
+const FRAME_ALIGN: u32 = 16;
+
+/// wasm has no integers narrower than `i32`
+fn class(t: Type,) -> &'static str {
+	match t {
+		Type::I8 | Type::I16 | Type::I32 => "i32",
+		Type::I64 => "i64",
+		Type::F32 => "f32",
+		Type::F64 => "f64",
+	}
+}
+
+/// a wasm float literal exactly equal to the constant
+fn float_literal(t: Type, c: i64,) -> String {
+	let v = t.float_value(c,);
+	if v.is_nan() {
+		let (sign, payload,) = match t {
+			Type::F32 => {
+				((v as f32).is_sign_negative(), (c as u32 & 0x7f_ffff) as u64,)
+			},
+			_ => (v.is_sign_negative(), c as u64 & 0xf_ffff_ffff_ffff,),
+		};
+		format!("{}nan:0x{payload:x}", if sign { "-" } else { "" })
+	} else if v.is_infinite() {
+		if v < 0.0 { "-inf" } else { "inf" }.to_string()
+	} else if t == Type::F32 {
+		format!("{:?}", v as f32)
+	} else {
+		format!("{v:?}")
+	}
+}
+
+/// mnemonic of `op` and whether it needs zero extended operands
+fn binary(op: BinOp,) -> (&'static str, bool,) {
+	match op {
+		BinOp::Add | BinOp::FAdd => ("add", false,),
+		BinOp::Sub | BinOp::FSub => ("sub", false,),
+		BinOp::Mul | BinOp::FMul => ("mul", false,),
+		BinOp::SDiv => ("div_s", false,),
+		BinOp::UDiv => ("div_u", true,),
+		BinOp::FDiv => ("div", false,),
+		BinOp::SRem => ("rem_s", false,),
+		BinOp::URem => ("rem_u", true,),
+		BinOp::And => ("and", false,),
+		BinOp::Or => ("or", false,),
+		BinOp::Xor => ("xor", false,),
+		BinOp::Shl => ("shl", false,),
+		BinOp::LShr => ("shr_u", true,),
+		BinOp::AShr => ("shr_s", false,),
+	}
+}
+
+/// mnemonic of `op` and whether it needs zero extended operands. `ne` is
+/// true for NaN operands like `fne`, the others are false
+fn cmp(op: CmpOp,) -> (&'static str, bool,) {
+	match op {
+		CmpOp::Eq | CmpOp::FEq => ("eq", false,),
+		CmpOp::Ne | CmpOp::FNe => ("ne", false,),
+		CmpOp::SLt => ("lt_s", false,),
+		CmpOp::SLe => ("le_s", false,),
+		CmpOp::SGt => ("gt_s", false,),
+		CmpOp::SGe => ("ge_s", false,),
+		CmpOp::ULt => ("lt_u", true,),
+		CmpOp::ULe => ("le_u", true,),
+		CmpOp::UGt => ("gt_u", true,),
+		CmpOp::UGe => ("ge_u", true,),
+		CmpOp::FLt => ("lt", false,),
+		CmpOp::FLe => ("le", false,),
+		CmpOp::FGt => ("gt", false,),
+		CmpOp::FGe => ("ge", false,),
+	}
+}
+
+/// load and store of a value of type `t`. narrow integers are kept sign
+/// extended in their `i32`
+fn access(t: Type,) -> (&'static str, &'static str,) {
+	match t {
+		Type::I8 => ("i32.load8_s", "i32.store8",),
+		Type::I16 => ("i32.load16_s", "i32.store16",),
+		Type::I32 => ("i32.load", "i32.store",),
+		Type::I64 => ("i64.load", "i64.store",),
+		Type::F32 => ("f32.load", "f32.store",),
+		Type::F64 => ("f64.load", "f64.store",),
+	}
+}
+
+/// parameter and result types of `callee` as seen by a call
+fn signature(params: &[Type], ret: Option<Type,>,) -> String {
+	let mut sig = String::new();
+	if !params.is_empty() {
+		let params: Vec<_,> = params.iter().map(|t| class(*t,),).collect();
+		sig.push_str(&format!(" (param {})", params.join(" ")),);
+	}
+	if let Some(ret,) = ret {
+		sig.push_str(&format!(" (result {})", class(ret)),);
+	}
+	sig
+}
+
+struct Emitter<'f,> {
+	module:  &'f Module,
+	func:    &'f Function,
+	dom:     DomTree,
+	/// position of each block in reverse postorder
+	rpo:     Vec<usize,>,
+	header:  Vec<bool,>,
+	merge:   Vec<bool,>,
+	/// frame offset of each slot
+	offsets: Vec<u32,>,
+	frame:   u32,
+	depth:   usize,
+	out:     String,
+}
+
+impl<'f,> Emitter<'f,> {
+	fn new(module: &'f Module, func: &'f Function,) -> LlccB<Self,> {
+		let cfg = Cfg::new(func,);
+		let dom = DomTree::new(&cfg,);
+		let len = cfg.block_count();
+		let mut rpo = vec![usize::MAX; len];
+		for (i, b,) in cfg.rpo().iter().enumerate() {
+			rpo[b.index()] = i;
+		}
+
+		let mut header = vec![false; len];
+		let mut merge = vec![false; len];
+		for b in cfg.rpo() {
+			let mut forward = 0;
+			for p in cfg.preds(*b,) {
+				if rpo[p.index()] < rpo[b.index()] {
+					forward += 1;
+				} else if dom.dominates(*b, *p,) {
+					header[b.index()] = true;
+				} else {
+					return Y(LlccError::unsupported(format!(
+						"irreducible control flow into {b} of `{}`",
+						func.name
+					),),);
+				}
+			}
+			merge[b.index()] = forward > 1;
+		}
+
+		let mut offsets = vec![];
+		let mut frame: u32 = 0;
+		for t in func.slots.iter() {
+			frame = frame.next_multiple_of(t.bytes(),);
+			offsets.push(frame,);
+			frame += t.bytes();
+		}
+		let frame = frame.next_multiple_of(FRAME_ALIGN,);
+
+		X(Self {
+			module,
+			func,
+			dom,
+			rpo,
+			header,
+			merge,
+			offsets,
+			frame,
+			depth: 2,
+			out: String::new(),
+		},)
+	}
+
+	/// one instruction per line of `text`
+	fn line(&mut self, text: impl AsRef<str,>,) {
+		for line in text.as_ref().lines() {
+			for _ in 0..self.depth {
+				self.out.push('\t',);
+			}
+			self.out.push_str(line,);
+			self.out.push('\n',);
+		}
+	}
+
+	fn get(&mut self, v: Value,) {
+		self.line(format!("local.get $v{}", v.0),);
+	}
+
+	fn set(&mut self, v: Value,) {
+		self.line(format!("local.set $v{}", v.0),);
+	}
+
+	/// restores the sign extension of a narrow integer after arithmetic
+	fn normalize(&mut self, t: Type,) {
+		match t {
+			Type::I8 => self.line("i32.extend8_s",),
+			Type::I16 => self.line("i32.extend16_s",),
+			_ => (),
+		}
+	}
+
+	fn zext(&mut self, t: Type,) {
+		match t {
+			Type::I8 => self.line("i32.const 0xff\ni32.and",),
+			Type::I16 => self.line("i32.const 0xffff\ni32.and",),
+			_ => (),
+		}
+	}
+
+	/// pushes both operands, zero extended if `unsigned`
+	fn operands(&mut self, a: Value, b: Value, unsigned: bool,) {
+		let t = self.func.ty(a,);
+		self.get(a,);
+		if unsigned {
+			self.zext(t,);
+		}
+		self.get(b,);
+		if unsigned {
+			self.zext(t,);
+		}
+	}
+
+	fn frame_pointer(&mut self,) {
+		self.line("local.get $fp",);
+	}
+
+	fn cast(&mut self, op: CastOp, from: Type, to: Type,) {
+		if from == to {
+			return;
+		}
+		let (src, dst,) = (class(from,), class(to,),);
+		match op {
+			CastOp::SExt if dst == "i64" && src == "i32" => {
+				self.line("i64.extend_i32_s",)
+			},
+			CastOp::SExt => (),
+			CastOp::ZExt => {
+				self.zext(from,);
+				if dst == "i64" && src == "i32" {
+					self.line("i64.extend_i32_u",);
+				}
+			},
+			CastOp::Trunc => {
+				if src == "i64" {
+					self.line("i32.wrap_i64",);
+				}
+				self.normalize(to,);
+			},
+			CastOp::SIToFP => self.line(format!("{dst}.convert_{src}_s"),),
+			CastOp::UIToFP => {
+				self.zext(from,);
+				self.line(format!("{dst}.convert_{src}_u"),);
+			},
+			CastOp::FPToSI => {
+				self.line(format!("{dst}.trunc_sat_{src}_s"),);
+				self.normalize(to,);
+			},
+			CastOp::FPToUI => {
+				self.line(format!("{dst}.trunc_sat_{src}_u"),);
+				self.normalize(to,);
+			},
+			CastOp::FPExt => self.line("f64.promote_f32",),
+			CastOp::FPTrunc => self.line("f32.demote_f64",),
+		}
+	}
+
+	fn inst(&mut self, dest: Option<Value,>, kind: &InstKind,) {
+		let dest_ty = dest.map(|d| self.func.ty(d,),);
+		let t = dest_ty.map(class,).unwrap_or_default();
+		match kind {
+			InstKind::Const(c,) => {
+				let ty = dest_ty.unwrap();
+				let c = if ty.is_float() {
+					float_literal(ty, *c,)
+				} else {
+					c.to_string()
+				};
+				self.line(format!("{t}.const {c}"),);
+			},
+			InstKind::Copy(v,) => self.get(*v,),
+			InstKind::Unary(UnOp::Neg, v,) => {
+				self.line(format!("{t}.const 0"),);
+				self.get(*v,);
+				self.line(format!("{t}.sub"),);
+				self.normalize(dest_ty.unwrap(),);
+			},
+			InstKind::Unary(UnOp::Not, v,) => {
+				self.get(*v,);
+				self.line(format!("{t}.const -1\n{t}.xor"),);
+			},
+			InstKind::Unary(UnOp::FNeg, v,) => {
+				self.get(*v,);
+				self.line(format!("{t}.neg"),);
+			},
+			InstKind::Binary(op, a, b,) => {
+				let (name, unsigned,) = binary(*op,);
+				self.operands(*a, *b, unsigned,);
+				self.line(format!("{t}.{name}"),);
+				self.normalize(dest_ty.unwrap(),);
+			},
+			InstKind::Cmp(op, a, b,) => {
+				let (name, unsigned,) = cmp(*op,);
+				let operand = class(self.func.ty(*a,),);
+				self.operands(*a, *b, unsigned,);
+				self.line(format!("{operand}.{name}"),);
+				if t == "i64" {
+					self.line("i64.extend_i32_u",);
+				}
+			},
+			InstKind::Cast(op, v,) => {
+				let from = self.func.ty(*v,);
+				self.get(*v,);
+				self.cast(*op, from, dest_ty.unwrap(),);
+			},
+			InstKind::Phi(_,) => {
+				unreachable!("phis are removed by `ssa::out_of_ssa` before")
+			},
+			InstKind::StackLoad(s,) => {
+				let (load, _,) = access(dest_ty.unwrap(),);
+				self.frame_pointer();
+				self.line(format!("{load} offset={}", self.offsets[s.index()]),);
+			},
+			InstKind::StackStore(s, v,) => {
+				let (_, store,) = access(self.func.ty(*v,),);
+				self.frame_pointer();
+				self.get(*v,);
+				self.line(format!(
+					"{store} offset={}",
+					self.offsets[s.index()]
+				),);
+			},
+			InstKind::StackAddr(s,) => {
+				self.frame_pointer();
+				self.line(format!(
+					"i32.const {}\ni32.add\ni64.extend_i32_u",
+					self.offsets[s.index()]
+				),);
+			},
+			InstKind::Load(addr,) => {
+				let (load, _,) = access(dest_ty.unwrap(),);
+				self.get(*addr,);
+				self.line(format!("i32.wrap_i64\n{load}"),);
+			},
+			InstKind::Store { addr, value, } => {
+				let (_, store,) = access(self.func.ty(*value,),);
+				self.get(*addr,);
+				self.line("i32.wrap_i64",);
+				self.get(*value,);
+				self.line(store,);
+			},
+			// wasm tail calls are a proposal not every runtime has
+			InstKind::Call { callee, args, .. } => {
+				for a in args.iter() {
+					self.get(*a,);
+				}
+				self.line(format!("call ${callee}"),);
+				let returns = match self.module.function(callee,) {
+					Some(f,) => f.ret.is_some(),
+					None => dest.is_some(),
+				};
+				if dest.is_none() && returns {
+					self.line("drop",);
+				}
+			},
+		}
+		if let Some(dest,) = dest {
+			self.set(dest,);
+		}
+	}
+
+	/// releases the frame, which every return has to do
+	fn epilogue(&mut self,) {
+		if self.frame != 0 {
+			self.frame_pointer();
+			self.line(format!(
+				"i32.const {}\ni32.add\nglobal.set $sp",
+				self.frame
+			),);
+		}
+	}
+
+	/// label left by a `br` to `to`, `None` if `to` is emitted in place
+	fn label(&self, from: Block, to: Block,) -> Option<String,> {
+		if self.rpo[to.index()] <= self.rpo[from.index()] {
+			Some(format!("$loop{}", to.0),)
+		} else if self.merge[to.index()] {
+			Some(format!("$bb{}", to.0),)
+		} else {
+			None
+		}
+	}
+
+	fn branch(&mut self, from: Block, to: Block,) {
+		match self.label(from, to,) {
+			Some(label,) => self.line(format!("br {label}"),),
+			None => self.tree(to,),
+		}
+	}
+
+	fn term(&mut self, b: Block,) {
+		match &self.func.block(b,).term {
+			Terminator::Jump(to,) => self.branch(b, *to,),
+			Terminator::Branch { then, els, .. } if then == els => {
+				self.branch(b, *then,)
+			},
+			Terminator::Branch { cond, then, els, } => {
+				let (cond, then, els,) = (*cond, *then, *els,);
+				self.get(cond,);
+				if class(self.func.ty(cond,),) == "i64" {
+					self.line("i64.const 0\ni64.ne",);
+				}
+				match (self.label(b, then,), self.label(b, els,),) {
+					(Some(label,), _,) => {
+						self.line(format!("br_if {label}"),);
+						self.branch(b, els,);
+					},
+					(None, Some(label,),) => {
+						self.line(format!("i32.eqz\nbr_if {label}"),);
+						self.branch(b, then,);
+					},
+					(None, None,) => {
+						self.line("if",);
+						self.depth += 1;
+						self.tree(then,);
+						self.depth -= 1;
+						self.line("else",);
+						self.depth += 1;
+						self.tree(els,);
+						self.depth -= 1;
+						self.line("end",);
+					},
+				}
+			},
+			Terminator::Return(v,) => {
+				if let Some(v,) = v {
+					self.get(*v,);
+				}
+				self.epilogue();
+				self.line("return",);
+			},
+			Terminator::Unreachable => self.line("unreachable",),
+		}
+	}
+
+	/// `x` and the blocks it immediately dominates
+	fn tree(&mut self, x: Block,) {
+		let mut merges: Vec<Block,> = self
+			.dom
+			.children(x,)
+			.iter()
+			.copied()
+			.filter(|y| self.merge[y.index()],)
+			.collect();
+		merges.sort_by_key(|y| self.rpo[y.index()],);
+		if self.header[x.index()] {
+			self.line(format!("loop $loop{}", x.0),);
+			self.depth += 1;
+			self.within(x, &merges,);
+			self.depth -= 1;
+			self.line("end",);
+		} else {
+			self.within(x, &merges,);
+		}
+	}
+
+	/// `x` inside a `block` for each of `merges`, the last one outermost
+	fn within(&mut self, x: Block, merges: &[Block],) {
+		let Some((y, inner,),) = merges.split_last() else {
+			for inst in self.func.block(x,).insts.iter() {
+				self.inst(inst.dest, &inst.kind,);
+			}
+			self.term(x,);
+			return;
+		};
+		self.line(format!("block $bb{}", y.0),);
+		self.depth += 1;
+		self.within(x, inner,);
+		self.depth -= 1;
+		self.line("end",);
+		self.tree(*y,);
+	}
+
+	fn function(mut self,) -> String {
+		let func = self.func;
+		let mut head = format!("\t(func ${}", func.name);
+		if func.linkage == Linkage::External {
+			head.push_str(&format!(" (export \"{}\")", func.name),);
+		}
+		for p in func.params.iter() {
+			head.push_str(&format!(
+				" (param $v{} {})",
+				p.0,
+				class(func.ty(*p,))
+			),);
+		}
+		if let Some(ret,) = func.ret {
+			head.push_str(&format!(" (result {})", class(ret)),);
+		}
+		self.out.push_str(&head,);
+		self.out.push('\n',);
+
+		if self.frame != 0 {
+			self.line("(local $fp i32)",);
+		}
+		for (i, t,) in func.value_types.iter().enumerate() {
+			if !func.params.contains(&Value(i as u32,),) {
+				self.line(format!("(local $v{i} {})", class(*t)),);
+			}
+		}
+		if self.frame != 0 {
+			self.line(format!(
+				"global.get $sp\ni32.const {}\ni32.sub\nlocal.tee \
+				 $fp\nglobal.set $sp",
+				self.frame
+			),);
+		}
+		self.tree(Block::ENTRY,);
+		// every path has returned, but validation does not know that
+		if func.ret.is_some() {
+			self.line("unreachable",);
+		}
+		self.out.push_str("\t)\n",);
+		self.out
+	}
+}
+
+/// prints `module`, which has to be out of SSA form, as a wasm module.
+/// callees defined elsewhere are imported from `env` with the types of
+/// their first call
+pub fn print_module(module: &Module,) -> LlccB<String,> {
+	let mut out = "(module\n".to_string();
+
+	let mut imported = vec![];
+	for func in module.functions.iter() {
+		for inst in func.blocks.iter().flat_map(|b| b.insts.iter(),) {
+			let InstKind::Call { callee, args, .. } = &inst.kind else {
+				continue;
+			};
+			if module.function(callee,).is_some() || imported.contains(callee,)
+			{
+				continue;
+			}
+			let params: Vec<_,> = args.iter().map(|a| func.ty(*a,),).collect();
+			let sig = signature(&params, inst.dest.map(|d| func.ty(d,),),);
+			out.push_str(&format!(
+				"\t(import \"env\" \"{callee}\" (func ${callee}{sig}))\n"
+			),);
+			imported.push(callee.clone(),);
+		}
+	}
+
+	out.push_str(&format!(
+		"\t(memory (export \"memory\") {PAGES})\n\t(global $sp (mut i32) \
+		 (i32.const {STACK_TOP}))\n"
+	),);
+	for func in module.functions.iter() {
+		out.push_str(&Emitter::new(module, func,)?.function(),);
+	}
+	out.push_str(")\n",);
+	X(out,)
+}
+
+/// compiles `src` like `asm_str`, but to a wasm module
+pub fn wat_str(src: &str, level: OptLevel,) -> LlccB<String,> {
+	let func = lower_expr(src,)?;
+	let mut module = Module { functions: vec![func], };
+	optimize_module(&mut module, level,);
+	for func in module.functions.iter_mut() {
+		ssa::out_of_ssa(func,);
+	}
+	print_module(&module,)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::err::Container;
+	use crate::ir::FunctionBuilder;
+	use std::fs;
+	use std::process::Command;
+	use uuid::Uuid;
+
+	/// whether `wat2wasm` assembles and validates `text`
+	fn wat2wasm(text: &str,) -> bool {
+		let path =
+			std::env::temp_dir().join(format!("llcc-{}.wat", Uuid::new_v4()),);
+		fs::write(&path, text,).unwrap();
+		let status = Command::new("wat2wasm",)
+			.arg(&path,)
+			.args(["-o", "/dev/null",],)
+			.status()
+			.expect("wat2wasm is installed",);
+		fs::remove_file(path,).unwrap();
+		status.success()
+	}
+
+	/// the nesting of `block`, `loop` and `if` down to their `end`s
+	fn structure(text: &str,) -> Vec<String,> {
+		text.lines()
+			.map(str::trim,)
+			.filter(|l| {
+				["block", "loop", "if", "else", "end", "br",]
+					.iter()
+					.any(|k| l.split(' ',).next() == Some(k,),)
+					|| l.starts_with("br_if",)
+			},)
+			.map(str::to_string,)
+			.collect()
+	}
+
+	/// int sum(int n) {
+	///   int s = 0;
+	///   for (int i = 0; i < n; i++) s += g(&s);
+	///   return s;
+	/// }
+	fn sum() -> Module {
+		let mut fb =
+			FunctionBuilder::new("sum", &[Type::I32,], Some(Type::I32,),);
+		let n = fb.param(0,);
+		let s = fb.slot(Type::I32,);
+		let i = fb.slot(Type::I32,);
+		let header = fb.create_block();
+		let body = fb.create_block();
+		let exit = fb.create_block();
+		let zero = fb.iconst(Type::I32, 0,);
+		fb.store_slot(s, zero,);
+		fb.store_slot(i, zero,);
+		fb.jump(header,);
+		fb.switch_to(header,);
+		let iv = fb.load_slot(i,);
+		let c = fb.cmp(CmpOp::SLt, iv, n,);
+		fb.branch(c, body, exit,);
+		fb.switch_to(body,);
+		let addr = fb.slot_addr(s,);
+		let r = fb.call("g", &[addr,], Some(Type::I32,),);
+		let old = fb.load(Type::I32, addr,);
+		let new = fb.binary(BinOp::Add, old, r,);
+		fb.store(addr, new,);
+		let one = fb.iconst(Type::I32, 1,);
+		let next = fb.binary(BinOp::Add, iv, one,);
+		fb.store_slot(i, next,);
+		fb.jump(header,);
+		fb.switch_to(exit,);
+		let total = fb.load_slot(s,);
+		fb.ret(Some(total,),);
+		Module { functions: vec![fb.finish()], }
+	}
+
+	/// float f(double d) { return (int)d + (unsigned)-d; }
+	fn casts() -> Module {
+		let mut fb =
+			FunctionBuilder::new("f", &[Type::F64,], Some(Type::F32,),);
+		let d = fb.param(0,);
+		let s = fb.cast(CastOp::FPToSI, d, Type::I32,);
+		let neg = fb.unary(UnOp::FNeg, d,);
+		let u = fb.cast(CastOp::FPToUI, neg, Type::I32,);
+		let sum = fb.binary(BinOp::Add, s, u,);
+		let f = fb.cast(CastOp::UIToFP, sum, Type::F32,);
+		fb.ret(Some(f,),);
+		Module { functions: vec![fb.finish()], }
+	}
+
+	#[test]
+	fn loops_and_slots() {
+		let text = print_module(&sum(),).unwrap();
+		assert!(
+			text.contains(
+				"(import \"env\" \"g\" (func $g (param i64) (result i32)))"
+			),
+			"{text}"
+		);
+		assert!(
+			text.contains(
+				"(func $sum (export \"sum\") (param $v0 i32) (result i32)"
+			),
+			"{text}"
+		);
+		assert!(
+			text.contains("global.get $sp\n\t\ti32.const 16\n\t\ti32.sub"),
+			"{text}"
+		);
+		assert!(text.contains("i32.store offset=4"), "{text}");
+		// both successors of the header have no other predecessor, so they
+		// are emitted in place. the body continues the loop with `br`
+		let expected =
+			["loop $loop1", "if", "br $loop1", "else", "end", "end",];
+		assert_eq!(structure(&text,), expected.map(str::to_string), "{text}");
+	}
+
+	/// if (a) x = 1; else x = 2; return x + (b ? 3 : 4);
+	#[test]
+	fn diamonds_merge_after_blocks() {
+		let mut fb = FunctionBuilder::new(
+			"pick",
+			&[Type::I32, Type::I32,],
+			Some(Type::I8,),
+		);
+		let (a, b,) = (fb.param(0,), fb.param(1,),);
+		let x = fb.slot(Type::I8,);
+		let y = fb.slot(Type::I8,);
+		let blocks: Vec<_,> = (0..6).map(|_| fb.create_block(),).collect();
+		fb.branch(a, blocks[0], blocks[1],);
+		for (i, v,) in [(0, 1,), (1, 2,),] {
+			fb.switch_to(blocks[i],);
+			let c = fb.iconst(Type::I8, v,);
+			fb.store_slot(x, c,);
+			fb.jump(blocks[2],);
+		}
+		fb.switch_to(blocks[2],);
+		fb.branch(b, blocks[3], blocks[4],);
+		for (i, v,) in [(3, 3,), (4, 4,),] {
+			fb.switch_to(blocks[i],);
+			let c = fb.iconst(Type::I8, v,);
+			fb.store_slot(y, c,);
+			fb.jump(blocks[5],);
+		}
+		fb.switch_to(blocks[5],);
+		let (xv, yv,) = (fb.load_slot(x,), fb.load_slot(y,),);
+		let sum = fb.binary(BinOp::Add, xv, yv,);
+		fb.ret(Some(sum,),);
+		let module = Module { functions: vec![fb.finish()], };
+
+		let text = print_module(&module,).unwrap();
+		assert!(text.contains("i32.store8 offset=1"), "{text}");
+		assert!(text.contains("i32.add\n\t\ti32.extend8_s"), "{text}");
+		let expected = [
+			"block $bb3",
+			"if",
+			"br $bb3",
+			"else",
+			"br $bb3",
+			"end",
+			"end",
+			"block $bb6",
+			"if",
+			"br $bb6",
+			"else",
+			"br $bb6",
+			"end",
+			"end",
+		];
+		assert_eq!(structure(&text,), expected.map(str::to_string), "{text}");
+	}
+
+	#[test]
+	fn expressions_are_exported() {
+		let text = wat_str("7.5f-2-0.25", OptLevel::O0,).unwrap();
+		assert!(
+			text.contains("(func $_start (export \"_start\") (result i32)"),
+			"{text}"
+		);
+		assert!(text.contains("f32.const 7.5"), "{text}");
+		assert!(text.ends_with("\tunreachable\n\t)\n)\n"), "{text}");
+	}
+
+	/// C leaves out-of-range conversions undefined, so they saturate
+	/// rather than trap
+	#[test]
+	fn float_casts_saturate() {
+		let text = print_module(&casts(),).unwrap();
+		assert!(text.contains("i32.trunc_sat_f64_s"), "{text}");
+		assert!(text.contains("i32.trunc_sat_f64_u"), "{text}");
+		assert!(!text.contains(".trunc_f"), "{text}");
+	}
+
+	#[test]
+	#[ignore = "needs wabt"]
+	fn modules_validate() {
+		for module in [sum(), casts(),] {
+			let text = print_module(&module,).unwrap();
+			assert!(wat2wasm(&text,), "{text}");
+		}
+		for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2, OptLevel::O3,] {
+			let text = wat_str("7.5f-2-0.25", level,).unwrap();
+			assert!(wat2wasm(&text,), "{level}\n{text}");
+		}
+	}
+}
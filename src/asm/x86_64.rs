@@ -0,0 +1,1036 @@
+//! instruction selection from `ir` for x86_64 System V, printed in GNU
+//! (AT&T) syntax
+//!
+//! every ir value and every stack slot owns an 8 byte home below `%rbp`.
+//! an instruction loads its operands into `%rax` and `%rcx`, or `%xmm0` and
+//! `%xmm1` for floats, computes and stores the result back to its home.
+//! integers are kept sign extended to 64 bits, floats keep their bit pattern
+//! in the low bytes
+
+use super::SectionKind;
+use super::Target;
+use crate::err::B::X;
+use crate::err::B::Y;
+use crate::err::LlccB;
+use crate::err::LlccError;
+use crate::ir::BinOp;
+use crate::ir::Block;
+use crate::ir::CastOp;
+use crate::ir::CmpOp;
+use crate::ir::Function;
+use crate::ir::Inst;
+use crate::ir::InstKind;
+use crate::ir::Linkage;
+use crate::ir::Module;
+use crate::ir::Slot;
+use crate::ir::Terminator;
+use crate::ir::Type;
+use crate::ir::UnOp;
+use crate::ir::Value;
+use crate::ir::cfg::Cfg;
+use crate::ir::lower;
+use crate::opt::fold::normalize;
+use crate::target;
+use crate::target::CallingConvention;
+use crate::target::RegisterFile;
+use crate::target::Syscall;
+use crate::target::Triple;
+use std::fmt::Display;
+
+/// System V passes the first six integer arguments in these registers
+const INT_ARGS: [Gpr; 6] =
+	[Gpr::Rdi, Gpr::Rsi, Gpr::Rdx, Gpr::Rcx, Gpr::R8, Gpr::R9,];
+
+/// and the first eight float arguments in `%xmm0`-`%xmm7`
+const FLOAT_ARGS: u8 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash,)]
+pub enum Gpr {
+	Rax,
+	Rcx,
+	Rdx,
+	Rbx,
+	Rsp,
+	Rbp,
+	Rsi,
+	Rdi,
+	R8,
+	R9,
+	R10,
+	R11,
+	R12,
+	R13,
+	R14,
+	R15,
+}
+
+impl TryFrom<u8,> for Gpr {
+	type Error = LlccError;
+
+	/// register of number `n` in instruction encodings
+	fn try_from(n: u8,) -> Result<Self, Self::Error,> {
+		use Gpr::*;
+		const ALL: [Gpr; 16] = [
+			Rax, Rcx, Rdx, Rbx, Rsp, Rbp, Rsi, Rdi, R8, R9, R10, R11, R12, R13,
+			R14, R15,
+		];
+		ALL.get(n as usize,)
+			.copied()
+			.ok_or(LlccError::mismatch_imm(n as i64, 4, false,),)
+	}
+}
+
+impl Gpr {
+	/// name of the low `bytes` of the register
+	fn name(self, bytes: u8,) -> String {
+		let legacy = match self {
+			Self::Rax => "a",
+			Self::Rcx => "c",
+			Self::Rdx => "d",
+			Self::Rbx => "b",
+			Self::Rsp => "sp",
+			Self::Rbp => "bp",
+			Self::Rsi => "si",
+			Self::Rdi => "di",
+			numbered => {
+				let n = numbered as u8;
+				return match bytes {
+					1 => format!("r{n}b"),
+					2 => format!("r{n}w"),
+					4 => format!("r{n}d"),
+					_ => format!("r{n}"),
+				};
+			},
+		};
+		// `a` through `d` take an `x` suffix, the others do not
+		let x = if legacy.len() == 1 { "x" } else { "" };
+		match bytes {
+			1 => format!("{legacy}l"),
+			2 => format!("{legacy}{x}"),
+			4 => format!("e{legacy}{x}"),
+			_ => format!("r{legacy}{x}"),
+		}
+	}
+}
+
+/// operand of an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub enum Operand {
+	/// the low `bytes` of a general purpose register
+	Gpr(Gpr, u8,),
+	Xmm(u8,),
+	Imm(i64,),
+	Mem {
+		base: Gpr,
+		disp: i32,
+	},
+}
+
+impl Operand {
+	fn q(gpr: Gpr,) -> Self {
+		Self::Gpr(gpr, 8,)
+	}
+}
+
+impl Display for Operand {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		match self {
+			Self::Gpr(r, bytes,) => write!(f, "%{}", r.name(*bytes)),
+			Self::Xmm(n,) => write!(f, "%xmm{n}"),
+			Self::Imm(i,) => write!(f, "${i}"),
+			Self::Mem { base, disp: 0, } => write!(f, "({})", Self::q(*base)),
+			Self::Mem { base, disp, } => {
+				write!(f, "{disp}({})", Self::q(*base))
+			},
+		}
+	}
+}
+
+/// mnemonics with their AT&T size suffix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display,)]
+#[strum(serialize_all = "lowercase")]
+pub enum Opcode {
+	Movb,
+	Movw,
+	Movl,
+	Movq,
+	Movabsq,
+	Movsbq,
+	Movswq,
+	Movslq,
+	Movzbq,
+	Movzwq,
+	Leaq,
+	Addq,
+	Subq,
+	Imulq,
+	Andq,
+	Orq,
+	Xorq,
+	Shlq,
+	Sarq,
+	Shrq,
+	Negq,
+	Notq,
+	Btcq,
+	Cmpq,
+	Cmovaeq,
+	Andb,
+	Orb,
+	Cqto,
+	Idivq,
+	Divq,
+	Pushq,
+	Leave,
+	Movss,
+	Movsd,
+	Addss,
+	Addsd,
+	Subss,
+	Subsd,
+	Mulss,
+	Mulsd,
+	Divss,
+	Divsd,
+	Ucomiss,
+	Ucomisd,
+	Cvtsi2ssq,
+	Cvtsi2sdq,
+	Cvttss2siq,
+	Cvttsd2siq,
+	Cvtss2sd,
+	Cvtsd2ss,
+}
+
+/// condition codes of `set` and `j`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display,)]
+#[strum(serialize_all = "lowercase")]
+pub enum Cond {
+	E,
+	Ne,
+	L,
+	Le,
+	G,
+	Ge,
+	B,
+	Be,
+	A,
+	Ae,
+	P,
+	Np,
+}
+
+/// operands are in AT&T order, the target comes last
+pub enum Instruction<'a,> {
+	Section(SectionKind,),
+	Global(&'a str,),
+	Symbol(&'a str,),
+	Label(Target<'a,>,),
+	Nullary(Opcode,),
+	Unary {
+		op:      Opcode,
+		operand: Operand,
+	},
+	Binary {
+		op:     Opcode,
+		source: Operand,
+		target: Operand,
+	},
+	/// writes 1 to the low byte of `target` if `cond` holds, 0 otherwise
+	Set {
+		cond:   Cond,
+		target: Gpr,
+	},
+	Jmp(Target<'a,>,),
+	Jcc {
+		cond:   Cond,
+		target: Target<'a,>,
+	},
+	Call(&'a str,),
+	Ret,
+	Syscall,
+}
+
+impl Display for Instruction<'_,> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		use Instruction::*;
+		match self {
+			Section(section_kind,) => {
+				let kind: String = (*section_kind).into();
+				write!(f, ".{kind}")
+			},
+			Global(s,) => write!(f, ".global {s}"),
+			Symbol(s,) => write!(f, "{s}:"),
+			Label(t,) => write!(f, "{t}:"),
+			Nullary(op,) => write!(f, "{op}"),
+			Unary { op, operand, } => write!(f, "{op} {operand}"),
+			Binary { op, source, target, } => {
+				write!(f, "{op} {source}, {target}")
+			},
+			Set { cond, target, } => {
+				write!(f, "set{cond} {}", Operand::Gpr(*target, 1,))
+			},
+			Jmp(target,) => write!(f, "jmp {target}"),
+			Jcc { cond, target, } => write!(f, "j{cond} {target}"),
+			Call(s,) => write!(f, "call {s}"),
+			Ret => write!(f, "ret"),
+			Syscall => write!(f, "syscall"),
+		}
+	}
+}
+
+/// x86_64 Linux with the System V ABI
+pub struct X86_64 {
+	pub triple: Triple,
+}
+
+impl target::Target for X86_64 {
+	fn triple(&self,) -> &Triple {
+		&self.triple
+	}
+
+	fn registers(&self,) -> RegisterFile {
+		RegisterFile {
+			general:       (0..16)
+				.filter_map(|n| Gpr::try_from(n,).ok(),)
+				.map(|r| r.name(8,),)
+				.collect(),
+			float:         (0..16).map(|n| format!("xmm{n}"),).collect(),
+			stack_pointer: Gpr::Rsp.name(8,),
+			frame_pointer: Gpr::Rbp.name(8,),
+		}
+	}
+
+	fn calling_convention(&self,) -> CallingConvention {
+		use Gpr::*;
+		CallingConvention {
+			int_args:     INT_ARGS.iter().map(|r| r.name(8,),).collect(),
+			float_args:   (0..FLOAT_ARGS).map(|n| format!("xmm{n}"),).collect(),
+			int_ret:      Rax.name(8,),
+			float_ret:    "xmm0".to_string(),
+			callee_saved: [Rbx, Rbp, R12, R13, R14, R15,]
+				.iter()
+				.map(|r| r.name(8,),)
+				.collect(),
+			temporaries:  [R10, R11,].iter().map(|r| r.name(8,),).collect(),
+		}
+	}
+
+	fn syscall(&self, call: Syscall,) -> u16 {
+		call.x86_64()
+	}
+
+	fn print(&self, module: &Module,) -> LlccB<String,> {
+		let inst_list = select_module(module, self,)?;
+		X(inst_list.into_iter().map(|inst| format!("{inst}\n"),).collect(),)
+	}
+}
+
+fn select_module<'m,>(
+	module: &'m Module,
+	target: &dyn target::Target,
+) -> LlccB<Vec<Instruction<'m,>,>,> {
+	let mut inst_list = vec![Instruction::Section(SectionKind::Text,)];
+	for func in module.functions.iter() {
+		inst_list.extend(select_function(func, target,)?,);
+	}
+	X(inst_list,)
+}
+
+fn cond(op: CmpOp,) -> Cond {
+	match op {
+		CmpOp::Eq | CmpOp::FEq => Cond::E,
+		CmpOp::Ne | CmpOp::FNe => Cond::Ne,
+		CmpOp::SLt => Cond::L,
+		CmpOp::SLe => Cond::Le,
+		CmpOp::SGt => Cond::G,
+		CmpOp::SGe => Cond::Ge,
+		CmpOp::ULt => Cond::B,
+		CmpOp::ULe => Cond::Be,
+		CmpOp::UGt => Cond::A,
+		CmpOp::UGe => Cond::Ae,
+		// `ucomis` sets `cf` for unordered operands as well, so `lhs < rhs`
+		// is tested as `rhs > lhs` with operands swapped
+		CmpOp::FLt | CmpOp::FGt => Cond::A,
+		CmpOp::FLe | CmpOp::FGe => Cond::Ae,
+	}
+}
+
+struct Selector<'f,> {
+	func:        &'f Function,
+	/// number of the exit system call, which ends `_start`
+	exit:        i64,
+	stack_align: i64,
+	insts:       Vec<Instruction<'f,>,>,
+}
+
+impl<'f,> Selector<'f,> {
+	fn ty(&self, v: Value,) -> Type {
+		self.func.ty(v,)
+	}
+
+	fn home(&self, v: Value,) -> Operand {
+		Operand::Mem { base: Gpr::Rbp, disp: -8 * (v.0 as i32 + 1), }
+	}
+
+	/// slots live below the homes of the values
+	fn slot(&self, s: Slot,) -> Operand {
+		let index = self.func.value_types.len() + s.index();
+		Operand::Mem { base: Gpr::Rbp, disp: -8 * (index as i32 + 1), }
+	}
+
+	/// bytes below `%rbp`, keeping `%rsp` aligned
+	fn frame_size(&self,) -> i64 {
+		let homes = self.func.value_types.len() + self.func.slots.len();
+		(homes as i64 * 8 + self.stack_align - 1) & -self.stack_align
+	}
+
+	fn label(&self, b: Block,) -> Target<'f,> {
+		Target::Block { func: &self.func.name, block: b.0, }
+	}
+
+	fn binary(&mut self, op: Opcode, source: Operand, target: Operand,) {
+		self.insts.push(Instruction::Binary { op, source, target, },);
+	}
+
+	fn unary(&mut self, op: Opcode, operand: Operand,) {
+		self.insts.push(Instruction::Unary { op, operand, },);
+	}
+
+	fn movq(&mut self, source: Operand, target: Operand,) {
+		self.binary(Opcode::Movq, source, target,);
+	}
+
+	/// loads a float of type `ty` into `%xmm{n}`
+	fn load_float(&mut self, source: Operand, ty: Type, n: u8,) {
+		let op = if ty == Type::F32 { Opcode::Movss } else { Opcode::Movsd };
+		self.binary(op, source, Operand::Xmm(n,),);
+	}
+
+	fn store_float(&mut self, n: u8, ty: Type, target: Operand,) {
+		let op = if ty == Type::F32 { Opcode::Movss } else { Opcode::Movsd };
+		self.binary(op, Operand::Xmm(n,), target,);
+	}
+
+	/// sign extends `%rax` from the width of `ty`
+	fn normalize(&mut self, ty: Type,) {
+		let (op, bytes,) = match ty {
+			Type::I8 => (Opcode::Movsbq, 1,),
+			Type::I16 => (Opcode::Movswq, 2,),
+			Type::I32 => (Opcode::Movslq, 4,),
+			_ => return,
+		};
+		self.binary(op, Operand::Gpr(Gpr::Rax, bytes,), Operand::q(Gpr::Rax,),);
+	}
+
+	/// zero extends `reg` from the width of `ty`
+	fn zext(&mut self, reg: Gpr, ty: Type,) {
+		match ty {
+			Type::I8 => self.binary(
+				Opcode::Movzbq,
+				Operand::Gpr(reg, 1,),
+				Operand::q(reg,),
+			),
+			Type::I16 => self.binary(
+				Opcode::Movzwq,
+				Operand::Gpr(reg, 2,),
+				Operand::q(reg,),
+			),
+			// writing a 32 bit register clears the upper half
+			Type::I32 | Type::F32 => self.binary(
+				Opcode::Movl,
+				Operand::Gpr(reg, 4,),
+				Operand::Gpr(reg, 4,),
+			),
+			_ => (),
+		}
+	}
+
+	/// loads a constant into `reg`
+	fn constant(&mut self, c: i64, reg: Gpr,) {
+		let op = if i32::try_from(c,).is_ok() {
+			Opcode::Movq
+		} else {
+			Opcode::Movabsq
+		};
+		self.binary(op, Operand::Imm(c,), Operand::q(reg,),);
+	}
+
+	/// `source` of type `ty` loaded into `%rax` like a value of its home
+	fn load(&mut self, source: Operand, ty: Type,) {
+		let (op, target,) = match ty {
+			Type::I8 => (Opcode::Movsbq, Operand::q(Gpr::Rax,),),
+			Type::I16 => (Opcode::Movswq, Operand::q(Gpr::Rax,),),
+			Type::I32 => (Opcode::Movslq, Operand::q(Gpr::Rax,),),
+			Type::F32 => (Opcode::Movl, Operand::Gpr(Gpr::Rax, 4,),),
+			Type::I64 | Type::F64 => (Opcode::Movq, Operand::q(Gpr::Rax,),),
+		};
+		self.binary(op, source, target,);
+	}
+
+	/// stores the low bytes of `%rax` holding a value of type `ty`
+	fn store(&mut self, ty: Type, target: Operand,) {
+		let op = match ty.bytes() {
+			1 => Opcode::Movb,
+			2 => Opcode::Movw,
+			4 => Opcode::Movl,
+			_ => Opcode::Movq,
+		};
+		self.binary(op, Operand::Gpr(Gpr::Rax, ty.bytes() as u8,), target,);
+	}
+
+	fn int_binary(&mut self, op: BinOp, ty: Type,) {
+		let (rax, rcx,) = (Operand::q(Gpr::Rax,), Operand::q(Gpr::Rcx,),);
+		match op {
+			BinOp::Add
+			| BinOp::Sub
+			| BinOp::Mul
+			| BinOp::And
+			| BinOp::Or
+			| BinOp::Xor => {
+				let op = match op {
+					BinOp::Add => Opcode::Addq,
+					BinOp::Sub => Opcode::Subq,
+					BinOp::Mul => Opcode::Imulq,
+					BinOp::And => Opcode::Andq,
+					BinOp::Or => Opcode::Orq,
+					_ => Opcode::Xorq,
+				};
+				self.binary(op, rcx, rax,);
+			},
+			BinOp::SDiv | BinOp::SRem => {
+				self.insts.push(Instruction::Nullary(Opcode::Cqto,),);
+				self.unary(Opcode::Idivq, rcx,);
+				if op == BinOp::SRem {
+					self.movq(Operand::q(Gpr::Rdx,), rax,);
+				}
+			},
+			BinOp::UDiv | BinOp::URem => {
+				self.zext(Gpr::Rax, ty,);
+				self.zext(Gpr::Rcx, ty,);
+				let rdx = Operand::q(Gpr::Rdx,);
+				self.binary(Opcode::Xorq, rdx, rdx,);
+				self.unary(Opcode::Divq, rcx,);
+				if op == BinOp::URem {
+					self.movq(rdx, rax,);
+				}
+			},
+			BinOp::Shl | BinOp::LShr | BinOp::AShr => {
+				let op = match op {
+					BinOp::Shl => Opcode::Shlq,
+					BinOp::LShr => {
+						self.zext(Gpr::Rax, ty,);
+						Opcode::Shrq
+					},
+					_ => Opcode::Sarq,
+				};
+				self.binary(op, Operand::Gpr(Gpr::Rcx, 1,), rax,);
+			},
+			BinOp::FAdd | BinOp::FSub | BinOp::FMul | BinOp::FDiv => {
+				unreachable!("float operations are selected by `float_binary`")
+			},
+		}
+		self.normalize(ty,);
+	}
+
+	fn float_binary(&mut self, op: BinOp, ty: Type,) {
+		let single = ty == Type::F32;
+		let op = match (op, single,) {
+			(BinOp::FAdd, true,) => Opcode::Addss,
+			(BinOp::FAdd, false,) => Opcode::Addsd,
+			(BinOp::FSub, true,) => Opcode::Subss,
+			(BinOp::FSub, false,) => Opcode::Subsd,
+			(BinOp::FMul, true,) => Opcode::Mulss,
+			(BinOp::FMul, false,) => Opcode::Mulsd,
+			(_, true,) => Opcode::Divss,
+			(_, false,) => Opcode::Divsd,
+		};
+		self.binary(op, Operand::Xmm(1,), Operand::Xmm(0,),);
+	}
+
+	/// compares `lhs` and `rhs` and leaves 0 or 1 in `%rax`
+	fn cmp(&mut self, op: CmpOp, lhs: Value, rhs: Value,) {
+		let ty = self.ty(lhs,);
+		if !ty.is_float() {
+			self.movq(self.home(lhs,), Operand::q(Gpr::Rax,),);
+			self.movq(self.home(rhs,), Operand::q(Gpr::Rcx,),);
+			self.binary(
+				Opcode::Cmpq,
+				Operand::q(Gpr::Rcx,),
+				Operand::q(Gpr::Rax,),
+			);
+			self.insts
+				.push(Instruction::Set { cond: cond(op,), target: Gpr::Rax, },);
+		} else {
+			self.load_float(self.home(lhs,), ty, 0,);
+			self.load_float(self.home(rhs,), ty, 1,);
+			let ucomis =
+				if ty == Type::F32 { Opcode::Ucomiss } else { Opcode::Ucomisd };
+			let (first, second,) = match op {
+				CmpOp::FLt | CmpOp::FLe => (0, 1,),
+				_ => (1, 0,),
+			};
+			self.binary(ucomis, Operand::Xmm(first,), Operand::Xmm(second,),);
+			self.insts
+				.push(Instruction::Set { cond: cond(op,), target: Gpr::Rax, },);
+			// `zf` is set for unordered operands as well, `pf` tells them
+			let parity = match op {
+				CmpOp::FEq => Some((Cond::Np, Opcode::Andb,),),
+				CmpOp::FNe => Some((Cond::P, Opcode::Orb,),),
+				_ => None,
+			};
+			if let Some((cond, op,),) = parity {
+				self.insts.push(Instruction::Set { cond, target: Gpr::Rcx, },);
+				self.binary(
+					op,
+					Operand::Gpr(Gpr::Rcx, 1,),
+					Operand::Gpr(Gpr::Rax, 1,),
+				);
+			}
+		}
+		self.binary(
+			Opcode::Movzbq,
+			Operand::Gpr(Gpr::Rax, 1,),
+			Operand::q(Gpr::Rax,),
+		);
+	}
+
+	/// converts `source` and leaves the result in the home of `dest`
+	fn cast(&mut self, op: CastOp, dest: Value, source: Value,) {
+		let (from, to,) = (self.ty(source,), self.ty(dest,),);
+		let rax = Operand::q(Gpr::Rax,);
+		let single = |ty: Type| ty == Type::F32;
+		match op {
+			CastOp::SExt | CastOp::Trunc => {
+				self.movq(self.home(source,), rax,);
+				self.normalize(to,);
+				self.movq(rax, self.home(dest,),);
+			},
+			CastOp::ZExt => {
+				self.movq(self.home(source,), rax,);
+				self.zext(Gpr::Rax, from,);
+				self.normalize(to,);
+				self.movq(rax, self.home(dest,),);
+			},
+			CastOp::SIToFP => {
+				let op = if single(to,) {
+					Opcode::Cvtsi2ssq
+				} else {
+					Opcode::Cvtsi2sdq
+				};
+				self.movq(self.home(source,), rax,);
+				self.binary(op, rax, Operand::Xmm(0,),);
+				self.store_float(0, to, self.home(dest,),);
+			},
+			CastOp::UIToFP if from == Type::I64 => {
+				// both halves convert exactly, so only the sum rounds
+				let rcx = Operand::q(Gpr::Rcx,);
+				self.movq(self.home(source,), rax,);
+				self.movq(rax, rcx,);
+				self.binary(Opcode::Shrq, Operand::Imm(32,), rcx,);
+				self.zext(Gpr::Rax, Type::I32,);
+				self.binary(Opcode::Cvtsi2sdq, rcx, Operand::Xmm(1,),);
+				self.constant(
+					Type::F64.float_bits((1u64 << 32) as f64,),
+					Gpr::Rcx,
+				);
+				self.movq(rcx, Operand::Xmm(2,),);
+				self.binary(Opcode::Mulsd, Operand::Xmm(2,), Operand::Xmm(1,),);
+				self.binary(Opcode::Cvtsi2sdq, rax, Operand::Xmm(0,),);
+				self.binary(Opcode::Addsd, Operand::Xmm(1,), Operand::Xmm(0,),);
+				if single(to,) {
+					self.binary(
+						Opcode::Cvtsd2ss,
+						Operand::Xmm(0,),
+						Operand::Xmm(0,),
+					);
+				}
+				self.store_float(0, to, self.home(dest,),);
+			},
+			CastOp::UIToFP => {
+				let op = if single(to,) {
+					Opcode::Cvtsi2ssq
+				} else {
+					Opcode::Cvtsi2sdq
+				};
+				self.movq(self.home(source,), rax,);
+				self.zext(Gpr::Rax, from,);
+				self.binary(op, rax, Operand::Xmm(0,),);
+				self.store_float(0, to, self.home(dest,),);
+			},
+			CastOp::FPToSI | CastOp::FPToUI => {
+				let convert = if single(from,) {
+					Opcode::Cvttss2siq
+				} else {
+					Opcode::Cvttsd2siq
+				};
+				self.load_float(self.home(source,), from, 0,);
+				self.binary(convert, Operand::Xmm(0,), rax,);
+				if op == CastOp::FPToUI && to == Type::I64 {
+					// values from 2^63 on are converted less 2^63
+					let (sub, ucomis,) = if single(from,) {
+						(Opcode::Subss, Opcode::Ucomiss,)
+					} else {
+						(Opcode::Subsd, Opcode::Ucomisd,)
+					};
+					let rcx = Operand::q(Gpr::Rcx,);
+					self.constant(from.float_bits(2f64.powi(63,),), Gpr::Rcx,);
+					self.movq(rcx, Operand::Xmm(1,),);
+					self.binary(sub, Operand::Xmm(1,), Operand::Xmm(0,),);
+					self.binary(convert, Operand::Xmm(0,), rcx,);
+					self.binary(Opcode::Btcq, Operand::Imm(63,), rcx,);
+					self.load_float(self.home(source,), from, 0,);
+					self.binary(ucomis, Operand::Xmm(1,), Operand::Xmm(0,),);
+					self.binary(Opcode::Cmovaeq, rcx, rax,);
+				}
+				self.normalize(to,);
+				self.movq(rax, self.home(dest,),);
+			},
+			CastOp::FPExt | CastOp::FPTrunc => {
+				let op = if single(from,) {
+					Opcode::Cvtss2sd
+				} else {
+					Opcode::Cvtsd2ss
+				};
+				self.load_float(self.home(source,), from, 0,);
+				self.binary(op, Operand::Xmm(0,), Operand::Xmm(0,),);
+				self.store_float(0, to, self.home(dest,),);
+			},
+		}
+	}
+
+	/// moves `args` into the argument registers
+	fn args(&mut self, args: &[Value],) -> LlccB<(),> {
+		let (mut ints, mut floats,) = (0, 0,);
+		for a in args {
+			let ty = self.ty(*a,);
+			if ty.is_float() {
+				if floats == FLOAT_ARGS {
+					return Y(LlccError::unsupported(
+						"arguments passed on the stack",
+					),);
+				}
+				self.load_float(self.home(*a,), ty, floats,);
+				floats += 1;
+			} else {
+				let Some(reg,) = INT_ARGS.get(ints,) else {
+					return Y(LlccError::unsupported(
+						"arguments passed on the stack",
+					),);
+				};
+				self.movq(self.home(*a,), Operand::q(*reg,),);
+				ints += 1;
+			}
+		}
+		X((),)
+	}
+
+	fn inst(&mut self, inst: &'f Inst,) -> LlccB<(),> {
+		let rax = Operand::q(Gpr::Rax,);
+		let rcx = Operand::q(Gpr::Rcx,);
+		match (&inst.kind, inst.dest,) {
+			(InstKind::Call { callee, args, .. }, dest,) => {
+				self.args(args,)?;
+				self.insts.push(Instruction::Call(callee,),);
+				if let Some(dest,) = dest {
+					let ty = self.ty(dest,);
+					if ty.is_float() {
+						self.store_float(0, ty, self.home(dest,),);
+					} else {
+						// the upper bits of narrow results are undefined
+						self.normalize(ty,);
+						self.movq(rax, self.home(dest,),);
+					}
+				}
+			},
+			(InstKind::Store { addr, value, }, _,) => {
+				self.movq(self.home(*addr,), rcx,);
+				self.movq(self.home(*value,), rax,);
+				self.store(
+					self.ty(*value,),
+					Operand::Mem { base: Gpr::Rcx, disp: 0, },
+				);
+			},
+			(InstKind::StackStore(slot, value,), _,) => {
+				self.movq(self.home(*value,), rax,);
+				self.store(self.ty(*value,), self.slot(*slot,),);
+			},
+			(_, None,) => (),
+			(kind, Some(dest,),) => {
+				let ty = self.ty(dest,);
+				match kind {
+					InstKind::Const(c,) => {
+						self.constant(normalize(ty, *c,), Gpr::Rax,);
+					},
+					InstKind::Copy(v,) => self.movq(self.home(*v,), rax,),
+					InstKind::Binary(op, lhs, rhs,) if ty.is_float() => {
+						self.load_float(self.home(*lhs,), ty, 0,);
+						self.load_float(self.home(*rhs,), ty, 1,);
+						self.float_binary(*op, ty,);
+						self.store_float(0, ty, self.home(dest,),);
+						return X((),);
+					},
+					InstKind::Binary(op, lhs, rhs,) => {
+						self.movq(self.home(*lhs,), rax,);
+						self.movq(self.home(*rhs,), rcx,);
+						self.int_binary(*op, ty,);
+					},
+					InstKind::Unary(op, v,) => {
+						self.movq(self.home(*v,), rax,);
+						match op {
+							UnOp::Neg => self.unary(Opcode::Negq, rax,),
+							UnOp::Not => self.unary(Opcode::Notq, rax,),
+							UnOp::FNeg => {
+								let sign = ty.bits() as i64 - 1;
+								self.binary(
+									Opcode::Btcq,
+									Operand::Imm(sign,),
+									rax,
+								);
+							},
+						}
+						if !ty.is_float() {
+							self.normalize(ty,);
+						}
+					},
+					InstKind::Cmp(op, lhs, rhs,) => self.cmp(*op, *lhs, *rhs,),
+					InstKind::Cast(op, v,) => {
+						self.cast(*op, dest, *v,);
+						return X((),);
+					},
+					InstKind::StackLoad(slot,) => {
+						self.load(self.slot(*slot,), ty,)
+					},
+					InstKind::StackAddr(slot,) => {
+						self.binary(Opcode::Leaq, self.slot(*slot,), rax,)
+					},
+					InstKind::Load(addr,) => {
+						self.movq(self.home(*addr,), rcx,);
+						self.load(
+							Operand::Mem { base: Gpr::Rcx, disp: 0, },
+							ty,
+						);
+					},
+					kind => {
+						return Y(LlccError::unsupported(format!(
+							"`{kind}` in x86_64 backend"
+						),),);
+					},
+				}
+				self.movq(rax, self.home(dest,),);
+			},
+		}
+		X((),)
+	}
+
+	fn term(&mut self, term: &Terminator, next: Option<Block,>,) -> LlccB<(),> {
+		let rax = Operand::q(Gpr::Rax,);
+		match *term {
+			Terminator::Jump(to,) => {
+				if next != Some(to,) {
+					self.insts.push(Instruction::Jmp(self.label(to,),),);
+				}
+			},
+			Terminator::Branch { cond: c, then, els, } => {
+				self.binary(Opcode::Cmpq, Operand::Imm(0,), self.home(c,),);
+				let (taken, cond, fallthrough,) = if next == Some(then,) {
+					(els, Cond::E, then,)
+				} else {
+					(then, Cond::Ne, els,)
+				};
+				self.insts.push(Instruction::Jcc {
+					cond,
+					target: self.label(taken,),
+				},);
+				if next != Some(fallthrough,) {
+					self.insts
+						.push(Instruction::Jmp(self.label(fallthrough,),),);
+				}
+			},
+			Terminator::Return(v,) => {
+				match v {
+					Some(v,) if self.ty(v,).is_float() => {
+						self.load_float(self.home(v,), self.ty(v,), 0,)
+					},
+					Some(v,) => self.movq(self.home(v,), rax,),
+					None => self.binary(Opcode::Xorq, rax, rax,),
+				}
+				if self.func.name == lower::ENTRY {
+					// `_start` has nowhere to return to
+					self.movq(rax, Operand::q(Gpr::Rdi,),);
+					self.constant(self.exit, Gpr::Rax,);
+					self.insts.push(Instruction::Syscall,);
+				} else {
+					self.insts.push(Instruction::Nullary(Opcode::Leave,),);
+					self.insts.push(Instruction::Ret,);
+				}
+			},
+			Terminator::Unreachable => (),
+		}
+		X((),)
+	}
+}
+
+fn select_function<'f,>(
+	func: &'f Function,
+	target: &dyn target::Target,
+) -> LlccB<Vec<Instruction<'f,>,>,> {
+	let cfg = Cfg::new(func,);
+	let layout = cfg.rpo();
+	let mut s = Selector {
+		func,
+		exit: target.syscall(Syscall::Exit,) as i64,
+		stack_align: target.data_layout().stack_align as i64,
+		insts: vec![],
+	};
+
+	if func.linkage == Linkage::External {
+		s.insts.push(Instruction::Global(&func.name,),);
+	}
+	s.insts.push(Instruction::Symbol(&func.name,),);
+	let (rsp, rbp,) = (Operand::q(Gpr::Rsp,), Operand::q(Gpr::Rbp,),);
+	s.unary(Opcode::Pushq, rbp,);
+	s.movq(rsp, rbp,);
+	s.binary(Opcode::Subq, Operand::Imm(s.frame_size(),), rsp,);
+	if func.name == lower::ENTRY {
+		// the kernel enters `_start` without a return address on the stack
+		s.binary(Opcode::Andq, Operand::Imm(-s.stack_align,), rsp,);
+	}
+
+	let (mut ints, mut floats,) = (0, 0,);
+	for p in func.params.iter() {
+		let ty = s.ty(*p,);
+		if ty.is_float() {
+			if floats == FLOAT_ARGS {
+				return Y(LlccError::unsupported(
+					"parameters passed on the stack",
+				),);
+			}
+			s.store_float(floats, ty, s.home(*p,),);
+			floats += 1;
+		} else {
+			let Some(reg,) = INT_ARGS.get(ints,) else {
+				return Y(LlccError::unsupported(
+					"parameters passed on the stack",
+				),);
+			};
+			s.movq(Operand::q(*reg,), Operand::q(Gpr::Rax,),);
+			s.normalize(ty,);
+			s.movq(Operand::q(Gpr::Rax,), s.home(*p,),);
+			ints += 1;
+		}
+	}
+
+	for (n, b,) in layout.iter().enumerate() {
+		let block = func.block(*b,);
+		if *b != Block::ENTRY {
+			s.insts.push(Instruction::Label(s.label(*b,),),);
+		}
+		for inst in block.insts.iter() {
+			s.inst(inst,)?;
+		}
+		s.term(&block.term, layout.get(n + 1,).copied(),)?;
+	}
+	X(s.insts,)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::asm::asm_str;
+	use crate::err::Container;
+	use crate::ir::FunctionBuilder;
+	use crate::opt::OptLevel;
+	use quickcheck_macros::quickcheck;
+	use std::process::Command;
+
+	fn lines(func: &Function,) -> Vec<String,> {
+		select_function(
+			func,
+			&X86_64 { triple: "x86_64-linux-gnu".parse().unwrap(), },
+		)
+		.unwrap()
+		.into_iter()
+		.map(|i| i.to_string(),)
+		.collect()
+	}
+
+	#[test]
+	fn register_names() {
+		let names: Vec<String,> = [1, 2, 4, 8,]
+			.into_iter()
+			.flat_map(|bytes| {
+				[0, 6, 9,].map(|n| {
+					Operand::Gpr(Gpr::try_from(n,).unwrap(), bytes,).to_string()
+				},)
+			},)
+			.collect();
+		assert_eq!(
+			names,
+			[
+				"%al", "%sil", "%r9b", "%ax", "%si", "%r9w", "%eax", "%esi",
+				"%r9d", "%rax", "%rsi", "%r9",
+			]
+		);
+		let home = Operand::Mem { base: Gpr::Rbp, disp: -8, };
+		assert_eq!(home.to_string(), "-8(%rbp)");
+		assert!(Gpr::try_from(16,).is_err());
+	}
+
+	#[test]
+	fn calls_follow_system_v() {
+		// int f(int x, double y) { return g(y, x) < 3; }
+		let mut fb = FunctionBuilder::new(
+			"f",
+			&[Type::I32, Type::F64,],
+			Some(Type::I32,),
+		);
+		let (x, y,) = (fb.param(0,), fb.param(1,),);
+		let r = fb.call("g", &[y, x,], Some(Type::I32,),);
+		let three = fb.iconst(Type::I32, 3,);
+		let c = fb.cmp(CmpOp::SLt, r, three,);
+		fb.ret(Some(c,),);
+		let asm = lines(&fb.finish(),);
+		let has = |line: &str| asm.contains(&line.to_string(),);
+		assert!(has("pushq %rbp",), "{asm:?}");
+		assert!(has("movq %rdi, %rax",), "{asm:?}");
+		assert!(has("movsd %xmm0, -16(%rbp)",), "{asm:?}");
+		assert!(has("movsd -16(%rbp), %xmm0",), "{asm:?}");
+		assert!(has("movq -8(%rbp), %rdi",), "{asm:?}");
+		assert!(has("call g",), "{asm:?}");
+		assert!(has("movslq %eax, %rax",), "{asm:?}");
+		assert!(has("setl %al",), "{asm:?}");
+		assert_eq!(asm[asm.len() - 2..], ["leave", "ret"]);
+	}
+
+	/// compiles `src` for the host and returns the exit status
+	fn run_native(src: &str,) -> Option<i32,> {
+		let dir = std::env::temp_dir()
+			.join(format!("llcc-x86_64-{}", uuid::Uuid::new_v4()),);
+		std::fs::create_dir_all(&dir,).unwrap();
+		let (asm, obj, exe,) =
+			(dir.join("out.s",), dir.join("out.o",), dir.join("out",),);
+		let target = X86_64 { triple: "x86_64-linux-gnu".parse().unwrap(), };
+		let text: String = asm_str(src, OptLevel::O0, &target,).unwrap().into();
+		std::fs::write(&asm, text,).unwrap();
+		let tool = |cmd: &str, args: &[&std::path::Path]| {
+			Command::new(cmd,).args(args,).status().is_ok_and(|s| s.success(),)
+		};
+		let code = (tool("as", &[std::path::Path::new("-o",), &obj, &asm,],)
+			&& tool("ld", &[std::path::Path::new("-o",), &exe, &obj,],))
+		.then(|| Command::new(&exe,).status().unwrap().code(),)
+		.flatten();
+		std::fs::remove_dir_all(&dir,).unwrap();
+		code
+	}
+
+	#[quickcheck]
+	fn native_runs_match(a: u8, b: u8,) -> bool {
+		if !cfg!(target_arch = "x86_64") {
+			return true;
+		}
+		let src = format!("{a}+{b}-2.5f+0.5");
+		let expected = (a as i32 + b as i32 - 2) as u8 as i32;
+		run_native(&src,).is_none_or(|code| code == expected,)
+	}
+}
@@ -7,6 +7,7 @@ use std::fmt::Display;
 use std::io;
 use std::ops::ControlFlow;
 use std::ops::FromResidual;
+use std::ops::Residual;
 use std::ops::Try;
 use std::panic::Location;
 use std::process::Termination;
@@ -41,6 +42,10 @@ impl<S, T: From<E,>, E: std::error::Error,> FromResidual<Result<Infallible, E,>,
 	}
 }
 
+impl<S, T,> Residual<S,> for B<Infallible, T,> {
+	type TryType = B<S, T,>;
+}
+
 impl<S, T,> Try for B<S, T,> {
 	type Output = S;
 	type Residual = B<Infallible, T,>;
@@ -150,12 +155,16 @@ pub enum LlccError {
 		source: std::num::ParseIntError,
 		loc:    &'static Location<'static,>,
 	},
+	ParseSrcFloat {
+		source: std::num::ParseFloatError,
+		loc:    &'static Location<'static,>,
+	},
 	Parse {
 		source: strum::ParseError,
 		loc:    &'static Location<'static,>,
 	},
 	MismatchImmediateType {
-		origin:    i32,
+		origin:    i64,
 		max_bit:   u8,
 		is_signed: bool,
 		loc:       &'static Location<'static,>,
@@ -165,6 +174,23 @@ pub enum LlccError {
 		type_name:    &'static str,
 		loc:          &'static Location<'static,>,
 	},
+	Unsupported {
+		what: String,
+		loc:  &'static Location<'static,>,
+	},
+	Syntax {
+		msg: String,
+		loc: &'static Location<'static,>,
+	},
+	Link {
+		msg: String,
+		loc: &'static Location<'static,>,
+	},
+	/// an external program is missing
+	Toolchain {
+		msg: String,
+		loc: &'static Location<'static,>,
+	},
 	Unknown {
 		msg: String,
 		loc: &'static Location<'static,>,
@@ -173,7 +199,7 @@ pub enum LlccError {
 
 impl LlccError {
 	#[track_caller]
-	pub fn mismatch_imm(origin: i32, max_bit: u8, is_signed: bool,) -> Self {
+	pub fn mismatch_imm(origin: i64, max_bit: u8, is_signed: bool,) -> Self {
 		LlccError::MismatchImmediateType {
 			origin,
 			max_bit,
@@ -190,6 +216,31 @@ impl LlccError {
 			loc:          Location::caller(),
 		}
 	}
+
+	#[track_caller]
+	pub fn unsupported(what: impl Into<String,>,) -> Self {
+		LlccError::Unsupported { what: what.into(), loc: Location::caller(), }
+	}
+
+	#[track_caller]
+	pub fn syntax(msg: impl Into<String,>,) -> Self {
+		LlccError::Syntax { msg: msg.into(), loc: Location::caller(), }
+	}
+
+	#[track_caller]
+	pub fn link(msg: impl Into<String,>,) -> Self {
+		LlccError::Link { msg: msg.into(), loc: Location::caller(), }
+	}
+
+	#[track_caller]
+	pub fn toolchain(msg: impl Into<String,>,) -> Self {
+		LlccError::Toolchain { msg: msg.into(), loc: Location::caller(), }
+	}
+
+	#[track_caller]
+	pub fn unknown(msg: impl Into<String,>,) -> Self {
+		LlccError::Unknown { msg: msg.into(), loc: Location::caller(), }
+	}
 }
 
 impl Display for LlccError {
@@ -201,6 +252,9 @@ impl Display for LlccError {
 			Self::ParseSrcInt { source, loc, } => {
 				f.write_fmt(format_args!("{source} at: [{loc}]"),)
 			},
+			Self::ParseSrcFloat { source, loc, } => {
+				f.write_fmt(format_args!("{source} at: [{loc}]"),)
+			},
 			Self::Parse { source, loc, } => {
 				f.write_fmt(format_args!("{source} at: [{loc}]",),)
 			},
@@ -215,6 +269,17 @@ impl Display for LlccError {
 					"context: `{type_name}` for {context_role} should take \
 					 enough info. at: [{loc}]"
 				),),
+			Self::Unsupported { what, loc, } => f.write_fmt(format_args!(
+				"{what} is not supported yet. at: [{loc}]"
+			),),
+			Self::Syntax { msg, loc, } => {
+				f.write_fmt(format_args!("syntax error: {msg}. at: [{loc}]"),)
+			},
+			Self::Link { msg, loc, } => {
+				f.write_fmt(format_args!("link error: {msg}. at: [{loc}]"),)
+			},
+			Self::Toolchain { msg, loc, } => f
+				.write_fmt(format_args!("toolchain error: {msg}. at: [{loc}]"),),
 			Self::Unknown { msg, loc, } => {
 				f.write_fmt(format_args!("{msg} at: [{loc}]"),)
 			},
@@ -238,6 +303,13 @@ impl From<std::num::ParseIntError,> for LlccError {
 	}
 }
 
+impl From<std::num::ParseFloatError,> for LlccError {
+	#[track_caller]
+	fn from(value: std::num::ParseFloatError,) -> Self {
+		LlccError::ParseSrcFloat { source: value, loc: Location::caller(), }
+	}
+}
+
 impl From<strum::ParseError,> for LlccError {
 	#[track_caller]
 	fn from(value: strum::ParseError,) -> Self {
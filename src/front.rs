@@ -1,28 +1,196 @@
 use crate::LlccB;
+use crate::asm::Arch;
 use crate::asm::asm_str;
+use crate::asm::assemble_str;
+use crate::asm::emulate as run_emulated;
+use crate::asm::function_object_of;
+use crate::asm::objdump as dump;
+use crate::asm::object_of;
 use crate::asm::run_cmd;
+use crate::asm::wat_str;
 use crate::asm::write_asm;
 use crate::err::B::X;
+use crate::err::B::Y;
+use crate::err::LlccError;
 use crate::err::ReShape;
-use crate::semantics::Convert;
-use crate::semantics::context::HasIn;
-use crate::semantics::context::HasOut;
-use crate::semantics::purpose::Layer;
-// use crate::file_io::Dest;
-// use crate::file_io::DestKind;
+use crate::ir::llvm::llvm_str;
+use crate::ir::lower;
+use crate::obj::Object;
+use crate::obj::ar;
+use crate::obj::ar::Archive;
+use crate::obj::ar::Member;
+use crate::obj::elf;
+use crate::obj::link;
+use crate::opt::OptLevel;
+use crate::orchestrator::file_manage::Dest;
+use crate::orchestrator::file_manage::DestKind;
 use crate::stringify_path;
+use crate::target::Triple;
+use crate::toolchain::Need;
+use crate::toolchain::Runner;
+use crate::toolchain::Toolchain;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt as _;
 use std::path::PathBuf;
 use std::process::ExitStatus;
 
+/// what `--emit` asks for. anything but `Exe` stops before linking
+#[derive(
+	Debug,
+	Default,
+	Clone,
+	Copy,
+	PartialEq,
+	Eq,
+	strum::Display,
+	strum::EnumString,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum Emit {
+	#[default]
+	Exe,
+	Asm,
+	/// a relocatable object, like `-c`
+	Obj,
+	/// a static library holding an object for each source
+	Lib,
+	Llvm,
+	Wat,
+}
+
 #[derive(Default,)]
-pub struct LlccCompiler {}
+pub struct LlccCompiler {
+	opt_level: OptLevel,
+	target:    Triple,
+	emit:      Emit,
+	/// C sources given on the command line
+	srcs:      Vec<PathBuf,>,
+	/// objects and archives linked into the executable
+	inputs:    Vec<PathBuf,>,
+	toolchain: Toolchain,
+	/// where the outputs are written
+	dest:      Dest,
+}
 
 impl LlccCompiler {
+	/// reads `-O<level>`, `--target <triple>` and `--emit <kind>`. options
+	/// with a value may also be written as `--option=<value>`. paths ending
+	/// in `.o` or `.a` are linked into the executable, other paths are
+	/// sources. the toolchain is discovered for the target from the
+	/// environment
+	pub fn from_args(args: impl IntoIterator<Item = String,>,) -> LlccB<Self,> {
+		let mut compiler = Self::default();
+		let mut args = args.into_iter();
+		while let Some(arg,) = args.next() {
+			if arg.starts_with("-O",) {
+				compiler.opt_level = arg.parse()?;
+			} else if let Some(target,) = arg.strip_prefix("--target=",) {
+				compiler.target = target.parse()?;
+			} else if arg == "--target" {
+				let Some(target,) = args.next() else {
+					return Y(LlccError::syntax("`--target` needs a value",),);
+				};
+				compiler.target = target.parse()?;
+			} else if let Some(emit,) = arg.strip_prefix("--emit=",) {
+				compiler.emit = parse_emit(emit,)?;
+			} else if arg == "--emit" {
+				let Some(emit,) = args.next() else {
+					return Y(LlccError::syntax("`--emit` needs a value",),);
+				};
+				compiler.emit = parse_emit(&emit,)?;
+			} else if arg.starts_with('-',) {
+				return Y(
+					LlccError::syntax(format!("unknown option `{arg}`"),),
+				);
+			} else if arg.ends_with(".o",) || arg.ends_with(".a",) {
+				compiler.inputs.push(arg.into(),);
+			} else {
+				compiler.srcs.push(arg.into(),);
+			}
+		}
+		compiler.toolchain = discover(&compiler.target,);
+		X(compiler,)
+	}
+
+	pub fn srcs(&self,) -> &[PathBuf] {
+		&self.srcs
+	}
+
+	pub fn with_dest(mut self, dest: Dest,) -> Self {
+		self.dest = dest;
+		self
+	}
+
+	pub fn with_opt_level(mut self, opt_level: OptLevel,) -> Self {
+		self.opt_level = opt_level;
+		self
+	}
+
+	/// also discovers the toolchain for `target` anew
+	pub fn with_target(mut self, target: Triple,) -> Self {
+		self.toolchain = discover(&target,);
+		self.target = target;
+		self
+	}
+
+	pub fn with_toolchain(mut self, toolchain: Toolchain,) -> Self {
+		self.toolchain = toolchain;
+		self
+	}
+
+	pub fn with_emit(mut self, emit: Emit,) -> Self {
+		self.emit = emit;
+		self
+	}
+
+	/// links the object or archive at `path` into the executable
+	pub fn with_input(mut self, path: impl Into<PathBuf,>,) -> Self {
+		self.inputs.push(path.into(),);
+		self
+	}
+
+	pub fn emit(&self,) -> Emit {
+		self.emit
+	}
+
+	pub fn dest(&self,) -> &Dest {
+		&self.dest
+	}
+
+	/// path of the output `kind`, creating the output directory first
+	fn out(&self, kind: DestKind,) -> LlccB<PathBuf,> {
+		fs::create_dir_all(self.dest.path(DestKind::OutDir,).into(),)?;
+		X(self.dest.path(kind,).into(),)
+	}
+
+	/// the external programs `emit` takes. targets with an integrated
+	/// assembler link in process too, and executables are run
+	pub fn needs(&self,) -> Vec<Need,> {
+		let external = self.target.arch != Arch::Aarch64;
+		match self.emit {
+			Emit::Exe if external => {
+				vec![Need::Assembler, Need::Linker, Need::Runner]
+			},
+			Emit::Exe => vec![Need::Runner],
+			Emit::Obj if external => vec![Need::Assembler],
+			_ => vec![],
+		}
+	}
+
+	/// fails with what is missing of `needs`, before anything is compiled
+	pub fn check_toolchain(&self,) -> LlccB<(),> {
+		self.toolchain.check(&self.needs(),)
+	}
+
 	#[deprecated(note = "入力はオーケストレーション層の管理領域")]
 	pub fn src_path(&self,) -> impl Into<PathBuf,> {
 		self.dest.path(DestKind::Src,)
 	}
 
+	/// assembles and links in process if the target has an integrated
+	/// assembler, and with the external tools otherwise
+	///
 	/// # Return
 	///
 	/// returns path to generated executable file
@@ -30,9 +198,19 @@ impl LlccCompiler {
 		&self,
 		src: impl Into<String,>,
 	) -> LlccB<impl Into<PathBuf,>,> {
-		self.emit_asm(src,)?;
-		self.assemble()?;
-		self.link()?;
+		let src = src.into();
+		let target = self.target.target();
+		match object_of(src.clone(), self.opt_level, &*target,)? {
+			Some(obj,) => {
+				self.write_obj(&obj,)?;
+				self.link_objects(&[obj,],)?;
+			},
+			None => {
+				self.emit_asm(src,)?;
+				self.assemble()?;
+				self.link()?;
+			},
+		}
 		X(self.dest.path(DestKind::Exe,),)
 	}
 
@@ -43,19 +221,135 @@ impl LlccCompiler {
 		&self,
 		src: impl Into<String,>,
 	) -> LlccB<impl Into<PathBuf,>,> {
-		let asm = asm_str(src,)?;
-		write_asm(asm, self.dest.path(DestKind::Asm,),)?;
+		let target = self.target.target();
+		let asm = asm_str(src, self.opt_level, &*target,)?;
+		write_asm(asm, self.out(DestKind::Asm,)?,)?;
 		X(self.dest.path(DestKind::Asm,),)
 	}
 
+	/// # Return
+	///
+	/// returns path to generated LLVM IR file
+	pub fn emit_llvm(
+		&self,
+		src: impl Into<String,>,
+	) -> LlccB<impl Into<PathBuf,>,> {
+		let llvm = llvm_str(&src.into(), self.opt_level, &self.target,)?;
+		write_asm(llvm, self.out(DestKind::Llvm,)?,)?;
+		X(self.dest.path(DestKind::Llvm,),)
+	}
+
+	/// # Return
+	///
+	/// returns path to generated WebAssembly text file
+	pub fn emit_wat(
+		&self,
+		src: impl Into<String,>,
+	) -> LlccB<impl Into<PathBuf,>,> {
+		let wat = wat_str(&src.into(), self.opt_level,)?;
+		write_asm(wat, self.out(DestKind::Wat,)?,)?;
+		X(self.dest.path(DestKind::Wat,),)
+	}
+
+	/// writes the object for `src` with the integrated assembler of the
+	/// target, or emits assembly and runs `assemble` if there is none
+	///
+	/// # Return
+	///
+	/// returns path to generated object file
+	pub fn emit_obj(
+		&self,
+		src: impl Into<String,>,
+	) -> LlccB<impl Into<PathBuf,>,> {
+		let src = src.into();
+		let target = self.target.target();
+		match object_of(src.clone(), self.opt_level, &*target,)? {
+			Some(obj,) => self.write_obj(&obj,)?,
+			None => {
+				self.emit_asm(src,)?;
+				self.assemble()?;
+			},
+		}
+		X(self.dest.path(DestKind::Obj,),)
+	}
+
+	fn write_obj(&self, obj: &Object,) -> LlccB<(),> {
+		fs::write(self.out(DestKind::Obj,)?, elf::write(obj,)?,)?;
+		X((),)
+	}
+
+	/// compiles each of `srcs` to a function `int <name>(void)` in an
+	/// object `<name>.o` and packs them into a static library with a symbol
+	/// index, the same as `llcc -c` followed by `ar rcs`. the members export
+	/// their own names, so programs linked with the library keep their
+	/// `_start`
+	///
+	/// # Return
+	///
+	/// returns path to generated archive
+	pub fn archive<N: Into<String,>, S: Into<String,>,>(
+		&self,
+		srcs: impl IntoIterator<Item = (N, S,),>,
+	) -> LlccB<impl Into<PathBuf,>,> {
+		let target = self.target.target();
+		let mut archive = Archive::default();
+		for (name, src,) in srcs {
+			let name = name.into();
+			if !is_identifier(&name,) || name == lower::ENTRY {
+				return Y(LlccError::syntax(format!(
+					"`{name}` cannot name a function of a library"
+				),),);
+			}
+			let obj_name = format!("{name}.o");
+			if archive.members.iter().any(|m| m.name == obj_name,) {
+				return Y(LlccError::syntax(format!(
+					"two members of the library are named `{name}`"
+				),),);
+			}
+			let obj =
+				function_object_of(src, &name, self.opt_level, &*target,)?;
+			let Some(obj,) = obj else {
+				return Y(LlccError::unsupported(format!(
+					"archives for {}, which has no integrated assembler",
+					self.target
+				),),);
+			};
+			archive
+				.members
+				.push(Member { name: obj_name, data: elf::write(&obj,)?, },);
+		}
+		archive.index_symbols();
+
+		let lib_path = self.out(DestKind::Lib,)?;
+		fs::write(&lib_path, ar::write(&archive,),)?;
+		X(lib_path,)
+	}
+
+	/// writes the object for the aarch64 assembly `asm` with the integrated
+	/// assembler instead of an external one
+	///
+	/// # Return
+	///
+	/// returns path to generated object file
+	pub fn assemble_src(&self, asm: &str,) -> LlccB<impl Into<PathBuf,>,> {
+		if self.target.arch != Arch::Aarch64 {
+			return Y(LlccError::unsupported(format!(
+				"assembling for {}, which has no integrated assembler",
+				self.target
+			),),);
+		}
+		self.write_obj(&assemble_str(asm,)?,)?;
+		X(self.dest.path(DestKind::Obj,),)
+	}
+
 	/// # Return
 	///
 	/// returns path to generated object file
 	pub fn assemble(&self,) -> LlccB<impl Into<PathBuf,>,> {
-		let obj_path = stringify_path(self.dest.path(DestKind::Obj,),)?;
+		let obj_path = stringify_path(self.out(DestKind::Obj,)?,)?;
 		let asm_path = stringify_path(self.dest.path(DestKind::Asm,),)?;
 
-		run_cmd("as", ["-o", &obj_path, &asm_path,],)?;
+		self.toolchain.assembler.run_ok(["-o", &obj_path, &asm_path,],)?;
 		X(obj_path,)
 	}
 
@@ -63,12 +357,88 @@ impl LlccCompiler {
 	///
 	/// returns path to generated executable file
 	pub fn link(&self,) -> LlccB<impl Into<PathBuf,>,> {
-		let exe_path = stringify_path(self.dest.path(DestKind::Exe,),)?;
+		let exe_path = stringify_path(self.out(DestKind::Exe,)?,)?;
 		let obj_path = stringify_path(self.dest.path(DestKind::Obj,),)?;
 
-		run_cmd("ld", ["-o", &exe_path, &obj_path,],)?;
+		let mut args = vec!["-o".to_string(), exe_path.clone(), obj_path];
+		for input in &self.inputs {
+			args.push(stringify_path(input.clone(),)?,);
+		}
+		self.toolchain.linker.run_ok(args,)?;
 		X(exe_path,)
 	}
+
+	/// links `objects` and the inputs with the built-in linker, taking
+	/// only the archive members that are needed
+	///
+	/// # Return
+	///
+	/// returns path to generated executable file
+	pub fn link_objects(
+		&self,
+		objects: &[Object],
+	) -> LlccB<impl Into<PathBuf,>,> {
+		let mut objects = objects.to_vec();
+		let mut archives = vec![];
+		for input in &self.inputs {
+			let bytes = fs::read(input,)?;
+			if bytes.starts_with(ar::MAGIC,) {
+				archives.push(ar::read(&bytes,)?,);
+			} else {
+				objects.push(elf::read(&bytes,)?,);
+			}
+		}
+		link::pull_members(&mut objects, &archives,)?;
+
+		let exe_path = self.out(DestKind::Exe,)?;
+		fs::write(&exe_path, link::link(&objects,)?,)?;
+		fs::set_permissions(&exe_path, fs::Permissions::from_mode(0o755,),)?;
+		X(exe_path,)
+	}
+
+	/// runs the executable at `exe_path` the way the toolchain says
+	pub fn exec(&self, exe_path: impl Into<PathBuf,>,) -> LlccB<ExitStatus,> {
+		match self.toolchain.runner()? {
+			Runner::Native => exec(exe_path,),
+			Runner::Command(runner,) => runner.run([exe_path.into(),],),
+			Runner::Emulator => emulate(exe_path,),
+		}
+	}
+}
+
+/// the toolchain for `target` from the environment of this process
+fn discover(target: &Triple,) -> Toolchain {
+	Toolchain::discover(target, |var| std::env::var(var,).ok(),)
+}
+
+/// whether `name` can be a C function name
+fn is_identifier(name: &str,) -> bool {
+	let mut chars = name.chars();
+	chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_',)
+		&& chars.all(|c| c.is_ascii_alphanumeric() || c == '_',)
+}
+
+fn parse_emit(emit: &str,) -> LlccB<Emit,> {
+	match emit.parse() {
+		Ok(emit,) => X(emit,),
+		Err(_,) => Y(LlccError::syntax(format!(
+			"unknown `--emit` kind `{emit}`, expected exe, asm, obj, lib, \
+			 llvm or wat"
+		),),),
+	}
+}
+
+/// sections, symbols and disassembly of the ELF object or executable at
+/// `path`
+pub fn objdump(path: impl Into<PathBuf,>,) -> LlccB<String,> {
+	dump(&fs::read(path.into(),)?,)
+}
+
+/// runs the aarch64 executable at `exe_path` in the built-in emulator, on
+/// any host
+pub fn emulate(exe_path: impl Into<PathBuf,>,) -> LlccB<ExitStatus,> {
+	let exe = fs::read(exe_path.into(),)?;
+	run_emulated(&exe, &mut io::stdin(), &mut io::stdout(),)
 }
 
 pub fn exec(exe_path: impl Into<PathBuf,>,) -> LlccB<ExitStatus,> {
@@ -77,3 +447,56 @@ pub fn exec(exe_path: impl Into<PathBuf,>,) -> LlccB<ExitStatus,> {
 		[],
 	)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::asm::clear_out;
+
+	#[test]
+	fn archive_members_export_their_names() -> LlccB<(),> {
+		let compiler = LlccCompiler::default()
+			.with_target("aarch64".parse()?,)
+			.with_dest(Dest::temp(Some("test".to_string(),),),);
+		let lib: PathBuf =
+			compiler.archive([("a", "40",), ("b", "-3 + 8",),],)?.into();
+		let archive = ar::read(&fs::read(&lib,)?,)?;
+		let names: Vec<_,> =
+			archive.members.iter().map(|m| m.name.as_str(),).collect();
+		assert_eq!(names, ["a.o", "b.o"]);
+		assert_eq!(archive.symbols.len(), 2, "{:?}", archive.symbols);
+		assert_eq!(archive.member_defining("a",), Some(0));
+		assert_eq!(archive.member_defining("b",), Some(1));
+
+		// a program of its own calling into the library
+		let main = assemble_str(
+			"	.text
+			.global _start
+		_start:
+			bl a
+			mov w19, w0
+			bl b
+			add w0, w0, w19
+			mov x8, #93
+			svc #0
+		",
+		)?;
+		let compiler = compiler.with_input(lib,);
+		let exe: PathBuf = compiler.link_objects(&[main,],)?.into();
+		let status =
+			run_emulated(&fs::read(exe,)?, &mut io::empty(), &mut io::sink(),)?;
+		assert_eq!(status.code(), Some(45));
+
+		for srcs in [
+			[("a", "1",), ("a", "2",),],
+			[("1a", "1",), ("b", "2",),],
+			[("_start", "1",), ("b", "2",),],
+		] {
+			let Y(_,) = compiler.archive(srcs,) else {
+				panic!("archived {srcs:?}");
+			};
+		}
+		clear_out(compiler.dest(),)?;
+		X((),)
+	}
+}
@@ -0,0 +1,699 @@
+//! compiler internal representation
+//!
+//! source → `ir::Function` → asm
+//!
+//! a function is a list of basic blocks. `Block(0)` is always the entry.
+//! values are defined exactly once while the function is in SSA form, mutable
+//! locals live in stack slots until `ssa::mem2reg` promotes them.
+
+use std::fmt::Display;
+
+pub mod cfg;
+pub mod dom;
+pub mod llvm;
+pub mod loops;
+pub mod lower;
+pub mod ssa;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord,)]
+pub struct Value(pub u32,);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord,)]
+pub struct Block(pub u32,);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord,)]
+pub struct Slot(pub u32,);
+
+impl Value {
+	pub fn index(self,) -> usize {
+		self.0 as usize
+	}
+}
+
+impl Block {
+	pub const ENTRY: Self = Self(0,);
+
+	pub fn index(self,) -> usize {
+		self.0 as usize
+	}
+}
+
+impl Slot {
+	pub fn index(self,) -> usize {
+		self.0 as usize
+	}
+}
+
+impl Display for Value {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		write!(f, "%{}", self.0)
+	}
+}
+
+impl Display for Block {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		write!(f, "bb{}", self.0)
+	}
+}
+
+impl Display for Slot {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		write!(f, "slot{}", self.0)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display,)]
+#[strum(serialize_all = "lowercase")]
+pub enum Type {
+	I8,
+	I16,
+	I32,
+	I64,
+	/// C `float`
+	F32,
+	/// C `double`
+	F64,
+}
+
+impl Type {
+	pub fn bits(self,) -> u32 {
+		match self {
+			Self::I8 => 8,
+			Self::I16 => 16,
+			Self::I32 | Self::F32 => 32,
+			Self::I64 | Self::F64 => 64,
+		}
+	}
+
+	pub fn bytes(self,) -> u32 {
+		self.bits() / 8
+	}
+
+	pub fn is_float(self,) -> bool {
+		matches!(self, Self::F32 | Self::F64)
+	}
+
+	/// constant of the float type `self` holding `v` rounded to `self`.
+	/// float constants are their bit pattern, sign extended like integers
+	pub fn float_bits(self, v: f64,) -> i64 {
+		match self {
+			Self::F32 => (v as f32).to_bits() as i32 as i64,
+			_ => v.to_bits() as i64,
+		}
+	}
+
+	/// value of the constant `c` of the float type `self`
+	pub fn float_value(self, c: i64,) -> f64 {
+		match self {
+			Self::F32 => f32::from_bits(c as u32,) as f64,
+			_ => f64::from_bits(c as u64,),
+		}
+	}
+}
+
+#[derive(
+	Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display, strum::EnumString,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum BinOp {
+	Add,
+	Sub,
+	Mul,
+	SDiv,
+	UDiv,
+	SRem,
+	URem,
+	And,
+	Or,
+	Xor,
+	Shl,
+	LShr,
+	AShr,
+	FAdd,
+	FSub,
+	FMul,
+	FDiv,
+}
+
+#[derive(
+	Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display, strum::EnumString,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum UnOp {
+	Neg,
+	Not,
+	FNeg,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display,)]
+#[strum(serialize_all = "lowercase")]
+pub enum CmpOp {
+	Eq,
+	Ne,
+	SLt,
+	SLe,
+	SGt,
+	SGe,
+	ULt,
+	ULe,
+	UGt,
+	UGe,
+	/// float comparisons are false if an operand is NaN, except `fne`
+	FEq,
+	FNe,
+	FLt,
+	FLe,
+	FGt,
+	FGe,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display,)]
+#[strum(serialize_all = "lowercase")]
+pub enum CastOp {
+	SExt,
+	ZExt,
+	Trunc,
+	/// signed integer to float
+	SIToFP,
+	/// unsigned integer to float
+	UIToFP,
+	/// float to signed integer, rounding toward zero
+	FPToSI,
+	/// float to unsigned integer, rounding toward zero
+	FPToUI,
+	FPExt,
+	FPTrunc,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash,)]
+pub enum InstKind {
+	Const(i64,),
+	Copy(Value,),
+	Unary(UnOp, Value,),
+	Binary(BinOp, Value, Value,),
+	Cmp(CmpOp, Value, Value,),
+	Cast(CastOp, Value,),
+	/// incoming value per predecessor block
+	Phi(Vec<(Block, Value,),>,),
+	StackLoad(Slot,),
+	StackStore(Slot, Value,),
+	/// address of a stack slot. a slot whose address is taken is never
+	/// promoted to a register
+	StackAddr(Slot,),
+	Load(Value,),
+	Store {
+		addr:  Value,
+		value: Value,
+	},
+	Call {
+		callee: String,
+		args:   Vec<Value,>,
+		/// the call is directly followed by a return of its result and the
+		/// caller frame is not needed any more. see `opt::tail`
+		tail:   bool,
+	},
+}
+
+impl InstKind {
+	pub fn operands(&self,) -> Vec<Value,> {
+		match self {
+			Self::Const(_,) | Self::StackLoad(_,) | Self::StackAddr(_,) => {
+				vec![]
+			},
+			Self::Copy(v,)
+			| Self::Unary(_, v,)
+			| Self::Cast(_, v,)
+			| Self::StackStore(_, v,)
+			| Self::Load(v,) => vec![*v],
+			Self::Binary(_, a, b,) | Self::Cmp(_, a, b,) => vec![*a, *b],
+			Self::Phi(incoming,) => {
+				incoming.iter().map(|(_, v,)| *v,).collect()
+			},
+			Self::Store { addr, value, } => vec![*addr, *value],
+			Self::Call { args, .. } => args.clone(),
+		}
+	}
+
+	pub fn operands_mut(&mut self,) -> Vec<&mut Value,> {
+		match self {
+			Self::Const(_,) | Self::StackLoad(_,) | Self::StackAddr(_,) => {
+				vec![]
+			},
+			Self::Copy(v,)
+			| Self::Unary(_, v,)
+			| Self::Cast(_, v,)
+			| Self::StackStore(_, v,)
+			| Self::Load(v,) => vec![v],
+			Self::Binary(_, a, b,) | Self::Cmp(_, a, b,) => vec![a, b],
+			Self::Phi(incoming,) => {
+				incoming.iter_mut().map(|(_, v,)| v,).collect()
+			},
+			Self::Store { addr, value, } => vec![addr, value],
+			Self::Call { args, .. } => args.iter_mut().collect(),
+		}
+	}
+
+	/// whether removing this instruction is observable apart from its result
+	pub fn has_side_effect(&self,) -> bool {
+		matches!(
+			self,
+			Self::StackStore(..,) | Self::Store { .. } | Self::Call { .. }
+		)
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub struct Inst {
+	pub dest: Option<Value,>,
+	pub kind: InstKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub enum Terminator {
+	Jump(Block,),
+	Branch { cond: Value, then: Block, els: Block, },
+	Return(Option<Value,>,),
+	Unreachable,
+}
+
+impl Terminator {
+	pub fn successors(&self,) -> Vec<Block,> {
+		match self {
+			Self::Jump(b,) => vec![*b],
+			Self::Branch { then, els, .. } if then == els => vec![*then],
+			Self::Branch { then, els, .. } => vec![*then, *els],
+			Self::Return(_,) | Self::Unreachable => vec![],
+		}
+	}
+
+	pub fn successors_mut(&mut self,) -> Vec<&mut Block,> {
+		match self {
+			Self::Jump(b,) => vec![b],
+			Self::Branch { then, els, .. } => vec![then, els],
+			Self::Return(_,) | Self::Unreachable => vec![],
+		}
+	}
+
+	pub fn operands(&self,) -> Vec<Value,> {
+		match self {
+			Self::Branch { cond, .. } => vec![*cond],
+			Self::Return(Some(v,),) => vec![*v],
+			_ => vec![],
+		}
+	}
+
+	pub fn operands_mut(&mut self,) -> Vec<&mut Value,> {
+		match self {
+			Self::Branch { cond, .. } => vec![cond],
+			Self::Return(Some(v,),) => vec![v],
+			_ => vec![],
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub struct BlockData {
+	pub insts: Vec<Inst,>,
+	pub term:  Terminator,
+}
+
+impl Default for BlockData {
+	fn default() -> Self {
+		Self { insts: vec![], term: Terminator::Unreachable, }
+	}
+}
+
+impl BlockData {
+	/// phi nodes are always placed at the head of a block
+	pub fn phis(&self,) -> impl Iterator<Item = &Inst,> {
+		self.insts.iter().take_while(|i| matches!(i.kind, InstKind::Phi(_,)),)
+	}
+}
+
+/// `Internal` is C `static`. such a function is invisible outside of its
+/// module, so it can be dropped once every call is inlined
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq,)]
+pub enum Linkage {
+	#[default]
+	External,
+	Internal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub struct Function {
+	pub name:        String,
+	pub linkage:     Linkage,
+	pub params:      Vec<Value,>,
+	pub ret:         Option<Type,>,
+	pub blocks:      Vec<BlockData,>,
+	pub value_types: Vec<Type,>,
+	pub slots:       Vec<Type,>,
+}
+
+impl Function {
+	pub fn new(name: impl Into<String,>, ret: Option<Type,>,) -> Self {
+		Self {
+			name: name.into(),
+			linkage: Linkage::default(),
+			params: vec![],
+			ret,
+			blocks: vec![BlockData::default()],
+			value_types: vec![],
+			slots: vec![],
+		}
+	}
+
+	pub fn new_value(&mut self, ty: Type,) -> Value {
+		self.value_types.push(ty,);
+		Value(self.value_types.len() as u32 - 1,)
+	}
+
+	pub fn new_block(&mut self,) -> Block {
+		self.blocks.push(BlockData::default(),);
+		Block(self.blocks.len() as u32 - 1,)
+	}
+
+	pub fn new_slot(&mut self, ty: Type,) -> Slot {
+		self.slots.push(ty,);
+		Slot(self.slots.len() as u32 - 1,)
+	}
+
+	pub fn ty(&self, v: Value,) -> Type {
+		self.value_types[v.index()]
+	}
+
+	pub fn block(&self, b: Block,) -> &BlockData {
+		&self.blocks[b.index()]
+	}
+
+	pub fn block_mut(&mut self, b: Block,) -> &mut BlockData {
+		&mut self.blocks[b.index()]
+	}
+
+	pub fn block_ids(&self,) -> impl Iterator<Item = Block,> + use<> {
+		(0..self.blocks.len() as u32).map(Block,)
+	}
+
+	/// rewrites every use of a key of `map` to its value. chains such as
+	/// `a → b → c` are followed to the end
+	pub fn replace_uses(
+		&mut self,
+		map: &std::collections::HashMap<Value, Value,>,
+	) {
+		if map.is_empty() {
+			return;
+		}
+		let resolve = |mut v: Value| {
+			while let Some(next,) = map.get(&v,) {
+				if *next == v {
+					break;
+				}
+				v = *next;
+			}
+			v
+		};
+
+		for block in self.blocks.iter_mut() {
+			for inst in block.insts.iter_mut() {
+				for op in inst.kind.operands_mut() {
+					*op = resolve(*op,);
+				}
+			}
+			for op in block.term.operands_mut() {
+				*op = resolve(*op,);
+			}
+		}
+	}
+
+	/// forgets the incoming values of `block`'s phis flowing from `pred`.
+	/// needed whenever the edge `pred → block` is removed
+	pub fn remove_phi_incoming(&mut self, block: Block, pred: Block,) {
+		for inst in self.block_mut(block,).insts.iter_mut() {
+			let InstKind::Phi(incoming,) = &mut inst.kind else { break };
+			incoming.retain(|(p, _,)| *p != pred,);
+		}
+	}
+
+	/// number of uses of every value, indexed by `Value::index`
+	pub fn use_counts(&self,) -> Vec<usize,> {
+		let mut counts = vec![0; self.value_types.len()];
+		for block in self.blocks.iter() {
+			for inst in block.insts.iter() {
+				for op in inst.kind.operands() {
+					counts[op.index()] += 1;
+				}
+			}
+			for op in block.term.operands() {
+				counts[op.index()] += 1;
+			}
+		}
+		counts
+	}
+}
+
+impl Display for Function {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		let params = self
+			.params
+			.iter()
+			.map(|p| format!("{}: {p}", self.ty(*p)),)
+			.collect::<Vec<_,>>()
+			.join(", ",);
+		let ret = self.ret.map(|t| t.to_string(),).unwrap_or("void".into(),);
+		let linkage = match self.linkage {
+			Linkage::External => "",
+			Linkage::Internal => "static ",
+		};
+		writeln!(f, "{linkage}fn {}({params}) -> {ret} {{", self.name)?;
+		for (i, ty,) in self.slots.iter().enumerate() {
+			writeln!(f, "\t{}: {ty}", Slot(i as u32,))?;
+		}
+		for b in self.block_ids() {
+			writeln!(f, "{b}:")?;
+			for inst in self.block(b,).insts.iter() {
+				f.write_str("\t",)?;
+				if let Some(dest,) = inst.dest {
+					write!(f, "{dest}: {} = ", self.ty(dest))?;
+				}
+				match (&inst.kind, inst.dest.map(|d| self.ty(d,),),) {
+					(InstKind::Const(c,), Some(ty,),) if ty.is_float() => {
+						writeln!(f, "const {:?}", ty.float_value(*c))?;
+					},
+					(kind, _,) => writeln!(f, "{kind}")?,
+				}
+			}
+			writeln!(f, "\t{}", self.block(b,).term)?;
+		}
+		f.write_str("}\n",)
+	}
+}
+
+/// a translation unit
+#[derive(Debug, Clone, Default, PartialEq, Eq,)]
+pub struct Module {
+	pub functions: Vec<Function,>,
+}
+
+impl Module {
+	pub fn function(&self, name: &str,) -> Option<&Function,> {
+		self.functions.iter().find(|f| f.name == name,)
+	}
+}
+
+impl Display for Module {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		for (i, func,) in self.functions.iter().enumerate() {
+			if i != 0 {
+				f.write_str("\n",)?;
+			}
+			write!(f, "{func}")?;
+		}
+		Ok((),)
+	}
+}
+
+impl Display for InstKind {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		match self {
+			Self::Const(c,) => write!(f, "const {c}"),
+			Self::Copy(v,) => write!(f, "copy {v}"),
+			Self::Unary(op, v,) => write!(f, "{op} {v}"),
+			Self::Binary(op, a, b,) => write!(f, "{op} {a}, {b}"),
+			Self::Cmp(op, a, b,) => write!(f, "cmp {op} {a}, {b}"),
+			Self::Cast(op, v,) => write!(f, "{op} {v}"),
+			Self::Phi(incoming,) => {
+				let incoming = incoming
+					.iter()
+					.map(|(b, v,)| format!("[{b}: {v}]"),)
+					.collect::<Vec<_,>>()
+					.join(", ",);
+				write!(f, "phi {incoming}")
+			},
+			Self::StackLoad(s,) => write!(f, "load {s}"),
+			Self::StackStore(s, v,) => write!(f, "store {s}, {v}"),
+			Self::StackAddr(s,) => write!(f, "addr {s}"),
+			Self::Load(a,) => write!(f, "load {a}"),
+			Self::Store { addr, value, } => write!(f, "store {addr}, {value}"),
+			Self::Call { callee, args, tail, } => {
+				let args = args
+					.iter()
+					.map(|a| a.to_string(),)
+					.collect::<Vec<_,>>()
+					.join(", ",);
+				let tail = if *tail { "tail " } else { "" };
+				write!(f, "{tail}call {callee}({args})")
+			},
+		}
+	}
+}
+
+impl Display for Terminator {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		match self {
+			Self::Jump(b,) => write!(f, "jump {b}"),
+			Self::Branch { cond, then, els, } => {
+				write!(f, "branch {cond}, {then}, {els}")
+			},
+			Self::Return(Some(v,),) => write!(f, "ret {v}"),
+			Self::Return(None,) => write!(f, "ret"),
+			Self::Unreachable => write!(f, "unreachable"),
+		}
+	}
+}
+
+/// appends instructions to a `Function` at a movable insertion point
+pub struct FunctionBuilder {
+	func:    Function,
+	current: Block,
+}
+
+impl FunctionBuilder {
+	pub fn new(
+		name: impl Into<String,>,
+		params: &[Type],
+		ret: Option<Type,>,
+	) -> Self {
+		let mut func = Function::new(name, ret,);
+		func.params = params.iter().map(|ty| func.new_value(*ty,),).collect();
+		Self { func, current: Block::ENTRY, }
+	}
+
+	pub fn set_linkage(&mut self, linkage: Linkage,) {
+		self.func.linkage = linkage;
+	}
+
+	pub fn param(&self, i: usize,) -> Value {
+		self.func.params[i]
+	}
+
+	pub fn current(&self,) -> Block {
+		self.current
+	}
+
+	pub fn ty(&self, v: Value,) -> Type {
+		self.func.ty(v,)
+	}
+
+	pub fn create_block(&mut self,) -> Block {
+		self.func.new_block()
+	}
+
+	pub fn switch_to(&mut self, block: Block,) {
+		self.current = block;
+	}
+
+	pub fn slot(&mut self, ty: Type,) -> Slot {
+		self.func.new_slot(ty,)
+	}
+
+	pub fn push(&mut self, ty: Option<Type,>, kind: InstKind,) -> Value {
+		let dest = ty.map(|ty| self.func.new_value(ty,),);
+		self.func.block_mut(self.current,).insts.push(Inst { dest, kind, },);
+		// instructions without result hand back a dummy that must not be used
+		dest.unwrap_or(Value(u32::MAX,),)
+	}
+
+	pub fn iconst(&mut self, ty: Type, value: i64,) -> Value {
+		self.push(Some(ty,), InstKind::Const(value,),)
+	}
+
+	pub fn fconst(&mut self, ty: Type, value: f64,) -> Value {
+		self.push(Some(ty,), InstKind::Const(ty.float_bits(value,),),)
+	}
+
+	pub fn unary(&mut self, op: UnOp, v: Value,) -> Value {
+		let ty = self.func.ty(v,);
+		self.push(Some(ty,), InstKind::Unary(op, v,),)
+	}
+
+	pub fn binary(&mut self, op: BinOp, lhs: Value, rhs: Value,) -> Value {
+		let ty = self.func.ty(lhs,);
+		self.push(Some(ty,), InstKind::Binary(op, lhs, rhs,),)
+	}
+
+	/// comparison results are C `int`s holding 0 or 1
+	pub fn cmp(&mut self, op: CmpOp, lhs: Value, rhs: Value,) -> Value {
+		self.push(Some(Type::I32,), InstKind::Cmp(op, lhs, rhs,),)
+	}
+
+	pub fn cast(&mut self, op: CastOp, v: Value, to: Type,) -> Value {
+		self.push(Some(to,), InstKind::Cast(op, v,),)
+	}
+
+	pub fn load_slot(&mut self, slot: Slot,) -> Value {
+		let ty = self.func.slots[slot.index()];
+		self.push(Some(ty,), InstKind::StackLoad(slot,),)
+	}
+
+	pub fn store_slot(&mut self, slot: Slot, value: Value,) {
+		self.push(None, InstKind::StackStore(slot, value,),);
+	}
+
+	pub fn slot_addr(&mut self, slot: Slot,) -> Value {
+		self.push(Some(Type::I64,), InstKind::StackAddr(slot,),)
+	}
+
+	pub fn load(&mut self, ty: Type, addr: Value,) -> Value {
+		self.push(Some(ty,), InstKind::Load(addr,),)
+	}
+
+	pub fn store(&mut self, addr: Value, value: Value,) {
+		self.push(None, InstKind::Store { addr, value, },);
+	}
+
+	pub fn call(
+		&mut self,
+		callee: impl Into<String,>,
+		args: &[Value],
+		ret: Option<Type,>,
+	) -> Value {
+		self.push(
+			ret,
+			InstKind::Call {
+				callee: callee.into(),
+				args:   args.to_vec(),
+				tail:   false,
+			},
+		)
+	}
+
+	pub fn terminate(&mut self, term: Terminator,) {
+		self.func.block_mut(self.current,).term = term;
+	}
+
+	pub fn jump(&mut self, to: Block,) {
+		self.terminate(Terminator::Jump(to,),);
+	}
+
+	pub fn branch(&mut self, cond: Value, then: Block, els: Block,) {
+		self.terminate(Terminator::Branch { cond, then, els, },);
+	}
+
+	pub fn ret(&mut self, value: Option<Value,>,) {
+		self.terminate(Terminator::Return(value,),);
+	}
+
+	pub fn finish(self,) -> Function {
+		self.func
+	}
+}
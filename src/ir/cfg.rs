@@ -0,0 +1,101 @@
+//! control flow graph of a `Function`
+
+use crate::ir::Block;
+use crate::ir::Function;
+
+pub struct Cfg {
+	succs: Vec<Vec<Block,>,>,
+	preds: Vec<Vec<Block,>,>,
+	/// reverse postorder of the blocks reachable from entry
+	rpo:   Vec<Block,>,
+}
+
+impl Cfg {
+	pub fn new(func: &Function,) -> Self {
+		let len = func.blocks.len();
+		let succs: Vec<Vec<Block,>,> =
+			func.blocks.iter().map(|b| b.term.successors(),).collect();
+
+		let mut postorder = Vec::with_capacity(len,);
+		let mut visited = vec![false; len];
+		// (block, index of the next successor to visit)
+		let mut stack = vec![(Block::ENTRY, 0,)];
+		visited[Block::ENTRY.index()] = true;
+		while let Some((b, next,),) = stack.last_mut() {
+			let b = *b;
+			if let Some(s,) = succs[b.index()].get(*next,) {
+				*next += 1;
+				if !visited[s.index()] {
+					visited[s.index()] = true;
+					stack.push((*s, 0,),);
+				}
+			} else {
+				postorder.push(b,);
+				stack.pop();
+			}
+		}
+
+		// unreachable blocks do not count as predecessors
+		let mut preds = vec![vec![]; len];
+		for b in postorder.iter().rev() {
+			for s in succs[b.index()].iter() {
+				preds[s.index()].push(*b,);
+			}
+		}
+
+		postorder.reverse();
+		Self { succs, preds, rpo: postorder, }
+	}
+
+	pub fn succs(&self, b: Block,) -> &[Block] {
+		&self.succs[b.index()]
+	}
+
+	pub fn preds(&self, b: Block,) -> &[Block] {
+		&self.preds[b.index()]
+	}
+
+	/// number of blocks of the function, reachable or not
+	pub fn block_count(&self,) -> usize {
+		self.succs.len()
+	}
+
+	pub fn rpo(&self,) -> &[Block] {
+		&self.rpo
+	}
+
+	pub fn is_reachable(&self, b: Block,) -> bool {
+		b == Block::ENTRY || !self.preds[b.index()].is_empty()
+	}
+
+	/// an edge is critical when its source has several successors and its
+	/// target several predecessors. copies for phis can not be placed on it
+	/// without splitting
+	pub fn is_critical(&self, from: Block, to: Block,) -> bool {
+		self.succs(from,).len() > 1 && self.preds(to,).len() > 1
+	}
+}
+
+/// inserts an empty block on the edge `from → to` and returns it.
+/// phis of `to` are updated to name the new block as their predecessor
+pub fn split_edge(func: &mut Function, from: Block, to: Block,) -> Block {
+	use crate::ir::InstKind;
+	use crate::ir::Terminator;
+
+	let mid = func.new_block();
+	func.block_mut(mid,).term = Terminator::Jump(to,);
+	for s in func.block_mut(from,).term.successors_mut() {
+		if *s == to {
+			*s = mid;
+		}
+	}
+	for inst in func.block_mut(to,).insts.iter_mut() {
+		let InstKind::Phi(incoming,) = &mut inst.kind else { break };
+		for (pred, _,) in incoming.iter_mut() {
+			if *pred == from {
+				*pred = mid;
+			}
+		}
+	}
+	mid
+}
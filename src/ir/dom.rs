@@ -0,0 +1,207 @@
+//! dominator tree and dominance frontiers
+//!
+//! uses the iterative algorithm from Cooper, Harvey and Kennedy,
+//! "A Simple, Fast Dominance Algorithm"
+
+use crate::ir::Block;
+use crate::ir::cfg::Cfg;
+use std::collections::BTreeSet;
+
+pub struct DomTree {
+	idom:      Vec<Option<Block,>,>,
+	children:  Vec<Vec<Block,>,>,
+	rpo_index: Vec<usize,>,
+}
+
+impl DomTree {
+	pub fn new(cfg: &Cfg,) -> Self {
+		let len = cfg.block_count();
+		let mut rpo_index = vec![usize::MAX; len];
+		for (i, b,) in cfg.rpo().iter().enumerate() {
+			rpo_index[b.index()] = i;
+		}
+
+		let mut idom: Vec<Option<Block,>,> = vec![None; len];
+		idom[Block::ENTRY.index()] = Some(Block::ENTRY,);
+		let mut changed = true;
+		while changed {
+			changed = false;
+			for b in cfg.rpo().iter().skip(1,) {
+				let mut new_idom = None;
+				for p in cfg.preds(*b,) {
+					if idom[p.index()].is_none() {
+						continue;
+					}
+					new_idom = Some(match new_idom {
+						None => *p,
+						Some(cur,) => intersect(&idom, &rpo_index, *p, cur,),
+					},);
+				}
+				if new_idom != idom[b.index()] {
+					idom[b.index()] = new_idom;
+					changed = true;
+				}
+			}
+		}
+
+		let mut children = vec![vec![]; len];
+		for b in cfg.rpo().iter().skip(1,) {
+			if let Some(parent,) = idom[b.index()] {
+				children[parent.index()].push(*b,);
+			}
+		}
+		// entry has no immediate dominator
+		idom[Block::ENTRY.index()] = None;
+
+		Self { idom, children, rpo_index, }
+	}
+
+	pub fn idom(&self, b: Block,) -> Option<Block,> {
+		self.idom[b.index()]
+	}
+
+	pub fn children(&self, b: Block,) -> &[Block] {
+		&self.children[b.index()]
+	}
+
+	pub fn is_reachable(&self, b: Block,) -> bool {
+		self.rpo_index[b.index()] != usize::MAX
+	}
+
+	/// every block dominates itself
+	pub fn dominates(&self, a: Block, b: Block,) -> bool {
+		if !self.is_reachable(a,) || !self.is_reachable(b,) {
+			return false;
+		}
+		let mut cur = b;
+		loop {
+			if cur == a {
+				return true;
+			}
+			match self.idom(cur,) {
+				Some(parent,) => cur = parent,
+				None => return false,
+			}
+		}
+	}
+
+	/// blocks of the tree in preorder, starting from entry
+	pub fn preorder(&self,) -> Vec<Block,> {
+		let mut order = vec![];
+		let mut stack = vec![Block::ENTRY];
+		while let Some(b,) = stack.pop() {
+			order.push(b,);
+			stack.extend(self.children(b,).iter().rev(),);
+		}
+		order
+	}
+
+	/// dominance frontier of every block, indexed by `Block::index`
+	pub fn frontiers(&self, cfg: &Cfg,) -> Vec<BTreeSet<Block,>,> {
+		let mut df = vec![BTreeSet::new(); self.idom.len()];
+		for b in cfg.rpo() {
+			let preds = cfg.preds(*b,);
+			if preds.len() < 2 {
+				continue;
+			}
+			for p in preds {
+				let mut runner = *p;
+				while Some(runner,) != self.idom(*b,) {
+					df[runner.index()].insert(*b,);
+					match self.idom(runner,) {
+						Some(up,) => runner = up,
+						None => break,
+					}
+				}
+			}
+		}
+		df
+	}
+
+	/// iterated dominance frontier of `blocks`, where phis for a variable
+	/// assigned in `blocks` have to be placed
+	pub fn iterated_frontier(
+		&self,
+		cfg: &Cfg,
+		blocks: impl IntoIterator<Item = Block,>,
+	) -> BTreeSet<Block,> {
+		let df = self.frontiers(cfg,);
+		let mut result = BTreeSet::new();
+		let mut work: Vec<Block,> = blocks.into_iter().collect();
+		while let Some(b,) = work.pop() {
+			for d in df[b.index()].iter() {
+				if result.insert(*d,) {
+					work.push(*d,);
+				}
+			}
+		}
+		result
+	}
+}
+
+fn intersect(
+	idom: &[Option<Block,>],
+	rpo_index: &[usize],
+	mut a: Block,
+	mut b: Block,
+) -> Block {
+	while a != b {
+		while rpo_index[a.index()] > rpo_index[b.index()] {
+			a = idom[a.index()].expect("processed block has idom",);
+		}
+		while rpo_index[b.index()] > rpo_index[a.index()] {
+			b = idom[b.index()].expect("processed block has idom",);
+		}
+	}
+	a
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ir::FunctionBuilder;
+	use crate::ir::Type;
+
+	/// bb0 → bb1 → {bb2, bb3} → bb4 → bb1 | bb5
+	fn looped_diamond() -> crate::ir::Function {
+		let mut fb = FunctionBuilder::new("f", &[Type::I32,], None,);
+		let header = fb.create_block();
+		let left = fb.create_block();
+		let right = fb.create_block();
+		let latch = fb.create_block();
+		let exit = fb.create_block();
+		let p = fb.param(0,);
+		fb.jump(header,);
+		fb.switch_to(header,);
+		fb.branch(p, left, right,);
+		fb.switch_to(left,);
+		fb.jump(latch,);
+		fb.switch_to(right,);
+		fb.jump(latch,);
+		fb.switch_to(latch,);
+		fb.branch(p, header, exit,);
+		fb.switch_to(exit,);
+		fb.ret(None,);
+		fb.finish()
+	}
+
+	#[test]
+	fn idom_and_frontier() {
+		let f = looped_diamond();
+		let cfg = Cfg::new(&f,);
+		let dom = DomTree::new(&cfg,);
+		assert_eq!(dom.idom(Block(1,),), Some(Block(0,),));
+		assert_eq!(dom.idom(Block(2,),), Some(Block(1,),));
+		assert_eq!(dom.idom(Block(3,),), Some(Block(1,),));
+		assert_eq!(dom.idom(Block(4,),), Some(Block(1,),));
+		assert_eq!(dom.idom(Block(5,),), Some(Block(4,),));
+		assert!(dom.dominates(Block(1,), Block(5,),));
+		assert!(!dom.dominates(Block(2,), Block(4,),));
+
+		let df = dom.frontiers(&cfg,);
+		assert_eq!(df[2], BTreeSet::from([Block(4,)]));
+		assert_eq!(df[3], BTreeSet::from([Block(4,)]));
+		assert_eq!(df[4], BTreeSet::from([Block(1,)]));
+		assert_eq!(df[1], BTreeSet::from([Block(1,)]));
+	}
+}
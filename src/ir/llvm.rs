@@ -0,0 +1,513 @@
+//! `ir::Module` → textual LLVM IR
+//!
+//! values keep their numbers as `%v{n}` and blocks as `%bb{n}`. constants and
+//! copies have no instruction of their own in LLVM, so their uses are
+//! replaced by the literal or the copied value. stack slots become `alloca`s
+//! in a block `%entry` placed in front of `bb0`, which lets `bb0` be the
+//! target of branches. addresses are `i64` in `ir`, so loads and stores go
+//! through `inttoptr`. typed pointers keep the output readable by LLVM 14.
+//! `_start` is printed as `main`, so that the C runtime LLVM links against
+//! calls it and exits with its result
+
+use crate::err::B::X;
+use crate::err::LlccB;
+use crate::ir::BinOp;
+use crate::ir::CastOp;
+use crate::ir::CmpOp;
+use crate::ir::Function;
+use crate::ir::InstKind;
+use crate::ir::Linkage;
+use crate::ir::Module;
+use crate::ir::Slot;
+use crate::ir::Terminator;
+use crate::ir::Type;
+use crate::ir::UnOp;
+use crate::ir::Value;
+use crate::ir::lower;
+use crate::ir::lower::lower_expr;
+use crate::opt::OptLevel;
+use crate::opt::optimize_module;
+use crate::target::Triple;
+use std::collections::HashMap;
+
+/// the LLVM name of the function `name`
+fn symbol(name: &str,) -> &str {
+	if name == lower::ENTRY { "main" } else { name }
+}
+
+fn ty(t: Type,) -> &'static str {
+	match t {
+		Type::I8 => "i8",
+		Type::I16 => "i16",
+		Type::I32 => "i32",
+		Type::I64 => "i64",
+		Type::F32 => "float",
+		Type::F64 => "double",
+	}
+}
+
+fn ret_ty(t: Option<Type,>,) -> &'static str {
+	t.map(ty,).unwrap_or("void",)
+}
+
+/// LLVM writes float literals as the hex bits of the equal `double`
+fn literal(t: Type, c: i64,) -> String {
+	if t.is_float() {
+		format!("0x{:016X}", t.float_value(c,).to_bits())
+	} else {
+		c.to_string()
+	}
+}
+
+fn binary(op: BinOp,) -> &'static str {
+	match op {
+		BinOp::Add => "add",
+		BinOp::Sub => "sub",
+		BinOp::Mul => "mul",
+		BinOp::SDiv => "sdiv",
+		BinOp::UDiv => "udiv",
+		BinOp::SRem => "srem",
+		BinOp::URem => "urem",
+		BinOp::And => "and",
+		BinOp::Or => "or",
+		BinOp::Xor => "xor",
+		BinOp::Shl => "shl",
+		BinOp::LShr => "lshr",
+		BinOp::AShr => "ashr",
+		BinOp::FAdd => "fadd",
+		BinOp::FSub => "fsub",
+		BinOp::FMul => "fmul",
+		BinOp::FDiv => "fdiv",
+	}
+}
+
+/// `icmp` or `fcmp` with its predicate. `fne` is true for NaN operands, so
+/// it is the unordered `une`
+fn cmp(op: CmpOp,) -> &'static str {
+	match op {
+		CmpOp::Eq => "icmp eq",
+		CmpOp::Ne => "icmp ne",
+		CmpOp::SLt => "icmp slt",
+		CmpOp::SLe => "icmp sle",
+		CmpOp::SGt => "icmp sgt",
+		CmpOp::SGe => "icmp sge",
+		CmpOp::ULt => "icmp ult",
+		CmpOp::ULe => "icmp ule",
+		CmpOp::UGt => "icmp ugt",
+		CmpOp::UGe => "icmp uge",
+		CmpOp::FEq => "fcmp oeq",
+		CmpOp::FNe => "fcmp une",
+		CmpOp::FLt => "fcmp olt",
+		CmpOp::FLe => "fcmp ole",
+		CmpOp::FGt => "fcmp ogt",
+		CmpOp::FGe => "fcmp oge",
+	}
+}
+
+fn cast(op: CastOp, from: Type, to: Type,) -> &'static str {
+	match op {
+		// LLVM rejects extending or truncating to the same width
+		_ if from == to => "bitcast",
+		CastOp::SExt => "sext",
+		CastOp::ZExt => "zext",
+		CastOp::Trunc => "trunc",
+		CastOp::SIToFP => "sitofp",
+		CastOp::UIToFP => "uitofp",
+		CastOp::FPToSI => "fptosi",
+		CastOp::FPToUI => "fptoui",
+		CastOp::FPExt => "fpext",
+		CastOp::FPTrunc => "fptrunc",
+	}
+}
+
+struct Printer<'f,> {
+	func:  &'f Function,
+	/// uses of constants and copies are replaced by these
+	alias: HashMap<Value, String,>,
+	/// number of the next pointer cast for a store
+	casts: u32,
+	out:   String,
+}
+
+impl<'f,> Printer<'f,> {
+	fn new(func: &'f Function,) -> Self {
+		let mut copies = HashMap::new();
+		let mut alias = HashMap::new();
+		for inst in func.blocks.iter().flat_map(|b| b.insts.iter(),) {
+			match (inst.dest, &inst.kind,) {
+				(Some(dest,), InstKind::Const(c,),) => {
+					alias.insert(dest, literal(func.ty(dest,), *c,),);
+				},
+				(Some(dest,), InstKind::Copy(v,),) => {
+					copies.insert(dest, *v,);
+				},
+				_ => (),
+			}
+		}
+		for (dest, mut v,) in copies.clone() {
+			while let Some(next,) = copies.get(&v,) {
+				v = *next;
+			}
+			let name = alias.get(&v,).cloned().unwrap_or(format!("%v{}", v.0),);
+			alias.insert(dest, name,);
+		}
+		Self { func, alias, casts: 0, out: String::new(), }
+	}
+
+	fn value(&self, v: Value,) -> String {
+		self.alias.get(&v,).cloned().unwrap_or(format!("%v{}", v.0),)
+	}
+
+	/// `v` with its type, as arguments are written
+	fn typed(&self, v: Value,) -> String {
+		format!("{} {}", ty(self.func.ty(v,)), self.value(v,))
+	}
+
+	fn slot(&self, s: Slot,) -> (&'static str, String,) {
+		(ty(self.func.slots[s.index()],), format!("%slot{}", s.0),)
+	}
+
+	fn line(&mut self, text: impl AsRef<str,>,) {
+		self.out.push('\t',);
+		self.out.push_str(text.as_ref(),);
+		self.out.push('\n',);
+	}
+
+	fn inst(&mut self, dest: Option<Value,>, kind: &InstKind,) {
+		let name = dest.map(|d| format!("%v{}", d.0),).unwrap_or_default();
+		let dest_ty = dest.map(|d| ty(self.func.ty(d,),),).unwrap_or("void",);
+		let text = match kind {
+			InstKind::Const(_,) | InstKind::Copy(_,) => return,
+			InstKind::Binary(op, a, b,) => format!(
+				"{name} = {} {dest_ty} {}, {}",
+				binary(*op),
+				self.value(*a),
+				self.value(*b)
+			),
+			InstKind::Unary(UnOp::Neg, v,) => {
+				format!("{name} = sub {dest_ty} 0, {}", self.value(*v))
+			},
+			InstKind::Unary(UnOp::Not, v,) => {
+				format!("{name} = xor {dest_ty} {}, -1", self.value(*v))
+			},
+			InstKind::Unary(UnOp::FNeg, v,) => {
+				format!("{name} = fneg {}", self.typed(*v))
+			},
+			InstKind::Cmp(op, a, b,) => {
+				// LLVM compares to an `i1`, `ir` to an `int`
+				self.line(format!(
+					"{name}.i1 = {} {}, {}",
+					cmp(*op),
+					self.typed(*a),
+					self.value(*b)
+				),);
+				format!("{name} = zext i1 {name}.i1 to {dest_ty}")
+			},
+			InstKind::Cast(op, v,) => {
+				let from = self.func.ty(*v,);
+				let to = self.func.ty(dest.unwrap(),);
+				format!(
+					"{name} = {} {} to {dest_ty}",
+					cast(*op, from, to),
+					self.typed(*v)
+				)
+			},
+			InstKind::Phi(incoming,) => {
+				let incoming = incoming
+					.iter()
+					.map(|(b, v,)| {
+						format!("[ {}, %bb{} ]", self.value(*v), b.0)
+					},)
+					.collect::<Vec<_,>>()
+					.join(", ",);
+				format!("{name} = phi {dest_ty} {incoming}")
+			},
+			InstKind::StackLoad(s,) => {
+				let (t, slot,) = self.slot(*s,);
+				format!("{name} = load {t}, {t}* {slot}")
+			},
+			InstKind::StackStore(s, v,) => {
+				let (t, slot,) = self.slot(*s,);
+				format!("store {}, {t}* {slot}", self.typed(*v))
+			},
+			InstKind::StackAddr(s,) => {
+				let (t, slot,) = self.slot(*s,);
+				format!("{name} = ptrtoint {t}* {slot} to i64")
+			},
+			InstKind::Load(addr,) => {
+				self.line(format!(
+					"{name}.ptr = inttoptr {} to {dest_ty}*",
+					self.typed(*addr)
+				),);
+				format!("{name} = load {dest_ty}, {dest_ty}* {name}.ptr")
+			},
+			InstKind::Store { addr, value, } => {
+				// stores have no result to name the pointer after
+				let t = ty(self.func.ty(*value,),);
+				let ptr = format!("%ptr{}", self.casts);
+				self.casts += 1;
+				self.line(format!(
+					"{ptr} = inttoptr {} to {t}*",
+					self.typed(*addr)
+				),);
+				format!("store {}, {t}* {ptr}", self.typed(*value))
+			},
+			InstKind::Call { callee, args, tail, } => {
+				let args = args
+					.iter()
+					.map(|a| self.typed(*a,),)
+					.collect::<Vec<_,>>()
+					.join(", ",);
+				let tail = if *tail { "tail " } else { "" };
+				let assign = if dest.is_some() {
+					format!("{name} = ")
+				} else {
+					String::new()
+				};
+				format!(
+					"{assign}{tail}call {dest_ty} @{}({args})",
+					symbol(callee)
+				)
+			},
+		};
+		self.line(text,);
+	}
+
+	fn term(&mut self, block: u32, term: &Terminator,) {
+		let text = match term {
+			Terminator::Jump(to,) => format!("br label %bb{}", to.0),
+			Terminator::Branch { then, els, .. } if then == els => {
+				format!("br label %bb{}", then.0)
+			},
+			Terminator::Branch { cond, then, els, } => {
+				self.line(format!(
+					"%bb{block}.cond = icmp ne {}, 0",
+					self.typed(*cond)
+				),);
+				format!(
+					"br i1 %bb{block}.cond, label %bb{}, label %bb{}",
+					then.0, els.0
+				)
+			},
+			Terminator::Return(_,) if self.func.ret.is_none() => {
+				"ret void".to_string()
+			},
+			Terminator::Return(Some(v,),) => format!("ret {}", self.typed(*v)),
+			Terminator::Return(None,) => {
+				format!("ret {} undef", ret_ty(self.func.ret))
+			},
+			Terminator::Unreachable => "unreachable".to_string(),
+		};
+		self.line(text,);
+	}
+
+	fn function(mut self,) -> String {
+		let func = self.func;
+		let params = func
+			.params
+			.iter()
+			.map(|p| format!("{} %v{}", ty(func.ty(*p,)), p.0),)
+			.collect::<Vec<_,>>()
+			.join(", ",);
+		let linkage = match func.linkage {
+			Linkage::External => "",
+			Linkage::Internal => "internal ",
+		};
+		self.out.push_str(&format!(
+			"define {linkage}{} @{}({params}) {{\nentry:\n",
+			ret_ty(func.ret),
+			symbol(&func.name)
+		),);
+		for (i, t,) in func.slots.iter().enumerate() {
+			self.line(format!("%slot{i} = alloca {}", ty(*t)),);
+		}
+		self.line("br label %bb0",);
+		for b in func.block_ids() {
+			self.out.push_str(&format!("bb{}:\n", b.0),);
+			let block = func.block(b,);
+			for inst in block.insts.iter() {
+				self.inst(inst.dest, &inst.kind,);
+			}
+			self.term(b.0, &block.term,);
+		}
+		self.out.push_str("}\n",);
+		self.out
+	}
+}
+
+/// prints `module` as an LLVM module for `triple`. callees defined
+/// elsewhere are declared with the types of their first call
+pub fn print_module(module: &Module, triple: &Triple,) -> LlccB<String,> {
+	let mut out = String::new();
+	out.push_str(&format!("target triple = \"{triple}\"\n\n"),);
+
+	let mut declared = vec![];
+	for func in module.functions.iter() {
+		for inst in func.blocks.iter().flat_map(|b| b.insts.iter(),) {
+			let InstKind::Call { callee, args, .. } = &inst.kind else {
+				continue;
+			};
+			if module.function(callee,).is_some() || declared.contains(callee,)
+			{
+				continue;
+			}
+			let params = args
+				.iter()
+				.map(|a| ty(func.ty(*a,),),)
+				.collect::<Vec<_,>>()
+				.join(", ",);
+			let ret = ret_ty(inst.dest.map(|d| func.ty(d,),),);
+			out.push_str(&format!(
+				"declare {ret} @{}({params})\n",
+				symbol(callee)
+			),);
+			declared.push(callee.clone(),);
+		}
+	}
+	if !declared.is_empty() {
+		out.push('\n',);
+	}
+
+	for (i, func,) in module.functions.iter().enumerate() {
+		if i != 0 {
+			out.push('\n',);
+		}
+		out.push_str(&Printer::new(func,).function(),);
+	}
+	X(out,)
+}
+
+/// compiles `src` like `asm::asm_str`, but to LLVM IR
+pub fn llvm_str(
+	src: &str, level: OptLevel, triple: &Triple,
+) -> LlccB<String,> {
+	let func = lower_expr(src,)?;
+	let mut module = Module { functions: vec![func], };
+	optimize_module(&mut module, level,);
+	print_module(&module, triple,)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::err::Container;
+	use crate::ir::Block;
+	use crate::ir::FunctionBuilder;
+	use std::io::Write as _;
+	use std::process::Command;
+	use std::process::Stdio;
+
+	/// whether `llvm-as` accepts `text`, `None` if it is not installed
+	fn llvm_as(text: &str,) -> Option<bool,> {
+		let mut child = Command::new("llvm-as",)
+			.args(["-o", "/dev/null", "-",],)
+			.stdin(Stdio::piped(),)
+			.stderr(Stdio::inherit(),)
+			.spawn()
+			.ok()?;
+		child.stdin.take()?.write_all(text.as_bytes(),).ok()?;
+		Some(child.wait().ok()?.success(),)
+	}
+
+	/// int sum(int n) {
+	///   int s = 0; double d = 0.5;
+	///   for (int i = 0; i < n; i++) s += g(&s, d);
+	///   return s;
+	/// }
+	fn sample() -> Module {
+		let mut fb =
+			FunctionBuilder::new("sum", &[Type::I32,], Some(Type::I32,),);
+		let n = fb.param(0,);
+		let s = fb.slot(Type::I32,);
+		let header = fb.create_block();
+		let body = fb.create_block();
+		let exit = fb.create_block();
+		let zero = fb.iconst(Type::I32, 0,);
+		let one = fb.iconst(Type::I32, 1,);
+		let d = fb.fconst(Type::F64, 0.5,);
+		fb.store_slot(s, zero,);
+		fb.jump(header,);
+		fb.switch_to(header,);
+		let i = fb.push(Some(Type::I32,), InstKind::Phi(vec![],),);
+		let c = fb.cmp(CmpOp::SLt, i, n,);
+		fb.branch(c, body, exit,);
+		fb.switch_to(body,);
+		let addr = fb.slot_addr(s,);
+		let r = fb.call("g", &[addr, d,], Some(Type::I32,),);
+		let old = fb.load(Type::I32, addr,);
+		let new = fb.binary(BinOp::Add, old, r,);
+		fb.store(addr, new,);
+		let next = fb.binary(BinOp::Add, i, one,);
+		fb.jump(header,);
+		fb.switch_to(exit,);
+		let total = fb.load_slot(s,);
+		let wide = fb.cast(CastOp::SExt, total, Type::I64,);
+		let f = fb.cast(CastOp::SIToFP, wide, Type::F32,);
+		let neg = fb.unary(UnOp::FNeg, f,);
+		let back = fb.cast(CastOp::FPToSI, neg, Type::I32,);
+		let result = fb.unary(UnOp::Neg, back,);
+		fb.ret(Some(result,),);
+		let mut func = fb.finish();
+		func.block_mut(header,).insts[0].kind =
+			InstKind::Phi(vec![(Block::ENTRY, zero,), (body, next,)],);
+		Module { functions: vec![func], }
+	}
+
+	#[test]
+	fn prints_valid_module() {
+		let triple: Triple = "aarch64-linux-gnu".parse().unwrap();
+		let text = print_module(&sample(), &triple,).unwrap();
+		let has = |line: &str| text.lines().any(|l| l.trim() == line,);
+		assert!(has("target triple = \"aarch64-linux-gnu\"",), "{text}");
+		assert!(has("declare i32 @g(i64, double)",), "{text}");
+		assert!(has("%slot0 = alloca i32",), "{text}");
+		assert!(has("%v4 = phi i32 [ 0, %bb0 ], [ %v10, %bb2 ]",), "{text}");
+		assert!(has("%v5.i1 = icmp slt i32 %v4, %v0",), "{text}");
+		assert!(has("br i1 %bb1.cond, label %bb2, label %bb3",), "{text}");
+		assert!(
+			has("%v7 = call i32 @g(i64 %v6, double 0x3FE0000000000000)",),
+			"{text}"
+		);
+		assert!(has("%v8.ptr = inttoptr i64 %v6 to i32*",), "{text}");
+		assert!(has("%v13 = sitofp i64 %v12 to float",), "{text}");
+		assert!(has("%v14 = fneg float %v13",), "{text}");
+		assert!(has("ret i32 %v16",), "{text}");
+		assert_ne!(llvm_as(&text,), Some(false), "{text}");
+	}
+
+	#[test]
+	fn entry_becomes_main() {
+		let text =
+			llvm_str("1+9-3", OptLevel::O0, &Triple::default(),).unwrap();
+		assert!(text.contains("define i32 @main()",), "{text}");
+		assert!(!text.contains(lower::ENTRY,), "{text}");
+	}
+
+	/// `lli` runs `main` and exits with its result
+	#[test]
+	#[ignore = "needs lli"]
+	fn lli_exits_with_result() {
+		let text =
+			llvm_str("1+9-3", OptLevel::O0, &Triple::default(),).unwrap();
+		let mut child = Command::new("lli",)
+			.stdin(Stdio::piped(),)
+			.stderr(Stdio::inherit(),)
+			.spawn()
+			.expect("lli is installed",);
+		child.stdin.take().unwrap().write_all(text.as_bytes(),).unwrap();
+		assert_eq!(child.wait().unwrap().code(), Some(7), "{text}");
+	}
+
+	#[test]
+	fn optimized_programs_are_valid() {
+		for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2, OptLevel::O3,] {
+			let mut module = sample();
+			module
+				.functions
+				.push(crate::ir::lower::lower_expr("7.5f-2-0.25",).unwrap(),);
+			optimize_module(&mut module, level,);
+			let text = print_module(&module, &Triple::default(),).unwrap();
+			assert_ne!(llvm_as(&text,), Some(false), "{level}\n{text}");
+		}
+	}
+}
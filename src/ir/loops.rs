@@ -0,0 +1,239 @@
+//! natural loops
+//!
+//! an edge `latch → header` where `header` dominates `latch` is a back edge.
+//! the loop of `header` is every block that reaches one of its latches
+//! without passing through `header`. back edges sharing a header form one
+//! loop
+
+use crate::ir::Block;
+use crate::ir::Function;
+use crate::ir::Inst;
+use crate::ir::InstKind;
+use crate::ir::Terminator;
+use crate::ir::cfg::Cfg;
+use crate::ir::dom::DomTree;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+pub struct Loop {
+	pub header:  Block,
+	pub latches: Vec<Block,>,
+	/// includes `header` and nested loops
+	pub blocks:  BTreeSet<Block,>,
+	/// index of the innermost enclosing loop
+	pub parent:  Option<usize,>,
+	/// outermost loops have depth 1
+	pub depth:   usize,
+}
+
+impl Loop {
+	pub fn contains(&self, b: Block,) -> bool {
+		self.blocks.contains(&b,)
+	}
+
+	/// predecessors of the header from outside the loop
+	pub fn entries<'a,>(
+		&self,
+		cfg: &'a Cfg,
+	) -> impl Iterator<Item = Block,> + 'a {
+		let latches = self.latches.clone();
+		cfg.preds(self.header,)
+			.iter()
+			.copied()
+			.filter(move |p| !latches.contains(p,),)
+	}
+
+	/// edges leaving the loop as `(inside, outside)`
+	pub fn exits(&self, cfg: &Cfg,) -> Vec<(Block, Block,),> {
+		self.blocks
+			.iter()
+			.flat_map(|b| cfg.succs(*b,).iter().map(move |s| (*b, *s,),),)
+			.filter(|(_, s,)| !self.contains(*s,),)
+			.collect()
+	}
+
+	/// the single outside predecessor of the header, if it has no other
+	/// successor. code hoisted out of the loop goes to its end
+	pub fn preheader(&self, cfg: &Cfg,) -> Option<Block,> {
+		let mut entries = self.entries(cfg,);
+		let p = entries.next()?;
+		(entries.next().is_none() && cfg.succs(p,) == [self.header,])
+			.then_some(p,)
+	}
+}
+
+pub struct Loops {
+	loops: Vec<Loop,>,
+}
+
+impl Loops {
+	pub fn new(cfg: &Cfg, dom: &DomTree,) -> Self {
+		let mut latches: HashMap<Block, Vec<Block,>,> = HashMap::new();
+		for b in cfg.rpo() {
+			for s in cfg.succs(*b,) {
+				if dom.dominates(*s, *b,) {
+					latches.entry(*s,).or_default().push(*b,);
+				}
+			}
+		}
+
+		let mut loops: Vec<Loop,> = latches
+			.into_iter()
+			.map(|(header, latches,)| {
+				let mut blocks = BTreeSet::from([header,],);
+				let mut work = latches.clone();
+				while let Some(b,) = work.pop() {
+					if blocks.insert(b,) {
+						work.extend(cfg.preds(b,),);
+					}
+				}
+				Loop { header, latches, blocks, parent: None, depth: 1, }
+			},)
+			.collect();
+		// outer loops first, so that a parent precedes its children
+		loops.sort_by_key(|l| (std::cmp::Reverse(l.blocks.len(),), l.header,),);
+
+		for i in 0..loops.len() {
+			let parent = (0..i).rev().find(|j| {
+				loops[*j].contains(loops[i].header,)
+					&& loops[*j].blocks.len() > loops[i].blocks.len()
+			},);
+			if let Some(p,) = parent {
+				loops[i].parent = Some(p,);
+				loops[i].depth = loops[p].depth + 1;
+			}
+		}
+		Self { loops, }
+	}
+
+	pub fn get(&self, i: usize,) -> &Loop {
+		&self.loops[i]
+	}
+
+	pub fn len(&self,) -> usize {
+		self.loops.len()
+	}
+
+	pub fn is_empty(&self,) -> bool {
+		self.loops.is_empty()
+	}
+
+	/// loop indices ordered so that nested loops come before their parents
+	pub fn innermost_first(&self,) -> Vec<usize,> {
+		let mut order: Vec<usize,> = (0..self.loops.len()).collect();
+		order.sort_by_key(|i| std::cmp::Reverse(self.loops[*i].depth,),);
+		order
+	}
+
+	/// whether no other loop is nested in loop `i`
+	pub fn is_innermost(&self, i: usize,) -> bool {
+		self.loops.iter().all(|l| l.parent != Some(i,),)
+	}
+}
+
+/// gives `lp` a preheader unless it already has one
+///
+/// # Return
+///
+/// returns the preheader and whether it was newly created
+pub fn ensure_preheader(
+	func: &mut Function,
+	lp: &Loop,
+	cfg: &Cfg,
+) -> (Block, bool,) {
+	if let Some(p,) = lp.preheader(cfg,) {
+		return (p, false,);
+	}
+	let entries: Vec<Block,> = lp.entries(cfg,).collect();
+	let pre = func.new_block();
+	for e in entries.iter() {
+		for s in func.block_mut(*e,).term.successors_mut() {
+			if *s == lp.header {
+				*s = pre;
+			}
+		}
+	}
+
+	// values flowing in from outside are merged in the preheader first
+	let mut pre_phis = vec![];
+	let mut header_insts =
+		std::mem::take(&mut func.block_mut(lp.header,).insts,);
+	for inst in header_insts.iter_mut() {
+		let (Some(dest,), InstKind::Phi(incoming,),) =
+			(inst.dest, &mut inst.kind,)
+		else {
+			break;
+		};
+		let (outside, inside,): (Vec<_,>, Vec<_,>,) =
+			incoming.drain(..,).partition(|(p, _,)| entries.contains(p,),);
+		let merged = match outside.as_slice() {
+			[] => None,
+			[(_, v,),] => Some(*v,),
+			_ if outside.iter().all(|(_, v,)| *v == outside[0].1,) => {
+				Some(outside[0].1,)
+			},
+			_ => {
+				let v = func.new_value(func.ty(dest,),);
+				pre_phis.push(Inst {
+					dest: Some(v,),
+					kind: InstKind::Phi(outside,),
+				},);
+				Some(v,)
+			},
+		};
+		*incoming = inside;
+		if let Some(v,) = merged {
+			incoming.insert(0, (pre, v,),);
+		}
+	}
+	func.block_mut(lp.header,).insts = header_insts;
+	let data = func.block_mut(pre,);
+	data.insts = pre_phis;
+	data.term = Terminator::Jump(lp.header,);
+	(pre, true,)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ir::CmpOp;
+	use crate::ir::FunctionBuilder;
+	use crate::ir::Type;
+
+	#[test]
+	fn nested_loops() {
+		// entry → outer ⇄ inner ⇄ inner, inner → outer, outer → exit
+		let mut fb = FunctionBuilder::new("f", &[Type::I32,], None,);
+		let n = fb.param(0,);
+		let outer = fb.create_block();
+		let inner = fb.create_block();
+		let exit = fb.create_block();
+		let zero = fb.iconst(Type::I32, 0,);
+		let c = fb.cmp(CmpOp::SLt, zero, n,);
+		fb.branch(c, outer, exit,);
+		fb.switch_to(outer,);
+		fb.branch(c, inner, exit,);
+		fb.switch_to(inner,);
+		fb.branch(c, inner, outer,);
+		fb.switch_to(exit,);
+		fb.ret(None,);
+		let mut func = fb.finish();
+
+		let cfg = Cfg::new(&func,);
+		let loops = Loops::new(&cfg, &DomTree::new(&cfg,),);
+		assert_eq!(loops.len(), 2);
+		let order = loops.innermost_first();
+		let (i, o,) = (loops.get(order[0],), loops.get(order[1],),);
+		assert_eq!((i.header, i.depth, i.blocks.len(),), (inner, 2, 1,));
+		assert_eq!((o.header, o.depth, o.blocks.len(),), (outer, 1, 2,));
+		assert_eq!(o.exits(&cfg,), vec![(outer, exit,)]);
+		assert_eq!(o.preheader(&cfg,), None);
+
+		let (pre, created,) = ensure_preheader(&mut func, o, &cfg,);
+		assert!(created);
+		let cfg = Cfg::new(&func,);
+		assert!(cfg.preds(outer,).contains(&pre,));
+		assert!(!cfg.preds(outer,).contains(&Block::ENTRY,));
+		assert_eq!(cfg.succs(pre,), [outer]);
+	}
+}
@@ -0,0 +1,206 @@
+//! source → `ir::Function`
+//!
+//! <expr>   ::= <term> { ("+" | "-") <term> }
+//!
+//! <term>   ::= [ "-" ] <number>
+//!
+//! <number> ::= <digits> [ "." <digits> [ "f" ] ]
+//!
+//! a whole number is an `int`, or a `long` if it does not fit in one. a
+//! number with a fraction is a `double`, or a `float` with the `f`
+//! suffix. mixed operands are converted like C does, to the wider of both.
+//! whitespace may separate the tokens
+
+use crate::err::B::X;
+use crate::err::B::Y;
+use crate::err::LlccB;
+use crate::err::LlccError;
+use crate::ir::BinOp;
+use crate::ir::CastOp;
+use crate::ir::Function;
+use crate::ir::FunctionBuilder;
+use crate::ir::Type;
+use crate::ir::UnOp;
+use crate::ir::Value;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// name of the function the whole program is lowered into. returning from it
+/// exits the process
+pub const ENTRY: &str = "_start";
+
+pub fn lower_expr(src: &str,) -> LlccB<Function,> {
+	lower_function(src, ENTRY,)
+}
+
+/// lowers `src` into `int name(void)`, which returns the value instead of
+/// exiting unless it is the `ENTRY`
+pub fn lower_function(src: &str, name: &str,) -> LlccB<Function,> {
+	let mut chars = src.chars().peekable();
+	let mut fb = FunctionBuilder::new(name, &[], Some(Type::I32,),);
+
+	let mut acc = parse_term(&mut chars, &mut fb,)?;
+	while let Some(c,) = next_token(&mut chars,) {
+		let op = match c {
+			'+' => BinOp::Add,
+			'-' => BinOp::Sub,
+			a => {
+				return Y(LlccError::syntax(format!(
+					"expected `+` or `-`, found `{a}`"
+				),),);
+			},
+		};
+		let rhs = parse_term(&mut chars, &mut fb,)?;
+		let ty = [fb.ty(acc,), fb.ty(rhs,),]
+			.into_iter()
+			.max_by_key(|t| rank(*t,),)
+			.unwrap();
+		let (lhs, rhs,) =
+			(convert(&mut fb, acc, ty,), convert(&mut fb, rhs, ty,),);
+		let op = match (op, ty.is_float(),) {
+			(BinOp::Add, true,) => BinOp::FAdd,
+			(BinOp::Sub, true,) => BinOp::FSub,
+			(op, _,) => op,
+		};
+		acc = fb.binary(op, lhs, rhs,);
+	}
+
+	let acc = convert(&mut fb, acc, Type::I32,);
+	fb.ret(Some(acc,),);
+	X(fb.finish(),)
+}
+
+/// order of the usual arithmetic conversions
+fn rank(ty: Type,) -> u8 {
+	match ty {
+		Type::F64 => 3,
+		Type::F32 => 2,
+		Type::I64 => 1,
+		_ => 0,
+	}
+}
+
+fn convert(fb: &mut FunctionBuilder, v: Value, to: Type,) -> Value {
+	let from = fb.ty(v,);
+	let op = match (from.is_float(), to.is_float(),) {
+		_ if from == to => return v,
+		(false, true,) => CastOp::SIToFP,
+		(true, false,) => CastOp::FPToSI,
+		(false, false,) if from.bits() < to.bits() => CastOp::SExt,
+		(false, false,) => CastOp::Trunc,
+		_ if from == Type::F32 => CastOp::FPExt,
+		_ => CastOp::FPTrunc,
+	};
+	fb.cast(op, v, to,)
+}
+
+/// the next character that is not whitespace
+fn next_token(chars: &mut Peekable<Chars,>,) -> Option<char,> {
+	chars.find(|c| !c.is_whitespace(),)
+}
+
+fn parse_term(
+	chars: &mut Peekable<Chars,>,
+	fb: &mut FunctionBuilder,
+) -> LlccB<Value,> {
+	while chars.next_if(|c| c.is_whitespace(),).is_some() {}
+	if chars.next_if_eq(&'-',).is_none() {
+		return parse_number(chars, fb,);
+	}
+
+	let v = parse_number(chars, fb,)?;
+	let op = if fb.ty(v,).is_float() { UnOp::FNeg } else { UnOp::Neg };
+	X(fb.unary(op, v,),)
+}
+
+fn parse_number(
+	chars: &mut Peekable<Chars,>,
+	fb: &mut FunctionBuilder,
+) -> LlccB<Value,> {
+	while chars.next_if(|c| c.is_whitespace(),).is_some() {}
+	let mut num = digits(chars,);
+	if num.is_empty() {
+		let found =
+			chars.peek().map_or("the end".to_string(), |c| format!("`{c}`"),);
+		return Y(LlccError::syntax(format!(
+			"expected a number, found {found}"
+		),),);
+	}
+	if chars.next_if_eq(&'.',).is_none() {
+		let Ok(value,) = num.parse::<i64>() else {
+			return Y(LlccError::syntax(format!(
+				"integer literal `{num}` does not fit in a `long`"
+			),),);
+		};
+		let ty =
+			if i32::try_from(value,).is_ok() { Type::I32 } else { Type::I64 };
+		return X(fb.iconst(ty, value,),);
+	}
+
+	num.push('.',);
+	num.push_str(&digits(chars,),);
+	let ty =
+		if chars.next_if_eq(&'f',).is_some() { Type::F32 } else { Type::F64 };
+	X(fb.fconst(ty, num.parse()?,),)
+}
+
+fn digits(chars: &mut Peekable<Chars,>,) -> String {
+	let mut num = "".to_string();
+	while let Some(c,) = chars.next_if(|c| c.is_ascii_digit(),) {
+		num.push(c,);
+	}
+	num
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::err::Container;
+	use crate::ir::InstKind;
+	use crate::opt::OptLevel;
+	use crate::opt::optimize;
+
+	#[test]
+	fn skips_whitespace() {
+		let spaced = lower_expr(" 1 +\t2\n- 3 ",).unwrap();
+		let packed = lower_expr("1+2-3",).unwrap();
+		assert_eq!(spaced, packed);
+	}
+
+	fn folded(src: &str,) -> InstKind {
+		let mut func = lower_expr(src,).unwrap();
+		optimize(&mut func, OptLevel::O1,);
+		func.blocks[0].insts[0].kind.clone()
+	}
+
+	#[test]
+	fn negative_and_long_literals() {
+		assert_eq!(folded("-1",), InstKind::Const(-1,));
+		assert_eq!(folded("2 - -3",), InstKind::Const(5,));
+		assert_eq!(folded("-2.5 + 1",), InstKind::Const(-1,));
+		// `long` arithmetic, truncated to the `int` exit code
+		assert_eq!(folded("3000000000 - 2999999999",), InstKind::Const(1,));
+		assert_eq!(folded("-2147483648",), InstKind::Const(i32::MIN as i64,));
+		assert_eq!(folded("4294967296 + 7",), InstKind::Const(7,));
+
+		let func = lower_expr("3000000000",).unwrap();
+		assert!(func.value_types.contains(&Type::I64,), "{func}");
+		let func = lower_expr("2147483647",).unwrap();
+		assert!(!func.value_types.contains(&Type::I64,), "{func}");
+	}
+
+	#[test]
+	fn rejects_unknown_characters() {
+		for src in ["1 * 2", "1+2;", "1 2",] {
+			let Y(e,) = lower_expr(src,) else {
+				panic!("`{src}` was accepted");
+			};
+			assert!(e.to_string().contains("expected `+` or `-`",), "{e}");
+		}
+		for src in ["", "1 +", "--1", "99999999999999999999",] {
+			let Y(_,) = lower_expr(src,) else {
+				panic!("`{src}` was accepted");
+			};
+		}
+	}
+}
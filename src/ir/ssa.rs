@@ -0,0 +1,350 @@
+//! construction and destruction of SSA form
+//!
+//! `mem2reg` promotes stack slots into SSA values, placing phis on the
+//! iterated dominance frontier of the stores (Cytron et al.).
+//! `out_of_ssa` replaces phis with copies in the predecessors, splitting
+//! critical edges and sequentializing each parallel copy.
+
+use crate::ir::Block;
+use crate::ir::Function;
+use crate::ir::Inst;
+use crate::ir::InstKind;
+use crate::ir::Slot;
+use crate::ir::Value;
+use crate::ir::cfg::Cfg;
+use crate::ir::cfg::split_edge;
+use crate::ir::dom::DomTree;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/// slots that are only accessed by `StackLoad`/`StackStore`
+pub fn promotable_slots(func: &Function,) -> Vec<Slot,> {
+	let mut escaped = HashSet::new();
+	for block in func.blocks.iter() {
+		for inst in block.insts.iter() {
+			if let InstKind::StackAddr(s,) = inst.kind {
+				escaped.insert(s,);
+			}
+		}
+	}
+	(0..func.slots.len() as u32)
+		.map(Slot,)
+		.filter(|s| !escaped.contains(s,),)
+		.collect()
+}
+
+/// # Return
+///
+/// returns number of promoted slots
+///
+/// entry block must not be a branch target
+pub fn mem2reg(func: &mut Function,) -> usize {
+	let slots = promotable_slots(func,);
+	if slots.is_empty() {
+		return 0;
+	}
+	let promoted: HashMap<Slot, usize,> =
+		slots.iter().enumerate().map(|(i, s,)| (*s, i,),).collect();
+
+	let cfg = Cfg::new(func,);
+	let dom = DomTree::new(&cfg,);
+
+	// phi placement
+	let mut phi_slot: HashMap<Value, usize,> = HashMap::new();
+	for (i, slot,) in slots.iter().enumerate() {
+		let defs = func.block_ids().filter(|b| {
+			cfg.is_reachable(*b,)
+				&& func.block(*b,).insts.iter().any(
+					|inst| matches!(inst.kind, InstKind::StackStore(s, _) if s == *slot),
+				)
+		},);
+		for b in dom.iterated_frontier(&cfg, defs,) {
+			let dest = func.new_value(func.slots[slot.index()],);
+			func.block_mut(b,).insts.insert(
+				0,
+				Inst { dest: Some(dest,), kind: InstKind::Phi(vec![],), },
+			);
+			phi_slot.insert(dest, i,);
+		}
+	}
+
+	// reading a slot before any store is undefined behavior in C. zero is as
+	// good a value as any
+	let undef: Vec<Value,> =
+		slots.iter().map(|s| func.new_value(func.slots[s.index()],),).collect();
+	let entry = &mut func.block_mut(Block::ENTRY,).insts;
+	for v in undef.iter().rev() {
+		entry.insert(0, Inst { dest: Some(*v,), kind: InstKind::Const(0,), },);
+	}
+
+	// renaming
+	let mut stacks: Vec<Vec<Value,>,> =
+		undef.iter().map(|v| vec![*v],).collect();
+	let mut replace = HashMap::new();
+	enum Visit {
+		Enter(Block,),
+		Exit(Vec<usize,>,),
+	}
+	let mut work = vec![Visit::Enter(Block::ENTRY,)];
+	while let Some(visit,) = work.pop() {
+		let b = match visit {
+			Visit::Enter(b,) => b,
+			Visit::Exit(heights,) => {
+				for (stack, h,) in stacks.iter_mut().zip(heights,) {
+					stack.truncate(h,);
+				}
+				continue;
+			},
+		};
+		work.push(Visit::Exit(stacks.iter().map(|s| s.len(),).collect(),),);
+
+		let block = func.block_mut(b,);
+		block.insts.retain(|inst| match &inst.kind {
+			InstKind::Phi(_,) => {
+				if let Some(i,) = inst.dest.and_then(|d| phi_slot.get(&d,),) {
+					stacks[*i].push(inst.dest.unwrap(),);
+				}
+				true
+			},
+			InstKind::StackLoad(s,) if promoted.contains_key(s,) => {
+				let top = *stacks[promoted[s]].last().unwrap();
+				replace.insert(inst.dest.unwrap(), top,);
+				false
+			},
+			InstKind::StackStore(s, v,) if promoted.contains_key(s,) => {
+				stacks[promoted[s]].push(*v,);
+				false
+			},
+			_ => true,
+		},);
+
+		for succ in cfg.succs(b,) {
+			for inst in func.block_mut(*succ,).insts.iter_mut() {
+				let Some(dest,) = inst.dest else { continue };
+				let InstKind::Phi(incoming,) = &mut inst.kind else { break };
+				if let Some(i,) = phi_slot.get(&dest,) {
+					incoming.push((b, *stacks[*i].last().unwrap(),),);
+				}
+			}
+		}
+
+		for child in dom.children(b,).iter().rev() {
+			work.push(Visit::Enter(*child,),);
+		}
+	}
+	func.replace_uses(&replace,);
+
+	let uses = func.use_counts();
+	func.block_mut(Block::ENTRY,).insts.retain(|inst| {
+		inst.dest.is_none_or(|d| !undef.contains(&d,) || uses[d.index()] > 0,)
+	},);
+
+	slots.len()
+}
+
+/// lowers phis into copies at the end of each predecessor. after this the
+/// function is no longer in SSA form
+pub fn out_of_ssa(func: &mut Function,) {
+	let cfg = Cfg::new(func,);
+	let mut critical = vec![];
+	for b in func.block_ids() {
+		if func.block(b,).phis().next().is_none() {
+			continue;
+		}
+		for p in cfg.preds(b,) {
+			if cfg.is_critical(*p, b,) {
+				critical.push((*p, b,),);
+			}
+		}
+	}
+	for (from, to,) in critical {
+		split_edge(func, from, to,);
+	}
+
+	for b in func.block_ids() {
+		let phi_count = func.block(b,).phis().count();
+		if phi_count == 0 {
+			continue;
+		}
+		let phis: Vec<Inst,> =
+			func.block_mut(b,).insts.drain(..phi_count,).collect();
+
+		let mut per_pred: HashMap<Block, Vec<(Value, Value,),>,> =
+			HashMap::new();
+		for phi in phis {
+			let (Some(dest,), InstKind::Phi(incoming,),) =
+				(phi.dest, phi.kind,)
+			else {
+				unreachable!("phis always define a value")
+			};
+			for (pred, src,) in incoming {
+				per_pred.entry(pred,).or_default().push((dest, src,),);
+			}
+		}
+
+		let mut preds: Vec<_,> = per_pred.into_iter().collect();
+		preds.sort_by_key(|(p, _,)| *p,);
+		for (pred, copies,) in preds {
+			let seq = sequentialize(&copies, |saved| {
+				let ty = func.ty(saved,);
+				func.new_value(ty,)
+			},);
+			func.block_mut(pred,).insts.extend(seq.into_iter().map(
+				|(d, s,)| Inst { dest: Some(d,), kind: InstKind::Copy(s,), },
+			),);
+		}
+	}
+}
+
+/// orders the parallel copy `dest ← src` so that it can be executed one
+/// copy at a time. cycles are broken with a fresh value obtained from
+/// `fresh`, which receives the value being saved
+///
+/// see Boissinot et al., "Revisiting Out-of-SSA Translation for Correctness,
+/// Code Quality, and Efficiency"
+pub fn sequentialize(
+	copies: &[(Value, Value,)],
+	mut fresh: impl FnMut(Value,) -> Value,
+) -> Vec<(Value, Value,),> {
+	let mut seq = vec![];
+	// where the original value of a source currently lives
+	let mut loc: HashMap<Value, Value,> = HashMap::new();
+	// which source a destination has to receive
+	let mut pred: HashMap<Value, Value,> = HashMap::new();
+	let mut ready = vec![];
+	let mut todo = vec![];
+	let mut done = HashSet::new();
+
+	for (dest, src,) in copies.iter().filter(|(d, s,)| d != s,) {
+		loc.insert(*src, *src,);
+		pred.insert(*dest, *src,);
+		todo.push(*dest,);
+	}
+	for (dest, _,) in copies.iter().filter(|(d, s,)| d != s,) {
+		// nobody reads `dest` before it is overwritten
+		if !loc.contains_key(dest,) {
+			ready.push(*dest,);
+		}
+	}
+
+	while let Some(b,) = todo.pop() {
+		while let Some(b,) = ready.pop() {
+			let a = pred[&b];
+			let c = loc[&a];
+			seq.push((b, c,),);
+			done.insert(b,);
+			loc.insert(a, b,);
+			if a == c && pred.contains_key(&a,) {
+				ready.push(a,);
+			}
+		}
+
+		if !done.contains(&b,) {
+			// with every chain drained, a pending `b` is part of a cycle. save
+			// it to free its location
+			let tmp = fresh(b,);
+			seq.push((tmp, b,),);
+			loc.insert(b, tmp,);
+			ready.push(b,);
+			todo.push(b,);
+		}
+	}
+
+	seq
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ir::BinOp;
+	use crate::ir::FunctionBuilder;
+	use crate::ir::Terminator;
+	use crate::ir::Type;
+	use quickcheck_macros::quickcheck;
+
+	#[test]
+	fn phi_on_join() {
+		// int x; if (p) x = 1; else x = 2; return x + p;
+		let mut fb =
+			FunctionBuilder::new("f", &[Type::I32,], Some(Type::I32,),);
+		let x = fb.slot(Type::I32,);
+		let then = fb.create_block();
+		let els = fb.create_block();
+		let join = fb.create_block();
+		let p = fb.param(0,);
+		fb.branch(p, then, els,);
+		fb.switch_to(then,);
+		let one = fb.iconst(Type::I32, 1,);
+		fb.store_slot(x, one,);
+		fb.jump(join,);
+		fb.switch_to(els,);
+		let two = fb.iconst(Type::I32, 2,);
+		fb.store_slot(x, two,);
+		fb.jump(join,);
+		fb.switch_to(join,);
+		let loaded = fb.load_slot(x,);
+		let sum = fb.binary(BinOp::Add, loaded, p,);
+		fb.ret(Some(sum,),);
+		let mut f = fb.finish();
+
+		assert_eq!(mem2reg(&mut f,), 1);
+		let join = f.block(join,);
+		let InstKind::Phi(incoming,) = &join.insts[0].kind else {
+			panic!("expected phi at join:\n{f}")
+		};
+		let mut incoming = incoming.clone();
+		incoming.sort();
+		assert_eq!(incoming, vec![(then, one,), (els, two,)]);
+		assert_eq!(
+			join.insts[1].kind,
+			InstKind::Binary(BinOp::Add, join.insts[0].dest.unwrap(), p)
+		);
+		assert!(f.blocks.iter().flat_map(|b| b.insts.iter(),).all(
+			|i| !matches!(
+				i.kind,
+				InstKind::StackLoad(_,) | InstKind::StackStore(..,)
+			)
+		));
+
+		out_of_ssa(&mut f,);
+		assert!(f.blocks.iter().all(|b| b.phis().next().is_none()));
+		assert!(
+			matches!(f.block(then,).insts.last().unwrap().kind, InstKind::Copy(v) if v == one)
+		);
+		assert!(matches!(
+			f.block(Block::ENTRY,).term,
+			Terminator::Branch { .. }
+		));
+	}
+
+	#[quickcheck]
+	fn sequentialized_copy_is_parallel(perm: Vec<(u8, u8,),>,) -> bool {
+		let mut seen = HashSet::new();
+		let copies: Vec<(Value, Value,),> = perm
+			.into_iter()
+			.filter(|(d, _,)| seen.insert(*d,),)
+			.map(|(d, s,)| (Value(d as u32 % 16,), Value(s as u32 % 16,),),)
+			.collect::<HashMap<_, _,>>()
+			.into_iter()
+			.collect();
+
+		let initial: HashMap<Value, u32,> =
+			(0..16).map(|i| (Value(i,), i,),).collect();
+		let mut expected = initial.clone();
+		for (d, s,) in copies.iter() {
+			expected.insert(*d, initial[s],);
+		}
+
+		let mut next = 100;
+		let seq = sequentialize(&copies, |_| {
+			next += 1;
+			Value(next,)
+		},);
+		let mut actual = initial;
+		for (d, s,) in seq {
+			let v = actual[&s];
+			actual.insert(d, v,);
+		}
+		(0..16).all(|i| actual[&Value(i,)] == expected[&Value(i,)],)
+	}
+}
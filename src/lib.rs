@@ -1,8 +1,5 @@
 #![feature(try_trait_v2)]
-#![feature(error_generic_member_access)]
-#![feature(tuple_trait)]
-#![feature(generic_const_items)]
-#![feature(associated_type_defaults)]
+#![feature(try_trait_v2_residual)]
 
 use crate::err::B::X;
 // use crate::err::B::Y;
@@ -13,10 +10,15 @@ use std::path::PathBuf;
 pub mod asm;
 pub mod err;
 pub mod front;
+pub mod ir;
+pub mod obj;
+pub mod opt;
 // pub mod parse;
 pub mod orchestrator;
 pub mod register;
 pub mod semantics;
+pub mod target;
+pub mod toolchain;
 
 // trait ExpressionConverter {
 // 	type Out: Evaluable;
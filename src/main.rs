@@ -1,10 +1,23 @@
 use colored::Colorize;
 use llcc::err::LlccB;
-use llcc::orchestrator::run;
+use llcc::front::LlccCompiler;
+use llcc::front::objdump;
+use llcc::orchestrator::run_cli;
 
 fn main() -> LlccB<(),> {
-	let status = run(None::<&str,>,)?;
-	eprintln!("{}", format!("exit status: {}", status).purple());
+	let mut args = std::env::args().skip(1,).peekable();
+	if args.next_if_eq("objdump",).is_some() {
+		for path in args {
+			println!("{path}: {}", objdump(&path,)?);
+		}
+		return LlccB::X((),);
+	}
+
+	let compiler = LlccCompiler::from_args(args,)?;
+	compiler.check_toolchain()?;
+	if let Some(status,) = run_cli(compiler,)? {
+		eprintln!("{}", format!("exit status: {}", status).purple());
+	}
 
 	LlccB::X((),)
 }
@@ -0,0 +1,159 @@
+//! relocatable objects
+//!
+//! an `Object` is what an assembler produces: the bytes of each section,
+//! the symbols defined or referenced, and the places the linker still has
+//! to patch once addresses are known
+
+pub mod ar;
+pub mod elf;
+pub mod link;
+
+/// sections of an object, in the order they are laid out
+#[derive(
+	Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, strum::Display,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum SectionKind {
+	Text,
+	Rodata,
+	Data,
+	/// zero initialized, takes no bytes in the file
+	Bss,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub struct Section {
+	pub kind:  SectionKind,
+	/// empty for `.bss`, which only has a `size`
+	pub data:  Vec<u8,>,
+	pub size:  u64,
+	pub align: u64,
+}
+
+impl Section {
+	pub fn new(kind: SectionKind,) -> Self {
+		let align = if kind == SectionKind::Text { 4 } else { 8 };
+		Self { kind, data: vec![], size: 0, align, }
+	}
+
+	/// appends `bytes` and returns their offset
+	pub fn push(&mut self, bytes: &[u8],) -> u64 {
+		let offset = self.size;
+		if self.kind != SectionKind::Bss {
+			self.data.extend_from_slice(bytes,);
+		}
+		self.size += bytes.len() as u64;
+		offset
+	}
+}
+
+/// visibility of a symbol to other objects
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash,)]
+pub enum Binding {
+	Local,
+	Global,
+	/// global, but another definition takes precedence and an undefined
+	/// reference resolves to 0
+	Weak,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub struct Symbol {
+	pub name:    String,
+	/// `None` if the symbol is only referenced
+	pub section: Option<SectionKind,>,
+	pub offset:  u64,
+	pub binding: Binding,
+}
+
+/// how a relocation patches its place. `S` is the address of the symbol
+/// plus the addend, `P` the address of the place
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash,)]
+pub enum RelocKind {
+	/// `bl`, bits 27:2 of `S - P`
+	Call26,
+	/// `b`, bits 27:2 of `S - P`
+	Jump26,
+	/// `b.cond`, `cbz` and `cbnz`, bits 20:2 of `S - P`
+	CondBr19,
+	/// `tbz` and `tbnz`, bits 15:2 of `S - P`
+	TstBr14,
+	/// `adr`, bits 20:0 of `S - P`
+	AdrPrelLo21,
+	/// `adrp`, bits 32:12 of `Page(S) - Page(P)`
+	AdrPrelPgHi21,
+	/// `add`, bits 11:0 of `S`
+	AddAbsLo12Nc,
+	/// byte `ldr` and `str`, bits 11:0 of `S`
+	Ldst8AbsLo12Nc,
+	/// 16 bit `ldr` and `str`, bits 11:1 of `S`
+	Ldst16AbsLo12Nc,
+	/// 32 bit `ldr` and `str`, bits 11:2 of `S`
+	Ldst32AbsLo12Nc,
+	/// 64 bit `ldr` and `str`, bits 11:3 of `S`
+	Ldst64AbsLo12Nc,
+	/// 128 bit `ldr` and `str`, bits 11:4 of `S`
+	Ldst128AbsLo12Nc,
+	/// `.quad`, all of `S`
+	Abs64,
+	/// `.word`, `S` if it fits 32 bits
+	Abs32,
+	/// `.word`, `S - P`
+	Prel32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub struct Relocation {
+	pub section: SectionKind,
+	pub offset:  u64,
+	pub kind:    RelocKind,
+	pub symbol:  String,
+	pub addend:  i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq,)]
+pub struct Object {
+	pub sections:    Vec<Section,>,
+	pub symbols:     Vec<Symbol,>,
+	pub relocations: Vec<Relocation,>,
+}
+
+impl Object {
+	pub fn section(&self, kind: SectionKind,) -> Option<&Section,> {
+		self.sections.iter().find(|s| s.kind == kind,)
+	}
+
+	/// the section `kind`, created empty if there is none yet
+	pub fn section_mut(&mut self, kind: SectionKind,) -> &mut Section {
+		let i = match self.sections.iter().position(|s| s.kind == kind,) {
+			Some(i,) => i,
+			None => {
+				self.sections.push(Section::new(kind,),);
+				self.sections.sort_by_key(|s| s.kind,);
+				self.sections.iter().position(|s| s.kind == kind,).unwrap()
+			},
+		};
+		&mut self.sections[i]
+	}
+
+	pub fn symbol(&self, name: &str,) -> Option<&Symbol,> {
+		self.symbols.iter().find(|s| s.name == name,)
+	}
+
+	/// the symbol `name`, added as an undefined local if it is unknown
+	pub fn symbol_mut(&mut self, name: &str,) -> &mut Symbol {
+		let i = match self.symbols.iter().position(|s| s.name == name,) {
+			Some(i,) => i,
+			None => {
+				self.symbols.push(Symbol {
+					name:    name.to_string(),
+					section: None,
+					offset:  0,
+					binding: Binding::Local,
+				},);
+				self.symbols.len() - 1
+			},
+		};
+		&mut self.symbols[i]
+	}
+}
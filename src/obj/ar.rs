@@ -0,0 +1,407 @@
+//! `ar` archives of objects
+//!
+//! an archive is `!<arch>\n` followed by members, each with a 60 byte
+//! header of space padded text fields and its data padded to an even
+//! length. GNU archives start with a symbol index named `/` (or `/SYM64/`
+//! with 64 bit offsets) and keep names longer than 15 bytes in a `//`
+//! member, which the headers of those members refer to as `/<offset>`.
+//! archives are written that way too, like `ar rcsD` would
+
+use super::Binding;
+use super::elf;
+use crate::err::B::X;
+use crate::err::B::Y;
+use crate::err::LlccB;
+use crate::err::LlccError;
+
+pub const MAGIC: &[u8; 8] = b"!<arch>\n";
+pub const HEADER_SIZE: usize = 60;
+
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub struct Member {
+	pub name: String,
+	pub data: Vec<u8,>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq,)]
+pub struct Archive {
+	pub members: Vec<Member,>,
+	/// global symbols and the index of the member defining them
+	pub symbols: Vec<(String, usize,),>,
+}
+
+impl Archive {
+	/// index of the member defining `name`
+	pub fn member_defining(&self, name: &str,) -> Option<usize,> {
+		self.symbols
+			.iter()
+			.find(|(symbol, _,)| symbol == name,)
+			.map(|(_, i,)| *i,)
+	}
+
+	/// fills `symbols` from the members that are objects
+	pub fn index_symbols(&mut self,) {
+		self.symbols.clear();
+		for (i, member,) in self.members.iter().enumerate() {
+			let X(obj,) = elf::read(&member.data,) else {
+				continue;
+			};
+			for symbol in obj.symbols {
+				if symbol.section.is_some() && symbol.binding != Binding::Local
+				{
+					self.symbols.push((symbol.name, i,),);
+				}
+			}
+		}
+	}
+}
+
+/// the space padded text field `bytes`
+fn text(bytes: &[u8],) -> &str {
+	std::str::from_utf8(bytes,).unwrap_or_default().trim_end()
+}
+
+fn malformed(what: impl Into<String,>,) -> LlccError {
+	LlccError::syntax(format!("malformed archive: {}", what.into()),)
+}
+
+pub fn read(bytes: &[u8],) -> LlccB<Archive,> {
+	if !bytes.starts_with(MAGIC,) {
+		return Y(LlccError::unsupported(
+			"file format, expected an `ar` archive",
+		),);
+	}
+
+	let mut archive = Archive::default();
+	let mut long_names: &[u8] = &[];
+	// symbol index as member offsets, resolved once all offsets are known
+	let mut index = vec![];
+	let mut offsets = vec![];
+	let mut at = MAGIC.len();
+	while at < bytes.len() {
+		let Some(header,) = bytes.get(at..at + HEADER_SIZE,) else {
+			return Y(malformed(format!("truncated header at {at:#x}"),),);
+		};
+		if &header[58..] != b"`\n" {
+			return Y(malformed(format!("bad header at {at:#x}"),),);
+		}
+		let Ok(size,) = text(&header[48..58],).parse::<usize>() else {
+			return Y(malformed(format!("bad member size at {at:#x}"),),);
+		};
+		let start = at + HEADER_SIZE;
+		let data =
+			start.checked_add(size,).and_then(|end| bytes.get(start..end,),);
+		let Some(mut data,) = data else {
+			return Y(malformed(format!("truncated member at {at:#x}"),),);
+		};
+		let header_at = at;
+		at = (start + size).next_multiple_of(2,);
+
+		let name = text(&header[..16],);
+		let name = match name {
+			"/" | "/SYM64/" => {
+				let width = if name == "/" { 4 } else { 8 };
+				index = symbol_index(data, width,)?;
+				continue;
+			},
+			"//" => {
+				long_names = data;
+				continue;
+			},
+			_ => {
+				if let Some(offset,) = name.strip_prefix('/',) {
+					// GNU long name
+					let Some(tail,) = offset
+						.parse::<usize>()
+						.ok()
+						.and_then(|o| long_names.get(o..,),)
+					else {
+						return Y(
+							malformed(format!("bad long name `{name}`"),),
+						);
+					};
+					let end = tail
+						.iter()
+						.position(|b| *b == b'\n',)
+						.unwrap_or(tail.len(),);
+					text(&tail[..end],).trim_end_matches('/',).to_string()
+				} else if let Some(len,) = name.strip_prefix("#1/",) {
+					// BSD name stored in front of the data
+					let len = len
+						.parse::<usize>()
+						.unwrap_or_default()
+						.min(data.len(),);
+					let name =
+						text(&data[..len],).trim_end_matches('\0',).to_string();
+					data = &data[len..];
+					name
+				} else {
+					name.trim_end_matches('/',).to_string()
+				}
+			},
+		};
+		offsets.push(header_at,);
+		archive.members.push(Member { name, data: data.to_vec(), },);
+	}
+
+	for (symbol, offset,) in index {
+		match offsets.iter().position(|o| *o == offset,) {
+			Some(member,) => archive.symbols.push((symbol, member,),),
+			None => {
+				return Y(malformed(format!(
+					"symbol `{symbol}` indexed at {offset:#x}, where no \
+					 member is"
+				),),);
+			},
+		}
+	}
+	if archive.symbols.is_empty() {
+		archive.index_symbols();
+	}
+	X(archive,)
+}
+
+fn push_member(out: &mut Vec<u8,>, name: &str, data: &[u8],) {
+	// zero dates and ids keep the output reproducible
+	let header = format!(
+		"{name:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+		0,
+		0,
+		0,
+		644,
+		data.len()
+	);
+	out.extend_from_slice(header.as_bytes(),);
+	out.extend_from_slice(data,);
+	if out.len() % 2 == 1 {
+		out.push(b'\n',);
+	}
+}
+
+/// encodes `archive` in the GNU format, indexing `symbols`
+pub fn write(archive: &Archive,) -> Vec<u8,> {
+	let mut long_names = vec![];
+	let mut names = vec![];
+	for member in &archive.members {
+		// names end in `/` so they may contain spaces
+		if member.name.len() < 16 {
+			names.push(format!("{}/", member.name),);
+		} else {
+			names.push(format!("/{}", long_names.len()),);
+			long_names.extend_from_slice(member.name.as_bytes(),);
+			long_names.extend_from_slice(b"/\n",);
+		}
+	}
+
+	let index_size = 4 + archive
+		.symbols
+		.iter()
+		.map(|(name, _,)| 4 + name.len() + 1,)
+		.sum::<usize>();
+	let mut at = MAGIC.len();
+	if !archive.symbols.is_empty() {
+		at += HEADER_SIZE + index_size.next_multiple_of(2,);
+	}
+	if !long_names.is_empty() {
+		at += HEADER_SIZE + long_names.len().next_multiple_of(2,);
+	}
+	let mut offsets = vec![];
+	for member in &archive.members {
+		offsets.push(at as u32,);
+		at += HEADER_SIZE + member.data.len().next_multiple_of(2,);
+	}
+
+	let mut out = MAGIC.to_vec();
+	if !archive.symbols.is_empty() {
+		let mut index = (archive.symbols.len() as u32).to_be_bytes().to_vec();
+		for (_, member,) in &archive.symbols {
+			index.extend_from_slice(&offsets[*member].to_be_bytes(),);
+		}
+		for (name, _,) in &archive.symbols {
+			index.extend_from_slice(name.as_bytes(),);
+			index.push(0,);
+		}
+		push_member(&mut out, "/", &index,);
+	}
+	if !long_names.is_empty() {
+		push_member(&mut out, "//", &long_names,);
+	}
+	for (member, name,) in archive.members.iter().zip(&names,) {
+		push_member(&mut out, name, &member.data,);
+	}
+	out
+}
+
+/// the names and member offsets of a big endian symbol index
+fn symbol_index(data: &[u8], width: usize,) -> LlccB<Vec<(String, usize,),>,> {
+	let number = |at: usize| {
+		let bytes = data.get(at..at + width,)?;
+		Some(bytes.iter().fold(0usize, |n, b| n << 8 | *b as usize,),)
+	};
+	let Some(count,) = number(0,) else {
+		return Y(malformed("truncated symbol index",),);
+	};
+	let names = count.checked_add(1,).and_then(|n| n.checked_mul(width,),);
+	let names = names.and_then(|start| data.get(start..,),);
+	let mut names = names.unwrap_or_default().split(|b| *b == 0,);
+	let mut index = vec![];
+	for i in 0..count {
+		let (Some(offset,), Some(name,),) =
+			(number(width * (i + 1),), names.next(),)
+		else {
+			return Y(malformed("truncated symbol index",),);
+		};
+		index.push((String::from_utf8_lossy(name,).into_owned(), offset,),);
+	}
+	X(index,)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::err::Container;
+	use std::process::Command;
+
+	fn header(name: &str, size: usize,) -> Vec<u8,> {
+		let header = format!(
+			"{name:<16}{:<12}{:<6}{:<6}{:<8}{size:<10}`\n",
+			0, 0, 0, 644
+		);
+		assert_eq!(header.len(), HEADER_SIZE);
+		header.into_bytes()
+	}
+
+	#[test]
+	fn reads_gnu_archives() {
+		let long_names = b"a_rather_long_name.o/\n";
+		let mut bytes = MAGIC.to_vec();
+		// one symbol `f`, defined by the member at 0xa0
+		let index =
+			[&1u32.to_be_bytes()[..], &0xa0u32.to_be_bytes(), b"f\0",].concat();
+		bytes.extend(header("/", index.len(),),);
+		bytes.extend(&index,);
+		bytes.extend(header("//", long_names.len(),),);
+		bytes.extend(long_names,);
+		assert_eq!(bytes.len(), 0xa0);
+		bytes.extend(header("/0", 3,),);
+		// odd sizes are padded
+		bytes.extend(b"abc\n",);
+		bytes.extend(header("b.o/", 2,),);
+		bytes.extend(b"de",);
+
+		let archive = read(&bytes,).unwrap();
+		let names: Vec<&str,> =
+			archive.members.iter().map(|m| m.name.as_str(),).collect();
+		assert_eq!(names, ["a_rather_long_name.o", "b.o"]);
+		assert_eq!(archive.members[0].data, b"abc");
+		assert_eq!(archive.members[1].data, b"de");
+		assert_eq!(archive.member_defining("f",), Some(0));
+
+		assert!(matches!(read(&bytes[..bytes.len() - 1],), Y(_,)));
+		assert!(matches!(read(b"!<arch>\nnonsense",), Y(_,)));
+	}
+
+	#[test]
+	#[ignore = "needs binutils"]
+	fn reads_host_archives() {
+		let f = elf::tests::llvm_mc(".globl f\nf: bl g",);
+		let g = elf::tests::llvm_mc(
+			".globl g\ng: ret\n.data\n.weak w\nw: .xword 0",
+		);
+		let dir = std::env::temp_dir()
+			.join(format!("llcc-ar-{}", std::process::id()),);
+		std::fs::create_dir_all(&dir,).unwrap();
+		std::fs::write(dir.join("f.o",), &f,).unwrap();
+		std::fs::write(dir.join("g_with_a_long_name.o",), &g,).unwrap();
+		let status = Command::new("ar",)
+			.args(["rcs", "libx.a", "f.o", "g_with_a_long_name.o",],)
+			.current_dir(&dir,)
+			.status()
+			.expect("ar is installed",);
+		let bytes = std::fs::read(dir.join("libx.a",),);
+		std::fs::remove_dir_all(&dir,).unwrap();
+		assert!(status.success());
+		let bytes = bytes.unwrap();
+
+		let archive = read(&bytes,).unwrap();
+		assert_eq!(
+			archive.members,
+			[
+				Member { name: "f.o".to_string(), data: f, },
+				Member { name: "g_with_a_long_name.o".to_string(), data: g, },
+			]
+		);
+		for (symbol, member,) in [("f", 0,), ("g", 1,), ("w", 1,),] {
+			assert_eq!(
+				archive.member_defining(symbol,),
+				Some(member),
+				"{symbol}"
+			);
+		}
+	}
+
+	fn objects() -> Archive {
+		let mut archive = Archive::default();
+		for name in ["first", "second_with_a_long_name",] {
+			let mut obj = crate::obj::Object::default();
+			obj.section_mut(crate::obj::SectionKind::Text,).push(&[0; 4],);
+			let symbol = obj.symbol_mut(name,);
+			symbol.section = Some(crate::obj::SectionKind::Text,);
+			symbol.binding = Binding::Global;
+			archive.members.push(Member {
+				name: format!("{name}.o"),
+				data: elf::write(&obj,).unwrap(),
+			},);
+		}
+		// an odd length to pad
+		archive.members.push(Member {
+			name: "notes.txt".to_string(),
+			data: b"odd".to_vec(),
+		},);
+		archive.index_symbols();
+		archive
+	}
+
+	#[test]
+	fn reads_what_it_writes() {
+		let archive = objects();
+		assert_eq!(
+			archive.symbols,
+			[
+				("first".to_string(), 0),
+				("second_with_a_long_name".to_string(), 1)
+			]
+		);
+		assert_eq!(read(&write(&archive,),).unwrap(), archive);
+		assert_eq!(
+			read(&write(&Archive::default(),),).unwrap(),
+			Archive::default()
+		);
+	}
+
+	#[test]
+	#[ignore = "needs binutils"]
+	fn host_tools_read_written_archives() {
+		let path = std::env::temp_dir()
+			.join(format!("llcc-ar-{}.a", std::process::id()),);
+		std::fs::write(&path, write(&objects(),),).unwrap();
+		let listing = Command::new("ar",).arg("t",).arg(&path,).output();
+		let index =
+			Command::new("nm",).arg("--print-armap",).arg(&path,).output();
+		std::fs::remove_file(&path,).unwrap();
+		let listing = listing.expect("ar is installed",);
+		let index = index.expect("nm is installed",);
+		assert_eq!(
+			String::from_utf8(listing.stdout,).unwrap(),
+			"first.o\nsecond_with_a_long_name.o\nnotes.txt\n"
+		);
+		let index = String::from_utf8(index.stdout,).unwrap();
+		assert!(index.contains("first in first.o",), "{index}");
+		assert!(
+			index.contains(
+				"second_with_a_long_name in second_with_a_long_name.o",
+			),
+			"{index}"
+		);
+	}
+}
@@ -0,0 +1,1053 @@
+//! ELF64 relocatable objects for aarch64 Linux
+//!
+//! the file is written as the header, the contents of each section, one
+//! `.rela` table per section with relocations, the symbol and string
+//! tables and finally the section header table. objects from other
+//! compilers are read back with every `.text.*` section merged into
+//! `.text` and so on
+
+use super::Binding;
+use super::Object;
+use super::RelocKind;
+use super::Relocation;
+use super::SectionKind;
+use super::Symbol;
+use crate::err::B::X;
+use crate::err::B::Y;
+use crate::err::LlccB;
+use crate::err::LlccError;
+
+pub const EM_AARCH64: u16 = 183;
+pub const ET_REL: u16 = 1;
+pub const ET_EXEC: u16 = 2;
+
+pub const SHT_PROGBITS: u32 = 1;
+pub const SHT_SYMTAB: u32 = 2;
+pub const SHT_STRTAB: u32 = 3;
+pub const SHT_RELA: u32 = 4;
+pub const SHT_NOBITS: u32 = 8;
+pub const SHT_REL: u32 = 9;
+
+pub const SHF_WRITE: u64 = 0x1;
+pub const SHF_ALLOC: u64 = 0x2;
+pub const SHF_EXECINSTR: u64 = 0x4;
+pub const SHF_INFO_LINK: u64 = 0x40;
+
+pub const PT_LOAD: u32 = 1;
+
+pub const PF_X: u32 = 0x1;
+pub const PF_W: u32 = 0x2;
+pub const PF_R: u32 = 0x4;
+
+pub const STT_NOTYPE: u8 = 0;
+pub const STT_OBJECT: u8 = 1;
+pub const STT_FUNC: u8 = 2;
+pub const STT_SECTION: u8 = 3;
+pub const STT_FILE: u8 = 4;
+
+pub const SHN_UNDEF: u16 = 0;
+pub const SHN_ABS: u16 = 0xfff1;
+pub const SHN_COMMON: u16 = 0xfff2;
+
+pub const HEADER_SIZE: u64 = 64;
+pub const PROGRAM_HEADER_SIZE: u64 = 56;
+pub const SECTION_HEADER_SIZE: u64 = 64;
+pub const SYMBOL_SIZE: u64 = 24;
+pub const RELA_SIZE: u64 = 24;
+
+impl SectionKind {
+	/// `.text` and so on
+	pub fn name(self,) -> String {
+		format!(".{self}")
+	}
+
+	pub fn flags(self,) -> u64 {
+		match self {
+			Self::Text => SHF_ALLOC | SHF_EXECINSTR,
+			Self::Rodata => SHF_ALLOC,
+			Self::Data | Self::Bss => SHF_ALLOC | SHF_WRITE,
+		}
+	}
+}
+
+impl Binding {
+	pub const fn elf(self,) -> u8 {
+		match self {
+			Self::Local => 0,
+			Self::Global => 1,
+			Self::Weak => 2,
+		}
+	}
+}
+
+impl RelocKind {
+	const ALL: [Self; 15] = [
+		Self::Call26,
+		Self::Jump26,
+		Self::CondBr19,
+		Self::TstBr14,
+		Self::AdrPrelLo21,
+		Self::AdrPrelPgHi21,
+		Self::AddAbsLo12Nc,
+		Self::Ldst8AbsLo12Nc,
+		Self::Ldst16AbsLo12Nc,
+		Self::Ldst32AbsLo12Nc,
+		Self::Ldst64AbsLo12Nc,
+		Self::Ldst128AbsLo12Nc,
+		Self::Abs64,
+		Self::Abs32,
+		Self::Prel32,
+	];
+
+	/// `R_AARCH64_*` number
+	pub const fn elf(self,) -> u32 {
+		match self {
+			Self::Abs64 => 257,
+			Self::Abs32 => 258,
+			Self::Prel32 => 261,
+			Self::AdrPrelLo21 => 274,
+			Self::AdrPrelPgHi21 => 275,
+			Self::AddAbsLo12Nc => 277,
+			Self::Ldst8AbsLo12Nc => 278,
+			Self::TstBr14 => 279,
+			Self::CondBr19 => 280,
+			Self::Jump26 => 282,
+			Self::Call26 => 283,
+			Self::Ldst16AbsLo12Nc => 284,
+			Self::Ldst32AbsLo12Nc => 285,
+			Self::Ldst64AbsLo12Nc => 286,
+			Self::Ldst128AbsLo12Nc => 299,
+		}
+	}
+
+	pub fn from_elf(ty: u32,) -> Option<Self,> {
+		Self::ALL.into_iter().find(|kind| kind.elf() == ty,)
+	}
+
+	/// the name `readelf` prints
+	pub const fn name(self,) -> &'static str {
+		match self {
+			Self::Call26 => "R_AARCH64_CALL26",
+			Self::Jump26 => "R_AARCH64_JUMP26",
+			Self::CondBr19 => "R_AARCH64_CONDBR19",
+			Self::TstBr14 => "R_AARCH64_TSTBR14",
+			Self::AdrPrelLo21 => "R_AARCH64_ADR_PREL_LO21",
+			Self::AdrPrelPgHi21 => "R_AARCH64_ADR_PREL_PG_HI21",
+			Self::AddAbsLo12Nc => "R_AARCH64_ADD_ABS_LO12_NC",
+			Self::Ldst8AbsLo12Nc => "R_AARCH64_LDST8_ABS_LO12_NC",
+			Self::Ldst16AbsLo12Nc => "R_AARCH64_LDST16_ABS_LO12_NC",
+			Self::Ldst32AbsLo12Nc => "R_AARCH64_LDST32_ABS_LO12_NC",
+			Self::Ldst64AbsLo12Nc => "R_AARCH64_LDST64_ABS_LO12_NC",
+			Self::Ldst128AbsLo12Nc => "R_AARCH64_LDST128_ABS_LO12_NC",
+			Self::Abs64 => "R_AARCH64_ABS64",
+			Self::Abs32 => "R_AARCH64_ABS32",
+			Self::Prel32 => "R_AARCH64_PREL32",
+		}
+	}
+}
+
+/// names separated by NUL, starting with the empty name
+pub struct StringTable {
+	pub bytes: Vec<u8,>,
+}
+
+impl Default for StringTable {
+	fn default() -> Self {
+		Self { bytes: vec![0], }
+	}
+}
+
+impl StringTable {
+	/// appends `name` and returns its offset
+	pub fn add(&mut self, name: &str,) -> u32 {
+		let offset = self.bytes.len() as u32;
+		self.bytes.extend_from_slice(name.as_bytes(),);
+		self.bytes.push(0,);
+		offset
+	}
+}
+
+#[derive(Debug, Clone, Default,)]
+pub struct SectionHeader {
+	pub name:    u32,
+	pub kind:    u32,
+	pub flags:   u64,
+	pub addr:    u64,
+	pub offset:  u64,
+	pub size:    u64,
+	pub link:    u32,
+	pub info:    u32,
+	pub align:   u64,
+	pub entsize: u64,
+}
+
+impl SectionHeader {
+	pub fn write(&self, out: &mut Vec<u8,>,) {
+		out.extend_from_slice(&self.name.to_le_bytes(),);
+		out.extend_from_slice(&self.kind.to_le_bytes(),);
+		out.extend_from_slice(&self.flags.to_le_bytes(),);
+		out.extend_from_slice(&self.addr.to_le_bytes(),);
+		out.extend_from_slice(&self.offset.to_le_bytes(),);
+		out.extend_from_slice(&self.size.to_le_bytes(),);
+		out.extend_from_slice(&self.link.to_le_bytes(),);
+		out.extend_from_slice(&self.info.to_le_bytes(),);
+		out.extend_from_slice(&self.align.to_le_bytes(),);
+		out.extend_from_slice(&self.entsize.to_le_bytes(),);
+	}
+}
+
+#[derive(Debug, Clone, Default,)]
+pub struct ProgramHeader {
+	pub kind:   u32,
+	pub flags:  u32,
+	pub offset: u64,
+	pub vaddr:  u64,
+	pub filesz: u64,
+	pub memsz:  u64,
+	pub align:  u64,
+}
+
+impl ProgramHeader {
+	pub fn write(&self, out: &mut Vec<u8,>,) {
+		out.extend_from_slice(&self.kind.to_le_bytes(),);
+		out.extend_from_slice(&self.flags.to_le_bytes(),);
+		out.extend_from_slice(&self.offset.to_le_bytes(),);
+		// the physical address is the virtual one
+		out.extend_from_slice(&self.vaddr.to_le_bytes(),);
+		out.extend_from_slice(&self.vaddr.to_le_bytes(),);
+		out.extend_from_slice(&self.filesz.to_le_bytes(),);
+		out.extend_from_slice(&self.memsz.to_le_bytes(),);
+		out.extend_from_slice(&self.align.to_le_bytes(),);
+	}
+}
+
+/// the fields of the file header that differ between files
+pub struct FileHeader {
+	pub kind:            u16,
+	pub entry:           u64,
+	pub program_headers: (u64, u16,),
+	pub section_headers: (u64, u16,),
+	pub shstrndx:        u16,
+}
+
+impl FileHeader {
+	pub fn write(&self, out: &mut [u8],) {
+		let mut header = vec![0x7f, b'E', b'L', b'F'];
+		// 64 bit, little endian, version 1, System V ABI
+		header.extend_from_slice(&[2, 1, 1, 0,],);
+		header.resize(16, 0,);
+		header.extend_from_slice(&self.kind.to_le_bytes(),);
+		header.extend_from_slice(&EM_AARCH64.to_le_bytes(),);
+		header.extend_from_slice(&1u32.to_le_bytes(),);
+		header.extend_from_slice(&self.entry.to_le_bytes(),);
+		header.extend_from_slice(&self.program_headers.0.to_le_bytes(),);
+		header.extend_from_slice(&self.section_headers.0.to_le_bytes(),);
+		// flags
+		header.extend_from_slice(&0u32.to_le_bytes(),);
+		header.extend_from_slice(&(HEADER_SIZE as u16).to_le_bytes(),);
+		header.extend_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes(),);
+		header.extend_from_slice(&self.program_headers.1.to_le_bytes(),);
+		header.extend_from_slice(&(SECTION_HEADER_SIZE as u16).to_le_bytes(),);
+		header.extend_from_slice(&self.section_headers.1.to_le_bytes(),);
+		header.extend_from_slice(&self.shstrndx.to_le_bytes(),);
+		out[..HEADER_SIZE as usize].copy_from_slice(&header,);
+	}
+}
+
+/// pads `out` to a multiple of `align` and returns its length
+pub fn align_to(out: &mut Vec<u8,>, align: u64,) -> u64 {
+	let offset = (out.len() as u64).next_multiple_of(align.max(1,),);
+	out.resize(offset as usize, 0,);
+	offset
+}
+
+/// appends a symbol table entry, `shndx` is 0 if `section` is `None`
+pub fn write_symbol(
+	out: &mut Vec<u8,>,
+	name: u32,
+	binding: Binding,
+	section: Option<SectionKind,>,
+	shndx: u16,
+	value: u64,
+) {
+	let kind = match section {
+		None => STT_NOTYPE,
+		Some(SectionKind::Text,) => STT_FUNC,
+		Some(_,) => STT_OBJECT,
+	};
+	out.extend_from_slice(&name.to_le_bytes(),);
+	out.push(binding.elf() << 4 | kind,);
+	// default visibility
+	out.push(0,);
+	out.extend_from_slice(&shndx.to_le_bytes(),);
+	out.extend_from_slice(&value.to_le_bytes(),);
+	// sizes are unknown
+	out.extend_from_slice(&0u64.to_le_bytes(),);
+}
+
+/// encodes `obj` as an ELF64 relocatable file
+pub fn write(obj: &Object,) -> LlccB<Vec<u8,>,> {
+	let mut out = vec![0; HEADER_SIZE as usize];
+	let mut shstrtab = StringTable::default();
+	let mut headers = vec![SectionHeader::default()];
+
+	for section in &obj.sections {
+		let offset = align_to(&mut out, section.align,);
+		out.extend_from_slice(&section.data,);
+		headers.push(SectionHeader {
+			name: shstrtab.add(&section.kind.name(),),
+			kind: if section.kind == SectionKind::Bss {
+				SHT_NOBITS
+			} else {
+				SHT_PROGBITS
+			},
+			flags: section.kind.flags(),
+			offset,
+			size: section.size,
+			align: section.align,
+			..SectionHeader::default()
+		},);
+	}
+	let section_index = |kind: SectionKind| {
+		obj.sections.iter().position(|s| s.kind == kind,).map(|i| i as u16 + 1,)
+	};
+
+	// ELF wants the locals before everything else
+	let mut symbols: Vec<&Symbol,> = obj.symbols.iter().collect();
+	symbols.sort_by_key(|s| s.binding != Binding::Local,);
+	let locals =
+		symbols.iter().filter(|s| s.binding == Binding::Local,).count();
+	let relocated: Vec<SectionKind,> = obj
+		.sections
+		.iter()
+		.map(|s| s.kind,)
+		.filter(|kind| obj.relocations.iter().any(|r| r.section == *kind,),)
+		.collect();
+	let symtab_index = (headers.len() + relocated.len()) as u32;
+
+	for kind in relocated {
+		let offset = align_to(&mut out, 8,);
+		let mut size = 0;
+		for reloc in obj.relocations.iter().filter(|r| r.section == kind,) {
+			let Some(symbol,) =
+				symbols.iter().position(|s| s.name == reloc.symbol,)
+			else {
+				return Y(LlccError::unknown(format!(
+					"relocation against unknown symbol `{}`",
+					reloc.symbol
+				),),);
+			};
+			let info = (symbol as u64 + 1) << 32 | reloc.kind.elf() as u64;
+			out.extend_from_slice(&reloc.offset.to_le_bytes(),);
+			out.extend_from_slice(&info.to_le_bytes(),);
+			out.extend_from_slice(&reloc.addend.to_le_bytes(),);
+			size += RELA_SIZE;
+		}
+		headers.push(SectionHeader {
+			name: shstrtab.add(&format!(".rela{}", kind.name()),),
+			kind: SHT_RELA,
+			flags: SHF_INFO_LINK,
+			offset,
+			size,
+			link: symtab_index,
+			info: section_index(kind,).unwrap_or_default() as u32,
+			align: 8,
+			entsize: RELA_SIZE,
+			..SectionHeader::default()
+		},);
+	}
+
+	let mut strtab = StringTable::default();
+	let offset = align_to(&mut out, 8,);
+	out.resize((offset + SYMBOL_SIZE) as usize, 0,);
+	for symbol in &symbols {
+		write_symbol(
+			&mut out,
+			strtab.add(&symbol.name,),
+			symbol.binding,
+			symbol.section,
+			symbol.section.and_then(section_index,).unwrap_or_default(),
+			symbol.offset,
+		);
+	}
+	headers.push(SectionHeader {
+		name: shstrtab.add(".symtab",),
+		kind: SHT_SYMTAB,
+		offset,
+		size: (symbols.len() as u64 + 1) * SYMBOL_SIZE,
+		link: symtab_index + 1,
+		info: locals as u32 + 1,
+		align: 8,
+		entsize: SYMBOL_SIZE,
+		..SectionHeader::default()
+	},);
+
+	let offset = out.len() as u64;
+	out.extend_from_slice(&strtab.bytes,);
+	headers.push(SectionHeader {
+		name: shstrtab.add(".strtab",),
+		kind: SHT_STRTAB,
+		offset,
+		size: strtab.bytes.len() as u64,
+		align: 1,
+		..SectionHeader::default()
+	},);
+
+	let name = shstrtab.add(".shstrtab",);
+	let offset = out.len() as u64;
+	out.extend_from_slice(&shstrtab.bytes,);
+	headers.push(SectionHeader {
+		name,
+		kind: SHT_STRTAB,
+		offset,
+		size: shstrtab.bytes.len() as u64,
+		align: 1,
+		..SectionHeader::default()
+	},);
+
+	let shoff = align_to(&mut out, 8,);
+	for header in &headers {
+		header.write(&mut out,);
+	}
+	FileHeader {
+		kind:            ET_REL,
+		entry:           0,
+		program_headers: (0, 0,),
+		section_headers: (shoff, headers.len() as u16,),
+		shstrndx:        headers.len() as u16 - 1,
+	}
+	.write(&mut out,);
+	X(out,)
+}
+
+/// `N` little endian bytes of `bytes` at `at`
+fn le<const N: usize,>(bytes: &[u8], at: u64,) -> LlccB<[u8; N],> {
+	let field = usize::try_from(at,)
+		.ok()
+		.and_then(|at| bytes.get(at..at.checked_add(N,)?,),);
+	match field {
+		Some(field,) => X(field.try_into().unwrap(),),
+		None => {
+			Y(LlccError::syntax(format!("ELF file truncated at {at:#x}"),),)
+		},
+	}
+}
+
+fn u16_at(bytes: &[u8], at: u64,) -> LlccB<u16,> {
+	X(u16::from_le_bytes(le(bytes, at,)?,),)
+}
+
+fn u32_at(bytes: &[u8], at: u64,) -> LlccB<u32,> {
+	X(u32::from_le_bytes(le(bytes, at,)?,),)
+}
+
+fn u64_at(bytes: &[u8], at: u64,) -> LlccB<u64,> {
+	X(u64::from_le_bytes(le(bytes, at,)?,),)
+}
+
+/// the NUL terminated string at `at`
+fn str_at(bytes: &[u8], at: u64,) -> LlccB<String,> {
+	let tail = usize::try_from(at,).ok().and_then(|at| bytes.get(at..,),);
+	let Some(tail,) = tail else {
+		return Y(LlccError::syntax(format!("ELF file truncated at {at:#x}"),),);
+	};
+	let end = tail.iter().position(|b| *b == 0,).unwrap_or(tail.len(),);
+	X(String::from_utf8_lossy(&tail[..end],).into_owned(),)
+}
+
+impl SectionHeader {
+	pub fn read(bytes: &[u8], at: u64,) -> LlccB<Self,> {
+		X(Self {
+			name:    u32_at(bytes, at,)?,
+			kind:    u32_at(bytes, at + 4,)?,
+			flags:   u64_at(bytes, at + 8,)?,
+			addr:    u64_at(bytes, at + 16,)?,
+			offset:  u64_at(bytes, at + 24,)?,
+			size:    u64_at(bytes, at + 32,)?,
+			link:    u32_at(bytes, at + 40,)?,
+			info:    u32_at(bytes, at + 44,)?,
+			align:   u64_at(bytes, at + 48,)?,
+			entsize: u64_at(bytes, at + 56,)?,
+		},)
+	}
+
+	/// the contents, empty for `SHT_NOBITS`
+	pub fn data<'a,>(&self, bytes: &'a [u8],) -> LlccB<&'a [u8],> {
+		if self.kind == SHT_NOBITS {
+			return X(&[],);
+		}
+		let data = usize::try_from(self.offset,).ok().and_then(|start| {
+			bytes.get(
+				start..start.checked_add(usize::try_from(self.size,).ok()?,)?,
+			)
+		},);
+		match data {
+			Some(data,) => X(data,),
+			None => Y(LlccError::syntax(format!(
+				"ELF section at {:#x} runs past the end of the file",
+				self.offset
+			),),),
+		}
+	}
+}
+
+/// `ET_REL`, `ET_EXEC` or another type of an ELF64 file for aarch64
+pub fn file_kind(bytes: &[u8],) -> LlccB<u16,> {
+	if bytes.get(..6,) != Some(b"\x7fELF\x02\x01",) {
+		return Y(LlccError::unsupported(
+			"file format, only little endian ELF64 files can be read",
+		),);
+	}
+	let machine = u16_at(bytes, 0x12,)?;
+	if machine != EM_AARCH64 {
+		return Y(LlccError::unsupported(format!(
+			"ELF machine {machine}, only aarch64 objects can be read"
+		),),);
+	}
+	u16_at(bytes, 0x10,)
+}
+
+/// the section headers of an ELF64 file for aarch64 with their names,
+/// after checking the file header
+pub fn section_headers(
+	bytes: &[u8],
+	kind: u16,
+) -> LlccB<Vec<(String, SectionHeader,),>,> {
+	let file_kind = file_kind(bytes,)?;
+	if file_kind != kind {
+		return Y(LlccError::unsupported(format!(
+			"ELF file type {file_kind}, expected {kind}"
+		),),);
+	}
+
+	let shoff = u64_at(bytes, 0x28,)?;
+	let count = u16_at(bytes, 0x3c,)? as u64;
+	let mut headers = vec![];
+	for i in 0..count {
+		headers
+			.push(
+				SectionHeader::read(bytes, shoff + i * SECTION_HEADER_SIZE,)?,
+			);
+	}
+	let names = headers.get(u16_at(bytes, 0x3e,)? as usize,).cloned();
+	let mut named = vec![];
+	for header in headers {
+		let name = match &names {
+			Some(names,) => str_at(names.data(bytes,)?, header.name as u64,)?,
+			None => String::new(),
+		};
+		named.push((name, header,),);
+	}
+	X(named,)
+}
+
+impl ProgramHeader {
+	pub fn read(bytes: &[u8], at: u64,) -> LlccB<Self,> {
+		X(Self {
+			kind:   u32_at(bytes, at,)?,
+			flags:  u32_at(bytes, at + 4,)?,
+			offset: u64_at(bytes, at + 8,)?,
+			vaddr:  u64_at(bytes, at + 16,)?,
+			filesz: u64_at(bytes, at + 32,)?,
+			memsz:  u64_at(bytes, at + 40,)?,
+			align:  u64_at(bytes, at + 48,)?,
+		},)
+	}
+
+	/// the bytes of the file mapped by `self`, without the zero fill up to
+	/// `memsz`
+	pub fn data<'a,>(&self, bytes: &'a [u8],) -> LlccB<&'a [u8],> {
+		let data = usize::try_from(self.offset,).ok().and_then(|start| {
+			bytes.get(
+				start
+					..start.checked_add(usize::try_from(self.filesz,).ok()?,)?,
+			)
+		},);
+		match data {
+			Some(data,) => X(data,),
+			None => Y(LlccError::syntax(format!(
+				"ELF segment at {:#x} runs past the end of the file",
+				self.offset
+			),),),
+		}
+	}
+}
+
+/// the entry point and program headers of an ELF64 executable for aarch64
+pub fn program_headers(bytes: &[u8],) -> LlccB<(u64, Vec<ProgramHeader,>,),> {
+	let kind = file_kind(bytes,)?;
+	if kind != ET_EXEC {
+		return Y(LlccError::unsupported(format!(
+			"ELF file type {kind}, expected {ET_EXEC}"
+		),),);
+	}
+
+	let phoff = u64_at(bytes, 0x20,)?;
+	let count = u16_at(bytes, 0x38,)? as u64;
+	let mut headers = vec![];
+	for i in 0..count {
+		headers
+			.push(
+				ProgramHeader::read(bytes, phoff + i * PROGRAM_HEADER_SIZE,)?,
+			);
+	}
+	X((u64_at(bytes, 0x18,)?, headers,),)
+}
+
+/// an entry of the symbol table as stored in the file
+#[derive(Debug, Clone,)]
+pub struct ElfSymbol {
+	pub name:  String,
+	/// binding in the high and type in the low four bits
+	pub info:  u8,
+	pub shndx: u16,
+	pub value: u64,
+	pub size:  u64,
+}
+
+impl ElfSymbol {
+	pub fn binding(&self,) -> Binding {
+		match self.info >> 4 {
+			0 => Binding::Local,
+			2 => Binding::Weak,
+			_ => Binding::Global,
+		}
+	}
+
+	/// `STT_*`
+	pub fn kind(&self,) -> u8 {
+		self.info & 0xf
+	}
+}
+
+/// the entries of the symbol table, empty if the file has none
+pub fn symbols(
+	bytes: &[u8],
+	headers: &[(String, SectionHeader,)],
+) -> LlccB<Vec<ElfSymbol,>,> {
+	let Some((_, symtab,),) =
+		headers.iter().find(|(_, h,)| h.kind == SHT_SYMTAB,)
+	else {
+		return X(vec![],);
+	};
+	let data = symtab.data(bytes,)?;
+	let strtab = match headers.get(symtab.link as usize,) {
+		Some((_, strtab,),) => strtab.data(bytes,)?,
+		None => &[],
+	};
+	let mut symbols = vec![];
+	for at in (0..data.len() as u64 / SYMBOL_SIZE).map(|i| i * SYMBOL_SIZE,) {
+		symbols.push(ElfSymbol {
+			name:  str_at(strtab, u32_at(data, at,)? as u64,)?,
+			info:  data[at as usize + 4],
+			shndx: u16_at(data, at + 6,)?,
+			value: u64_at(data, at + 8,)?,
+			size:  u64_at(data, at + 16,)?,
+		},);
+	}
+	X(symbols,)
+}
+
+/// an entry of a `SHT_RELA` section
+#[derive(Debug, Clone,)]
+pub struct Rela {
+	pub offset: u64,
+	/// `R_AARCH64_*` number, see `RelocKind::from_elf`
+	pub kind:   u32,
+	/// index into the symbol table
+	pub symbol: usize,
+	pub addend: i64,
+}
+
+/// the entries of the relocation section `header`
+pub fn relas(bytes: &[u8], header: &SectionHeader,) -> LlccB<Vec<Rela,>,> {
+	let data = header.data(bytes,)?;
+	let mut relas = vec![];
+	for at in (0..data.len() as u64 / RELA_SIZE).map(|i| i * RELA_SIZE,) {
+		let info = u64_at(data, at + 8,)?;
+		relas.push(Rela {
+			offset: u64_at(data, at,)?,
+			kind:   info as u32,
+			symbol: (info >> 32) as usize,
+			addend: u64_at(data, at + 16,)? as i64,
+		},);
+	}
+	X(relas,)
+}
+
+/// the kind of output section `name` is merged into
+fn section_kind(name: &str,) -> Option<SectionKind,> {
+	[
+		SectionKind::Text,
+		SectionKind::Rodata,
+		SectionKind::Data,
+		SectionKind::Bss,
+	]
+	.into_iter()
+	.find(|kind| {
+		let base = kind.name();
+		name == base || name.starts_with(&format!("{base}."),)
+	},)
+}
+
+/// decodes an ELF64 relocatable file. sections other than code and data,
+/// such as `.comment` and `.eh_frame`, are dropped along with their
+/// symbols and relocations
+pub fn read(bytes: &[u8],) -> LlccB<Object,> {
+	let headers = section_headers(bytes, ET_REL,)?;
+	let mut obj = Object::default();
+
+	// where each input section went in `obj`
+	let mut placed = vec![None; headers.len()];
+	for (i, (name, header,),) in headers.iter().enumerate() {
+		let loaded = header.kind == SHT_PROGBITS || header.kind == SHT_NOBITS;
+		let Some(kind,) = section_kind(name,) else {
+			continue;
+		};
+		if !loaded || header.flags & SHF_ALLOC == 0 {
+			continue;
+		}
+		let data = header.data(bytes,)?;
+		let section = obj.section_mut(kind,);
+		section.align = section.align.max(header.align,);
+		let offset = section.size.next_multiple_of(header.align.max(1,),);
+		section.push(&vec![0; (offset - section.size) as usize],);
+		section.push(data,);
+		section.size = offset + header.size;
+		placed[i] = Some((kind, offset,),);
+	}
+
+	// names of the symbols by their index, `None` for dropped ones
+	let mut names = vec![];
+	for (i, symbol,) in symbols(bytes, &headers,)?.into_iter().enumerate() {
+		let mut name = symbol.name.clone();
+		let (section, offset,) = match (symbol.kind(), symbol.shndx,) {
+			(STT_FILE, _,) | (_, SHN_ABS,) => {
+				names.push(None,);
+				continue;
+			},
+			(STT_SECTION, shndx,) => {
+				name = headers[shndx as usize].0.clone();
+				match placed.get(shndx as usize,).copied().flatten() {
+					Some((kind, offset,),) => (Some(kind,), offset,),
+					None => {
+						names.push(None,);
+						continue;
+					},
+				}
+			},
+			(_, SHN_UNDEF,) => (None, 0,),
+			// tentative definitions take space in `.bss`
+			(_, SHN_COMMON,) => {
+				let bss = obj.section_mut(SectionKind::Bss,);
+				bss.align = bss.align.max(symbol.value,);
+				let offset = bss.size.next_multiple_of(symbol.value.max(1,),);
+				bss.size = offset + symbol.size;
+				(Some(SectionKind::Bss,), offset,)
+			},
+			(_, shndx,) => match placed.get(shndx as usize,).copied().flatten()
+			{
+				Some((kind, offset,),) => (Some(kind,), offset + symbol.value,),
+				None => {
+					names.push(None,);
+					continue;
+				},
+			},
+		};
+		// the null symbol and `$x` or `$d` marking code and data
+		if name.is_empty() || name.starts_with('$',) {
+			names.push(None,);
+			continue;
+		}
+		if obj.symbol(&name,).is_some() {
+			name = format!("{name}.{i}");
+		}
+		obj.symbols.push(Symbol {
+			name: name.clone(),
+			section,
+			offset,
+			binding: symbol.binding(),
+		},);
+		names.push(Some(name,),);
+	}
+
+	for (name, header,) in &headers {
+		if header.kind == SHT_REL {
+			return Y(LlccError::unsupported(format!(
+				"`{name}`, aarch64 relocations have to carry addends"
+			),),);
+		}
+		if header.kind != SHT_RELA {
+			continue;
+		}
+		let Some((section, base,),) =
+			placed.get(header.info as usize,).copied().flatten()
+		else {
+			continue;
+		};
+		for rela in relas(bytes, header,)? {
+			let Some(kind,) = RelocKind::from_elf(rela.kind,) else {
+				return Y(LlccError::unsupported(format!(
+					"relocation type {} in `{name}`, only static code built \
+					 with -fno-pic can be linked",
+					rela.kind
+				),),);
+			};
+			let index = rela.symbol;
+			let Some(Some(symbol,),) = names.get(index,) else {
+				return Y(LlccError::unsupported(format!(
+					"relocation in `{name}` against symbol {index} of a \
+					 dropped section"
+				),),);
+			};
+			obj.relocations.push(Relocation {
+				section,
+				offset: base + rela.offset,
+				kind,
+				symbol: symbol.clone(),
+				addend: rela.addend,
+			},);
+		}
+	}
+	X(obj,)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use super::*;
+	use crate::err::Container;
+	use crate::obj::Relocation;
+	use std::process::Command;
+
+	/// calls `g` and loads a pointer from `.data` that points at `.rodata`
+	fn sample() -> Object {
+		let mut obj = Object::default();
+		let text = obj.section_mut(SectionKind::Text,);
+		for word in [
+			0x9400_0000u32, // bl g
+			0x9000_0000,    // adrp x0, ptr
+			0xf940_0000,    // ldr x0, [x0, :lo12:ptr]
+			0xd65f_03c0,    // ret
+		] {
+			text.push(&word.to_le_bytes(),);
+		}
+		let rodata = obj.section_mut(SectionKind::Rodata,).push(b"hi\0",);
+		let data = obj.section_mut(SectionKind::Data,).push(&[0; 8],);
+		let bss = obj.section_mut(SectionKind::Bss,).push(&[0; 32],);
+		let symbols = [
+			("f", Some(SectionKind::Text,), 0, Binding::Global,),
+			("msg", Some(SectionKind::Rodata,), rodata, Binding::Local,),
+			("ptr", Some(SectionKind::Data,), data, Binding::Global,),
+			("buf", Some(SectionKind::Bss,), bss, Binding::Weak,),
+			("g", None, 0, Binding::Global,),
+			("h", None, 0, Binding::Weak,),
+		];
+		for (name, section, offset, binding,) in symbols {
+			let symbol = obj.symbol_mut(name,);
+			symbol.section = section;
+			symbol.offset = offset;
+			symbol.binding = binding;
+		}
+		let relocs = [
+			(SectionKind::Text, 0, RelocKind::Call26, "g",),
+			(SectionKind::Text, 4, RelocKind::AdrPrelPgHi21, "ptr",),
+			(SectionKind::Text, 8, RelocKind::Ldst64AbsLo12Nc, "ptr",),
+			(SectionKind::Data, 0, RelocKind::Abs64, "msg",),
+		];
+		for (section, offset, kind, symbol,) in relocs {
+			obj.relocations.push(Relocation {
+				section,
+				offset,
+				kind,
+				symbol: symbol.to_string(),
+				addend: 0,
+			},);
+		}
+		obj
+	}
+
+	#[test]
+	fn layout() {
+		let obj = sample();
+		let bytes = write(&obj,).unwrap();
+		assert_eq!(bytes[..4], *b"\x7fELF");
+		let u16_at =
+			|at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1],],);
+		let shoff =
+			u64::from_le_bytes(bytes[0x28..0x30].try_into().unwrap(),) as usize;
+		// null, 4 sections, 2 relocation tables, symtab, strtab, shstrtab
+		assert_eq!(
+			(u16_at(0x10,), u16_at(0x12,), u16_at(0x3c,)),
+			(ET_REL, EM_AARCH64, 10)
+		);
+		assert_eq!(bytes.len(), shoff + 10 * SECTION_HEADER_SIZE as usize);
+		let text = &bytes[64..80];
+		assert_eq!(text, obj.section(SectionKind::Text).unwrap().data);
+
+		let mut broken = obj;
+		broken.relocations[0].symbol = "nowhere".to_string();
+		assert!(matches!(write(&broken,), Y(_,)));
+	}
+
+	#[test]
+	#[ignore = "needs binutils"]
+	fn readelf_accepts() {
+		let path = std::env::temp_dir()
+			.join(format!("llcc-elf-{}.o", std::process::id()),);
+		std::fs::write(&path, write(&sample(),).unwrap(),).unwrap();
+		let output = Command::new("readelf",)
+			.args(["-W", "-s", "-r",],)
+			.arg(&path,)
+			.output()
+			.expect("readelf is installed",);
+		std::fs::remove_file(&path,).unwrap();
+		assert!(output.status.success());
+		assert!(
+			output.stderr.is_empty(),
+			"{}",
+			String::from_utf8_lossy(&output.stderr)
+		);
+		let text = String::from_utf8(output.stdout,).unwrap();
+		for expected in [
+			"R_AARCH64_CALL26",
+			"R_AARCH64_ADR_PREL_PG_HI21",
+			"R_AARCH64_LDST64_ABS_LO12_NC",
+			"R_AARCH64_ABS64",
+			"FUNC    GLOBAL DEFAULT    1 f",
+			"OBJECT  LOCAL  DEFAULT    2 msg",
+			"OBJECT  WEAK   DEFAULT    4 buf",
+			"NOTYPE  WEAK   DEFAULT  UND h",
+		] {
+			assert!(text.contains(expected,), "{expected} in\n{text}");
+		}
+	}
+
+	/// `ld` resolves every relocation once `g` is defined
+	#[test]
+	#[ignore = "needs binutils"]
+	fn ld_links() {
+		let mut obj = sample();
+		let g = obj.symbol_mut("g",);
+		g.section = Some(SectionKind::Text,);
+		g.offset = 12;
+		let dir = std::env::temp_dir()
+			.join(format!("llcc-ld-{}", std::process::id()),);
+		std::fs::create_dir_all(&dir,).unwrap();
+		std::fs::write(dir.join("f.o",), write(&obj,).unwrap(),).unwrap();
+		let output = Command::new("ld",)
+			.args(["-m", "aarch64linux", "-e", "f", "-o", "f", "f.o",],)
+			.current_dir(&dir,)
+			.output()
+			.expect("ld is installed",);
+		std::fs::remove_dir_all(&dir,).unwrap();
+		assert!(
+			output.status.success(),
+			"{}",
+			String::from_utf8_lossy(&output.stderr)
+		);
+	}
+
+	#[test]
+	fn reads_back_what_it_writes() {
+		let obj = sample();
+		let mut back = read(&write(&obj,).unwrap(),).unwrap();
+		let mut expected = obj;
+		for obj in [&mut back, &mut expected,] {
+			obj.symbols.sort_by(|a, b| a.name.cmp(&b.name,),);
+		}
+		assert_eq!(back, expected);
+	}
+
+	/// `src` assembled by `llvm-mc`
+	pub(crate) fn llvm_mc(src: &str,) -> Vec<u8,> {
+		use std::io::Write as _;
+		use std::process::Stdio;
+
+		let mut child = Command::new("llvm-mc",)
+			.args(["-triple=aarch64-linux-gnu", "-filetype=obj", "-o", "-",],)
+			.stdin(Stdio::piped(),)
+			.stdout(Stdio::piped(),)
+			.spawn()
+			.expect("llvm-mc is installed",);
+		child.stdin.take().unwrap().write_all(src.as_bytes(),).unwrap();
+		let output = child.wait_with_output().unwrap();
+		assert!(output.status.success());
+		output.stdout
+	}
+
+	#[test]
+	#[ignore = "needs llvm-mc"]
+	fn reads_foreign_objects() {
+		let bytes = llvm_mc(
+			"	.text
+			.globl f
+		f:	bl g
+			adrp x0, .L.str
+			add x0, x0, :lo12:.L.str
+			ret
+			.section .text.unlikely,\"ax\",@progbits
+		cold:	b f
+			.section .rodata.str1.1,\"aMS\",@progbits,1
+		.L.str:	.asciz \"hi\"
+			.data
+			.p2align 3
+			.weak ptr
+		ptr:	.xword .L.str + 1
+			.comm buf, 16, 8",
+		);
+		let obj = read(&bytes,).unwrap();
+		// `.text.unlikely` follows `.text`, branching back through a relocation
+		let text = obj.section(SectionKind::Text,).unwrap();
+		assert_eq!(text.size, 20);
+		assert_eq!(text.data[16..], 0x1400_0000u32.to_le_bytes());
+		// `$x` and `$d` are gone, section symbols named after the section
+		let mut names: Vec<&str,> =
+			obj.symbols.iter().map(|s| s.name.as_str(),).collect();
+		names.sort();
+		assert_eq!(
+			names,
+			[".L.str", ".rodata.str1.1", "buf", "cold", "f", "g", "ptr"]
+		);
+		let symbol = |name| obj.symbol(name,).unwrap();
+		assert_eq!(
+			(symbol("cold",).section, symbol("cold",).offset,),
+			(Some(SectionKind::Text,), 16)
+		);
+		assert_eq!(symbol("ptr",).binding, Binding::Weak);
+		assert_eq!(
+			(symbol("buf",).section, symbol("g",).section,),
+			(Some(SectionKind::Bss,), None)
+		);
+		assert_eq!(obj.section(SectionKind::Bss,).unwrap().size, 16);
+
+		let relocs: Vec<_,> = obj
+			.relocations
+			.iter()
+			.map(
+				|r| (r.section, r.offset, r.kind, r.symbol.as_str(), r.addend,),
+			)
+			.collect();
+		assert_eq!(
+			relocs,
+			[
+				(SectionKind::Text, 0, RelocKind::Call26, "g", 0),
+				(
+					SectionKind::Text,
+					4,
+					RelocKind::AdrPrelPgHi21,
+					".rodata.str1.1",
+					0
+				),
+				(
+					SectionKind::Text,
+					8,
+					RelocKind::AddAbsLo12Nc,
+					".rodata.str1.1",
+					0
+				),
+				(SectionKind::Text, 16, RelocKind::Jump26, "f", 0),
+				(SectionKind::Data, 0, RelocKind::Abs64, ".L.str", 1),
+			]
+		);
+	}
+}
@@ -0,0 +1,610 @@
+//! static linking of objects into an executable
+//!
+//! sections of one kind are concatenated in input order. `.text` and
+//! `.rodata` share a read only segment that also maps the file headers,
+//! `.data` and `.bss` follow in a writable one starting on a fresh page.
+//! once every symbol has an address the relocations are applied in place
+
+use super::Binding;
+use super::Object;
+use super::RelocKind;
+use super::Section;
+use super::SectionKind;
+use super::ar::Archive;
+use super::elf;
+use super::elf::FileHeader;
+use super::elf::HEADER_SIZE;
+use super::elf::PROGRAM_HEADER_SIZE;
+use super::elf::ProgramHeader;
+use super::elf::SectionHeader;
+use super::elf::StringTable;
+use crate::err::B::X;
+use crate::err::B::Y;
+use crate::err::LlccB;
+use crate::err::LlccError;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/// address the file headers are mapped at
+pub const BASE: u64 = 0x40_0000;
+/// alignment of segments, the largest page size of aarch64 Linux
+pub const PAGE: u64 = 0x1_0000;
+/// `nop`, replacing calls to undefined weak symbols
+const NOP: u32 = 0xd503_201f;
+
+/// a resolved global symbol
+struct Global {
+	addr:    u64,
+	section: SectionKind,
+	binding: Binding,
+}
+
+/// appends the members of `archives` that define symbols `objects` leave
+/// undefined, repeating for the references of the added members. the
+/// first archive defining a symbol wins, and weak references pull nothing
+pub fn pull_members(
+	objects: &mut Vec<Object,>,
+	archives: &[Archive],
+) -> LlccB<(),> {
+	let mut pulled = HashSet::new();
+	loop {
+		let defined: HashSet<&str,> = objects
+			.iter()
+			.flat_map(|obj| &obj.symbols,)
+			.filter(|s| s.section.is_some() && s.binding != Binding::Local,)
+			.map(|s| s.name.as_str(),)
+			.collect();
+		let undefined: Vec<String,> = objects
+			.iter()
+			.flat_map(|obj| &obj.symbols,)
+			.filter(|s| s.section.is_none() && s.binding == Binding::Global,)
+			.filter(|s| !defined.contains(s.name.as_str(),),)
+			.map(|s| s.name.clone(),)
+			.collect();
+
+		let mut members = vec![];
+		for name in undefined {
+			let found = archives.iter().enumerate().find_map(|(a, archive,)| {
+				Some((a, archive.member_defining(&name,)?,),)
+			},);
+			if let Some(member,) = found
+				&& pulled.insert(member,)
+			{
+				members.push(member,);
+			}
+		}
+		if members.is_empty() {
+			return X((),);
+		}
+		for (a, m,) in members {
+			objects.push(elf::read(&archives[a].members[m].data,)?,);
+		}
+	}
+}
+
+/// links `objects` into a static executable entering at `_start`
+pub fn link(objects: &[Object],) -> LlccB<Vec<u8,>,> {
+	// offset of each input section in the output section of its kind
+	let mut places: Vec<HashMap<SectionKind, u64,>,> = vec![];
+	let mut sections: BTreeMap<SectionKind, Section,> = BTreeMap::new();
+	for obj in objects {
+		let mut place = HashMap::new();
+		for section in &obj.sections {
+			let out = sections
+				.entry(section.kind,)
+				.or_insert_with(|| Section::new(section.kind,),);
+			out.align = out.align.max(section.align,);
+			let offset = out.size.next_multiple_of(section.align,);
+			out.push(&vec![0; (offset - out.size) as usize],);
+			out.push(&section.data,);
+			// `.bss` has no data but still takes its size
+			out.size = offset + section.size;
+			place.insert(section.kind, offset,);
+		}
+		places.push(place,);
+	}
+
+	let writable = |kind: SectionKind| kind.flags() & elf::SHF_WRITE != 0;
+	let segments = 1 + sections.keys().any(|kind| writable(*kind,),) as u64;
+	let headers = HEADER_SIZE + segments * PROGRAM_HEADER_SIZE;
+	let mut offset = headers;
+	let mut addr = BASE + headers;
+	let mut addrs = BTreeMap::new();
+	let mut offsets = BTreeMap::new();
+	let mut text = ProgramHeader {
+		kind: elf::PT_LOAD,
+		flags: elf::PF_R | elf::PF_X,
+		vaddr: BASE,
+		filesz: headers,
+		memsz: headers,
+		align: PAGE,
+		..ProgramHeader::default()
+	};
+	let mut data: Option<ProgramHeader,> = None;
+	for (kind, section,) in &sections {
+		if writable(*kind,) && data.is_none() {
+			// addresses stay congruent to file offsets modulo the page size
+			addr = addr.next_multiple_of(PAGE,) + offset % PAGE;
+			data = Some(ProgramHeader {
+				kind: elf::PT_LOAD,
+				flags: elf::PF_R | elf::PF_W,
+				align: PAGE,
+				..ProgramHeader::default()
+			},);
+		}
+		offset = offset.next_multiple_of(section.align,);
+		addr = addr.next_multiple_of(section.align,);
+		addrs.insert(*kind, addr,);
+		offsets.insert(*kind, offset,);
+		addr += section.size;
+		if *kind != SectionKind::Bss {
+			offset += section.size;
+		}
+
+		let segment = data.as_mut().unwrap_or(&mut text,);
+		if segment.memsz == 0 && segment.vaddr == 0 {
+			segment.offset = offsets[kind];
+			segment.vaddr = addrs[kind];
+		}
+		segment.filesz = offset - segment.offset;
+		segment.memsz = addr - segment.vaddr;
+	}
+	let addr_of = |i: usize, kind: SectionKind, offset: u64| {
+		addrs[&kind] + places[i][&kind] + offset
+	};
+
+	let mut globals: BTreeMap<&str, Global,> = BTreeMap::new();
+	for (i, obj,) in objects.iter().enumerate() {
+		for symbol in &obj.symbols {
+			let Some(section,) = symbol.section else {
+				continue;
+			};
+			let existing =
+				globals.get(symbol.name.as_str(),).map(|g| g.binding,);
+			match (existing, symbol.binding,) {
+				(_, Binding::Local,) => (),
+				(Some(Binding::Global,), Binding::Global,) => {
+					return Y(LlccError::link(format!(
+						"multiple definition of `{}`",
+						symbol.name
+					),),);
+				},
+				(None, _,) | (Some(Binding::Weak,), Binding::Global,) => {
+					globals.insert(
+						&symbol.name,
+						Global {
+							addr: addr_of(i, section, symbol.offset,),
+							section,
+							binding: symbol.binding,
+						},
+					);
+				},
+				_ => (),
+			}
+		}
+	}
+
+	for (i, obj,) in objects.iter().enumerate() {
+		for reloc in &obj.relocations {
+			// `None` for undefined weak symbols
+			let target = match obj.symbol(&reloc.symbol,) {
+				Some(s,) if s.binding == Binding::Local => {
+					s.section.map(|section| addr_of(i, section, s.offset,),)
+				},
+				_ => globals.get(reloc.symbol.as_str(),).map(|g| g.addr,),
+			};
+			let weak = obj
+				.symbol(&reloc.symbol,)
+				.is_some_and(|s| s.binding == Binding::Weak,);
+			if target.is_none() && !weak {
+				return Y(LlccError::link(format!(
+					"undefined reference to `{}`",
+					reloc.symbol
+				),),);
+			}
+
+			let at = (places[i][&reloc.section] + reloc.offset) as usize;
+			let p = addr_of(i, reloc.section, reloc.offset,);
+			let width = if reloc.kind == RelocKind::Abs64 { 8 } else { 4 };
+			let place = sections
+				.get_mut(&reloc.section,)
+				.and_then(|s| s.data.get_mut(at..at + width,),);
+			let Some(place,) = place else {
+				return Y(LlccError::link(format!(
+					"relocation at {:#x} outside of {}",
+					reloc.offset,
+					reloc.section.name()
+				),),);
+			};
+			apply(reloc.kind, place, target, reloc.addend, p,)?;
+		}
+	}
+
+	let Some(entry,) = globals.get("_start",) else {
+		return Y(LlccError::link("undefined entry point `_start`",),);
+	};
+	let entry = entry.addr;
+	let mut out = vec![0; headers as usize];
+	for (kind, section,) in &sections {
+		if *kind != SectionKind::Bss {
+			out.resize(offsets[kind] as usize, 0,);
+			out.extend_from_slice(&section.data,);
+		}
+	}
+	let mut program_headers = vec![];
+	for segment in [Some(text,), data,].into_iter().flatten() {
+		segment.write(&mut program_headers,);
+	}
+	out[HEADER_SIZE as usize..headers as usize]
+		.copy_from_slice(&program_headers,);
+
+	// section headers and symbols only serve tools inspecting the file
+	let index = |kind: SectionKind| {
+		sections.keys().position(|k| *k == kind,).unwrap_or_default() as u16 + 1
+	};
+	let mut shstrtab = StringTable::default();
+	let mut section_headers = vec![SectionHeader::default()];
+	for (kind, section,) in &sections {
+		section_headers.push(SectionHeader {
+			name: shstrtab.add(&kind.name(),),
+			kind: if *kind == SectionKind::Bss {
+				elf::SHT_NOBITS
+			} else {
+				elf::SHT_PROGBITS
+			},
+			flags: kind.flags(),
+			addr: addrs[kind],
+			offset: offsets[kind],
+			size: section.size,
+			align: section.align,
+			..SectionHeader::default()
+		},);
+	}
+
+	let mut strtab = StringTable::default();
+	let symtab = elf::align_to(&mut out, 8,);
+	out.resize((symtab + elf::SYMBOL_SIZE) as usize, 0,);
+	let mut locals = 1;
+	for (i, obj,) in objects.iter().enumerate() {
+		for symbol in &obj.symbols {
+			if let (Some(section,), Binding::Local,) =
+				(symbol.section, symbol.binding,)
+			{
+				elf::write_symbol(
+					&mut out,
+					strtab.add(&symbol.name,),
+					Binding::Local,
+					Some(section,),
+					index(section,),
+					addr_of(i, section, symbol.offset,),
+				);
+				locals += 1;
+			}
+		}
+	}
+	for (name, global,) in &globals {
+		elf::write_symbol(
+			&mut out,
+			strtab.add(name,),
+			global.binding,
+			Some(global.section,),
+			index(global.section,),
+			global.addr,
+		);
+	}
+	let symtab_index = section_headers.len() as u32;
+	section_headers.push(SectionHeader {
+		name: shstrtab.add(".symtab",),
+		kind: elf::SHT_SYMTAB,
+		offset: symtab,
+		size: out.len() as u64 - symtab,
+		link: symtab_index + 1,
+		info: locals,
+		align: 8,
+		entsize: elf::SYMBOL_SIZE,
+		..SectionHeader::default()
+	},);
+	section_headers.push(SectionHeader {
+		name: shstrtab.add(".strtab",),
+		kind: elf::SHT_STRTAB,
+		offset: out.len() as u64,
+		size: strtab.bytes.len() as u64,
+		align: 1,
+		..SectionHeader::default()
+	},);
+	out.extend_from_slice(&strtab.bytes,);
+	let name = shstrtab.add(".shstrtab",);
+	section_headers.push(SectionHeader {
+		name,
+		kind: elf::SHT_STRTAB,
+		offset: out.len() as u64,
+		size: shstrtab.bytes.len() as u64,
+		align: 1,
+		..SectionHeader::default()
+	},);
+	out.extend_from_slice(&shstrtab.bytes,);
+
+	let shoff = elf::align_to(&mut out, 8,);
+	for header in &section_headers {
+		header.write(&mut out,);
+	}
+	FileHeader {
+		kind: elf::ET_EXEC,
+		entry,
+		program_headers: (HEADER_SIZE, segments as u16,),
+		section_headers: (shoff, section_headers.len() as u16,),
+		shstrndx: section_headers.len() as u16 - 1,
+	}
+	.write(&mut out,);
+	X(out,)
+}
+
+/// patches `place` at address `p` to refer to `s` plus `addend`, where
+/// `s` is `None` for an undefined weak symbol
+fn apply(
+	kind: RelocKind,
+	place: &mut [u8],
+	s: Option<u64,>,
+	addend: i64,
+	p: u64,
+) -> LlccB<(),> {
+	let word = u32::from_le_bytes([place[0], place[1], place[2], place[3],],);
+	let Some(s,) = s else {
+		if matches!(kind, RelocKind::Call26 | RelocKind::Jump26) {
+			place.copy_from_slice(&NOP.to_le_bytes(),);
+			return X((),);
+		}
+		return apply(kind, place, Some(0,), addend, p,);
+	};
+	let x = s.wrapping_add_signed(addend,);
+	let relative = x.wrapping_sub(p,) as i64;
+	// `adr` and `adrp` split their immediate into bits 30:29 and 23:5
+	let adr = |imm: u32| {
+		word & !(0x3 << 29 | 0x7_ffff << 5)
+			| (imm & 0x3) << 29
+			| (imm >> 2) << 5
+	};
+	let patched = match kind {
+		RelocKind::Abs64 => {
+			place.copy_from_slice(&x.to_le_bytes(),);
+			return X((),);
+		},
+		RelocKind::Abs32 => {
+			if x > u32::MAX as u64 {
+				return Y(LlccError::link(format!(
+					"{kind:?} relocation out of range: {x:#x}"
+				),),);
+			}
+			x as u32
+		},
+		RelocKind::Prel32 => field(kind, relative, 0, 32,)?,
+		RelocKind::Call26 | RelocKind::Jump26 => {
+			word & !0x3ff_ffff | field(kind, relative, 2, 26,)?
+		},
+		RelocKind::CondBr19 => {
+			word & !(0x7_ffff << 5) | field(kind, relative, 2, 19,)? << 5
+		},
+		RelocKind::TstBr14 => {
+			word & !(0x3fff << 5) | field(kind, relative, 2, 14,)? << 5
+		},
+		RelocKind::AdrPrelLo21 => adr(field(kind, relative, 0, 21,)?,),
+		RelocKind::AdrPrelPgHi21 => {
+			let pages = (x & !0xfff).wrapping_sub(p & !0xfff,) as i64;
+			adr(field(kind, pages, 12, 21,)?,)
+		},
+		RelocKind::AddAbsLo12Nc => {
+			word & !(0xfff << 10) | (x as u32 & 0xfff) << 10
+		},
+		RelocKind::Ldst8AbsLo12Nc
+		| RelocKind::Ldst16AbsLo12Nc
+		| RelocKind::Ldst32AbsLo12Nc
+		| RelocKind::Ldst64AbsLo12Nc
+		| RelocKind::Ldst128AbsLo12Nc => {
+			// the offset is scaled by the access size
+			let shift = match kind {
+				RelocKind::Ldst8AbsLo12Nc => 0,
+				RelocKind::Ldst16AbsLo12Nc => 1,
+				RelocKind::Ldst32AbsLo12Nc => 2,
+				RelocKind::Ldst64AbsLo12Nc => 3,
+				_ => 4,
+			};
+			if x % (1 << shift) != 0 {
+				return Y(LlccError::link(format!(
+					"{kind:?} to {x:#x}, which is not {} byte aligned",
+					1 << shift
+				),),);
+			}
+			word & !(0xfff << 10) | (x as u32 & 0xfff) >> shift << 10
+		},
+	};
+	place.copy_from_slice(&patched.to_le_bytes(),);
+	X((),)
+}
+
+/// `value >> shift` as a `bits` wide field, if no set bits are dropped
+fn field(kind: RelocKind, value: i64, shift: u32, bits: u32,) -> LlccB<u32,> {
+	let limit = 1i64 << (bits + shift - 1);
+	if value % (1 << shift) != 0 || value < -limit || value >= limit {
+		return Y(LlccError::link(format!(
+			"{kind:?} relocation out of range: {value:#x}"
+		),),);
+	}
+	X((value >> shift) as u32 & u32::MAX >> (32 - bits),)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::err::Container;
+	use crate::obj::Relocation;
+	use crate::obj::ar::Member;
+
+	fn word(bytes: &[u8], at: u64,) -> u32 {
+		let at = at as usize;
+		u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap(),)
+	}
+
+	fn object(
+		words: &[u32],
+		symbols: &[(&str, Option<SectionKind,>, u64, Binding,)],
+		relocs: &[(u64, RelocKind, &str,)],
+	) -> Object {
+		let mut obj = Object::default();
+		for word in words {
+			obj.section_mut(SectionKind::Text,).push(&word.to_le_bytes(),);
+		}
+		for (name, section, offset, binding,) in symbols {
+			let symbol = obj.symbol_mut(name,);
+			symbol.section = *section;
+			symbol.offset = *offset;
+			symbol.binding = *binding;
+		}
+		for (offset, kind, symbol,) in relocs {
+			obj.relocations.push(Relocation {
+				section: SectionKind::Text,
+				offset:  *offset,
+				kind:    *kind,
+				symbol:  symbol.to_string(),
+				addend:  0,
+			},);
+		}
+		obj
+	}
+
+	/// `_start` calls `f` from another object, takes the address of
+	/// `counter` in `.bss` and calls the missing weak `hook`
+	fn objects() -> Vec<Object,> {
+		let text = Some(SectionKind::Text,);
+		let mut main = object(
+			&[0x9400_0000, 0x9000_0000, 0x9100_0000, 0x9400_0000, 0xd65f_03c0,],
+			&[
+				("_start", text, 0, Binding::Global,),
+				("f", None, 0, Binding::Global,),
+				("counter", None, 0, Binding::Global,),
+				("hook", None, 0, Binding::Weak,),
+			],
+			&[
+				(0, RelocKind::Call26, "f",),
+				(4, RelocKind::AdrPrelPgHi21, "counter",),
+				(8, RelocKind::AddAbsLo12Nc, "counter",),
+				(12, RelocKind::Call26, "hook",),
+			],
+		);
+		main.section_mut(SectionKind::Rodata,).push(b"x",);
+		let mut lib = object(
+			&[0xd65f_03c0, 0xd65f_03c0,],
+			&[
+				("f", text, 4, Binding::Global,),
+				("weak_f", text, 0, Binding::Weak,),
+				("counter", Some(SectionKind::Bss,), 8, Binding::Global,),
+			],
+			&[],
+		);
+		lib.section_mut(SectionKind::Bss,).push(&[0; 16],);
+		vec![main, lib]
+	}
+
+	#[test]
+	fn resolves_and_relocates() {
+		let exe = link(&objects(),).unwrap();
+		let entry = u64::from_le_bytes(exe[0x18..0x20].try_into().unwrap(),);
+		// the file headers, then `.text` of both objects
+		let text = HEADER_SIZE + 2 * PROGRAM_HEADER_SIZE;
+		assert_eq!(entry, BASE + text);
+		// `f` is 4 bytes into the second `.text`, after the 20 of `_start`
+		assert_eq!(word(&exe, text,), 0x9400_0000 | ((20 + 4) / 4));
+		assert_eq!(word(&exe, text + 12,), NOP);
+
+		// `.bss` is on the next page, at the same offset into it as the end
+		// of the one byte of `.rodata` after the 28 bytes of `.text`
+		let end = (text + 28).next_multiple_of(8,) + 1;
+		let counter = (BASE + PAGE + end).next_multiple_of(8,) + 8;
+		let adrp = word(&exe, text + 4,);
+		let pages = ((adrp >> 5) & 0x7_ffff) << 2 | (adrp >> 29) & 0x3;
+		assert_eq!(pages as u64, (counter >> 12) - ((entry + 4) >> 12));
+		assert_eq!(
+			word(&exe, text + 8,),
+			0x9100_0000 | (counter as u32 & 0xfff) << 10
+		);
+
+		// both segments are page congruent
+		let phdr = |i: u64, field: u64| {
+			let at = (HEADER_SIZE + i * PROGRAM_HEADER_SIZE + field) as usize;
+			u64::from_le_bytes(exe[at..at + 8].try_into().unwrap(),)
+		};
+		for i in 0..2 {
+			assert_eq!(phdr(i, 8,) % PAGE, phdr(i, 16,) % PAGE);
+		}
+		// `.bss` takes no file space
+		assert_eq!(phdr(1, 32,), 0);
+		assert_eq!(phdr(1, 40,), 16);
+	}
+
+	#[test]
+	fn reports_bad_symbols() {
+		let mut objs = objects();
+		objs[1].symbols.retain(|s| s.name != "f",);
+		assert!(matches!(link(&objs), Y(LlccError::Link { .. },)));
+
+		let mut objs = objects();
+		objs.push(objs[1].clone(),);
+		assert!(matches!(link(&objs), Y(LlccError::Link { .. },)));
+
+		let mut objs = objects();
+		objs[0].symbols.retain(|s| s.name != "_start",);
+		assert!(matches!(link(&objs), Y(LlccError::Link { .. },)));
+	}
+
+	#[test]
+	fn branches_out_of_range() {
+		assert_eq!(field(RelocKind::Call26, -4, 2, 26,).unwrap(), 0x3ff_ffff);
+		assert!(matches!(field(RelocKind::Call26, 1 << 27, 2, 26,), Y(_,)));
+		assert!(matches!(field(RelocKind::CondBr19, 6, 2, 19,), Y(_,)));
+	}
+
+	#[test]
+	fn pulls_needed_members() {
+		let text = Some(SectionKind::Text,);
+		let member = |defines: &str, needs: &[&str]| {
+			let mut symbols = vec![(defines, text, 0, Binding::Global,)];
+			let mut relocs = vec![];
+			for (i, name,) in needs.iter().enumerate() {
+				symbols.push((name, None, 0, Binding::Global,),);
+				relocs.push((i as u64 * 4, RelocKind::Call26, *name,),);
+			}
+			let obj =
+				object(&vec![0x9400_0000; needs.len() + 1], &symbols, &relocs,);
+			Member {
+				name: format!("{defines}.o"),
+				data: elf::write(&obj,).unwrap(),
+			}
+		};
+		let mut archive = Archive {
+			members: vec![
+				member("f", &["g",],),
+				member("g", &[],),
+				member("unused", &["nowhere",],),
+			],
+			symbols: vec![],
+		};
+		archive.index_symbols();
+
+		let mut objects = vec![object(
+			&[0x9400_0000,],
+			&[
+				("_start", text, 0, Binding::Global,),
+				("f", None, 0, Binding::Global,),
+				("weak", None, 0, Binding::Weak,),
+			],
+			&[(0, RelocKind::Call26, "f",),],
+		)];
+		pull_members(&mut objects, &[archive,],).unwrap();
+		let defined: Vec<&str,> = objects[1..]
+			.iter()
+			.map(|obj| obj.symbols[0].name.as_str(),)
+			.collect();
+		assert_eq!(defined, ["f", "g"]);
+		link(&objects,).unwrap();
+	}
+}
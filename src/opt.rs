@@ -0,0 +1,217 @@
+//! scalar optimizations over `ir::Function`
+//!
+//! every pass expects SSA form and reports whether it changed anything.
+//! `optimize` runs the passes selected by an `OptLevel` until nothing changes,
+//! `optimize_module` adds the interprocedural ones around it
+
+use crate::ir::Function;
+use crate::ir::Module;
+use crate::ir::ssa;
+
+pub mod copy_prop;
+pub mod cse;
+pub mod dce;
+pub mod egraph;
+pub mod fold;
+pub mod indvars;
+pub mod inline;
+pub mod licm;
+pub mod sccp;
+pub mod simplify_cfg;
+pub mod tail;
+pub mod unroll;
+
+#[derive(
+	Debug,
+	Default,
+	Clone,
+	Copy,
+	PartialEq,
+	Eq,
+	PartialOrd,
+	Ord,
+	strum::Display,
+	strum::EnumString,
+)]
+pub enum OptLevel {
+	#[default]
+	#[strum(serialize = "-O0", serialize = "O0", serialize = "0")]
+	O0,
+	#[strum(serialize = "-O1", serialize = "O1", serialize = "1")]
+	O1,
+	#[strum(serialize = "-O2", serialize = "O2", serialize = "2")]
+	O2,
+	/// `O2` followed by unrolling
+	#[strum(serialize = "-O3", serialize = "O3", serialize = "3")]
+	O3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display,)]
+#[strum(serialize_all = "snake_case")]
+pub enum Pass {
+	Mem2Reg,
+	ConstFold,
+	Sccp,
+	Cse,
+	CopyProp,
+	Dce,
+	SimplifyCfg,
+	TailRecursion,
+	Licm,
+	IndVars,
+	Unroll,
+	/// experimental, so no `OptLevel` selects it. run it with `Pass::run`
+	EqSat,
+}
+
+impl Pass {
+	/// # Return
+	///
+	/// returns whether `func` changed
+	pub fn run(self, func: &mut Function,) -> bool {
+		match self {
+			Self::Mem2Reg => ssa::mem2reg(func,) > 0,
+			Self::ConstFold => fold::run(func,),
+			Self::Sccp => sccp::run(func,),
+			Self::Cse => cse::run(func,),
+			Self::CopyProp => copy_prop::run(func,),
+			Self::Dce => dce::run(func,),
+			Self::SimplifyCfg => simplify_cfg::run(func,),
+			Self::TailRecursion => tail::eliminate_self_recursion(func,),
+			Self::Licm => licm::run(func,),
+			Self::IndVars => indvars::run(func,),
+			Self::Unroll => unroll::run(func, unroll::FACTOR,),
+			Self::EqSat => egraph::run(func,),
+		}
+	}
+}
+
+impl OptLevel {
+	/// passes run once, before the fixpoint loop
+	pub fn setup(self,) -> &'static [Pass] {
+		match self {
+			Self::O0 => &[],
+			Self::O1 | Self::O2 | Self::O3 => {
+				&[Pass::TailRecursion, Pass::Mem2Reg,]
+			},
+		}
+	}
+
+	/// passes run once after the fixpoint, which is then reached again
+	pub fn late(self,) -> &'static [Pass] {
+		match self {
+			Self::O0 | Self::O1 | Self::O2 => &[],
+			Self::O3 => &[Pass::Unroll,],
+		}
+	}
+
+	/// passes repeated until none of them changes the function
+	pub fn passes(self,) -> &'static [Pass] {
+		match self {
+			Self::O0 => &[],
+			Self::O1 => {
+				&[Pass::ConstFold, Pass::CopyProp, Pass::Dce, Pass::SimplifyCfg,]
+			},
+			Self::O2 | Self::O3 => &[
+				Pass::Sccp,
+				Pass::ConstFold,
+				Pass::CopyProp,
+				Pass::Cse,
+				Pass::Licm,
+				Pass::IndVars,
+				Pass::Dce,
+				Pass::SimplifyCfg,
+			],
+		}
+	}
+}
+
+/// upper bound of fixpoint iterations. each round either shrinks the
+/// function or folds something, so this is only a guard
+const MAX_ROUNDS: usize = 16;
+
+pub fn optimize(func: &mut Function, level: OptLevel,) {
+	for pass in level.setup() {
+		pass.run(func,);
+	}
+	fixpoint(func, level,);
+	if level
+		.late()
+		.iter()
+		.fold(false, |changed, pass| pass.run(func,) | changed,)
+	{
+		fixpoint(func, level,);
+	}
+}
+
+fn fixpoint(func: &mut Function, level: OptLevel,) {
+	for _ in 0..MAX_ROUNDS {
+		let mut changed = false;
+		for pass in level.passes() {
+			changed |= pass.run(func,);
+		}
+		if !changed {
+			break;
+		}
+	}
+}
+
+/// `O2` inlines between two rounds of `optimize` so that the cost model sees
+/// simplified callees. tail calls are marked last, once no pass can move
+/// anything behind them
+pub fn optimize_module(module: &mut Module, level: OptLevel,) {
+	for func in module.functions.iter_mut() {
+		optimize(func, level,);
+	}
+	if level >= OptLevel::O2
+		&& inline::run(module, &inline::InlineParams::default(),)
+	{
+		for func in module.functions.iter_mut() {
+			optimize(func, level,);
+		}
+	}
+	if level >= OptLevel::O1 {
+		for func in module.functions.iter_mut() {
+			tail::mark(func,);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::err::Container;
+	use crate::ir::InstKind;
+	use crate::ir::lower::lower_expr;
+
+	#[test]
+	fn chain_of_constants_folds() {
+		let mut func = lower_expr("1+2+3-4",).unwrap();
+		optimize(&mut func, OptLevel::O1,);
+		let insts = &func.blocks[0].insts;
+		assert_eq!(insts.len(), 1, "{func}");
+		assert_eq!(insts[0].kind, InstKind::Const(2,));
+
+		let mut unoptimized = lower_expr("1+2+3-4",).unwrap();
+		optimize(&mut unoptimized, OptLevel::O0,);
+		assert_eq!(unoptimized.blocks[0].insts.len(), 7);
+	}
+
+	#[test]
+	fn float_literals_fold() {
+		// 1.5 + 2.25f is a `double`, its truncation the exit code
+		let mut func = lower_expr("1.5+2.25f-1",).unwrap();
+		optimize(&mut func, OptLevel::O1,);
+		let insts = &func.blocks[0].insts;
+		assert_eq!(insts.len(), 1, "{func}");
+		assert_eq!(insts[0].kind, InstKind::Const(2,));
+	}
+
+	#[test]
+	fn levels_leave_out_eqsat() {
+		for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2, OptLevel::O3,] {
+			let passes = [level.setup(), level.passes(), level.late(),];
+			assert!(!passes.concat().contains(&Pass::EqSat,), "{level}");
+		}
+	}
+}
@@ -0,0 +1,26 @@
+//! copy propagation
+//!
+//! only valid while the function is in SSA form. after `ssa::out_of_ssa`
+//! copies are real moves that must stay
+
+use crate::ir::Function;
+use crate::ir::InstKind;
+use std::collections::HashMap;
+
+/// # Return
+///
+/// returns whether `func` changed
+pub fn run(func: &mut Function,) -> bool {
+	let mut replace = HashMap::new();
+	for block in func.blocks.iter_mut() {
+		block.insts.retain(|inst| match (inst.dest, &inst.kind,) {
+			(Some(dest,), InstKind::Copy(src,),) => {
+				replace.insert(dest, *src,);
+				false
+			},
+			_ => true,
+		},);
+	}
+	func.replace_uses(&replace,);
+	!replace.is_empty()
+}
@@ -0,0 +1,100 @@
+//! common subexpression elimination
+//!
+//! walks the dominator tree with a scoped table of pure expressions. an
+//! expression computed in a dominating block is reused instead of recomputed
+
+use crate::ir::BinOp;
+use crate::ir::Block;
+use crate::ir::CmpOp;
+use crate::ir::Function;
+use crate::ir::InstKind;
+use crate::ir::Type;
+use crate::ir::Value;
+use crate::ir::cfg::Cfg;
+use crate::ir::dom::DomTree;
+use std::collections::HashMap;
+
+/// operands of commutative operations are ordered so that `a + b` and
+/// `b + a` share a key
+fn key(kind: &InstKind, ty: Type,) -> Option<(InstKind, Type,),> {
+	let kind = match kind {
+		InstKind::Binary(
+			op
+			@ (BinOp::Add | BinOp::Mul | BinOp::And | BinOp::Or | BinOp::Xor),
+			a,
+			b,
+		) => InstKind::Binary(*op, *a.min(b,), *a.max(b,),),
+		InstKind::Cmp(op @ (CmpOp::Eq | CmpOp::Ne), a, b,) => {
+			InstKind::Cmp(*op, *a.min(b,), *a.max(b,),)
+		},
+		InstKind::Const(_,)
+		| InstKind::Unary(..,)
+		| InstKind::Binary(..,)
+		| InstKind::Cmp(..,)
+		| InstKind::Cast(..,)
+		| InstKind::StackAddr(_,) => kind.clone(),
+		_ => return None,
+	};
+	Some((kind, ty,),)
+}
+
+/// # Return
+///
+/// returns whether `func` changed
+pub fn run(func: &mut Function,) -> bool {
+	let cfg = Cfg::new(func,);
+	let dom = DomTree::new(&cfg,);
+
+	let mut table: HashMap<(InstKind, Type,), Value,> = HashMap::new();
+	let mut replace = HashMap::new();
+	enum Visit {
+		Enter(Block,),
+		Exit(Vec<(InstKind, Type,),>,),
+	}
+	let mut work = vec![Visit::Enter(Block::ENTRY,)];
+	while let Some(visit,) = work.pop() {
+		let b = match visit {
+			Visit::Enter(b,) => b,
+			Visit::Exit(scope,) => {
+				for k in scope {
+					table.remove(&k,);
+				}
+				continue;
+			},
+		};
+
+		let mut scope = vec![];
+		let value_types = &func.value_types;
+		func.blocks[b.index()].insts.retain_mut(|inst| {
+			// operands may name values already found redundant in this walk
+			for op in inst.kind.operands_mut() {
+				if let Some(existing,) = replace.get(op,) {
+					*op = *existing;
+				}
+			}
+			let Some(dest,) = inst.dest else { return true };
+			let Some(k,) = key(&inst.kind, value_types[dest.index()],) else {
+				return true;
+			};
+			match table.get(&k,) {
+				Some(existing,) => {
+					replace.insert(dest, *existing,);
+					false
+				},
+				None => {
+					table.insert(k.clone(), dest,);
+					scope.push(k,);
+					true
+				},
+			}
+		},);
+
+		work.push(Visit::Exit(scope,),);
+		for child in dom.children(b,).iter().rev() {
+			work.push(Visit::Enter(*child,),);
+		}
+	}
+
+	func.replace_uses(&replace,);
+	!replace.is_empty()
+}
@@ -0,0 +1,78 @@
+//! dead code elimination
+//!
+//! unreachable blocks are emptied first, then instructions whose results
+//! never reach a side effect or a terminator are swept. dead phi cycles go
+//! away as well since liveness is propagated from the roots only
+
+use crate::ir::Function;
+use crate::ir::Terminator;
+use crate::ir::cfg::Cfg;
+
+/// # Return
+///
+/// returns whether `func` changed
+pub fn run(func: &mut Function,) -> bool {
+	let mut changed = remove_unreachable(func,);
+
+	let mut live = vec![false; func.value_types.len()];
+	let mut work = vec![];
+	for block in func.blocks.iter() {
+		for inst in block.insts.iter().filter(|i| i.kind.has_side_effect(),) {
+			work.extend(inst.kind.operands(),);
+		}
+		work.extend(block.term.operands(),);
+	}
+
+	let mut defs = vec![None; func.value_types.len()];
+	for (b, block,) in func.blocks.iter().enumerate() {
+		for (i, inst,) in block.insts.iter().enumerate() {
+			if let Some(dest,) = inst.dest {
+				defs[dest.index()] = Some((b, i,),);
+			}
+		}
+	}
+
+	while let Some(v,) = work.pop() {
+		if live[v.index()] {
+			continue;
+		}
+		live[v.index()] = true;
+		if let Some((b, i,),) = defs[v.index()] {
+			work.extend(func.blocks[b].insts[i].kind.operands(),);
+		}
+	}
+
+	for block in func.blocks.iter_mut() {
+		let before = block.insts.len();
+		block.insts.retain(|inst| {
+			inst.kind.has_side_effect()
+				|| inst.dest.is_some_and(|d| live[d.index()],)
+		},);
+		changed |= before != block.insts.len();
+	}
+	changed
+}
+
+/// empties blocks not reachable from entry and detaches them from the phis
+/// of their successors
+pub fn remove_unreachable(func: &mut Function,) -> bool {
+	let cfg = Cfg::new(func,);
+	let mut changed = false;
+	for b in func.block_ids() {
+		if cfg.is_reachable(b,) {
+			continue;
+		}
+		let block = func.block(b,);
+		if block.insts.is_empty() && block.term == Terminator::Unreachable {
+			continue;
+		}
+		for s in block.term.successors() {
+			func.remove_phi_incoming(s, b,);
+		}
+		let block = func.block_mut(b,);
+		block.insts.clear();
+		block.term = Terminator::Unreachable;
+		changed = true;
+	}
+	changed
+}
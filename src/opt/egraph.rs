@@ -0,0 +1,506 @@
+//! equality saturation over the pure expressions of a block
+//!
+//! the expressions of a block are put into an e-graph, where each e-class
+//! holds every form of a value proven equal so far. the rewrite rules of
+//! `rules::RULES` are applied to all classes at once until nothing new is
+//! learned or a limit is hit, then the cheapest form of every value still
+//! needed is extracted with the aarch64 cost model of `cost`. the block is
+//! only rewritten when that is cheaper than what was there
+//!
+//! values defined by anything but a pure arithmetic instruction enter the
+//! graph as opaque leaves
+
+pub mod cost;
+pub mod pattern;
+pub mod rules;
+
+use crate::ir::BinOp;
+use crate::ir::CastOp;
+use crate::ir::CmpOp;
+use crate::ir::Function;
+use crate::ir::Inst;
+use crate::ir::InstKind;
+use crate::ir::Type;
+use crate::ir::UnOp;
+use crate::ir::Value;
+use crate::opt::fold;
+use pattern::Rewrite;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord,)]
+pub struct Id(pub u32,);
+
+impl Id {
+	pub fn index(self,) -> usize {
+		self.0 as usize
+	}
+}
+
+/// an operation whose operands are e-classes
+#[derive(Debug, Clone, PartialEq, Eq, Hash,)]
+pub enum Node {
+	Leaf(Value,),
+	Const(i64,),
+	Unary(UnOp, Id,),
+	Binary(BinOp, Id, Id,),
+	Cmp(CmpOp, Id, Id,),
+	Cast(CastOp, Id,),
+}
+
+impl Node {
+	pub fn children(&self,) -> Vec<Id,> {
+		match self {
+			Self::Leaf(_,) | Self::Const(_,) => vec![],
+			Self::Unary(_, a,) | Self::Cast(_, a,) => vec![*a],
+			Self::Binary(_, a, b,) | Self::Cmp(_, a, b,) => vec![*a, *b],
+		}
+	}
+
+	fn map_children(&self, f: impl Fn(Id,) -> Id,) -> Self {
+		match self {
+			Self::Leaf(_,) | Self::Const(_,) => self.clone(),
+			Self::Unary(op, a,) => Self::Unary(*op, f(*a,),),
+			Self::Cast(op, a,) => Self::Cast(*op, f(*a,),),
+			Self::Binary(op, a, b,) => Self::Binary(*op, f(*a,), f(*b,),),
+			Self::Cmp(op, a, b,) => Self::Cmp(*op, f(*a,), f(*b,),),
+		}
+	}
+}
+
+pub struct EClass {
+	pub nodes:    Vec<Node,>,
+	pub ty:       Type,
+	/// value of the class, if constant folding found one
+	pub constant: Option<i64,>,
+}
+
+pub struct EGraph {
+	/// union-find over class ids
+	parent:  Vec<Id,>,
+	/// indexed by id, `None` once merged into another class
+	classes: Vec<Option<EClass,>,>,
+	/// hashcons of canonical nodes. the type tells `1:i32` from `1:i64`
+	memo:    HashMap<(Node, Type,), Id,>,
+	/// unions since the last `rebuild`
+	dirty:   bool,
+}
+
+impl Default for EGraph {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl EGraph {
+	pub fn new() -> Self {
+		Self {
+			parent:  vec![],
+			classes: vec![],
+			memo:    HashMap::new(),
+			dirty:   false,
+		}
+	}
+
+	pub fn find(&self, mut id: Id,) -> Id {
+		while self.parent[id.index()] != id {
+			id = self.parent[id.index()];
+		}
+		id
+	}
+
+	pub fn class(&self, id: Id,) -> &EClass {
+		self.classes[self.find(id,).index()]
+			.as_ref()
+			.expect("canonical class exists",)
+	}
+
+	/// canonical ids of all classes, in creation order
+	pub fn class_ids(&self,) -> Vec<Id,> {
+		(0..self.classes.len() as u32)
+			.map(Id,)
+			.filter(|id| self.classes[id.index()].is_some(),)
+			.collect()
+	}
+
+	pub fn node_count(&self,) -> usize {
+		self.classes.iter().flatten().map(|c| c.nodes.len(),).sum()
+	}
+
+	fn canonicalize(&self, node: &Node,) -> Node {
+		node.map_children(|c| self.find(c,),)
+	}
+
+	fn eval(&self, node: &Node, ty: Type,) -> Option<i64,> {
+		let k = |id: Id| self.class(id,).constant;
+		match node {
+			Node::Leaf(_,) => None,
+			Node::Const(c,) => Some(fold::normalize(ty, *c,),),
+			Node::Unary(op, a,) => Some(fold::eval_unary(*op, ty, k(*a,)?,),),
+			Node::Binary(op, a, b,) => {
+				fold::eval_binary(*op, ty, k(*a,)?, k(*b,)?,)
+			},
+			Node::Cmp(op, a, b,) => {
+				Some(fold::eval_cmp(*op, self.class(*a,).ty, k(*a,)?, k(*b,)?,)
+					as i64,)
+			},
+			Node::Cast(op, a,) => {
+				Some(fold::eval_cast(*op, self.class(*a,).ty, ty, k(*a,)?,),)
+			},
+		}
+	}
+
+	/// # Return
+	///
+	/// returns the class of `node`, which is new unless an equal node exists
+	pub fn add(&mut self, node: Node, ty: Type,) -> Id {
+		let node = self.canonicalize(&node,);
+		if let Some(id,) = self.memo.get(&(node.clone(), ty,),) {
+			return self.find(*id,);
+		}
+		let id = Id(self.classes.len() as u32,);
+		let constant = self.eval(&node, ty,);
+		self.parent.push(id,);
+		self.classes.push(Some(EClass {
+			nodes: vec![node.clone()],
+			ty,
+			constant,
+		},),);
+		self.memo.insert((node.clone(), ty,), id,);
+		if let Some(c,) = constant
+			&& !matches!(node, Node::Const(_,))
+		{
+			let k = self.add(Node::Const(c,), ty,);
+			self.union(id, k,);
+		}
+		id
+	}
+
+	/// # Return
+	///
+	/// returns whether `a` and `b` were different classes
+	pub fn union(&mut self, a: Id, b: Id,) -> bool {
+		let (a, b,) = (self.find(a,), self.find(b,),);
+		if a == b {
+			return false;
+		}
+		// the older class survives, which keeps ids of roots stable
+		let (keep, gone,) = (a.min(b,), a.max(b,),);
+		self.parent[gone.index()] = keep;
+		let gone =
+			self.classes[gone.index()].take().expect("canonical class exists",);
+		let keep = self.classes[keep.index()]
+			.as_mut()
+			.expect("canonical class exists",);
+		debug_assert_eq!(keep.ty, gone.ty);
+		keep.nodes.extend(gone.nodes,);
+		keep.constant = keep.constant.or(gone.constant,);
+		self.dirty = true;
+		true
+	}
+
+	/// restores the invariants after unions: nodes are canonical, equal nodes
+	/// share a class (congruence) and constants are propagated
+	pub fn rebuild(&mut self,) {
+		while std::mem::take(&mut self.dirty,) {
+			let mut memo: HashMap<(Node, Type,), Id,> = HashMap::new();
+			let mut merges = vec![];
+			let mut folded = vec![];
+			for id in self.class_ids() {
+				let class = self.classes[id.index()].as_ref().unwrap();
+				let ty = class.ty;
+				let mut nodes: Vec<Node,> = vec![];
+				for n in class.nodes.iter().map(|n| self.canonicalize(n,),) {
+					if !nodes.contains(&n,) {
+						nodes.push(n,);
+					}
+				}
+				for n in nodes.iter() {
+					if class.constant.is_none()
+						&& let Some(c,) = self.eval(n, ty,)
+					{
+						folded.push((id, c,),);
+					}
+					if let Some(other,) = memo.insert((n.clone(), ty,), id,) {
+						merges.push((other, id,),);
+					}
+				}
+				self.classes[id.index()].as_mut().unwrap().nodes = nodes;
+			}
+			self.memo = memo;
+			for (a, b,) in merges {
+				self.union(a, b,);
+			}
+			for (id, c,) in folded {
+				let id = self.find(id,);
+				if self.class(id,).constant.is_none() {
+					self.classes[id.index()].as_mut().unwrap().constant =
+						Some(c,);
+					let ty = self.class(id,).ty;
+					let k = self.add(Node::Const(c,), ty,);
+					self.union(id, k,);
+					self.dirty = true;
+				}
+			}
+		}
+	}
+
+	/// applies `rules` until no rule adds anything new, or `limits` is hit
+	pub fn saturate(&mut self, rules: &[Rewrite], limits: &Limits,) {
+		for _ in 0..limits.iterations {
+			let mut matches = vec![];
+			for rule in rules {
+				for id in self.class_ids() {
+					for subst in rule.lhs.search(self, id,) {
+						matches.push((rule, id, subst,),);
+					}
+				}
+			}
+			let before = (self.node_count(), self.class_ids().len(),);
+			let mut changed = false;
+			for (rule, id, subst,) in matches {
+				let ty = self.class(id,).ty;
+				if let Some(new,) = rule.rhs.instantiate(self, &subst, ty,) {
+					changed |= self.union(id, new,);
+				}
+			}
+			self.rebuild();
+			let after = (self.node_count(), self.class_ids().len(),);
+			if (!changed && before == after) || self.node_count() > limits.nodes
+			{
+				break;
+			}
+		}
+	}
+}
+
+pub struct Limits {
+	pub iterations: usize,
+	/// saturation stops once the graph has grown past this many nodes
+	pub nodes:      usize,
+}
+
+impl Default for Limits {
+	fn default() -> Self {
+		Self { iterations: 8, nodes: 4096, }
+	}
+}
+
+/// instructions kept as they are, with their results entering as leaves
+fn is_pure(kind: &InstKind,) -> bool {
+	matches!(
+		kind,
+		InstKind::Const(_,)
+			| InstKind::Copy(_,)
+			| InstKind::Unary(..,)
+			| InstKind::Binary(..,)
+			| InstKind::Cmp(..,)
+			| InstKind::Cast(..,)
+	)
+}
+
+/// # Return
+///
+/// returns whether `func` changed
+pub fn run(func: &mut Function,) -> bool {
+	let rules = rules::rules();
+	let limits = Limits::default();
+	let mut changed = false;
+	for b in func.block_ids() {
+		changed |= run_block(func, b, &rules, &limits,);
+	}
+	changed
+}
+
+fn run_block(
+	func: &mut Function,
+	b: crate::ir::Block,
+	rules: &[Rewrite],
+	limits: &Limits,
+) -> bool {
+	let insts = func.block(b,).insts.clone();
+	if insts.iter().filter(|i| is_pure(&i.kind,),).count() < 2 {
+		return false;
+	}
+
+	let mut graph = EGraph::new();
+	let mut class_of: HashMap<Value, Id,> = HashMap::new();
+	// position from which a leaf may be used
+	let mut avail: HashMap<Value, usize,> = HashMap::new();
+	for (j, inst,) in insts.iter().enumerate() {
+		let Some(dest,) = inst.dest else { continue };
+		if !is_pure(&inst.kind,) {
+			avail.insert(dest, j + 1,);
+			class_of
+				.insert(dest, graph.add(Node::Leaf(dest,), func.ty(dest,),),);
+			continue;
+		}
+		let mut ops = vec![];
+		for v in inst.kind.operands() {
+			let id = *class_of
+				.entry(v,)
+				.or_insert_with(|| graph.add(Node::Leaf(v,), func.ty(v,),),);
+			ops.push(id,);
+		}
+		let node = match inst.kind {
+			InstKind::Copy(_,) => {
+				class_of.insert(dest, ops[0],);
+				continue;
+			},
+			InstKind::Const(c,) => Node::Const(c,),
+			InstKind::Unary(op, _,) => Node::Unary(op, ops[0],),
+			InstKind::Binary(op, ..,) => Node::Binary(op, ops[0], ops[1],),
+			InstKind::Cmp(op, ..,) => Node::Cmp(op, ops[0], ops[1],),
+			InstKind::Cast(op, _,) => Node::Cast(op, ops[0],),
+			_ => unreachable!("only pure instructions reach here"),
+		};
+		class_of.insert(dest, graph.add(node, func.ty(dest,),),);
+	}
+	graph.rebuild();
+	let before = cost::list_cost(&insts,);
+	graph.saturate(rules, limits,);
+
+	// a pure value is rebuilt where it is first needed by something other
+	// than the pure instructions of this block
+	let mut inner_uses: HashMap<Value, usize,> = HashMap::new();
+	for inst in insts.iter().filter(|i| is_pure(&i.kind,),) {
+		for op in inst.kind.operands() {
+			*inner_uses.entry(op,).or_default() += 1;
+		}
+	}
+	let uses = func.use_counts();
+	let is_root =
+		|v: Value| uses[v.index()] > inner_uses.get(&v,).copied().unwrap_or(0,);
+
+	let mark = func.value_types.len();
+	let mut emitted: HashMap<Id, Value,> = HashMap::new();
+	let mut new_insts = vec![];
+	let mut replace = HashMap::new();
+	for (j, inst,) in insts.iter().enumerate() {
+		let Some(dest,) = inst.dest.filter(|_| is_pure(&inst.kind,),) else {
+			new_insts.push(inst.clone(),);
+			if let Some(dest,) = inst.dest {
+				emitted.insert(graph.find(class_of[&dest],), dest,);
+			}
+			continue;
+		};
+		if !is_root(dest,) {
+			continue;
+		}
+		let best = cost::extract(&graph, &avail, &emitted, j,);
+		let v = emit(
+			func,
+			&graph,
+			&best,
+			graph.find(class_of[&dest],),
+			&mut emitted,
+			&mut new_insts,
+		);
+		if v != dest {
+			replace.insert(dest, v,);
+		}
+	}
+
+	if cost::list_cost(&new_insts,) >= before {
+		func.value_types.truncate(mark,);
+		return false;
+	}
+	func.block_mut(b,).insts = new_insts;
+	func.replace_uses(&replace,);
+	true
+}
+
+/// emits the instructions computing class `id` by the `best` nodes
+fn emit(
+	func: &mut Function,
+	graph: &EGraph,
+	best: &HashMap<Id, Node,>,
+	id: Id,
+	emitted: &mut HashMap<Id, Value,>,
+	insts: &mut Vec<Inst,>,
+) -> Value {
+	if let Some(v,) = emitted.get(&id,) {
+		return *v;
+	}
+	let node = &best[&id];
+	let mut op =
+		|c: Id| emit(func, graph, best, graph.find(c,), emitted, insts,);
+	let kind = match node {
+		Node::Leaf(v,) => return *v,
+		Node::Const(c,) => InstKind::Const(*c,),
+		Node::Unary(u, a,) => InstKind::Unary(*u, op(*a,),),
+		Node::Binary(o, a, b,) => {
+			let a = op(*a,);
+			InstKind::Binary(*o, a, op(*b,),)
+		},
+		Node::Cmp(o, a, b,) => {
+			let a = op(*a,);
+			InstKind::Cmp(*o, a, op(*b,),)
+		},
+		Node::Cast(o, a,) => InstKind::Cast(*o, op(*a,),),
+	};
+	let dest = func.new_value(graph.class(id,).ty,);
+	insts.push(Inst { dest: Some(dest,), kind, },);
+	emitted.insert(id, dest,);
+	dest
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ir::FunctionBuilder;
+
+	#[test]
+	fn constants_fold_through_unions() {
+		let mut g = EGraph::new();
+		let x = g.add(Node::Leaf(Value(0,),), Type::I32,);
+		let two = g.add(Node::Const(2,), Type::I32,);
+		let three = g.add(Node::Const(3,), Type::I32,);
+		let sum = g.add(Node::Binary(BinOp::Add, x, three,), Type::I32,);
+		assert_eq!(g.class(sum,).constant, None);
+		g.union(x, two,);
+		g.rebuild();
+		assert_eq!(g.class(sum,).constant, Some(5));
+	}
+
+	#[test]
+	fn factoring_saves_a_multiply() {
+		// (a * b) + (a * c) becomes a * (b + c)
+		let mut fb = FunctionBuilder::new(
+			"f",
+			&[Type::I32, Type::I32, Type::I32,],
+			Some(Type::I32,),
+		);
+		let (a, b, c,) = (fb.param(0,), fb.param(1,), fb.param(2,),);
+		let ab = fb.binary(BinOp::Mul, a, b,);
+		let ac = fb.binary(BinOp::Mul, a, c,);
+		let sum = fb.binary(BinOp::Add, ab, ac,);
+		fb.ret(Some(sum,),);
+		let mut func = fb.finish();
+		assert!(run(&mut func,));
+		let muls = func.blocks[0]
+			.insts
+			.iter()
+			.filter(|i| matches!(i.kind, InstKind::Binary(BinOp::Mul, ..)),)
+			.count();
+		assert_eq!(muls, 1, "{func}");
+	}
+
+	#[test]
+	fn multiply_by_power_of_two_is_a_shift() {
+		let mut fb =
+			FunctionBuilder::new("f", &[Type::I64,], Some(Type::I64,),);
+		let x = fb.param(0,);
+		let eight = fb.iconst(Type::I64, 8,);
+		let y = fb.binary(BinOp::Mul, x, eight,);
+		fb.ret(Some(y,),);
+		let mut func = fb.finish();
+		assert!(run(&mut func,));
+		assert!(
+			func.blocks[0].insts.iter().any(
+				|i| matches!(i.kind, InstKind::Binary(BinOp::Shl, a, _) if a == x)
+			),
+			"{func}"
+		);
+		assert!(!run(&mut func,), "{func}");
+	}
+}
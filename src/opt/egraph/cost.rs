@@ -0,0 +1,183 @@
+//! aarch64 cost model used to extract expressions from an e-graph
+//!
+//! costs roughly follow the latency of a Cortex-A class core: simple alu
+//! operations are 1, `mul` is 3, a division 12 and a remainder needs an
+//! extra `msub`. constants cost the `movz`/`movk` sequence materializing
+//! them, or nothing when they fit the immediate field of their user
+
+use super::EGraph;
+use super::Id;
+use super::Node;
+use crate::ir::BinOp;
+use crate::ir::Inst;
+use crate::ir::InstKind;
+use crate::ir::Value;
+use std::collections::HashMap;
+
+/// instructions needed to move `c` into a register. zero is `xzr`
+pub fn const_cost(c: i64,) -> usize {
+	if c == 0 {
+		return 0;
+	}
+	let halves =
+		|v: i64| (0..4).filter(|i| (v >> (16 * i)) & 0xffff != 0,).count();
+	// `movn` covers values that are all ones but for one half
+	halves(c,).min(halves(!c,),).max(1,)
+}
+
+/// whether constant `c` as operand `index` of `op` is encoded in the
+/// instruction itself
+pub fn is_immediate(op: BinOp, index: usize, c: i64,) -> bool {
+	match op {
+		// `sub` takes over negative addends and vice versa
+		BinOp::Add | BinOp::Sub => index == 1 && (-4095..4096).contains(&c,),
+		BinOp::Shl | BinOp::LShr | BinOp::AShr => index == 1,
+		_ => false,
+	}
+}
+
+pub fn op_cost(kind: &InstKind,) -> usize {
+	match kind {
+		InstKind::Const(c,) => const_cost(*c,),
+		InstKind::Copy(_,) => 0,
+		InstKind::Binary(BinOp::Mul, ..,) => 3,
+		InstKind::Binary(BinOp::SDiv | BinOp::UDiv, ..,) => 12,
+		InstKind::Binary(BinOp::SRem | BinOp::URem, ..,) => 15,
+		// `cmp` and `cset`
+		InstKind::Cmp(..,) => 2,
+		_ => 1,
+	}
+}
+
+fn node_cost(node: &Node,) -> usize {
+	match node {
+		Node::Leaf(_,) => 0,
+		Node::Const(c,) => const_cost(*c,),
+		Node::Unary(..,) | Node::Cast(..,) => 1,
+		Node::Binary(op, ..,) => {
+			op_cost(&InstKind::Binary(*op, Value(0,), Value(0,),),)
+		},
+		Node::Cmp(..,) => 2,
+	}
+}
+
+/// cost of the pure instructions of `insts`. a constant only used as
+/// immediate operands is free
+pub fn list_cost(insts: &[Inst],) -> usize {
+	let consts: HashMap<Value, i64,> = insts
+		.iter()
+		.filter_map(|i| match i.kind {
+			InstKind::Const(c,) => Some((i.dest?, c,),),
+			_ => None,
+		},)
+		.collect();
+	let mut in_register: Vec<Value,> = vec![];
+	let mut cost = 0;
+	for inst in insts {
+		match &inst.kind {
+			InstKind::Const(_,) => continue,
+			InstKind::Binary(op, a, b,) => {
+				for (i, v,) in [*a, *b,].into_iter().enumerate() {
+					if consts
+						.get(&v,)
+						.is_some_and(|c| !is_immediate(*op, i, *c,),)
+					{
+						in_register.push(v,);
+					}
+				}
+			},
+			kind => in_register.extend(
+				kind.operands()
+					.into_iter()
+					.filter(|v| consts.contains_key(v,),),
+			),
+		}
+		cost += match inst.kind {
+			InstKind::Phi(_,) | InstKind::Load(_,) | InstKind::Store { .. } => {
+				0
+			},
+			InstKind::Call { .. }
+			| InstKind::StackLoad(_,)
+			| InstKind::StackStore(..,) => 0,
+			InstKind::StackAddr(_,) => 0,
+			ref kind => op_cost(kind,),
+		};
+	}
+	in_register.sort();
+	in_register.dedup();
+	cost + in_register.iter().map(|v| const_cost(consts[v],),).sum::<usize>()
+}
+
+/// picks the cheapest node of every class computable at position `at` of
+/// the block. classes in `emitted` already have a value and cost nothing
+pub fn extract(
+	graph: &EGraph,
+	avail: &HashMap<Value, usize,>,
+	emitted: &HashMap<Id, Value,>,
+	at: usize,
+) -> HashMap<Id, Node,> {
+	let ids = graph.class_ids();
+	let mut best: HashMap<Id, (usize, Node,),> = emitted
+		.iter()
+		.map(|(id, v,)| (graph.find(*id,), (0, Node::Leaf(*v,),),),)
+		.collect();
+	let child_cost =
+		|best: &HashMap<Id, (usize, Node,),>, node: &Node, i: usize, c: Id| {
+			let c = graph.find(c,);
+			if let (Node::Binary(op, ..,), Some(k,),) =
+				(node, graph.class(c,).constant,)
+				&& is_immediate(*op, i, k,)
+			{
+				return Some(0,);
+			}
+			best.get(&c,).map(|(cost, _,)| *cost,)
+		};
+
+	let mut changed = true;
+	while changed {
+		changed = false;
+		for id in ids.iter() {
+			if emitted.contains_key(id,) {
+				continue;
+			}
+			for node in graph.class(*id,).nodes.iter() {
+				if let Node::Leaf(v,) = node
+					&& avail.get(v,).is_some_and(|p| *p > at,)
+				{
+					continue;
+				}
+				let children: Option<Vec<usize,>,> = node
+					.children()
+					.into_iter()
+					.enumerate()
+					.map(|(i, c,)| child_cost(&best, node, i, c,),)
+					.collect();
+				let Some(children,) = children else { continue };
+				let cost = children
+					.into_iter()
+					.fold(node_cost(node,), usize::saturating_add,);
+				if best.get(id,).is_none_or(|(old, _,)| cost < *old,) {
+					best.insert(*id, (cost, node.clone(),),);
+					changed = true;
+				}
+			}
+		}
+	}
+	best.into_iter().map(|(id, (_, node,),)| (id, node,),).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn constant_materialization() {
+		assert_eq!(const_cost(0,), 0);
+		assert_eq!(const_cost(0xffff,), 1);
+		assert_eq!(const_cost(0x1_0000,), 1);
+		assert_eq!(const_cost(0x1_ffff,), 2);
+		assert_eq!(const_cost(-1,), 1);
+		assert_eq!(const_cost(-0x1_0000,), 1);
+		assert_eq!(const_cost(0x1234_5678_9abc_def0,), 4);
+	}
+}
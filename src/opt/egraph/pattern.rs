@@ -0,0 +1,275 @@
+//! patterns over e-graphs, written as s-expressions
+//!
+//! `?x` is a variable, an integer is a constant of the matched type and
+//! `(op args...)` is an operation named like `ir::BinOp` or `ir::UnOp`.
+//! `(log2 ?c)` may only appear on the right hand side. it is computed when
+//! the rule fires and the rule does not apply when `?c` is not a constant
+//! power of two
+
+use super::EGraph;
+use super::Id;
+use super::Node;
+use crate::err::B::X;
+use crate::err::B::Y;
+use crate::err::LlccB;
+use crate::err::LlccError;
+use crate::err::ReShape;
+use crate::ir::BinOp;
+use crate::ir::Type;
+use crate::ir::UnOp;
+use crate::opt::fold;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub enum Pattern {
+	Var(String,),
+	Const(i64,),
+	Unary(UnOp, Box<Pattern,>,),
+	Binary(BinOp, Box<Pattern,>, Box<Pattern,>,),
+	Log2(Box<Pattern,>,),
+}
+
+/// bindings of pattern variables
+pub type Subst = Vec<(String, Id,),>;
+
+fn lookup(subst: &Subst, var: &str,) -> Option<Id,> {
+	subst.iter().find(|(v, _,)| v == var,).map(|(_, id,)| *id,)
+}
+
+impl Pattern {
+	/// every way `self` matches class `id`
+	pub fn search(&self, graph: &EGraph, id: Id,) -> Vec<Subst,> {
+		self.matches(graph, id, vec![],)
+	}
+
+	fn matches(&self, graph: &EGraph, id: Id, subst: Subst,) -> Vec<Subst,> {
+		let id = graph.find(id,);
+		let class = graph.class(id,);
+		match self {
+			Self::Var(var,) => match lookup(&subst, var,) {
+				Some(bound,) if graph.find(bound,) != id => vec![],
+				Some(_,) => vec![subst],
+				None => {
+					let mut subst = subst;
+					subst.push((var.clone(), id,),);
+					vec![subst]
+				},
+			},
+			Self::Const(c,) => {
+				if class.constant == Some(fold::normalize(class.ty, *c,),) {
+					vec![subst]
+				} else {
+					vec![]
+				}
+			},
+			Self::Unary(op, a,) => class
+				.nodes
+				.iter()
+				.filter_map(|n| match n {
+					Node::Unary(o, x,) if o == op => Some(*x,),
+					_ => None,
+				},)
+				.flat_map(|x| a.matches(graph, x, subst.clone(),),)
+				.collect(),
+			Self::Binary(op, a, b,) => class
+				.nodes
+				.iter()
+				.filter_map(|n| match n {
+					Node::Binary(o, x, y,) if o == op => Some((*x, *y,),),
+					_ => None,
+				},)
+				.flat_map(|(x, y,)| {
+					a.matches(graph, x, subst.clone(),)
+						.into_iter()
+						.flat_map(move |s| b.matches(graph, y, s,),)
+				},)
+				.collect(),
+			Self::Log2(_,) => panic!("`log2` only computes right hand sides"),
+		}
+	}
+
+	/// adds `self` with `subst` applied to `graph`. new nodes get type `ty`
+	///
+	/// # Return
+	///
+	/// returns `None` when a computed operand is not defined
+	pub fn instantiate(
+		&self,
+		graph: &mut EGraph,
+		subst: &Subst,
+		ty: Type,
+	) -> Option<Id,> {
+		let id = match self {
+			Self::Var(var,) => {
+				lookup(subst, var,).expect("variable bound by the left side",)
+			},
+			Self::Const(c,) => {
+				graph.add(Node::Const(fold::normalize(ty, *c,),), ty,)
+			},
+			Self::Unary(op, a,) => {
+				let a = a.instantiate(graph, subst, ty,)?;
+				graph.add(Node::Unary(*op, a,), ty,)
+			},
+			Self::Binary(op, a, b,) => {
+				let a = a.instantiate(graph, subst, ty,)?;
+				let b = b.instantiate(graph, subst, ty,)?;
+				graph.add(Node::Binary(*op, a, b,), ty,)
+			},
+			Self::Log2(a,) => {
+				let a = a.instantiate(graph, subst, ty,)?;
+				let c = graph.class(a,).constant?;
+				if c <= 0 || c.count_ones() != 1 {
+					return None;
+				}
+				graph.add(Node::Const(c.trailing_zeros() as i64,), ty,)
+			},
+		};
+		Some(id,)
+	}
+
+	fn parse(
+		tokens: &mut std::iter::Peekable<std::vec::IntoIter<String,>,>,
+	) -> LlccB<Self,> {
+		let Some(token,) = tokens.next() else {
+			return Y(LlccError::syntax("unexpected end of pattern",),);
+		};
+		if token != "(" {
+			return X(match token.strip_prefix('?',) {
+				Some(var,) => Self::Var(var.to_string(),),
+				None => Self::Const(token.parse()?,),
+			},);
+		}
+
+		let Some(op,) = tokens.next() else {
+			return Y(LlccError::syntax("missing operator",),);
+		};
+		let mut args = vec![];
+		while tokens.peek().is_some_and(|t| t != ")",) {
+			args.push(Self::parse(tokens,)?,);
+		}
+		if tokens.next().is_none() {
+			return Y(LlccError::syntax(format!("unclosed `({op}`"),),);
+		}
+
+		let arity = |n: usize| {
+			if args.len() == n {
+				X((),)
+			} else {
+				Y(LlccError::syntax(format!("`{op}` takes {n} operands"),),)
+			}
+		};
+		let rslt = if op == "log2" {
+			arity(1,)?;
+			Self::Log2(Box::new(args.remove(0,),),)
+		} else if let Ok(un,) = UnOp::from_str(&op,) {
+			arity(1,)?;
+			Self::Unary(un, Box::new(args.remove(0,),),)
+		} else {
+			let bin = BinOp::from_str(&op,)?;
+			arity(2,)?;
+			let b = args.pop().unwrap();
+			Self::Binary(bin, Box::new(args.pop().unwrap(),), Box::new(b,),)
+		};
+		X(rslt,)
+	}
+
+	fn computes(&self,) -> bool {
+		match self {
+			Self::Var(_,) | Self::Const(_,) => false,
+			Self::Log2(_,) => true,
+			Self::Unary(_, a,) => a.computes(),
+			Self::Binary(_, a, b,) => a.computes() || b.computes(),
+		}
+	}
+
+	fn vars(&self, out: &mut Vec<String,>,) {
+		match self {
+			Self::Var(v,) => out.push(v.clone(),),
+			Self::Const(_,) => (),
+			Self::Unary(_, a,) | Self::Log2(a,) => a.vars(out,),
+			Self::Binary(_, a, b,) => {
+				a.vars(out,);
+				b.vars(out,);
+			},
+		}
+	}
+}
+
+impl FromStr for Pattern {
+	type Err = LlccError;
+
+	fn from_str(s: &str,) -> Result<Self, Self::Err,> {
+		let tokens: Vec<String,> = s
+			.replace('(', " ( ",)
+			.replace(')', " ) ",)
+			.split_whitespace()
+			.map(str::to_string,)
+			.collect();
+		let mut tokens = tokens.into_iter().peekable();
+		let pattern: Result<Self, _,> = Self::parse(&mut tokens,).reshape((),);
+		let pattern = pattern?;
+		match tokens.next() {
+			Some(extra,) => {
+				Err(LlccError::syntax(format!("trailing `{extra}`"),),)
+			},
+			None => Ok(pattern,),
+		}
+	}
+}
+
+pub struct Rewrite {
+	pub name: &'static str,
+	pub lhs:  Pattern,
+	pub rhs:  Pattern,
+}
+
+impl Rewrite {
+	pub fn new(name: &'static str, lhs: &str, rhs: &str,) -> LlccB<Self,> {
+		let (lhs, rhs,) = (Pattern::from_str(lhs,)?, Pattern::from_str(rhs,)?,);
+		if matches!(lhs, Pattern::Var(_,)) {
+			return Y(LlccError::syntax(format!(
+				"`{name}` matches everything"
+			),),);
+		}
+		if lhs.computes() {
+			return Y(LlccError::syntax(format!(
+				"`{name}` computes on the left"
+			),),);
+		}
+		let (mut bound, mut used,) = (vec![], vec![],);
+		lhs.vars(&mut bound,);
+		rhs.vars(&mut used,);
+		if let Some(free,) = used.iter().find(|v| !bound.contains(v,),) {
+			return Y(LlccError::syntax(format!(
+				"`?{free}` of `{name}` is unbound"
+			),),);
+		}
+		X(Self { name, lhs, rhs, },)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_nested() {
+		let p = Pattern::from_str("(add (mul ?a 2) (neg ?b))",).unwrap();
+		let var = |v: &str| Box::new(Pattern::Var(v.to_string(),),);
+		assert_eq!(
+			p,
+			Pattern::Binary(
+				BinOp::Add,
+				Box::new(Pattern::Binary(
+					BinOp::Mul,
+					var("a",),
+					Box::new(Pattern::Const(2,),),
+				),),
+				Box::new(Pattern::Unary(UnOp::Neg, var("b",),),),
+			)
+		);
+		assert!(Pattern::from_str("(add ?a)",).is_err());
+		assert!(Pattern::from_str("(add ?a ?b",).is_err());
+		assert!(matches!(Rewrite::new("bad", "(add ?a ?b)", "?c",), Y(_,)));
+	}
+}
@@ -0,0 +1,86 @@
+//! the rewrite rules of the equality saturation pass
+//!
+//! each entry is `(name, lhs, rhs)` in the syntax of `pattern`. a rule only
+//! states an equality, so a rule and its reverse are both safe. which side
+//! ends up in the code is decided by `cost`
+
+use super::pattern::Rewrite;
+use crate::err::Container;
+
+pub const RULES: &[(&str, &str, &str,)] = &[
+	// commutativity
+	("add-commute", "(add ?a ?b)", "(add ?b ?a)",),
+	("mul-commute", "(mul ?a ?b)", "(mul ?b ?a)",),
+	("and-commute", "(and ?a ?b)", "(and ?b ?a)",),
+	("or-commute", "(or ?a ?b)", "(or ?b ?a)",),
+	("xor-commute", "(xor ?a ?b)", "(xor ?b ?a)",),
+	// associativity
+	("add-assoc", "(add (add ?a ?b) ?c)", "(add ?a (add ?b ?c))",),
+	("mul-assoc", "(mul (mul ?a ?b) ?c)", "(mul ?a (mul ?b ?c))",),
+	("and-assoc", "(and (and ?a ?b) ?c)", "(and ?a (and ?b ?c))",),
+	("or-assoc", "(or (or ?a ?b) ?c)", "(or ?a (or ?b ?c))",),
+	("xor-assoc", "(xor (xor ?a ?b) ?c)", "(xor ?a (xor ?b ?c))",),
+	("sub-assoc", "(sub (add ?a ?b) ?c)", "(add ?a (sub ?b ?c))",),
+	// distributivity
+	("mul-distribute", "(mul ?a (add ?b ?c))", "(add (mul ?a ?b) (mul ?a ?c))",),
+	("mul-factor", "(add (mul ?a ?b) (mul ?a ?c))", "(mul ?a (add ?b ?c))",),
+	(
+		"mul-distribute-sub",
+		"(mul ?a (sub ?b ?c))",
+		"(sub (mul ?a ?b) (mul ?a ?c))",
+	),
+	("mul-factor-sub", "(sub (mul ?a ?b) (mul ?a ?c))", "(mul ?a (sub ?b ?c))",),
+	("shl-distribute", "(shl (add ?a ?b) ?k)", "(add (shl ?a ?k) (shl ?b ?k))",),
+	// shifts and multiplies
+	("shl-to-mul", "(shl ?a ?k)", "(mul ?a (shl 1 ?k))",),
+	("mul-to-shl", "(mul ?a ?c)", "(shl ?a (log2 ?c))",),
+	("add-self", "(add ?a ?a)", "(shl ?a 1)",),
+	// negation
+	("sub-to-add", "(sub ?a ?b)", "(add ?a (neg ?b))",),
+	("add-neg", "(add ?a (neg ?b))", "(sub ?a ?b)",),
+	("neg-to-sub", "(neg ?a)", "(sub 0 ?a)",),
+	("neg-neg", "(neg (neg ?a))", "?a",),
+	("not-not", "(not (not ?a))", "?a",),
+	("not-to-xor", "(not ?a)", "(xor ?a -1)",),
+	// identities
+	("add-0", "(add ?a 0)", "?a",),
+	("sub-0", "(sub ?a 0)", "?a",),
+	("sub-self", "(sub ?a ?a)", "0",),
+	("mul-0", "(mul ?a 0)", "0",),
+	("mul-1", "(mul ?a 1)", "?a",),
+	("mul--1", "(mul ?a -1)", "(neg ?a)",),
+	("sdiv-1", "(sdiv ?a 1)", "?a",),
+	("udiv-1", "(udiv ?a 1)", "?a",),
+	("and-0", "(and ?a 0)", "0",),
+	("and--1", "(and ?a -1)", "?a",),
+	("and-self", "(and ?a ?a)", "?a",),
+	("or-0", "(or ?a 0)", "?a",),
+	("or--1", "(or ?a -1)", "-1",),
+	("or-self", "(or ?a ?a)", "?a",),
+	("xor-0", "(xor ?a 0)", "?a",),
+	("xor-self", "(xor ?a ?a)", "0",),
+	("shl-0", "(shl ?a 0)", "?a",),
+	("lshr-0", "(lshr ?a 0)", "?a",),
+	("ashr-0", "(ashr ?a 0)", "?a",),
+];
+
+/// parses `RULES`. they are part of the compiler, so a bad one is a bug
+pub fn rules() -> Vec<Rewrite,> {
+	RULES
+		.iter()
+		.map(|(name, lhs, rhs,)| {
+			Rewrite::new(name, lhs, rhs,)
+				.expect("rules of `RULES` are well formed",)
+		},)
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn all_rules_parse() {
+		assert_eq!(rules().len(), RULES.len());
+	}
+}
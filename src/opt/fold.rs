@@ -0,0 +1,325 @@
+//! constant folding with C integer semantics
+//!
+//! values of every integer type are kept sign extended to 64 bits, floats
+//! as their bit pattern. float arithmetic rounds like the target, in the
+//! precision of its type.
+//! operations whose result is undefined in C (division by zero,
+//! `INT_MIN / -1`, oversized shifts) are never folded, so the optimized
+//! program misbehaves exactly like the unoptimized one. signed overflow wraps
+//! as it does on the target
+
+use crate::ir::BinOp;
+use crate::ir::CastOp;
+use crate::ir::CmpOp;
+use crate::ir::Function;
+use crate::ir::InstKind;
+use crate::ir::Terminator;
+use crate::ir::Type;
+use crate::ir::UnOp;
+use crate::ir::Value;
+use std::collections::HashMap;
+
+/// sign extends the low `ty.bits()` bits of `v`
+pub fn normalize(ty: Type, v: i64,) -> i64 {
+	let shift = 64 - ty.bits();
+	(v << shift) >> shift
+}
+
+/// `v` reinterpreted as unsigned value of `ty`
+pub fn unsigned(ty: Type, v: i64,) -> u64 {
+	let shift = 64 - ty.bits();
+	((v as u64) << shift) >> shift
+}
+
+pub fn eval_binary(op: BinOp, ty: Type, a: i64, b: i64,) -> Option<i64,> {
+	let min = normalize(ty, 1 << (ty.bits() - 1),);
+	let (ua, ub,) = (unsigned(ty, a,), unsigned(ty, b,),);
+	let shift_ok = 0 <= b && b < ty.bits() as i64;
+	let v = match op {
+		BinOp::Add => a.wrapping_add(b,),
+		BinOp::Sub => a.wrapping_sub(b,),
+		BinOp::Mul => a.wrapping_mul(b,),
+		BinOp::SDiv | BinOp::SRem if b == 0 || (a == min && b == -1) => {
+			return None;
+		},
+		BinOp::SDiv => a / b,
+		BinOp::SRem => a % b,
+		BinOp::UDiv | BinOp::URem if ub == 0 => return None,
+		BinOp::UDiv => (ua / ub) as i64,
+		BinOp::URem => (ua % ub) as i64,
+		BinOp::And => a & b,
+		BinOp::Or => a | b,
+		BinOp::Xor => a ^ b,
+		BinOp::Shl | BinOp::LShr | BinOp::AShr if !shift_ok => return None,
+		BinOp::Shl => a << b,
+		BinOp::LShr => (ua >> b) as i64,
+		BinOp::AShr => a >> b,
+		BinOp::FAdd | BinOp::FSub | BinOp::FMul | BinOp::FDiv => {
+			return Some(eval_float(op, ty, a, b,),);
+		},
+	};
+	Some(normalize(ty, v,),)
+}
+
+/// `f64` is wide enough that computing `f32` operations in it and rounding
+/// the result once gives the `f32` result
+fn eval_float(op: BinOp, ty: Type, a: i64, b: i64,) -> i64 {
+	let (a, b,) = (ty.float_value(a,), ty.float_value(b,),);
+	let v = match op {
+		BinOp::FAdd => a + b,
+		BinOp::FSub => a - b,
+		BinOp::FMul => a * b,
+		_ => a / b,
+	};
+	ty.float_bits(v,)
+}
+
+pub fn eval_unary(op: UnOp, ty: Type, a: i64,) -> i64 {
+	match op {
+		UnOp::Neg => normalize(ty, a.wrapping_neg(),),
+		UnOp::Not => normalize(ty, !a,),
+		// flips the sign bit, also of NaN
+		UnOp::FNeg => normalize(ty, a ^ 1 << (ty.bits() - 1),),
+	}
+}
+
+pub fn eval_cmp(op: CmpOp, ty: Type, a: i64, b: i64,) -> bool {
+	let (ua, ub,) = (unsigned(ty, a,), unsigned(ty, b,),);
+	let (fa, fb,) = if ty.is_float() {
+		(ty.float_value(a,), ty.float_value(b,),)
+	} else {
+		(0.0, 0.0,)
+	};
+	match op {
+		CmpOp::Eq => a == b,
+		CmpOp::Ne => a != b,
+		CmpOp::SLt => a < b,
+		CmpOp::SLe => a <= b,
+		CmpOp::SGt => a > b,
+		CmpOp::SGe => a >= b,
+		CmpOp::ULt => ua < ub,
+		CmpOp::ULe => ua <= ub,
+		CmpOp::UGt => ua > ub,
+		CmpOp::UGe => ua >= ub,
+		CmpOp::FEq => fa == fb,
+		CmpOp::FNe => fa != fb,
+		CmpOp::FLt => fa < fb,
+		CmpOp::FLe => fa <= fb,
+		CmpOp::FGt => fa > fb,
+		CmpOp::FGe => fa >= fb,
+	}
+}
+
+/// float to integer conversions saturate and turn NaN into 0 like
+/// `fcvtzs` and `fcvtzu`. out of range results are undefined in C anyway
+pub fn eval_cast(op: CastOp, from: Type, to: Type, v: i64,) -> i64 {
+	match op {
+		CastOp::SExt | CastOp::Trunc => normalize(to, v,),
+		CastOp::ZExt => normalize(to, unsigned(from, v,) as i64,),
+		CastOp::SIToFP => to.float_bits(v as f64,),
+		CastOp::UIToFP => to.float_bits(unsigned(from, v,) as f64,),
+		// narrow results are converted in a `w` register and wrap
+		CastOp::FPToSI if to == Type::I64 => from.float_value(v,) as i64,
+		CastOp::FPToSI => normalize(to, from.float_value(v,) as i32 as i64,),
+		CastOp::FPToUI if to == Type::I64 => from.float_value(v,) as u64 as i64,
+		CastOp::FPToUI => normalize(to, from.float_value(v,) as u32 as i64,),
+		CastOp::FPExt | CastOp::FPTrunc => to.float_bits(from.float_value(v,),),
+	}
+}
+
+/// evaluates `kind` if all of its operands are known
+pub fn eval(
+	func: &Function,
+	kind: &InstKind,
+	ty: Type,
+	known: impl Fn(Value,) -> Option<i64,>,
+) -> Option<i64,> {
+	match kind {
+		InstKind::Const(c,) => Some(normalize(ty, *c,),),
+		InstKind::Copy(v,) => known(*v,),
+		InstKind::Unary(op, v,) => Some(eval_unary(*op, ty, known(*v,)?,),),
+		InstKind::Binary(op, a, b,) => {
+			eval_binary(*op, ty, known(*a,)?, known(*b,)?,)
+		},
+		InstKind::Cmp(op, a, b,) => {
+			let cmp = eval_cmp(*op, func.ty(*a,), known(*a,)?, known(*b,)?,);
+			Some(cmp as i64,)
+		},
+		InstKind::Cast(op, v,) => {
+			Some(eval_cast(*op, func.ty(*v,), ty, known(*v,)?,),)
+		},
+		_ => None,
+	}
+}
+
+/// algebraic identities that hold for one constant operand
+fn simplify(
+	kind: &InstKind,
+	known: &HashMap<Value, i64,>,
+) -> Option<InstKind,> {
+	let InstKind::Binary(op, a, b,) = kind else { return None };
+	let (ka, kb,) = (known.get(a,).copied(), known.get(b,).copied(),);
+	let rslt = match (op, ka, kb,) {
+		(BinOp::Sub | BinOp::Xor, ..,) if a == b => InstKind::Const(0,),
+		(BinOp::And | BinOp::Or, ..,) if a == b => InstKind::Copy(*a,),
+		(BinOp::Add | BinOp::Or | BinOp::Xor, Some(0,), _,) => {
+			InstKind::Copy(*b,)
+		},
+		(BinOp::Mul, Some(1,), _,) | (BinOp::And, Some(-1,), _,) => {
+			InstKind::Copy(*b,)
+		},
+		(
+			BinOp::Add
+			| BinOp::Sub
+			| BinOp::Or
+			| BinOp::Xor
+			| BinOp::Shl
+			| BinOp::LShr
+			| BinOp::AShr,
+			_,
+			Some(0,),
+		) => InstKind::Copy(*a,),
+		(BinOp::Mul | BinOp::SDiv | BinOp::UDiv, _, Some(1,),)
+		| (BinOp::And, _, Some(-1,),) => InstKind::Copy(*a,),
+		(BinOp::Mul | BinOp::And, Some(0,), _,)
+		| (BinOp::Mul | BinOp::And, _, Some(0,),) => InstKind::Const(0,),
+		_ => return None,
+	};
+	Some(rslt,)
+}
+
+/// # Return
+///
+/// returns whether `func` changed
+pub fn run(func: &mut Function,) -> bool {
+	let mut known: HashMap<Value, i64,> = HashMap::new();
+	let mut changed = false;
+	let mut progress = true;
+	while progress {
+		progress = false;
+		for b in func.block_ids() {
+			let mut phi_folded = false;
+			for i in 0..func.block(b,).insts.len() {
+				let inst = &func.block(b,).insts[i];
+				let Some(dest,) = inst.dest else { continue };
+				if known.contains_key(&dest,) {
+					continue;
+				}
+
+				let ty = func.ty(dest,);
+				let folded =
+					eval(func, &inst.kind, ty, |v| known.get(&v,).copied(),)
+						.map(InstKind::Const,)
+						.or_else(|| simplify(&inst.kind, &known,),)
+						.or_else(|| fold_phi(&inst.kind, &known,),);
+				let Some(folded,) = folded else { continue };
+
+				if let InstKind::Const(c,) = folded {
+					known.insert(dest, c,);
+				}
+				let inst = &mut func.block_mut(b,).insts[i];
+				if inst.kind != folded {
+					phi_folded |= matches!(inst.kind, InstKind::Phi(_,));
+					inst.kind = folded;
+					progress = true;
+				}
+			}
+			if phi_folded {
+				// keep remaining phis at the head of the block
+				func.block_mut(b,)
+					.insts
+					.sort_by_key(|i| !matches!(i.kind, InstKind::Phi(_,)),);
+			}
+		}
+		changed |= progress;
+	}
+
+	for b in func.block_ids() {
+		let Terminator::Branch { cond, then, els, } = func.block(b,).term
+		else {
+			continue;
+		};
+		let Some(c,) = known.get(&cond,) else { continue };
+		let (taken, dropped,) =
+			if *c != 0 { (then, els,) } else { (els, then,) };
+		func.block_mut(b,).term = Terminator::Jump(taken,);
+		if dropped != taken {
+			func.remove_phi_incoming(dropped, b,);
+		}
+		changed = true;
+	}
+
+	changed
+}
+
+/// phis whose incoming values are all the same
+fn fold_phi(
+	kind: &InstKind,
+	known: &HashMap<Value, i64,>,
+) -> Option<InstKind,> {
+	let InstKind::Phi(incoming,) = kind else { return None };
+	let (_, first,) = incoming.first()?;
+	if incoming.iter().all(|(_, v,)| v == first,) {
+		return Some(InstKind::Copy(*first,),);
+	}
+	let c = known.get(first,)?;
+	incoming
+		.iter()
+		.all(|(_, v,)| known.get(v,) == Some(c,),)
+		.then_some(InstKind::Const(*c,),)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use quickcheck_macros::quickcheck;
+
+	#[test]
+	fn undefined_operations_are_not_folded() {
+		assert_eq!(eval_binary(BinOp::SDiv, Type::I32, 1, 0,), None);
+		assert_eq!(
+			eval_binary(BinOp::SDiv, Type::I32, i32::MIN as i64, -1,),
+			None
+		);
+		assert_eq!(eval_binary(BinOp::Shl, Type::I32, 1, 32,), None);
+		assert_eq!(eval_binary(BinOp::Shl, Type::I64, 1, 32,), Some(1 << 32));
+	}
+
+	#[quickcheck]
+	fn matches_i32_semantics(a: i32, b: i32,) -> bool {
+		let (x, y,) = (a as i64, b as i64,);
+		let i32_ops: [(BinOp, Option<i32,>,); 6] = [
+			(BinOp::Add, Some(a.wrapping_add(b,),),),
+			(BinOp::Sub, Some(a.wrapping_sub(b,),),),
+			(BinOp::Mul, Some(a.wrapping_mul(b,),),),
+			(BinOp::SDiv, a.checked_div(b,),),
+			(BinOp::SRem, a.checked_rem(b,),),
+			(BinOp::UDiv, (a as u32).checked_div(b as u32,).map(|v| v as i32,),),
+		];
+		i32_ops.into_iter().all(|(op, expected,)| {
+			eval_binary(op, Type::I32, x, y,) == expected.map(|v| v as i64,)
+		},) && eval_cmp(CmpOp::ULt, Type::I32, x, y,)
+			== ((a as u32) < (b as u32))
+			&& eval_cast(CastOp::ZExt, Type::I32, Type::I64, x,)
+				== a as u32 as i64
+			&& eval_cast(CastOp::Trunc, Type::I32, Type::I8, x,)
+				== a as i8 as i64
+	}
+
+	#[quickcheck]
+	fn matches_f32_semantics(a: f32, b: f32,) -> bool {
+		let ty = Type::F32;
+		let (x, y,) = (ty.float_bits(a as f64,), ty.float_bits(b as f64,),);
+		let same = |c: Option<i64,>, v: f32| {
+			c.map(|c| ty.float_value(c,).to_bits() == (v as f64).to_bits(),)
+				== Some(true,)
+				|| v.is_nan()
+		};
+		same(eval_binary(BinOp::FAdd, ty, x, y,), a + b,)
+			&& same(eval_binary(BinOp::FMul, ty, x, y,), a * b,)
+			&& same(eval_binary(BinOp::FDiv, ty, x, y,), a / b,)
+			&& eval_cmp(CmpOp::FLt, ty, x, y,) == (a < b)
+			&& eval_cmp(CmpOp::FNe, ty, x, y,) == (a != b)
+			&& eval_cast(CastOp::FPToSI, ty, Type::I32, x,) == a as i32 as i64
+	}
+}
@@ -0,0 +1,353 @@
+//! induction variables and strength reduction
+//!
+//! a basic induction variable is a header phi `i = phi [init, pre], [next,
+//! latch]` with `next = i ± step` and `step` loop invariant. an expression
+//! `i * k`, `i << k` or `i ± b` with invariant `k` and `b` is affine in `i`,
+//! so it can be carried along as an induction variable of its own, started
+//! in the preheader and bumped by a single add in the latch. this turns
+//! array indexing `base + i * size` into a pointer that is incremented by
+//! `size` every iteration
+//!
+//! all ir arithmetic wraps, so the rewrite is exact even on overflow
+
+use crate::ir::BinOp;
+use crate::ir::Block;
+use crate::ir::Function;
+use crate::ir::Inst;
+use crate::ir::InstKind;
+use crate::ir::Value;
+use crate::ir::cfg::Cfg;
+use crate::ir::dom::DomTree;
+use crate::ir::loops::Loop;
+use crate::ir::loops::Loops;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash,)]
+struct Iv {
+	/// value on loop entry, available in the preheader
+	init:    Value,
+	/// invariant amount added (or subtracted) once per iteration
+	step:    Value,
+	negated: bool,
+}
+
+struct LoopCtx<'a,> {
+	lp:        &'a Loop,
+	pre:       Block,
+	latch:     Block,
+	def_block: HashMap<Value, Block,>,
+	ivs:       HashMap<Value, Iv,>,
+	/// the `next` value of every induction variable
+	bumps:     HashMap<Value, Value,>,
+}
+
+impl LoopCtx<'_,> {
+	fn is_invariant(&self, v: Value,) -> bool {
+		self.def_block.get(&v,).is_none_or(|b| !self.lp.contains(*b,),)
+	}
+
+	/// appends `kind` to the preheader
+	fn hoist(
+		&mut self,
+		func: &mut Function,
+		ty_of: Value,
+		kind: InstKind,
+	) -> Value {
+		let dest = func.new_value(func.ty(ty_of,),);
+		func.block_mut(self.pre,)
+			.insts
+			.push(Inst { dest: Some(dest,), kind, },);
+		self.def_block.insert(dest, self.pre,);
+		dest
+	}
+
+	/// adds a new induction variable to the header and the latch
+	fn create(&mut self, func: &mut Function, ty_of: Value, iv: Iv,) -> Value {
+		let ty = func.ty(ty_of,);
+		let phi = func.new_value(ty,);
+		let next = func.new_value(ty,);
+		let op = if iv.negated { BinOp::Sub } else { BinOp::Add };
+		func.block_mut(self.latch,).insts.push(Inst {
+			dest: Some(next,),
+			kind: InstKind::Binary(op, phi, iv.step,),
+		},);
+		func.block_mut(self.lp.header,).insts.insert(
+			0,
+			Inst {
+				dest: Some(phi,),
+				kind: InstKind::Phi(vec![
+					(self.pre, iv.init,),
+					(self.latch, next,),
+				],),
+			},
+		);
+		self.def_block.insert(phi, self.lp.header,);
+		self.def_block.insert(next, self.latch,);
+		self.ivs.insert(phi, iv,);
+		self.bumps.insert(phi, next,);
+		phi
+	}
+}
+
+fn find_basic(func: &Function, cx: &mut LoopCtx<'_,>,) {
+	let defs: HashMap<Value, &InstKind,> = cx
+		.lp
+		.blocks
+		.iter()
+		.flat_map(|b| func.block(*b,).insts.iter(),)
+		.filter_map(|i| Some((i.dest?, &i.kind,),),)
+		.collect();
+	for phi in func.block(cx.lp.header,).phis() {
+		let (Some(dest,), InstKind::Phi(incoming,),) = (phi.dest, &phi.kind,)
+		else {
+			continue;
+		};
+		let [(p0, v0,), (p1, v1,),] = incoming.as_slice() else { continue };
+		let (init, next,) = match (*p0 == cx.pre, *p1 == cx.pre,) {
+			(true, false,) if *p1 == cx.latch => (*v0, *v1,),
+			(false, true,) if *p0 == cx.latch => (*v1, *v0,),
+			_ => continue,
+		};
+		let (step, negated,) = match defs.get(&next,) {
+			Some(InstKind::Binary(BinOp::Add, a, s,),) if *a == dest => {
+				(*s, false,)
+			},
+			Some(InstKind::Binary(BinOp::Add, s, a,),) if *a == dest => {
+				(*s, false,)
+			},
+			Some(InstKind::Binary(BinOp::Sub, a, s,),) if *a == dest => {
+				(*s, true,)
+			},
+			_ => continue,
+		};
+		if cx.is_invariant(step,) {
+			cx.ivs.insert(dest, Iv { init, step, negated, },);
+			cx.bumps.insert(dest, next,);
+		}
+	}
+}
+
+/// the induction variable `kind` computes, given the ones already known
+fn derive(
+	func: &mut Function,
+	cx: &mut LoopCtx<'_,>,
+	kind: &InstKind,
+	uses: &[usize],
+) -> Option<Iv,> {
+	let (op, a, b,) = match kind {
+		InstKind::Binary(op, a, b,) => (*op, *a, *b,),
+		_ => return None,
+	};
+	// `x * k` is commutative, the others need the variable on the left
+	let (x, k,) = match (cx.ivs.get(&a,), cx.ivs.get(&b,),) {
+		(Some(_,), None,) => (a, b,),
+		(None, Some(_,),) if matches!(op, BinOp::Mul | BinOp::Add) => (b, a,),
+		_ => return None,
+	};
+	if !cx.is_invariant(k,) {
+		return None;
+	}
+	let iv = cx.ivs[&x];
+	match op {
+		BinOp::Mul | BinOp::Shl => {
+			let init = cx.hoist(func, x, InstKind::Binary(op, iv.init, k,),);
+			let step = cx.hoist(func, x, InstKind::Binary(op, iv.step, k,),);
+			Some(Iv { init, step, negated: iv.negated, },)
+		},
+		// only worth it when `x` dies, otherwise two variables are bumped
+		// where one add sufficed
+		BinOp::Add | BinOp::Sub if uses[x.index()] == 2 => {
+			let init = cx.hoist(func, x, InstKind::Binary(op, iv.init, k,),);
+			Some(Iv { init, ..iv },)
+		},
+		_ => None,
+	}
+}
+
+fn reduce(func: &mut Function, cx: &mut LoopCtx<'_,>,) -> bool {
+	let mut changed = false;
+	// candidates that turn out not to be affine are not retried
+	let mut rejected = HashSet::new();
+	loop {
+		let uses = func.use_counts();
+		let bumps: HashSet<Value,> = cx.bumps.values().copied().collect();
+		let blocks: Vec<Block,> = cx.lp.blocks.iter().copied().collect();
+		let candidate = blocks.iter().find_map(|b| {
+			func.block(*b,)
+				.insts
+				.iter()
+				.position(|i| {
+					i.dest.is_some_and(|d| {
+						!bumps.contains(&d,) && !rejected.contains(&d,)
+					},) && matches!(
+						i.kind,
+						InstKind::Binary(
+							BinOp::Mul | BinOp::Shl | BinOp::Add | BinOp::Sub,
+							..
+						)
+					) && i
+						.kind
+						.operands()
+						.iter()
+						.any(|op| cx.ivs.contains_key(op,),)
+				},)
+				.map(|i| (*b, i,),)
+		},);
+		let Some((b, i,),) = candidate else { return changed };
+		let inst = func.block(b,).insts[i].clone();
+		let dest = inst.dest.unwrap();
+		let Some(iv,) = derive(func, cx, &inst.kind, &uses,) else {
+			rejected.insert(dest,);
+			continue;
+		};
+		let phi = cx.create(func, dest, iv,);
+		func.block_mut(b,).insts.retain(|i| i.dest != Some(dest,),);
+		func.replace_uses(&HashMap::from([(dest, phi,),],),);
+		changed = true;
+	}
+}
+
+/// merges induction variables with the same start and step
+fn merge_duplicates(func: &mut Function, cx: &LoopCtx<'_,>,) -> bool {
+	let mut seen: HashMap<(Iv, crate::ir::Type,), Value,> = HashMap::new();
+	let mut replace = HashMap::new();
+	let mut ivs: Vec<_,> = cx.ivs.iter().collect();
+	ivs.sort_by_key(|(v, _,)| **v,);
+	for (v, iv,) in ivs {
+		match seen.get(&(*iv, func.ty(*v,),),) {
+			Some(first,) => {
+				replace.insert(*v, *first,);
+				replace.insert(cx.bumps[v], cx.bumps[first],);
+			},
+			None => {
+				seen.insert((*iv, func.ty(*v,),), *v,);
+			},
+		}
+	}
+	func.replace_uses(&replace,);
+	!replace.is_empty()
+}
+
+/// runs on loops that have a preheader and a single latch, see
+/// `licm::insert_preheaders`
+///
+/// # Return
+///
+/// returns whether `func` changed
+pub fn run(func: &mut Function,) -> bool {
+	let cfg = Cfg::new(func,);
+	let loops = Loops::new(&cfg, &DomTree::new(&cfg,),);
+	let mut def_block = HashMap::new();
+	for b in cfg.rpo() {
+		for inst in func.block(*b,).insts.iter() {
+			if let Some(dest,) = inst.dest {
+				def_block.insert(dest, *b,);
+			}
+		}
+	}
+
+	let mut changed = false;
+	for l in loops.innermost_first() {
+		let lp = loops.get(l,);
+		let (Some(pre,), [latch,],) =
+			(lp.preheader(&cfg,), lp.latches.as_slice(),)
+		else {
+			continue;
+		};
+		let mut cx = LoopCtx {
+			lp,
+			pre,
+			latch: *latch,
+			def_block: def_block.clone(),
+			ivs: HashMap::new(),
+			bumps: HashMap::new(),
+		};
+		find_basic(func, &mut cx,);
+		if cx.ivs.is_empty() {
+			continue;
+		}
+		changed |= reduce(func, &mut cx,);
+		changed |= merge_duplicates(func, &cx,);
+		def_block = cx.def_block;
+	}
+	changed
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ir::CmpOp;
+	use crate::ir::FunctionBuilder;
+	use crate::ir::Type;
+	use crate::opt::OptLevel;
+	use crate::opt::optimize;
+
+	/// `for (long i = 0; i < n; i++) s += a[i];` over `int *a`
+	fn sum() -> (Function, Block,) {
+		let mut fb = FunctionBuilder::new(
+			"sum",
+			&[Type::I64, Type::I64,],
+			Some(Type::I64,),
+		);
+		let (a, n,) = (fb.param(0,), fb.param(1,),);
+		let (i, s,) = (fb.slot(Type::I64,), fb.slot(Type::I64,),);
+		let header = fb.create_block();
+		let body = fb.create_block();
+		let exit = fb.create_block();
+		let zero = fb.iconst(Type::I64, 0,);
+		fb.store_slot(i, zero,);
+		fb.store_slot(s, zero,);
+		fb.jump(header,);
+
+		fb.switch_to(header,);
+		let iv = fb.load_slot(i,);
+		let c = fb.cmp(CmpOp::SLt, iv, n,);
+		fb.branch(c, body, exit,);
+
+		fb.switch_to(body,);
+		let iv = fb.load_slot(i,);
+		let size = fb.iconst(Type::I64, 4,);
+		let offset = fb.binary(BinOp::Mul, iv, size,);
+		let addr = fb.binary(BinOp::Add, a, offset,);
+		let elem = fb.load(Type::I32, addr,);
+		let elem = fb.cast(crate::ir::CastOp::SExt, elem, Type::I64,);
+		let acc = fb.load_slot(s,);
+		let acc = fb.binary(BinOp::Add, acc, elem,);
+		fb.store_slot(s, acc,);
+		let one = fb.iconst(Type::I64, 1,);
+		let iv = fb.binary(BinOp::Add, iv, one,);
+		fb.store_slot(i, iv,);
+		fb.jump(header,);
+
+		fb.switch_to(exit,);
+		let acc = fb.load_slot(s,);
+		fb.ret(Some(acc,),);
+		(fb.finish(), body,)
+	}
+
+	#[test]
+	fn array_index_becomes_pointer_bump() {
+		let (mut func, body,) = sum();
+		optimize(&mut func, OptLevel::O2,);
+		let insts = &func.block(body,).insts;
+		assert!(
+			insts
+				.iter()
+				.all(|i| !matches!(i.kind, InstKind::Binary(BinOp::Mul, ..))),
+			"{func}"
+		);
+		// the load reads straight from a pointer phi
+		let Some(InstKind::Load(addr,),) = insts
+			.iter()
+			.map(|i| &i.kind,)
+			.find(|k| matches!(k, InstKind::Load(_,)),)
+		else {
+			panic!("{func}")
+		};
+		let header = func
+			.block_ids()
+			.find(|b| func.block(*b,).phis().any(|p| p.dest == Some(*addr,),),);
+		assert!(header.is_some(), "{func}");
+	}
+}
@@ -0,0 +1,293 @@
+//! function inlining
+//!
+//! a call is inlined when the callee is cheap by `cost`, or when the callee is
+//! `static` and this is its only call site. functions on a cycle of the call
+//! graph are never inlined, so the pass always terminates
+
+use crate::ir::Block;
+use crate::ir::Function;
+use crate::ir::Inst;
+use crate::ir::InstKind;
+use crate::ir::Linkage;
+use crate::ir::Module;
+use crate::ir::Slot;
+use crate::ir::Terminator;
+use crate::ir::Value;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+pub struct InlineParams {
+	/// callees up to this cost are inlined everywhere
+	pub small:        usize,
+	/// static callees with a single call site are inlined up to this cost
+	pub single_site:  usize,
+	/// a caller stops growing once it reaches this cost
+	pub caller_limit: usize,
+}
+
+impl Default for InlineParams {
+	fn default() -> Self {
+		Self { small: 16, single_site: 256, caller_limit: 2048, }
+	}
+}
+
+/// rough size of the machine code `func` turns into
+pub fn cost(func: &Function,) -> usize {
+	func.blocks
+		.iter()
+		.map(|b| {
+			let insts: usize = b
+				.insts
+				.iter()
+				.map(|i| match &i.kind {
+					InstKind::Phi(_,) | InstKind::Copy(_,) => 0,
+					InstKind::Call { args, .. } => 1 + args.len(),
+					_ => 1,
+				},)
+				.sum();
+			insts + (b.term != Terminator::Unreachable) as usize
+		},)
+		.sum()
+}
+
+fn callees(func: &Function,) -> impl Iterator<Item = &str,> {
+	func.blocks.iter().flat_map(|b| b.insts.iter(),).filter_map(|i| {
+		match &i.kind {
+			InstKind::Call { callee, .. } => Some(callee.as_str(),),
+			_ => None,
+		}
+	},)
+}
+
+/// functions that can reach themselves through calls
+fn recursive(module: &Module,) -> HashSet<String,> {
+	let graph: HashMap<&str, Vec<&str,>,> = module
+		.functions
+		.iter()
+		.map(|f| (f.name.as_str(), callees(f,).collect(),),)
+		.collect();
+	let mut rslt = HashSet::new();
+	for f in module.functions.iter() {
+		let mut seen = HashSet::new();
+		let mut work = graph[f.name.as_str()].clone();
+		while let Some(g,) = work.pop() {
+			if g == f.name {
+				rslt.insert(f.name.clone(),);
+				break;
+			}
+			if seen.insert(g,)
+				&& let Some(next,) = graph.get(g,)
+			{
+				work.extend(next,);
+			}
+		}
+	}
+	rslt
+}
+
+/// # Return
+///
+/// returns whether `module` changed
+pub fn run(module: &mut Module, params: &InlineParams,) -> bool {
+	let recursive = recursive(module,);
+	let mut sites: HashMap<String, usize,> = HashMap::new();
+	for f in module.functions.iter() {
+		for callee in callees(f,) {
+			*sites.entry(callee.to_string(),).or_default() += 1;
+		}
+	}
+
+	let mut changed = false;
+	for caller_idx in 0..module.functions.len() {
+		loop {
+			let caller = &module.functions[caller_idx];
+			if cost(caller,) >= params.caller_limit {
+				break;
+			}
+			let candidate = caller.block_ids().find_map(|b| {
+				caller.block(b,).insts.iter().enumerate().find_map(
+					|(i, inst,)| {
+						let InstKind::Call { callee, .. } = &inst.kind else {
+							return None;
+						};
+						if recursive.contains(callee,) || *callee == caller.name
+						{
+							return None;
+						}
+						let callee = module.function(callee,)?;
+						let c = cost(callee,);
+						let single = callee.linkage == Linkage::Internal
+							&& sites.get(&callee.name,) == Some(&1,);
+						(c <= params.small
+							|| (single && c <= params.single_site))
+							.then(|| (b, i, callee.clone(),),)
+					},
+				)
+			},);
+			let Some((b, i, callee,),) = candidate else { break };
+
+			inline_call(&mut module.functions[caller_idx], b, i, &callee,);
+			if let Some(n,) = sites.get_mut(&callee.name,) {
+				*n -= 1;
+			}
+			for nested in callees(&callee,) {
+				*sites.entry(nested.to_string(),).or_default() += 1;
+			}
+			changed = true;
+		}
+	}
+
+	// static functions nobody calls any more
+	let before = module.functions.len();
+	module.functions.retain(|f| {
+		f.linkage == Linkage::External
+			|| sites.get(&f.name,).is_some_and(|n| *n > 0,)
+	},);
+	changed || before != module.functions.len()
+}
+
+/// replaces the call at `caller.blocks[b].insts[i]` with a copy of `callee`'s
+/// body. the block is split after the call and every return of the callee
+/// jumps to the second half, where a phi collects the returned value
+pub fn inline_call(
+	caller: &mut Function,
+	b: Block,
+	i: usize,
+	callee: &Function,
+) {
+	let rest = caller.block_mut(b,).insts.split_off(i + 1,);
+	let Some(Inst { dest, kind: InstKind::Call { args, .. }, },) =
+		caller.block_mut(b,).insts.pop()
+	else {
+		panic!("{b} of `{}` has no call at {i}", caller.name)
+	};
+
+	let cont = caller.new_block();
+	let term = std::mem::replace(
+		&mut caller.block_mut(b,).term,
+		Terminator::Unreachable,
+	);
+	for s in term.successors() {
+		for inst in caller.block_mut(s,).insts.iter_mut() {
+			let InstKind::Phi(incoming,) = &mut inst.kind else { break };
+			for (pred, _,) in incoming.iter_mut() {
+				if *pred == b {
+					*pred = cont;
+				}
+			}
+		}
+	}
+	caller.block_mut(cont,).insts = rest;
+	caller.block_mut(cont,).term = term;
+
+	let mut values: Vec<Value,> =
+		callee.value_types.iter().map(|ty| caller.new_value(*ty,),).collect();
+	for (param, arg,) in callee.params.iter().zip(args,) {
+		values[param.index()] = arg;
+	}
+	let slots: Vec<Slot,> =
+		callee.slots.iter().map(|ty| caller.new_slot(*ty,),).collect();
+	let blocks: Vec<Block,> =
+		callee.blocks.iter().map(|_| caller.new_block(),).collect();
+
+	let mut returns = vec![];
+	for (from, data,) in callee.block_ids().zip(callee.blocks.iter(),) {
+		let to = blocks[from.index()];
+		let insts = data
+			.insts
+			.iter()
+			.map(|inst| {
+				let mut kind = inst.kind.clone();
+				for op in kind.operands_mut() {
+					*op = values[op.index()];
+				}
+				match &mut kind {
+					InstKind::StackLoad(s,)
+					| InstKind::StackStore(s, _,)
+					| InstKind::StackAddr(s,) => *s = slots[s.index()],
+					InstKind::Phi(incoming,) => {
+						for (pred, _,) in incoming.iter_mut() {
+							*pred = blocks[pred.index()];
+						}
+					},
+					// the inlined call no longer directly precedes a return
+					InstKind::Call { tail, .. } => *tail = false,
+					_ => (),
+				}
+				Inst { dest: inst.dest.map(|d| values[d.index()],), kind, }
+			},)
+			.collect();
+
+		let term = match &data.term {
+			Terminator::Return(v,) => {
+				if let Some(v,) = v {
+					returns.push((to, values[v.index()],),);
+				}
+				Terminator::Jump(cont,)
+			},
+			other => {
+				let mut term = other.clone();
+				for s in term.successors_mut() {
+					*s = blocks[s.index()];
+				}
+				for op in term.operands_mut() {
+					*op = values[op.index()];
+				}
+				term
+			},
+		};
+		*caller.block_mut(to,) = crate::ir::BlockData { insts, term, };
+	}
+
+	caller.block_mut(b,).term = Terminator::Jump(blocks[Block::ENTRY.index()],);
+	if let Some(dest,) = dest {
+		caller.block_mut(cont,).insts.insert(
+			0,
+			Inst { dest: Some(dest,), kind: InstKind::Phi(returns,), },
+		);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ir::BinOp;
+	use crate::ir::FunctionBuilder;
+	use crate::ir::Type;
+	use crate::opt::OptLevel;
+	use crate::opt::optimize;
+
+	#[test]
+	fn small_static_helper_disappears() {
+		// static int twice(int x) { return x + x; }
+		let mut fb =
+			FunctionBuilder::new("twice", &[Type::I32,], Some(Type::I32,),);
+		fb.set_linkage(Linkage::Internal,);
+		let x = fb.param(0,);
+		let y = fb.binary(BinOp::Add, x, x,);
+		fb.ret(Some(y,),);
+		let twice = fb.finish();
+
+		// int main() { return twice(3) + twice(4); }
+		let mut fb = FunctionBuilder::new("main", &[], Some(Type::I32,),);
+		let three = fb.iconst(Type::I32, 3,);
+		let four = fb.iconst(Type::I32, 4,);
+		let a = fb.call("twice", &[three,], Some(Type::I32,),);
+		let b = fb.call("twice", &[four,], Some(Type::I32,),);
+		let sum = fb.binary(BinOp::Add, a, b,);
+		fb.ret(Some(sum,),);
+		let main = fb.finish();
+
+		let mut module = Module { functions: vec![twice, main], };
+		assert!(run(&mut module, &InlineParams::default(),));
+		assert_eq!(module.functions.len(), 1, "{module}");
+
+		let main = &mut module.functions[0];
+		optimize(main, OptLevel::O2,);
+		let Terminator::Return(Some(ret,),) = main.blocks[0].term else {
+			panic!("{main}")
+		};
+		let def = main.blocks[0].insts.iter().find(|i| i.dest == Some(ret,),);
+		assert_eq!(def.unwrap().kind, InstKind::Const(14,), "{main}");
+	}
+}
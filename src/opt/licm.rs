@@ -0,0 +1,154 @@
+//! loop invariant code motion
+//!
+//! a pure instruction inside a loop whose operands are all defined outside
+//! of it computes the same value on every iteration. it is moved to the end
+//! of the loop preheader. inner loops are handled first, so that an
+//! expression can climb through several levels of nesting
+
+use crate::ir::BinOp;
+use crate::ir::Block;
+use crate::ir::Function;
+use crate::ir::InstKind;
+use crate::ir::Value;
+use crate::ir::cfg::Cfg;
+use crate::ir::dom::DomTree;
+use crate::ir::loops::Loops;
+use crate::ir::loops::ensure_preheader;
+use std::collections::HashMap;
+
+/// whether `kind` may be executed even when the loop body would not have
+/// run it. memory accesses and calls stay where they are
+fn is_hoistable(kind: &InstKind, consts: &HashMap<Value, i64,>,) -> bool {
+	match kind {
+		InstKind::Binary(
+			BinOp::SDiv | BinOp::UDiv | BinOp::SRem | BinOp::URem,
+			_,
+			rhs,
+		) => {
+			// a division must not start trapping by being speculated
+			consts.get(rhs,).is_some_and(|c| *c != 0 && *c != -1,)
+		},
+		InstKind::Const(_,)
+		| InstKind::Copy(_,)
+		| InstKind::Unary(..,)
+		| InstKind::Binary(..,)
+		| InstKind::Cmp(..,)
+		| InstKind::Cast(..,)
+		| InstKind::StackAddr(_,) => true,
+		_ => false,
+	}
+}
+
+/// gives every loop a preheader. loops are recomputed after each change,
+/// since a new preheader belongs to the enclosing loops
+///
+/// # Return
+///
+/// returns whether `func` changed
+pub fn insert_preheaders(func: &mut Function,) -> bool {
+	let mut changed = false;
+	'restart: loop {
+		let cfg = Cfg::new(func,);
+		let loops = Loops::new(&cfg, &DomTree::new(&cfg,),);
+		for i in loops.innermost_first() {
+			if ensure_preheader(func, loops.get(i,), &cfg,).1 {
+				changed = true;
+				continue 'restart;
+			}
+		}
+		return changed;
+	}
+}
+
+/// # Return
+///
+/// returns whether `func` changed
+pub fn run(func: &mut Function,) -> bool {
+	let mut changed = insert_preheaders(func,);
+	let cfg = Cfg::new(func,);
+	let loops = Loops::new(&cfg, &DomTree::new(&cfg,),);
+
+	let mut def_block: HashMap<Value, Block,> = HashMap::new();
+	let mut consts = HashMap::new();
+	for b in cfg.rpo() {
+		for inst in func.block(*b,).insts.iter() {
+			let Some(dest,) = inst.dest else { continue };
+			def_block.insert(dest, *b,);
+			if let InstKind::Const(c,) = inst.kind {
+				consts.insert(dest, c,);
+			}
+		}
+	}
+
+	for l in loops.innermost_first() {
+		let lp = loops.get(l,);
+		let Some(pre,) = lp.preheader(&cfg,) else { continue };
+		// blocks in reverse postorder see definitions before their uses
+		for b in cfg.rpo().iter().filter(|b| lp.contains(**b,),) {
+			let insts = std::mem::take(&mut func.block_mut(*b,).insts,);
+			let (hoisted, kept,): (Vec<_,>, Vec<_,>,) =
+				insts.into_iter().partition(|inst| {
+					let invariant = inst.dest.is_some()
+						&& is_hoistable(&inst.kind, &consts,)
+						&& inst.kind.operands().iter().all(|op| {
+							def_block
+								.get(op,)
+								.is_none_or(|d| !lp.contains(*d,),)
+						},);
+					if invariant {
+						def_block.insert(inst.dest.unwrap(), pre,);
+					}
+					invariant
+				},);
+			changed |= !hoisted.is_empty();
+			func.block_mut(*b,).insts = kept;
+			func.block_mut(pre,).insts.extend(hoisted,);
+		}
+	}
+	changed
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ir::CmpOp;
+	use crate::ir::FunctionBuilder;
+	use crate::ir::Type;
+
+	#[test]
+	fn invariant_product_leaves_loop() {
+		// int s = 0; while (s < 100) s += a * b; return s;
+		let mut fb = FunctionBuilder::new(
+			"f",
+			&[Type::I32, Type::I32,],
+			Some(Type::I32,),
+		);
+		let (a, b,) = (fb.param(0,), fb.param(1,),);
+		let header = fb.create_block();
+		let body = fb.create_block();
+		let exit = fb.create_block();
+		let zero = fb.iconst(Type::I32, 0,);
+		fb.jump(header,);
+		fb.switch_to(header,);
+		let s = fb.push(Some(Type::I32,), InstKind::Phi(vec![],),);
+		let limit = fb.iconst(Type::I32, 100,);
+		let c = fb.cmp(CmpOp::SLt, s, limit,);
+		fb.branch(c, body, exit,);
+		fb.switch_to(body,);
+		let ab = fb.binary(BinOp::Mul, a, b,);
+		let next = fb.binary(BinOp::Add, s, ab,);
+		fb.jump(header,);
+		fb.switch_to(exit,);
+		fb.ret(Some(s,),);
+		let mut func = fb.finish();
+		func.block_mut(header,).insts[0].kind =
+			InstKind::Phi(vec![(Block::ENTRY, zero,), (body, next,)],);
+
+		assert!(run(&mut func,));
+		let entry = &func.block(Block::ENTRY,).insts;
+		assert!(entry.iter().any(|i| i.dest == Some(ab,)), "{func}");
+		assert!(entry.iter().any(|i| i.dest == Some(limit,)), "{func}");
+		assert_eq!(func.block(body,).insts.len(), 1, "{func}");
+		assert!(!run(&mut func,));
+	}
+}
@@ -0,0 +1,308 @@
+//! sparse conditional constant propagation
+//!
+//! Wegman and Zadeck, "Constant Propagation with Conditional Branches".
+//! values and cfg edges are only considered once they are proven
+//! executable, so constants flowing around a loop or through a branch that
+//! is never taken are still found
+
+use crate::ir::Block;
+use crate::ir::Function;
+use crate::ir::InstKind;
+use crate::ir::Terminator;
+use crate::ir::Value;
+use crate::opt::fold::eval;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+enum Lattice {
+	/// no evidence yet
+	Top,
+	Const(i64,),
+	/// not a constant
+	Bottom,
+}
+
+impl Lattice {
+	fn meet(self, other: Self,) -> Self {
+		match (self, other,) {
+			(Self::Top, x,) | (x, Self::Top,) => x,
+			(Self::Const(a,), Self::Const(b,),) if a == b => self,
+			_ => Self::Bottom,
+		}
+	}
+}
+
+#[derive(Clone, Copy,)]
+enum Site {
+	Inst(Block, usize,),
+	Term(Block,),
+}
+
+struct Solver<'f,> {
+	func:       &'f Function,
+	values:     Vec<Lattice,>,
+	users:      Vec<Vec<Site,>,>,
+	executable: Vec<bool,>,
+	edges:      HashSet<(Block, Block,),>,
+	flow_work:  Vec<(Option<Block,>, Block,),>,
+	ssa_work:   Vec<Value,>,
+}
+
+impl<'f,> Solver<'f,> {
+	fn new(func: &'f Function,) -> Self {
+		let mut users = vec![vec![]; func.value_types.len()];
+		for b in func.block_ids() {
+			let block = func.block(b,);
+			for (i, inst,) in block.insts.iter().enumerate() {
+				for op in inst.kind.operands() {
+					users[op.index()].push(Site::Inst(b, i,),);
+				}
+			}
+			for op in block.term.operands() {
+				users[op.index()].push(Site::Term(b,),);
+			}
+		}
+
+		let mut values = vec![Lattice::Top; func.value_types.len()];
+		for p in func.params.iter() {
+			values[p.index()] = Lattice::Bottom;
+		}
+
+		Self {
+			func,
+			values,
+			users,
+			executable: vec![false; func.blocks.len()],
+			edges: HashSet::new(),
+			flow_work: vec![(None, Block::ENTRY,)],
+			ssa_work: vec![],
+		}
+	}
+
+	fn solve(&mut self,) {
+		loop {
+			if let Some((from, to,),) = self.flow_work.pop() {
+				if let Some(from,) = from
+					&& !self.edges.insert((from, to,),)
+				{
+					continue;
+				}
+				if self.executable[to.index()] {
+					// only phis depend on the newly executable edge
+					for i in 0..self.func.block(to,).phis().count() {
+						self.visit(Site::Inst(to, i,),);
+					}
+				} else {
+					self.executable[to.index()] = true;
+					for i in 0..self.func.block(to,).insts.len() {
+						self.visit(Site::Inst(to, i,),);
+					}
+					self.visit(Site::Term(to,),);
+				}
+			} else if let Some(v,) = self.ssa_work.pop() {
+				for site in self.users[v.index()].clone() {
+					let (Site::Inst(b, _,) | Site::Term(b,)) = site;
+					if self.executable[b.index()] {
+						self.visit(site,);
+					}
+				}
+			} else {
+				break;
+			}
+		}
+	}
+
+	fn visit(&mut self, site: Site,) {
+		match site {
+			Site::Inst(b, i,) => self.visit_inst(b, i,),
+			Site::Term(b,) => self.visit_term(b,),
+		}
+	}
+
+	fn visit_inst(&mut self, b: Block, i: usize,) {
+		let inst = &self.func.block(b,).insts[i];
+		let Some(dest,) = inst.dest else { return };
+		let new = match &inst.kind {
+			InstKind::Phi(incoming,) => incoming
+				.iter()
+				.filter(|(p, _,)| self.edges.contains(&(*p, b,),),)
+				.fold(Lattice::Top, |acc, (_, v,)| {
+					acc.meet(self.values[v.index()],)
+				},),
+			kind @ (InstKind::Const(_,)
+			| InstKind::Copy(_,)
+			| InstKind::Unary(..,)
+			| InstKind::Binary(..,)
+			| InstKind::Cmp(..,)
+			| InstKind::Cast(..,)) => {
+				let operands = kind.operands();
+				if operands
+					.iter()
+					.any(|v| self.values[v.index()] == Lattice::Bottom,)
+				{
+					Lattice::Bottom
+				} else if operands
+					.iter()
+					.any(|v| self.values[v.index()] == Lattice::Top,)
+				{
+					Lattice::Top
+				} else {
+					let known = |v: Value| match self.values[v.index()] {
+						Lattice::Const(c,) => Some(c,),
+						_ => None,
+					};
+					// undefined operations such as division by zero stay in
+					// the program
+					eval(self.func, kind, self.func.ty(dest,), known,)
+						.map_or(Lattice::Bottom, Lattice::Const,)
+				}
+			},
+			_ => Lattice::Bottom,
+		};
+		self.update(dest, new,);
+	}
+
+	fn update(&mut self, v: Value, new: Lattice,) {
+		let old = self.values[v.index()];
+		let new = old.meet(new,);
+		if new != old {
+			self.values[v.index()] = new;
+			self.ssa_work.push(v,);
+		}
+	}
+
+	fn visit_term(&mut self, b: Block,) {
+		let targets = match &self.func.block(b,).term {
+			Terminator::Branch { cond, then, els, } => {
+				match self.values[cond.index()] {
+					Lattice::Top => vec![],
+					Lattice::Const(0,) => vec![*els],
+					Lattice::Const(_,) => vec![*then],
+					Lattice::Bottom => vec![*then, *els],
+				}
+			},
+			term => term.successors(),
+		};
+		for t in targets {
+			self.flow_work.push((Some(b,), t,),);
+		}
+	}
+}
+
+/// # Return
+///
+/// returns whether `func` changed
+pub fn run(func: &mut Function,) -> bool {
+	let mut solver = Solver::new(func,);
+	solver.solve();
+	let Solver { values, executable, edges, .. } = solver;
+	let mut changed = false;
+
+	for b in func.block_ids() {
+		if !executable[b.index()] {
+			let block = func.block(b,);
+			if block.insts.is_empty() && block.term == Terminator::Unreachable {
+				continue;
+			}
+			for s in block.term.successors() {
+				func.remove_phi_incoming(s, b,);
+			}
+			let block = func.block_mut(b,);
+			block.insts.clear();
+			block.term = Terminator::Unreachable;
+			changed = true;
+			continue;
+		}
+
+		let block = func.block_mut(b,);
+		for inst in block.insts.iter_mut() {
+			if let InstKind::Phi(incoming,) = &mut inst.kind {
+				let before = incoming.len();
+				incoming.retain(|(p, _,)| edges.contains(&(*p, b,),),);
+				changed |= before != incoming.len();
+			}
+			let Some(dest,) = inst.dest else { continue };
+			if let Lattice::Const(c,) = values[dest.index()]
+				&& !inst.kind.has_side_effect()
+				&& inst.kind != InstKind::Const(c,)
+			{
+				inst.kind = InstKind::Const(c,);
+				changed = true;
+			}
+		}
+		// folded phis must not stay in front of the remaining ones
+		block.insts.sort_by_key(|i| !matches!(i.kind, InstKind::Phi(_,)),);
+
+		if let Terminator::Branch { cond, then, els, } = block.term
+			&& let Lattice::Const(c,) = values[cond.index()]
+		{
+			let (taken, dropped,) =
+				if c != 0 { (then, els,) } else { (els, then,) };
+			block.term = Terminator::Jump(taken,);
+			if dropped != taken {
+				func.remove_phi_incoming(dropped, b,);
+			}
+			changed = true;
+		}
+	}
+
+	changed
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ir::BinOp;
+	use crate::ir::CmpOp;
+	use crate::ir::FunctionBuilder;
+	use crate::ir::Type;
+	use crate::ir::ssa::mem2reg;
+
+	#[test]
+	fn constant_through_loop() {
+		// int x = 1; while (p) { if (x != 1) x = 2; } return x;
+		let mut fb =
+			FunctionBuilder::new("f", &[Type::I32,], Some(Type::I32,),);
+		let x = fb.slot(Type::I32,);
+		let header = fb.create_block();
+		let body = fb.create_block();
+		let assign = fb.create_block();
+		let latch = fb.create_block();
+		let exit = fb.create_block();
+		let one = fb.iconst(Type::I32, 1,);
+		fb.store_slot(x, one,);
+		fb.jump(header,);
+		fb.switch_to(header,);
+		let p = fb.param(0,);
+		fb.branch(p, body, exit,);
+		fb.switch_to(body,);
+		let cur = fb.load_slot(x,);
+		let one_again = fb.iconst(Type::I32, 1,);
+		let ne = fb.cmp(CmpOp::Ne, cur, one_again,);
+		fb.branch(ne, assign, latch,);
+		fb.switch_to(assign,);
+		let two = fb.iconst(Type::I32, 2,);
+		fb.store_slot(x, two,);
+		fb.jump(latch,);
+		fb.switch_to(latch,);
+		fb.jump(header,);
+		fb.switch_to(exit,);
+		let rslt = fb.load_slot(x,);
+		let rslt = fb.binary(BinOp::Add, rslt, one,);
+		fb.ret(Some(rslt,),);
+		let mut f = fb.finish();
+
+		mem2reg(&mut f,);
+		assert!(run(&mut f,));
+		let Terminator::Return(Some(rslt,),) = f.block(exit,).term else {
+			panic!("{f}")
+		};
+		let def = f
+			.blocks
+			.iter()
+			.flat_map(|b| b.insts.iter(),)
+			.find(|i| i.dest == Some(rslt,),);
+		assert_eq!(def.unwrap().kind, InstKind::Const(2,), "{f}");
+		assert_eq!(f.block(assign,).term, Terminator::Unreachable);
+	}
+}
@@ -0,0 +1,57 @@
+//! merges a block into its predecessor when the edge between them is the
+//! only way in and out. inlining and branch folding leave such chains behind
+
+use crate::ir::Block;
+use crate::ir::Function;
+use crate::ir::InstKind;
+use crate::ir::Terminator;
+use crate::ir::cfg::Cfg;
+use std::collections::HashMap;
+
+/// # Return
+///
+/// returns whether `func` changed
+pub fn run(func: &mut Function,) -> bool {
+	let mut changed = false;
+	loop {
+		let cfg = Cfg::new(func,);
+		let merge = cfg.rpo().iter().find_map(|p| {
+			let Terminator::Jump(b,) = func.block(*p,).term else {
+				return None;
+			};
+			(b != *p && b != Block::ENTRY && cfg.preds(b,) == [*p,])
+				.then_some((*p, b,),)
+		},);
+		let Some((p, b,),) = merge else { break };
+
+		let mut data = std::mem::take(func.block_mut(b,),);
+		// with a single predecessor every phi has one incoming value
+		let mut replace = HashMap::new();
+		data.insts.retain(|inst| match (&inst.kind, inst.dest,) {
+			(InstKind::Phi(incoming,), Some(dest,),) => {
+				if let Some((_, v,),) = incoming.first() {
+					replace.insert(dest, *v,);
+				}
+				false
+			},
+			_ => true,
+		},);
+
+		for s in data.term.successors() {
+			for inst in func.block_mut(s,).insts.iter_mut() {
+				let InstKind::Phi(incoming,) = &mut inst.kind else { break };
+				for (pred, _,) in incoming.iter_mut() {
+					if *pred == b {
+						*pred = p;
+					}
+				}
+			}
+		}
+		let pred = func.block_mut(p,);
+		pred.insts.append(&mut data.insts,);
+		pred.term = data.term;
+		func.replace_uses(&replace,);
+		changed = true;
+	}
+	changed
+}
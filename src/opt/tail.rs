@@ -0,0 +1,202 @@
+//! tail calls
+//!
+//! a self tail call is turned into a jump back to the top of the function,
+//! so recursion like `return f(n - 1, acc * n);` runs in constant stack.
+//! remaining calls in tail position are marked so that the backend emits a
+//! plain branch after tearing down the frame instead of `bl` + `ret`.
+//! `lower::ENTRY` keeps its calls, it exits instead of returning
+
+use crate::ir::Block;
+use crate::ir::BlockData;
+use crate::ir::Function;
+use crate::ir::Inst;
+use crate::ir::InstKind;
+use crate::ir::Terminator;
+use crate::ir::Value;
+use crate::ir::lower;
+use std::collections::HashMap;
+
+/// a callee can reuse the caller frame only if nothing points into it
+fn frame_escapes(func: &Function,) -> bool {
+	func.blocks
+		.iter()
+		.flat_map(|b| b.insts.iter(),)
+		.any(|i| matches!(i.kind, InstKind::StackAddr(_,)),)
+}
+
+/// index of the call in tail position of `block`, if any
+fn tail_call(block: &BlockData,) -> Option<usize,> {
+	let last = block.insts.len().checked_sub(1,)?;
+	let Inst { dest, kind: InstKind::Call { .. }, } = &block.insts[last] else {
+		return None;
+	};
+	match block.term {
+		Terminator::Return(ret,) if ret == *dest => Some(last,),
+		_ => None,
+	}
+}
+
+/// # Return
+///
+/// returns whether `func` changed
+pub fn mark(func: &mut Function,) -> bool {
+	if func.name == lower::ENTRY || frame_escapes(func,) {
+		return false;
+	}
+	let mut changed = false;
+	for block in func.blocks.iter_mut() {
+		let Some(i,) = tail_call(block,) else { continue };
+		if let InstKind::Call { tail, .. } = &mut block.insts[i].kind
+			&& !*tail
+		{
+			*tail = true;
+			changed = true;
+		}
+	}
+	changed
+}
+
+/// replaces self tail calls with a loop. the entry block is moved into a new
+/// header whose phis take the place of the parameters
+///
+/// # Return
+///
+/// returns whether `func` changed
+pub fn eliminate_self_recursion(func: &mut Function,) -> bool {
+	if frame_escapes(func,) {
+		return false;
+	}
+	let sites: Vec<Block,> = func
+		.block_ids()
+		.filter(|b| {
+			tail_call(func.block(*b,),).is_some_and(|i| {
+				matches!(
+					&func.block(*b,).insts[i].kind,
+					InstKind::Call { callee, .. } if *callee == func.name
+				)
+			},)
+		},)
+		.collect();
+	if sites.is_empty() {
+		return false;
+	}
+
+	// entry must not be a branch target, so its body moves to a header
+	let header = func.new_block();
+	let body = std::mem::replace(
+		func.block_mut(Block::ENTRY,),
+		BlockData { insts: vec![], term: Terminator::Jump(header,), },
+	);
+	*func.block_mut(header,) = body;
+	for s in func.block(header,).term.successors() {
+		for inst in func.block_mut(s,).insts.iter_mut() {
+			let InstKind::Phi(incoming,) = &mut inst.kind else { break };
+			for (pred, _,) in incoming.iter_mut() {
+				if *pred == Block::ENTRY {
+					*pred = header;
+				}
+			}
+		}
+	}
+
+	let params = func.params.clone();
+	let phis: Vec<Value,> =
+		params.iter().map(|p| func.new_value(func.ty(*p,),),).collect();
+	let renamed: HashMap<Value, Value,> =
+		params.iter().copied().zip(phis.iter().copied(),).collect();
+	func.replace_uses(&renamed,);
+
+	let mut incoming: Vec<Vec<(Block, Value,),>,> =
+		params.iter().map(|p| vec![(Block::ENTRY, *p,)],).collect();
+	for site in sites {
+		let block = func.block_mut(site,);
+		let Some(Inst { kind: InstKind::Call { args, .. }, .. },) =
+			block.insts.pop()
+		else {
+			unreachable!("tail call site ends with a call")
+		};
+		block.term = Terminator::Jump(header,);
+		for (inc, arg,) in incoming.iter_mut().zip(args,) {
+			inc.push((site, arg,),);
+		}
+	}
+
+	let phi_insts = phis.into_iter().zip(incoming,).map(|(dest, inc,)| Inst {
+		dest: Some(dest,),
+		kind: InstKind::Phi(inc,),
+	},);
+	func.block_mut(header,).insts.splice(0..0, phi_insts,);
+	true
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ir::BinOp;
+	use crate::ir::CmpOp;
+	use crate::ir::FunctionBuilder;
+	use crate::ir::Type;
+	use crate::ir::cfg::Cfg;
+
+	/// int fact(int n, int acc) { return n == 0 ? acc : fact(n - 1, acc * n); }
+	fn fact() -> Function {
+		let mut fb = FunctionBuilder::new(
+			"fact",
+			&[Type::I32, Type::I32,],
+			Some(Type::I32,),
+		);
+		let (n, acc,) = (fb.param(0,), fb.param(1,),);
+		let base = fb.create_block();
+		let rec = fb.create_block();
+		let zero = fb.iconst(Type::I32, 0,);
+		let is_zero = fb.cmp(CmpOp::Eq, n, zero,);
+		fb.branch(is_zero, base, rec,);
+		fb.switch_to(base,);
+		fb.ret(Some(acc,),);
+		fb.switch_to(rec,);
+		let one = fb.iconst(Type::I32, 1,);
+		let n1 = fb.binary(BinOp::Sub, n, one,);
+		let acc1 = fb.binary(BinOp::Mul, acc, n,);
+		let r = fb.call("fact", &[n1, acc1,], Some(Type::I32,),);
+		fb.ret(Some(r,),);
+		fb.finish()
+	}
+
+	#[test]
+	fn self_tail_call_becomes_loop() {
+		let mut f = fact();
+		assert!(eliminate_self_recursion(&mut f,));
+		assert!(
+			f.blocks
+				.iter()
+				.flat_map(|b| b.insts.iter(),)
+				.all(|i| !matches!(i.kind, InstKind::Call { .. })),
+			"{f}"
+		);
+		let cfg = Cfg::new(&f,);
+		assert!(cfg.preds(Block::ENTRY,).is_empty());
+		let header = cfg.succs(Block::ENTRY,)[0];
+		assert_eq!(f.block(header,).phis().count(), 2, "{f}");
+		assert_eq!(cfg.preds(header,).len(), 2);
+	}
+
+	#[test]
+	fn sibling_call_is_marked() {
+		let mut f = fact();
+		f.name = "other".to_string();
+		assert!(!eliminate_self_recursion(&mut f,));
+		assert!(mark(&mut f,));
+		let rec = f.block(Block(2,),);
+		assert!(matches!(
+			rec.insts.last().unwrap().kind,
+			InstKind::Call { tail: true, .. }
+		));
+	}
+
+	#[test]
+	fn entry_keeps_its_calls() {
+		let mut f = fact();
+		f.name = lower::ENTRY.to_string();
+		assert!(!mark(&mut f,), "{f}");
+	}
+}
@@ -0,0 +1,238 @@
+//! loop unrolling by a constant factor
+//!
+//! the body of an innermost loop with a single latch is copied `factor - 1`
+//! times and the copies are chained through their back edges. every copy
+//! keeps the exit tests of the original, so no remainder loop is needed and
+//! the trip count does not have to be known
+//!
+//! loops whose values are used after the loop other than through phis of
+//! the exit blocks are left alone
+
+use crate::ir::Block;
+use crate::ir::BlockData;
+use crate::ir::Function;
+use crate::ir::InstKind;
+use crate::ir::Value;
+use crate::ir::cfg::Cfg;
+use crate::ir::dom::DomTree;
+use crate::ir::loops::Loop;
+use crate::ir::loops::Loops;
+use crate::opt::inline::cost;
+use std::collections::HashMap;
+
+/// factor used by `Pass::Unroll`
+pub const FACTOR: usize = 4;
+
+/// an unrolled loop may grow up to this cost, see `inline::cost`
+const MAX_COST: usize = 256;
+
+fn loop_cost(func: &Function, lp: &Loop,) -> usize {
+	let mut body = Function::new("", None,);
+	body.blocks = lp.blocks.iter().map(|b| func.block(*b,).clone(),).collect();
+	cost(&body,)
+}
+
+/// whether every use of a value defined in `lp` outside of it is an
+/// incoming value of an exit block phi
+fn is_closed(func: &Function, lp: &Loop,) -> bool {
+	let defined: std::collections::HashSet<Value,> = lp
+		.blocks
+		.iter()
+		.flat_map(|b| func.block(*b,).insts.iter().filter_map(|i| i.dest,),)
+		.collect();
+	func.block_ids().filter(|b| !lp.contains(*b,),).all(|b| {
+		let data = func.block(b,);
+		data.insts.iter().all(|i| match &i.kind {
+			InstKind::Phi(incoming,) => incoming
+				.iter()
+				.all(|(p, v,)| !defined.contains(v,) || lp.contains(*p,),),
+			kind => kind.operands().iter().all(|v| !defined.contains(v,),),
+		},) && data.term.operands().iter().all(|v| !defined.contains(v,),)
+	},)
+}
+
+/// # Return
+///
+/// returns whether `func` changed
+pub fn run(func: &mut Function, factor: usize,) -> bool {
+	if factor < 2 {
+		return false;
+	}
+	let cfg = Cfg::new(func,);
+	let loops = Loops::new(&cfg, &DomTree::new(&cfg,),);
+	let mut changed = false;
+	for l in 0..loops.len() {
+		let lp = loops.get(l,);
+		if !loops.is_innermost(l,)
+			|| lp.latches.len() != 1
+			|| loop_cost(func, lp,) * factor > MAX_COST
+			|| !is_closed(func, lp,)
+		{
+			continue;
+		}
+		unroll(func, lp, &cfg, factor,);
+		changed = true;
+	}
+	changed
+}
+
+fn unroll(func: &mut Function, lp: &Loop, cfg: &Cfg, factor: usize,) {
+	let latch = lp.latches[0];
+	let exits = lp.exits(cfg,);
+	let header_phis: Vec<(Value, Value,),> = func
+		.block(lp.header,)
+		.phis()
+		.filter_map(|i| {
+			let InstKind::Phi(incoming,) = &i.kind else { return None };
+			let (_, v,) = incoming.iter().find(|(p, _,)| *p == latch,)?;
+			Some((i.dest?, *v,),)
+		},)
+		.collect();
+	let original: Vec<(Block, BlockData,),> =
+		lp.blocks.iter().map(|b| (*b, func.block(*b,).clone(),),).collect();
+
+	// values and blocks of the previous copy, the original one at first
+	let mut prev_values: HashMap<Value, Value,> = HashMap::new();
+	let mut prev_blocks: HashMap<Block, Block,> =
+		lp.blocks.iter().map(|b| (*b, *b,),).collect();
+	for _ in 1..factor {
+		let blocks: HashMap<Block, Block,> =
+			lp.blocks.iter().map(|b| (*b, func.new_block(),),).collect();
+		let mut values: HashMap<Value, Value,> = HashMap::new();
+		// header phis of a copy are the values of the previous latch
+		for (phi, from_latch,) in header_phis.iter() {
+			values.insert(
+				*phi,
+				*prev_values.get(from_latch,).unwrap_or(from_latch,),
+			);
+		}
+		for (_, data,) in original.iter() {
+			for dest in data.insts.iter().filter_map(|i| i.dest,) {
+				values
+					.entry(dest,)
+					.or_insert_with(|| func.new_value(func.ty(dest,),),);
+			}
+		}
+		let map = |v: &mut Value| {
+			if let Some(n,) = values.get(v,) {
+				*v = *n;
+			}
+		};
+
+		for (b, data,) in original.iter() {
+			let mut data = data.clone();
+			if *b == lp.header {
+				data.insts.retain(|i| !matches!(i.kind, InstKind::Phi(_,)),);
+			}
+			for inst in data.insts.iter_mut() {
+				inst.dest = inst.dest.map(|d| values[&d],);
+				inst.kind.operands_mut().into_iter().for_each(map,);
+				if let InstKind::Phi(incoming,) = &mut inst.kind {
+					for (p, _,) in incoming.iter_mut() {
+						*p = blocks[p];
+					}
+				}
+			}
+			data.term.operands_mut().into_iter().for_each(map,);
+			for s in data.term.successors_mut() {
+				if let Some(n,) = blocks.get(s,)
+					&& *s != lp.header
+				{
+					*s = *n;
+				}
+			}
+			*func.block_mut(blocks[b],) = data;
+		}
+
+		// the previous latch continues into this copy
+		for s in func.block_mut(prev_blocks[&latch],).term.successors_mut() {
+			if *s == lp.header {
+				*s = blocks[&lp.header];
+			}
+		}
+		for (from, to,) in exits.iter() {
+			let mut extra = vec![];
+			for inst in func.block(*to,).insts.iter() {
+				let InstKind::Phi(incoming,) = &inst.kind else { break };
+				let v = incoming
+					.iter()
+					.find(|(p, _,)| p == from,)
+					.map(|(_, v,)| *values.get(v,).unwrap_or(v,),);
+				extra.push(v.map(|v| (blocks[from], v,),),);
+			}
+			for (inst, extra,) in
+				func.block_mut(*to,).insts.iter_mut().zip(extra,)
+			{
+				if let (InstKind::Phi(incoming,), Some(e,),) =
+					(&mut inst.kind, extra,)
+				{
+					incoming.push(e,);
+				}
+			}
+		}
+		prev_values = values;
+		prev_blocks = blocks;
+	}
+
+	// the last copy jumps back to the original header
+	let last_latch = prev_blocks[&latch];
+	for inst in func.block_mut(lp.header,).insts.iter_mut() {
+		let InstKind::Phi(incoming,) = &mut inst.kind else { break };
+		for (p, v,) in incoming.iter_mut() {
+			if *p == latch {
+				*p = last_latch;
+				*v = *prev_values.get(v,).unwrap_or(v,);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ir::BinOp;
+	use crate::ir::CmpOp;
+	use crate::ir::FunctionBuilder;
+	use crate::ir::Terminator;
+	use crate::ir::Type;
+
+	#[test]
+	fn copies_are_chained() {
+		// int f(int n) { int i = 0; while (i < n) i++; return i; }
+		let mut fb =
+			FunctionBuilder::new("f", &[Type::I32,], Some(Type::I32,),);
+		let n = fb.param(0,);
+		let header = fb.create_block();
+		let body = fb.create_block();
+		let exit = fb.create_block();
+		let zero = fb.iconst(Type::I32, 0,);
+		let one = fb.iconst(Type::I32, 1,);
+		fb.jump(header,);
+		fb.switch_to(header,);
+		let i = fb.push(Some(Type::I32,), InstKind::Phi(vec![],),);
+		let c = fb.cmp(CmpOp::SLt, i, n,);
+		fb.branch(c, body, exit,);
+		fb.switch_to(body,);
+		let next = fb.binary(BinOp::Add, i, one,);
+		fb.jump(header,);
+		fb.switch_to(exit,);
+		let out =
+			fb.push(Some(Type::I32,), InstKind::Phi(vec![(header, i,)],),);
+		fb.ret(Some(out,),);
+		let mut func = fb.finish();
+		func.block_mut(header,).insts[0].kind =
+			InstKind::Phi(vec![(Block::ENTRY, zero,), (body, next,)],);
+
+		assert!(run(&mut func, 3,));
+		let cfg = Cfg::new(&func,);
+		let loops = Loops::new(&cfg, &DomTree::new(&cfg,),);
+		assert_eq!(loops.len(), 1, "{func}");
+		assert_eq!(loops.get(0,).blocks.len(), 6, "{func}");
+		assert_eq!(cfg.preds(exit,).len(), 3, "{func}");
+		let InstKind::Phi(incoming,) = &func.block(exit,).insts[0].kind else {
+			panic!("{func}")
+		};
+		assert_eq!(incoming.len(), 3);
+		assert!(matches!(func.block(exit,).term, Terminator::Return(_,)));
+	}
+}
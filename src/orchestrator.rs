@@ -2,20 +2,21 @@ use crate::LlccB;
 use crate::err::B::X;
 use crate::err::B::Y;
 use crate::err::LlccError;
+use crate::front::Emit;
 use crate::front::LlccCompiler;
-use crate::front::exec;
 use crate::semantics::Ctx;
 use crate::semantics::RunCtx;
 use crate::semantics::SrcCtx;
 use crate::semantics::context::HasIn;
 use crate::semantics::context::HasOut;
+use std::fs;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::ExitStatus;
 
-mod file_manage;
+pub mod file_manage;
 
 pub enum Src<'a,> {
 	Str(&'a str,),
@@ -78,7 +79,7 @@ impl<'a,> HasOut for MockRunMeta<'a,> {
 	}
 
 	fn out_info_owned(&self,) -> Option<Self::OutInfo,> {
-		todo!()
+		Some(self.files.clone(),)
 	}
 }
 
@@ -86,8 +87,58 @@ impl<'a,> Ctx for MockRunMeta<'a,> {
 	const ROLE: &'static str = "mock orchestration";
 }
 
-pub fn post_process<R: SrcCtx,>(ctx: R,) -> LlccB<(String, LlccCompiler,),>
-where R: HasIn<InInfo = SrcOwned,> {
+/// a source file named on the command line, compiled into `files`
+pub struct CliRunMeta<'a,> {
+	src:   Src<'a,>,
+	files: file_manage::Dest,
+}
+
+impl<'a,> CliRunMeta<'a,> {
+	pub fn new(src: &'a Path, files: file_manage::Dest,) -> Self {
+		Self { src: Src::Path(src,), files, }
+	}
+}
+
+impl<'a,> SrcCtx for CliRunMeta<'a,> {}
+impl<'a,> RunCtx for CliRunMeta<'a,> {}
+
+impl<'a,> HasIn for CliRunMeta<'a,> {
+	type InInfo = SrcOwned;
+	type InInfoRef<'i,>
+		= &'i Src<'i,>
+	where Self: 'i;
+
+	fn in_info(&self,) -> Option<Self::InInfoRef<'_,>,> {
+		Some(&self.src,)
+	}
+
+	fn in_info_owned(&self,) -> Option<Self::InInfo,> {
+		Some(self.src.own(),)
+	}
+}
+
+impl<'a,> HasOut for CliRunMeta<'a,> {
+	type OutInfo = file_manage::Dest;
+	type OutInfoRef<'o,>
+		= &'o file_manage::Dest
+	where Self: 'o;
+
+	fn out_info(&self,) -> Option<Self::OutInfoRef<'_,>,> {
+		Some(&self.files,)
+	}
+
+	fn out_info_owned(&self,) -> Option<Self::OutInfo,> {
+		Some(self.files.clone(),)
+	}
+}
+
+impl<'a,> Ctx for CliRunMeta<'a,> {
+	const ROLE: &'static str = "command line";
+}
+
+/// the source `ctx` names
+pub fn post_process<R,>(ctx: R,) -> LlccB<String,>
+where R: SrcCtx + HasIn<InInfo = SrcOwned,> {
 	let Some(src,) = ctx.in_info_owned() else {
 		return Y(LlccError::lack_of_ctx::<R,>(),);
 	};
@@ -103,44 +154,165 @@ where R: HasIn<InInfo = SrcOwned,> {
 		},
 	};
 
-	X((src, LlccCompiler::default(),),)
+	X(src,)
 }
 
+/// compiles and runs the source of `ctx` with the default options, writing
+/// the outputs where `ctx` says
 pub fn run<RC,>(ctx: RC,) -> LlccB<ExitStatus,>
 where
 	RC: RunCtx + SrcCtx,
-	RC: HasIn<InInfo = SrcOwned,>,
+	RC: HasIn<InInfo = SrcOwned,> + HasOut<OutInfo = file_manage::Dest,>,
 {
-	//  TODO: unwrap_or/unwrap_or_defaultにする
-	let (src, compiler,) = post_process(ctx,)?;
-	let exe_path = compiler.compile(src,)?;
-	exec(exe_path,)
+	let Some(status,) = run_with(ctx, LlccCompiler::default(),)? else {
+		unreachable!("the default compiler emits an executable")
+	};
+	X(status,)
+}
+
+/// compiles the source named on the command line the way `compiler` was
+/// configured by `LlccCompiler::from_args`. a library may be built from
+/// several sources, each a function named after its file
+pub fn run_cli(compiler: LlccCompiler,) -> LlccB<Option<ExitStatus,>,> {
+	if compiler.emit() == Emit::Lib && !compiler.srcs().is_empty() {
+		let mut srcs = vec![];
+		for path in compiler.srcs() {
+			let name = path.file_stem().unwrap_or_default().to_string_lossy();
+			srcs.push((name.into_owned(), fs::read_to_string(path,)?,),);
+		}
+		compiler.archive(srcs,)?;
+		return X(None,);
+	}
+
+	let [src,] = compiler.srcs() else {
+		return Y(LlccError::syntax(format!(
+			"expected one source file, got {}",
+			compiler.srcs().len()
+		),),);
+	};
+	let src = src.clone();
+	run_with(CliRunMeta::new(&src, compiler.dest().clone(),), compiler,)
+}
+
+/// `run` with options such as `--target` taken from the command line. runs
+/// the program unless `compiler` only emits an intermediate file. the
+/// outputs go where `ctx` says
+pub fn run_with<RC,>(
+	ctx: RC,
+	compiler: LlccCompiler,
+) -> LlccB<Option<ExitStatus,>,>
+where
+	RC: RunCtx + SrcCtx,
+	RC: HasIn<InInfo = SrcOwned,> + HasOut<OutInfo = file_manage::Dest,>,
+{
+	let compiler = match ctx.out_info_owned() {
+		Some(dest,) => compiler.with_dest(dest,),
+		None => compiler,
+	};
+	let src = post_process(ctx,)?;
+	match compiler.emit() {
+		Emit::Exe => {
+			let exe_path = compiler.compile(src,)?;
+			X(Some(compiler.exec(exe_path,)?,),)
+		},
+		Emit::Asm => {
+			compiler.emit_asm(src,)?;
+			X(None,)
+		},
+		Emit::Obj => {
+			compiler.emit_obj(src,)?;
+			X(None,)
+		},
+		Emit::Lib => {
+			compiler.archive([("out", src,),],)?;
+			X(None,)
+		},
+		Emit::Llvm => {
+			compiler.emit_llvm(src,)?;
+			X(None,)
+		},
+		Emit::Wat => {
+			compiler.emit_wat(src,)?;
+			X(None,)
+		},
+	}
 }
 
 #[cfg(test)]
 pub fn run_fixture(src: &str,) -> LlccB<MockRunMeta<'_,>,> {
-	use uuid::Uuid;
-
 	X(MockRunMeta {
 		src:   Src::Str(src,),
-		files: file_manage::Dest::new(
-			Some("test".to_string(),),
-			Uuid::new_v4(),
-			None,
-		)?,
+		files: file_manage::Dest::temp(Some("test".to_string(),),),
 	},)
 }
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use crate::asm::clear_out;
 	use quickcheck_macros::quickcheck;
+	use uuid::Uuid;
 
 	#[quickcheck]
 	fn test_run_single_number(es: u8,) -> LlccB<(),> {
 		let es_str = es.to_string();
-		let es_str = es_str.as_str();
-		let exit_status = run(run_fixture(es_str,)?,)?;
-		assert_eq!(exit_status.code(), Some(es as i32));
+		let ctx = run_fixture(es_str.as_str(),)?;
+		let dest = ctx.files.clone();
+		let exit_status = run(ctx,);
+		clear_out(&dest,)?;
+		assert_eq!(exit_status?.code(), Some(es as i32));
+		X((),)
+	}
+
+	/// what `main` does with `args`, given a source file holding each of
+	/// `srcs`
+	fn main_with(args: &[&str], srcs: &[&str],) -> LlccB<Option<ExitStatus,>,> {
+		let dir =
+			std::env::temp_dir().join(format!("llcc-{}", Uuid::new_v4()),);
+		fs::create_dir_all(&dir,)?;
+		let mut args: Vec<String,> =
+			args.iter().map(|arg| arg.to_string(),).collect();
+		for (i, src,) in srcs.iter().enumerate() {
+			let path = dir.join(format!("f{i}.c"),);
+			fs::write(&path, src,)?;
+			args.push(path.to_string_lossy().into_owned(),);
+		}
+		let compiler = LlccCompiler::from_args(args,)?
+			.with_dest(file_manage::Dest::temp(Some("test".to_string(),),),);
+		compiler.check_toolchain()?;
+		let dest = compiler.dest().clone();
+		let rslt = run_cli(compiler,);
+		fs::remove_dir_all(dir,)?;
+		clear_out(&dest,)?;
+		rslt
+	}
+
+	#[test]
+	fn test_run_from_args() -> LlccB<(),> {
+		for args in [
+			&["-O2", "--target", "aarch64-linux-gnu",][..],
+			&["--target=aarch64", "--emit=exe", "-O0",],
+			&[],
+		] {
+			let status = main_with(args, &["40 - -3 + 2",],)?;
+			assert_eq!(status.and_then(|s| s.code()), Some(45), "{args:?}");
+		}
+		for emit in ["asm", "obj", "lib", "llvm", "wat",] {
+			let args = ["--target", "aarch64", "--emit", emit,];
+			assert!(main_with(&args, &["1 + 2",],)?.is_none(), "{emit}");
+		}
+		let lib = ["--target", "aarch64", "--emit", "lib",];
+		assert!(main_with(&lib, &["1", "2", "3",],)?.is_none());
+
+		let Y(e,) = main_with(&["--target", "aarch64", "-x",], &["1",],) else {
+			panic!("`-x` was accepted");
+		};
+		assert!(e.to_string().contains("unknown option `-x`",), "{e}");
+		for srcs in [&[][..], &["1", "2",],] {
+			let Y(e,) = main_with(&["--target", "aarch64",], srcs,) else {
+				panic!("ran {} source files", srcs.len());
+			};
+			assert!(e.to_string().contains("one source file",), "{e}");
+		}
 		X((),)
 	}
 }
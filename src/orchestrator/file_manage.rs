@@ -1,15 +1,14 @@
-use crate::err::B::X;
-use crate::err::Container;
-use std::fs;
 use std::path::PathBuf;
 use uuid::Uuid;
 
-use crate::err::LlccB;
-
 const PRJ_DIR: &str = env!("CARGO_MANIFEST_DIR");
 
-#[derive(Debug,)]
+/// a directory of outputs named by a uuid. it is created by whoever writes
+/// the first output into it
+#[derive(Debug, Clone,)]
 pub struct Dest {
+	/// holds `out/<uuid>` and the source of `DestKind::Src`
+	root:    PathBuf,
 	uuid:    Uuid,
 	prefix:  Option<String,>,
 	postfix: Option<String,>,
@@ -17,7 +16,7 @@ pub struct Dest {
 
 impl Default for Dest {
 	fn default() -> Self {
-		Self::new(None, Uuid::new_v4(), None,).unwrap()
+		Self::new(None, Uuid::new_v4(), None,)
 		// let rslt =
 		// 	Self { uuid: Uuid::new_v4(), prefix: None, postfix: None, };
 		// let out_path = rslt.path(DestKind::OutDir,).into();
@@ -40,14 +39,16 @@ impl Dest {
 		prefix: Option<String,>,
 		uuid: Uuid,
 		postfix: Option<String,>,
-	) -> LlccB<Self,> {
-		let rslt = Self { uuid, prefix, postfix, };
-		let out_path = rslt.path(DestKind::OutDir,).into();
-		if !out_path.exists() {
-			fs::create_dir_all(&out_path,)?;
-		}
+	) -> Self {
+		let root = PathBuf::from(PRJ_DIR,).join("assets",);
+		Self { root, uuid, prefix, postfix, }
+	}
 
-		X(rslt,)
+	/// outputs under the temporary directory of the system instead of the
+	/// project, for runs that clean up after themselves
+	pub fn temp(prefix: Option<String,>,) -> Self {
+		let root = std::env::temp_dir().join("llcc",);
+		Self { root, ..Self::new(prefix, Uuid::new_v4(), None,) }
 	}
 
 	pub fn path(&self, kind: DestKind,) -> impl Into<PathBuf,> {
@@ -55,7 +56,7 @@ impl Dest {
 	}
 
 	pub fn base_of(&self, kind: DestKind,) -> impl Into<PathBuf,> {
-		let mut assets = PathBuf::from(PRJ_DIR,).join("assets",);
+		let mut assets = self.root.clone();
 		if kind == DestKind::Src {
 			return assets.join(kind.path(),);
 		}
@@ -83,7 +84,10 @@ impl Dest {
 pub enum DestKind {
 	Src,
 	Asm,
+	Llvm,
+	Wat,
 	Obj,
+	Lib,
 	Exe,
 	OutDir,
 }
@@ -93,7 +97,10 @@ impl DestKind {
 		let path_str = match self {
 			Self::Src => "main.c",
 			Self::Asm => "out.s",
+			Self::Llvm => "out.ll",
+			Self::Wat => "out.wat",
 			Self::Obj => "out.o",
+			Self::Lib => "libout.a",
 			Self::Exe => "out",
 			Self::OutDir => "",
 		};
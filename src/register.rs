@@ -2,7 +2,7 @@ use crate::err::LlccError;
 use std::fmt::Display;
 use std::str::FromStr;
 
-#[derive(strum::Display,)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display,)]
 pub enum RegisterOrImmediate<
 	const BIT: u8,
 	const IS_SIGNED: bool,
@@ -16,6 +16,10 @@ pub enum RegisterOrImmediate<
 	Register(R,),
 	#[strum(to_string = "#{0}")]
 	Immediate(I,),
+	#[strum(to_string = "{0}")]
+	Shifted(ShiftedRegister<R,>,),
+	#[strum(to_string = "{0}")]
+	Extended(ExtendedRegister<R,>,),
 }
 
 impl<const BIT: u8, const IS_SIGNED: bool,> TryFrom<&str,>
@@ -28,38 +32,566 @@ impl<const BIT: u8, const IS_SIGNED: bool,> TryFrom<&str,>
 	}
 }
 
-impl<const BIT: u8, const IS_SIGNED: bool,> TryFrom<i32,>
-	for RegisterOrImmediate<BIT, IS_SIGNED,>
+impl<const BIT: u8, const IS_SIGNED: bool, R: Display,> TryFrom<i64,>
+	for RegisterOrImmediate<BIT, IS_SIGNED, R,>
 {
 	type Error = LlccError;
 
-	/// if `value` overflows, max value allowed fot the immediate
-	fn try_from(value: i32,) -> Result<Self, Self::Error,> {
-		Ok(Self::Immediate(Immediate(value,),),)
+	fn try_from(value: i64,) -> Result<Self, Self::Error,> {
+		Ok(Self::Immediate(Immediate::try_from(value,)?,),)
+	}
+}
+
+impl<const BIT: u8, const IS_SIGNED: bool, R: Display, I: Display,>
+	RegisterOrImmediate<BIT, IS_SIGNED, R, I,>
+{
+	pub fn map_register<S: Display,>(
+		self,
+		f: impl FnOnce(R,) -> S,
+	) -> RegisterOrImmediate<BIT, IS_SIGNED, S, I,> {
+		match self {
+			Self::Register(r,) => RegisterOrImmediate::Register(f(r,),),
+			Self::Immediate(i,) => RegisterOrImmediate::Immediate(i,),
+			Self::Shifted(s,) => {
+				RegisterOrImmediate::Shifted(s.map_register(f,),)
+			},
+			Self::Extended(e,) => {
+				RegisterOrImmediate::Extended(e.map_register(f,),)
+			},
+		}
+	}
+
+	/// the register read by `self`, if any
+	pub fn register(&self,) -> Option<&R,> {
+		match self {
+			Self::Register(r,) => Some(r,),
+			Self::Shifted(s,) => Some(&s.reg,),
+			Self::Extended(e,) => Some(&e.reg,),
+			Self::Immediate(_,) => None,
+		}
+	}
+}
+
+/// registers whose class is known, so that operands built from them can be
+/// validated
+pub trait GeneralRegister: Display + Copy {
+	fn class(&self,) -> RegisterClass;
+
+	/// `sp` or `wsp`, which share their encoding with the zero registers
+	fn is_sp(&self,) -> bool;
+
+	fn is_zero(&self,) -> bool;
+
+	fn is_64(&self,) -> bool {
+		self.class() == RegisterClass::Gpr64
+	}
+
+	/// whether `self` is an `x` or `w` register at all
+	fn is_gpr(&self,) -> bool {
+		self.class().is_gpr()
+	}
+}
+
+#[derive(
+	Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display, strum::EnumString,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum Shift {
+	Lsl,
+	Lsr,
+	Asr,
+	Ror,
+}
+
+#[derive(
+	Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display, strum::EnumString,
+)]
+#[strum(serialize_all = "lowercase")]
+pub enum Extend {
+	Uxtb,
+	Uxth,
+	Uxtw,
+	Uxtx,
+	Sxtb,
+	Sxth,
+	Sxtw,
+	Sxtx,
+}
+
+impl Extend {
+	/// whether the extended register is an `x` register
+	pub fn reads_64(self,) -> bool {
+		matches!(self, Self::Uxtx | Self::Sxtx)
+	}
+}
+
+/// `reg, shift #amount`
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub struct ShiftedRegister<R = Register,> {
+	reg:    R,
+	shift:  Shift,
+	amount: u8,
+}
+
+impl<R: GeneralRegister,> ShiftedRegister<R,> {
+	/// `amount` has to be less than the width of `reg`
+	pub fn new(reg: R, shift: Shift, amount: u8,) -> Result<Self, LlccError,> {
+		let bits = if reg.is_64() { 64 } else { 32 };
+		if amount >= bits {
+			return Err(LlccError::mismatch_imm(
+				amount as i64,
+				bits.trailing_zeros() as u8,
+				false,
+			),);
+		}
+		Ok(Self { reg, shift, amount, },)
+	}
+}
+
+impl<R,> ShiftedRegister<R,> {
+	pub fn reg(&self,) -> &R {
+		&self.reg
+	}
+
+	pub fn shift(&self,) -> Shift {
+		self.shift
+	}
+
+	pub fn amount(&self,) -> u8 {
+		self.amount
+	}
+
+	/// `f` has to keep the width of the register
+	pub fn map_register<S,>(
+		self,
+		f: impl FnOnce(R,) -> S,
+	) -> ShiftedRegister<S,> {
+		ShiftedRegister {
+			reg:    f(self.reg,),
+			shift:  self.shift,
+			amount: self.amount,
+		}
 	}
 }
 
-//  TODO: shift演算どうする?
-pub struct Immediate<const BIT: u8, const IS_SIGNED: bool,>(i32,);
+impl<R: Display,> Display for ShiftedRegister<R,> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		write!(f, "{}, {} #{}", self.reg, self.shift, self.amount)
+	}
+}
+
+/// `reg, extend #amount`, the extended value is shifted left by `amount`
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub struct ExtendedRegister<R = Register,> {
+	reg:    R,
+	extend: Extend,
+	amount: u8,
+}
+
+impl<R: GeneralRegister,> ExtendedRegister<R,> {
+	/// `reg` has to be an `x` register for `uxtx` and `sxtx` and a `w`
+	/// register otherwise. `amount` is at most 4
+	pub fn new(
+		reg: R, extend: Extend, amount: u8,
+	) -> Result<Self, LlccError,> {
+		if reg.is_64() != extend.reads_64() {
+			return Err(LlccError::unsupported(format!("`{reg}, {extend}`"),),);
+		}
+		if amount > 4 {
+			return Err(LlccError::mismatch_imm(amount as i64, 2, false,),);
+		}
+		Ok(Self { reg, extend, amount, },)
+	}
+}
+
+impl<R,> ExtendedRegister<R,> {
+	pub fn reg(&self,) -> &R {
+		&self.reg
+	}
+
+	pub fn extend(&self,) -> Extend {
+		self.extend
+	}
+
+	pub fn amount(&self,) -> u8 {
+		self.amount
+	}
+
+	/// `f` has to keep the width of the register
+	pub fn map_register<S,>(
+		self,
+		f: impl FnOnce(R,) -> S,
+	) -> ExtendedRegister<S,> {
+		ExtendedRegister {
+			reg:    f(self.reg,),
+			extend: self.extend,
+			amount: self.amount,
+		}
+	}
+}
+
+impl<R: Display,> Display for ExtendedRegister<R,> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		match self.amount {
+			0 => write!(f, "{}, {}", self.reg, self.extend),
+			amount => write!(f, "{}, {} #{amount}", self.reg, self.extend),
+		}
+	}
+}
+
+/// immediate of logical instructions: a rotated run of ones, repeated to
+/// fill the register
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub struct BitmaskImmediate {
+	value: u64,
+	n:     u8,
+	immr:  u8,
+	imms:  u8,
+}
+
+impl BitmaskImmediate {
+	/// `value` truncated to 32 bits unless `is_64`
+	pub fn new(value: u64, is_64: bool,) -> Result<Self, LlccError,> {
+		let value = if is_64 { value } else { value & u32::MAX as u64 };
+		let pattern = if is_64 { value } else { value | value << 32 };
+		if pattern == 0 || pattern == u64::MAX {
+			return Err(LlccError::unsupported(format!(
+				"bitmask immediate {value:#x}"
+			),),);
+		}
+
+		// the smallest element the pattern repeats
+		let mut size = 64;
+		while size > 2 {
+			let half = size / 2;
+			let mask = (1 << half) - 1;
+			if pattern & mask != (pattern >> half) & mask {
+				break;
+			}
+			size = half;
+		}
+		let mask = if size == 64 { u64::MAX } else { (1 << size) - 1 };
+		let element = pattern & mask;
+		let ones = element.count_ones();
+		let run = (1u64 << ones) - 1;
+		let rotl = |x: u64, r: u32| {
+			if r == 0 { x } else { (x << r | x >> (size - r)) & mask }
+		};
+		let Some(immr,) = (0..size).find(|r| rotl(element, *r,) == run,) else {
+			return Err(LlccError::unsupported(format!(
+				"bitmask immediate {value:#x}"
+			),),);
+		};
+		Ok(Self {
+			value,
+			n: (size == 64) as u8,
+			immr: immr as u8,
+			imms: ((!(size * 2 - 1) & 0x3f) | (ones - 1)) as u8,
+		},)
+	}
+
+	/// inverse of the encoding `(n, immr, imms)`
+	pub fn decode(n: u8, immr: u8, imms: u8, is_64: bool,) -> Option<Self,> {
+		let combined = (n as u32) << 6 | (!imms as u32 & 0x3f);
+		if combined < 2 || (n == 1 && !is_64) {
+			return None;
+		}
+		let len = 31 - combined.leading_zeros();
+		let size = 1u32 << len;
+		let ones = (imms as u32 & (size - 1)) + 1;
+		if ones == size {
+			return None;
+		}
+		let mask = if size == 64 { u64::MAX } else { (1 << size) - 1 };
+		let run = (1u64 << ones) - 1;
+		let r = immr as u32 & (size - 1);
+		let element =
+			if r == 0 { run } else { (run >> r | run << (size - r)) & mask };
+		let mut value = element;
+		let mut filled = size;
+		while filled < 64 {
+			value |= value << filled;
+			filled *= 2;
+		}
+		Self::new(value, is_64,).ok()
+	}
+
+	pub fn value(&self,) -> u64 {
+		self.value
+	}
 
-impl<const BIT: u8, const IS_SIGNED: bool,> TryFrom<i32,>
+	/// `(n, immr, imms)` fields of the instruction
+	pub fn encoding(&self,) -> (u8, u8, u8,) {
+		(self.n, self.immr, self.imms,)
+	}
+}
+
+impl Display for BitmaskImmediate {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		write!(f, "{:#x}", self.value)
+	}
+}
+
+/// size of a memory access, in bytes. pairs access two registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub enum Access {
+	Single(u8,),
+	Pair(u8,),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub enum AddressMode<R = Register,> {
+	/// `[base, #offset]`
+	Offset(i64,),
+	/// `[base, #offset]!`, `base` is updated before the access
+	PreIndex(i64,),
+	/// `[base], #offset`, `base` is updated after the access
+	PostIndex(i64,),
+	/// `[base, index]` with `index` extended and scaled by `amount`
+	Register { index: R, extend: Extend, amount: u8, },
+}
+
+/// memory operand of loads and stores
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub struct Address<R = Register,> {
+	base: R,
+	mode: AddressMode<R,>,
+}
+
+impl<R: GeneralRegister,> Address<R,> {
+	/// single accesses take an unsigned offset scaled by the access size,
+	/// pairs a signed 7 bit one
+	pub fn offset(
+		base: R,
+		offset: i64,
+		access: Access,
+	) -> Result<Self, LlccError,> {
+		let addr = Self { base, mode: AddressMode::Offset(offset,), };
+		addr.check(access,)?;
+		Ok(addr,)
+	}
+
+	pub fn pre_index(
+		base: R,
+		offset: i64,
+		access: Access,
+	) -> Result<Self, LlccError,> {
+		let addr = Self { base, mode: AddressMode::PreIndex(offset,), };
+		addr.check(access,)?;
+		Ok(addr,)
+	}
+
+	pub fn post_index(
+		base: R,
+		offset: i64,
+		access: Access,
+	) -> Result<Self, LlccError,> {
+		let addr = Self { base, mode: AddressMode::PostIndex(offset,), };
+		addr.check(access,)?;
+		Ok(addr,)
+	}
+
+	/// `index` is scaled by the access size if `scaled`. only single
+	/// accesses take a register offset
+	pub fn register(
+		base: R,
+		index: R,
+		extend: Extend,
+		scaled: bool,
+		access: Access,
+	) -> Result<Self, LlccError,> {
+		let size = match access {
+			Access::Single(size,) | Access::Pair(size,) => size,
+		};
+		let amount = if scaled { size.trailing_zeros() as u8 } else { 0 };
+		let addr = Self {
+			base,
+			mode: AddressMode::Register { index, extend, amount, },
+		};
+		addr.check(access,)?;
+		Ok(addr,)
+	}
+
+	/// whether `self` can address `access`
+	pub fn check(&self, access: Access,) -> Result<(), LlccError,> {
+		let base = self.base;
+		if !base.is_64() || base.is_zero() {
+			return Err(LlccError::unsupported(format!("`{base}` as base"),),);
+		}
+		let scaled = |offset: i64, size: u8| {
+			(offset % size as i64 == 0).then_some(offset / size as i64,)
+		};
+		let (offset, valid,) = match (self.mode, access,) {
+			(AddressMode::Offset(o,), Access::Single(size,),) => (
+				o,
+				scaled(o, size,).is_some_and(|o| (0..1 << 12).contains(&o,),),
+			),
+			(
+				AddressMode::PreIndex(o,) | AddressMode::PostIndex(o,),
+				Access::Single(_,),
+			) => (o, (-256..256).contains(&o,),),
+			(
+				AddressMode::Offset(o,)
+				| AddressMode::PreIndex(o,)
+				| AddressMode::PostIndex(o,),
+				Access::Pair(size,),
+			) => {
+				(o, scaled(o, size,).is_some_and(|o| (-64..64).contains(&o,),),)
+			},
+			(
+				AddressMode::Register { index, extend, amount, },
+				Access::Single(size,),
+			) => {
+				let valid_extend = matches!(
+					extend,
+					Extend::Uxtw | Extend::Sxtw | Extend::Uxtx | Extend::Sxtx
+				) && index.is_64() == extend.reads_64()
+					&& !index.is_sp();
+				if !valid_extend
+					|| (amount != 0 && amount as u32 != size.trailing_zeros())
+				{
+					return Err(LlccError::unsupported(format!("`{self}`"),),);
+				}
+				return Ok((),);
+			},
+			(AddressMode::Register { .. }, Access::Pair(_,),) => {
+				return Err(
+					LlccError::unsupported("register offset of pairs",),
+				);
+			},
+		};
+		if valid {
+			Ok((),)
+		} else {
+			Err(LlccError::mismatch_imm(offset, 12, true,),)
+		}
+	}
+}
+
+impl<R: Copy,> Address<R,> {
+	pub fn base(&self,) -> R {
+		self.base
+	}
+
+	pub fn mode(&self,) -> &AddressMode<R,> {
+		&self.mode
+	}
+
+	/// registers read to compute the address
+	pub fn registers(&self,) -> Vec<R,> {
+		match self.mode {
+			AddressMode::Register { index, .. } => vec![self.base, index],
+			_ => vec![self.base],
+		}
+	}
+
+	/// whether `base` is updated
+	pub fn writes_back(&self,) -> bool {
+		matches!(
+			self.mode,
+			AddressMode::PreIndex(_,) | AddressMode::PostIndex(_,)
+		)
+	}
+
+	/// `f` has to keep the width of the registers
+	pub fn map_register<S,>(self, mut f: impl FnMut(R,) -> S,) -> Address<S,> {
+		let mode = match self.mode {
+			AddressMode::Offset(o,) => AddressMode::Offset(o,),
+			AddressMode::PreIndex(o,) => AddressMode::PreIndex(o,),
+			AddressMode::PostIndex(o,) => AddressMode::PostIndex(o,),
+			AddressMode::Register { index, extend, amount, } => {
+				AddressMode::Register { index: f(index,), extend, amount, }
+			},
+		};
+		Address { base: f(self.base,), mode, }
+	}
+}
+
+impl<R: Display,> Display for Address<R,> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		let base = &self.base;
+		match &self.mode {
+			AddressMode::Offset(0,) => write!(f, "[{base}]"),
+			AddressMode::Offset(o,) => write!(f, "[{base}, #{o}]"),
+			AddressMode::PreIndex(o,) => write!(f, "[{base}, #{o}]!"),
+			AddressMode::PostIndex(o,) => write!(f, "[{base}], #{o}"),
+			AddressMode::Register {
+				index,
+				extend: Extend::Uxtx,
+				amount: 0,
+			} => {
+				write!(f, "[{base}, {index}]")
+			},
+			AddressMode::Register { index, extend: Extend::Uxtx, amount, } => {
+				write!(f, "[{base}, {index}, lsl #{amount}]")
+			},
+			AddressMode::Register { index, extend, amount: 0, } => {
+				write!(f, "[{base}, {index}, {extend}]")
+			},
+			AddressMode::Register { index, extend, amount, } => {
+				write!(f, "[{base}, {index}, {extend} #{amount}]")
+			},
+		}
+	}
+}
+
+/// `BIT` bit field, optionally shifted left by `shift`
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub struct Immediate<const BIT: u8, const IS_SIGNED: bool,> {
+	field: i64,
+	shift: u8,
+}
+
+impl<const BIT: u8, const IS_SIGNED: bool,> Immediate<BIT, IS_SIGNED,> {
+	/// `value` as a field shifted by the first of `shifts` that fits
+	pub fn shifted(value: i64, shifts: &[u8],) -> Result<Self, LlccError,> {
+		shifts
+			.iter()
+			.find_map(|shift| {
+				let field = value >> shift;
+				if field << shift != value {
+					return None;
+				}
+				Self::with_shift(field, *shift,).ok()
+			},)
+			.ok_or(LlccError::mismatch_imm(value, BIT, IS_SIGNED,),)
+	}
+
+	/// `field` shifted left by `shift`
+	pub fn with_shift(field: i64, shift: u8,) -> Result<Self, LlccError,> {
+		Ok(Self { shift, ..Self::try_from(field,)? },)
+	}
+
+	pub fn field(&self,) -> i64 {
+		self.field
+	}
+
+	pub fn shift(&self,) -> u8 {
+		self.shift
+	}
+
+	/// the value after shifting
+	pub fn value(&self,) -> i64 {
+		self.field << self.shift
+	}
+}
+
+impl<const BIT: u8, const IS_SIGNED: bool,> TryFrom<i64,>
 	for Immediate<BIT, IS_SIGNED,>
 {
 	type Error = LlccError;
 
-	fn try_from(value: i32,) -> Result<Self, Self::Error,> {
-		// 型チェックフラグ達
-		let undesired_sign = !IS_SIGNED && value < 0;
-		let overflowing = {
-			let unsignated_value =
-				if IS_SIGNED { value & i32::MAX } else { value };
-			(unsignated_value >> BIT) != 0
+	fn try_from(value: i64,) -> Result<Self, Self::Error,> {
+		let range = if IS_SIGNED {
+			-(1 << (BIT - 1))..1 << (BIT - 1)
+		} else {
+			0..1 << BIT
 		};
 
-		if undesired_sign || overflowing {
-			Err(LlccError::mismatch_imm(value, BIT, IS_SIGNED,),)
+		if range.contains(&value,) {
+			Ok(Self { field: value, shift: 0, },)
 		} else {
-			Ok(Self(value,),)
+			Err(LlccError::mismatch_imm(value, BIT, IS_SIGNED,),)
 		}
 	}
 }
@@ -68,143 +600,558 @@ impl<const BIT: u8, const IS_SIGNED: bool,> Display
 	for Immediate<BIT, IS_SIGNED,>
 {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
-		let formatted = format_args!("{}", self.0);
-		f.write_fmt(formatted,)
+		match self.shift {
+			0 => write!(f, "{}", self.field),
+			shift => write!(f, "{}, lsl #{shift}", self.field),
+		}
+	}
+}
+
+impl Register {
+	/// register `index` of the file `class` belongs to
+	pub fn new(class: RegisterClass, index: u8,) -> Result<Self, LlccError,> {
+		Ok(Self::from_str(&format!("{}{index}", class.prefix()?),)?,)
+	}
+
+	/// general purpose register `index` viewed as 64 bit `x` or 32 bit `w`
+	pub fn gpr(index: u8, is_64: bool,) -> Result<Self, LlccError,> {
+		let class =
+			if is_64 { RegisterClass::Gpr64 } else { RegisterClass::Gpr32 };
+		Self::new(class, index,)
+	}
+
+	pub fn class(&self,) -> RegisterClass {
+		match self.to_string().as_bytes()[0] {
+			_ if *self == Self::Sp => RegisterClass::Gpr64,
+			b'x' => RegisterClass::Gpr64,
+			b'w' => RegisterClass::Gpr32,
+			b'v' => RegisterClass::Simd,
+			b'b' => RegisterClass::Fp { bytes: 1, },
+			b'h' => RegisterClass::Fp { bytes: 2, },
+			b's' => RegisterClass::Fp { bytes: 4, },
+			b'd' => RegisterClass::Fp { bytes: 8, },
+			_ => RegisterClass::Fp { bytes: 16, },
+		}
+	}
+
+	pub fn is_64(&self,) -> bool {
+		self.class() == RegisterClass::Gpr64
+	}
+
+	pub fn is_sp(&self,) -> bool {
+		matches!(self, Self::Sp | Self::Wsp)
+	}
+
+	pub fn is_zero(&self,) -> bool {
+		matches!(self, Self::Xzr | Self::Wzr)
+	}
+
+	/// number of the register in its file. `None` for `sp` and the zero
+	/// registers
+	pub fn index(&self,) -> Option<u8,> {
+		self.to_string()[1..].parse().ok()
+	}
+
+	/// the same register viewed as `class`, which has to be in the same file
+	pub fn view(&self, class: RegisterClass,) -> Result<Self, LlccError,> {
+		if self.class().is_gpr() != class.is_gpr() {
+			return Err(LlccError::unsupported(format!(
+				"`{self}` viewed as {class}"
+			),),);
+		}
+		match (self.index(), class,) {
+			(Some(index,), _,) => Self::new(class, index,),
+			(None, RegisterClass::Gpr64,) if self.is_sp() => Ok(Self::Sp,),
+			(None, _,) if self.is_sp() => Ok(Self::Wsp,),
+			(None, RegisterClass::Gpr64,) => Ok(Self::Xzr,),
+			(None, _,) => Ok(Self::Wzr,),
+		}
+	}
+
+	pub fn role(&self,) -> AbiRole {
+		if self.is_sp() {
+			return AbiRole::StackPointer;
+		}
+		let Some(index,) = self.index() else {
+			return AbiRole::Zero;
+		};
+		if !self.class().is_gpr() {
+			return match index {
+				0..8 => AbiRole::Argument,
+				8..16 => AbiRole::CalleeSaved,
+				_ => AbiRole::Scratch,
+			};
+		}
+		match index {
+			0..8 => AbiRole::Argument,
+			8 => AbiRole::IndirectResult,
+			9..16 => AbiRole::Scratch,
+			16 | 17 => AbiRole::IntraProcedure,
+			18 => AbiRole::Platform,
+			19..29 => AbiRole::CalleeSaved,
+			29 => AbiRole::FramePointer,
+			_ => AbiRole::LinkRegister,
+		}
+	}
+}
+
+impl GeneralRegister for Register {
+	fn class(&self,) -> RegisterClass {
+		Register::class(self,)
+	}
+
+	fn is_sp(&self,) -> bool {
+		Register::is_sp(self,)
+	}
+
+	fn is_zero(&self,) -> bool {
+		Register::is_zero(self,)
+	}
+}
+
+/// register file and width a register name refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display,)]
+pub enum RegisterClass {
+	/// `x0`-`x30`, `sp` and `xzr`
+	#[strum(to_string = "x")]
+	Gpr64,
+	/// `w0`-`w30`, `wsp` and `wzr`
+	#[strum(to_string = "w")]
+	Gpr32,
+	/// `b`, `h`, `s`, `d` or `q`, the low `bytes` of a vector register
+	#[strum(to_string = "{bytes} byte fp")]
+	Fp { bytes: u8, },
+	/// `v0`-`v31`, a whole vector register
+	#[strum(to_string = "v")]
+	Simd,
+}
+
+impl RegisterClass {
+	pub fn is_gpr(self,) -> bool {
+		matches!(self, Self::Gpr64 | Self::Gpr32)
+	}
+
+	pub fn bytes(self,) -> u8 {
+		match self {
+			Self::Gpr64 => 8,
+			Self::Gpr32 => 4,
+			Self::Fp { bytes, } => bytes,
+			Self::Simd => 16,
+		}
+	}
+
+	fn prefix(self,) -> Result<&'static str, LlccError,> {
+		Ok(match self {
+			Self::Gpr64 => "x",
+			Self::Gpr32 => "w",
+			Self::Simd => "v",
+			Self::Fp { bytes: 1, } => "b",
+			Self::Fp { bytes: 2, } => "h",
+			Self::Fp { bytes: 4, } => "s",
+			Self::Fp { bytes: 8, } => "d",
+			Self::Fp { bytes: 16, } => "q",
+			Self::Fp { bytes, } => {
+				return Err(LlccError::unsupported(format!(
+					"{bytes} byte fp registers"
+				),),);
+			},
+		},)
 	}
 }
 
-#[derive(strum::Display, strum::EnumString,)]
+/// use of a register under the AAPCS64 calling convention
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash,)]
+pub enum AbiRole {
+	/// `x0`-`x7` and `v0`-`v7` pass arguments and results
+	Argument,
+	/// `x8` passes the address of a returned aggregate
+	IndirectResult,
+	/// `x9`-`x15` and `v16`-`v31` may be clobbered by any call
+	Scratch,
+	/// `x16` and `x17` may be clobbered by linker veneers
+	IntraProcedure,
+	/// `x18` is reserved by some platforms
+	Platform,
+	/// `x19`-`x28` and the low halves of `v8`-`v15` survive calls
+	CalleeSaved,
+	/// `x29`
+	FramePointer,
+	/// `x30`
+	LinkRegister,
+	StackPointer,
+	Zero,
+}
+
+#[derive(
+	Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display, strum::EnumString,
+)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
 pub enum Register {
-	#[strum(ascii_case_insensitive)]
-	X31,
-	#[strum(ascii_case_insensitive)]
-	X30,
-	#[strum(ascii_case_insensitive)]
-	X29,
-	#[strum(ascii_case_insensitive)]
-	X28,
-	#[strum(ascii_case_insensitive)]
-	X27,
-	#[strum(ascii_case_insensitive)]
-	X26,
-	#[strum(ascii_case_insensitive)]
-	X25,
-	#[strum(ascii_case_insensitive)]
-	X24,
-	#[strum(ascii_case_insensitive)]
-	X23,
-	#[strum(ascii_case_insensitive)]
-	X22,
-	#[strum(ascii_case_insensitive)]
-	X21,
-	#[strum(ascii_case_insensitive)]
-	X20,
-	#[strum(ascii_case_insensitive)]
-	X19,
-	#[strum(ascii_case_insensitive)]
-	X18,
-	#[strum(ascii_case_insensitive)]
-	X17,
-	#[strum(ascii_case_insensitive)]
-	X16,
-	#[strum(ascii_case_insensitive)]
-	X15,
-	#[strum(ascii_case_insensitive)]
-	X14,
-	#[strum(ascii_case_insensitive)]
-	X13,
-	#[strum(ascii_case_insensitive)]
-	X12,
-	#[strum(ascii_case_insensitive)]
-	X11,
-	#[strum(ascii_case_insensitive)]
-	X10,
-	#[strum(ascii_case_insensitive)]
-	X9,
-	#[strum(ascii_case_insensitive)]
-	X8,
-	#[strum(ascii_case_insensitive)]
-	X7,
-	#[strum(ascii_case_insensitive)]
-	X6,
-	#[strum(ascii_case_insensitive)]
-	X5,
-	#[strum(ascii_case_insensitive)]
-	X4,
-	#[strum(ascii_case_insensitive)]
-	X3,
-	#[strum(ascii_case_insensitive)]
-	X2,
-	#[strum(ascii_case_insensitive)]
-	X1,
-	#[strum(ascii_case_insensitive)]
 	X0,
-	#[strum(ascii_case_insensitive)]
-	W30,
-	#[strum(ascii_case_insensitive)]
-	W29,
-	#[strum(ascii_case_insensitive)]
-	W28,
-	#[strum(ascii_case_insensitive)]
-	W27,
-	#[strum(ascii_case_insensitive)]
-	W26,
-	#[strum(ascii_case_insensitive)]
-	W25,
-	#[strum(ascii_case_insensitive)]
-	W24,
-	#[strum(ascii_case_insensitive)]
-	W23,
-	#[strum(ascii_case_insensitive)]
-	W22,
-	#[strum(ascii_case_insensitive)]
-	W21,
-	#[strum(ascii_case_insensitive)]
-	W20,
-	#[strum(ascii_case_insensitive)]
-	W19,
-	#[strum(ascii_case_insensitive)]
-	W18,
-	#[strum(ascii_case_insensitive)]
-	W17,
-	#[strum(ascii_case_insensitive)]
-	W16,
-	#[strum(ascii_case_insensitive)]
-	W15,
-	#[strum(ascii_case_insensitive)]
-	W14,
-	#[strum(ascii_case_insensitive)]
-	W13,
-	#[strum(ascii_case_insensitive)]
-	W12,
-	#[strum(ascii_case_insensitive)]
-	W11,
-	#[strum(ascii_case_insensitive)]
-	W10,
-	#[strum(ascii_case_insensitive)]
-	W9,
-	#[strum(ascii_case_insensitive)]
-	W8,
-	#[strum(ascii_case_insensitive)]
-	W7,
-	#[strum(ascii_case_insensitive)]
-	W6,
-	#[strum(ascii_case_insensitive)]
-	W5,
-	#[strum(ascii_case_insensitive)]
-	W4,
-	#[strum(ascii_case_insensitive)]
-	W3,
-	#[strum(ascii_case_insensitive)]
-	W2,
-	#[strum(ascii_case_insensitive)]
-	W1,
-	#[strum(ascii_case_insensitive)]
+	X1,
+	X2,
+	X3,
+	X4,
+	X5,
+	X6,
+	X7,
+	X8,
+	X9,
+	X10,
+	X11,
+	X12,
+	X13,
+	X14,
+	X15,
+	X16,
+	X17,
+	X18,
+	X19,
+	X20,
+	X21,
+	X22,
+	X23,
+	X24,
+	X25,
+	X26,
+	X27,
+	X28,
+	X29,
+	X30,
 	W0,
-	#[strum(ascii_case_insensitive)]
+	W1,
+	W2,
+	W3,
+	W4,
+	W5,
+	W6,
+	W7,
+	W8,
+	W9,
+	W10,
+	W11,
+	W12,
+	W13,
+	W14,
+	W15,
+	W16,
+	W17,
+	W18,
+	W19,
+	W20,
+	W21,
+	W22,
+	W23,
+	W24,
+	W25,
+	W26,
+	W27,
+	W28,
+	W29,
+	W30,
 	Sp,
-	#[strum(ascii_case_insensitive)]
+	Wsp,
 	Xzr,
-	#[strum(ascii_case_insensitive)]
 	Wzr,
+	V0,
+	V1,
+	V2,
+	V3,
+	V4,
+	V5,
+	V6,
+	V7,
+	V8,
+	V9,
+	V10,
+	V11,
+	V12,
+	V13,
+	V14,
+	V15,
+	V16,
+	V17,
+	V18,
+	V19,
+	V20,
+	V21,
+	V22,
+	V23,
+	V24,
+	V25,
+	V26,
+	V27,
+	V28,
+	V29,
+	V30,
+	V31,
+	Q0,
+	Q1,
+	Q2,
+	Q3,
+	Q4,
+	Q5,
+	Q6,
+	Q7,
+	Q8,
+	Q9,
+	Q10,
+	Q11,
+	Q12,
+	Q13,
+	Q14,
+	Q15,
+	Q16,
+	Q17,
+	Q18,
+	Q19,
+	Q20,
+	Q21,
+	Q22,
+	Q23,
+	Q24,
+	Q25,
+	Q26,
+	Q27,
+	Q28,
+	Q29,
+	Q30,
+	Q31,
+	D0,
+	D1,
+	D2,
+	D3,
+	D4,
+	D5,
+	D6,
+	D7,
+	D8,
+	D9,
+	D10,
+	D11,
+	D12,
+	D13,
+	D14,
+	D15,
+	D16,
+	D17,
+	D18,
+	D19,
+	D20,
+	D21,
+	D22,
+	D23,
+	D24,
+	D25,
+	D26,
+	D27,
+	D28,
+	D29,
+	D30,
+	D31,
+	S0,
+	S1,
+	S2,
+	S3,
+	S4,
+	S5,
+	S6,
+	S7,
+	S8,
+	S9,
+	S10,
+	S11,
+	S12,
+	S13,
+	S14,
+	S15,
+	S16,
+	S17,
+	S18,
+	S19,
+	S20,
+	S21,
+	S22,
+	S23,
+	S24,
+	S25,
+	S26,
+	S27,
+	S28,
+	S29,
+	S30,
+	S31,
+	H0,
+	H1,
+	H2,
+	H3,
+	H4,
+	H5,
+	H6,
+	H7,
+	H8,
+	H9,
+	H10,
+	H11,
+	H12,
+	H13,
+	H14,
+	H15,
+	H16,
+	H17,
+	H18,
+	H19,
+	H20,
+	H21,
+	H22,
+	H23,
+	H24,
+	H25,
+	H26,
+	H27,
+	H28,
+	H29,
+	H30,
+	H31,
+	B0,
+	B1,
+	B2,
+	B3,
+	B4,
+	B5,
+	B6,
+	B7,
+	B8,
+	B9,
+	B10,
+	B11,
+	B12,
+	B13,
+	B14,
+	B15,
+	B16,
+	B17,
+	B18,
+	B19,
+	B20,
+	B21,
+	B22,
+	B23,
+	B24,
+	B25,
+	B26,
+	B27,
+	B28,
+	B29,
+	B30,
+	B31,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use quickcheck_macros::quickcheck;
+
+	#[quickcheck]
+	fn bitmask_roundtrip(
+		size: u8,
+		ones: u8,
+		rotation: u8,
+		is_64: bool,
+	) -> bool {
+		let sizes: &[u32] =
+			if is_64 { &[2, 4, 8, 16, 32, 64,] } else { &[2, 4, 8, 16, 32,] };
+		let size = sizes[size as usize % sizes.len()];
+		let ones = 1 + ones as u32 % (size - 1);
+		let mut element = (1u64 << ones) - 1;
+		element = element.rotate_right(rotation as u32 % size,);
+		if size < 64 {
+			// `rotate_right` works on 64 bits, fold the wrapped bits back
+			element = (element | element >> (64 - size)) & ((1 << size) - 1);
+		}
+		let mut value = element;
+		let mut filled = size;
+		while filled < 64 {
+			value |= value << filled;
+			filled *= 2;
+		}
+
+		let imm = BitmaskImmediate::new(value, is_64,).unwrap();
+		let (n, immr, imms,) = imm.encoding();
+		BitmaskImmediate::decode(n, immr, imms, is_64,) == Some(imm,)
+	}
+
+	#[test]
+	fn bitmask_rejects() {
+		assert!(BitmaskImmediate::new(0, true,).is_err());
+		assert!(BitmaskImmediate::new(u64::MAX, true,).is_err());
+		assert!(BitmaskImmediate::new(u32::MAX as u64, false,).is_err());
+		assert!(BitmaskImmediate::new(0b101, true,).is_err());
+		assert!(BitmaskImmediate::new(0xff00, true,).is_ok());
+	}
+
+	#[test]
+	fn operands_are_validated() {
+		use Register::*;
+		assert!(ShiftedRegister::new(W1, Shift::Lsl, 31,).is_ok());
+		assert!(ShiftedRegister::new(W1, Shift::Lsl, 32,).is_err());
+		assert_eq!(
+			ExtendedRegister::new(W1, Extend::Sxtw, 0,).unwrap().to_string(),
+			"w1, sxtw"
+		);
+		assert!(ExtendedRegister::new(X1, Extend::Sxtw, 0,).is_err());
+
+		let single = Access::Single(8,);
+		assert_eq!(
+			Address::offset(X0, 32760, single,).unwrap().to_string(),
+			"[x0, #32760]"
+		);
+		assert!(Address::offset(X0, 4, single,).is_err());
+		assert!(Address::offset(W0, 0, single,).is_err());
+		assert_eq!(
+			Address::pre_index(Sp, -512, Access::Pair(8,),)
+				.unwrap()
+				.to_string(),
+			"[sp, #-512]!"
+		);
+		assert!(Address::post_index(Sp, 512, Access::Pair(8,),).is_err());
+		assert_eq!(
+			Address::register(X0, W1, Extend::Sxtw, true, Access::Single(4,),)
+				.unwrap()
+				.to_string(),
+			"[x0, w1, sxtw #2]"
+		);
+		assert!(
+			Address::register(X0, X1, Extend::Sxtw, false, single,).is_err()
+		);
+	}
+
+	#[test]
+	fn classes_views_and_roles() {
+		use Register::*;
+		assert!("x31".parse::<Register>().is_err());
+		assert_eq!("V7".parse::<Register>().unwrap(), V7);
+		assert_eq!(X3.view(RegisterClass::Gpr32,).unwrap(), W3);
+		assert_eq!(Sp.view(RegisterClass::Gpr32,).unwrap(), Wsp);
+		assert_eq!(Wzr.view(RegisterClass::Gpr64,).unwrap(), Xzr);
+		assert_eq!(V5.view(RegisterClass::Fp { bytes: 8, },).unwrap(), D5);
+		assert_eq!(B5.view(RegisterClass::Simd,).unwrap(), V5);
+		assert!(X5.view(RegisterClass::Simd,).is_err());
+		assert_eq!(S9.class(), RegisterClass::Fp { bytes: 4, });
+		assert_eq!(Sp.class(), RegisterClass::Gpr64);
+
+		assert_eq!(X0.role(), AbiRole::Argument);
+		assert_eq!(W20.role(), AbiRole::CalleeSaved);
+		assert_eq!(X12.role(), AbiRole::Scratch);
+		assert_eq!(D9.role(), AbiRole::CalleeSaved);
+		assert_eq!(Q20.role(), AbiRole::Scratch);
+		assert_eq!(Xzr.role(), AbiRole::Zero);
+
+		let single = Access::Single(8,);
+		assert!(Address::offset(Sp, 8, single,).is_ok());
+		assert!(Address::offset(Xzr, 8, single,).is_err());
+		assert!(
+			Address::register(X0, Sp, Extend::Uxtx, false, single,).is_err()
+		);
+	}
 }
@@ -7,7 +7,7 @@ pub mod context;
 pub mod purpose;
 
 /// 表現変換
-pub trait Convert<LayerFrom, LayerTo,const REVERSIBLE: > {
+pub trait Convert<LayerFrom, LayerTo,const REVERSIBLE: bool,> {
 	fn convert(&self,);
 }
 
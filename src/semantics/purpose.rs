@@ -3,6 +3,6 @@ use crate::semantics::ability::ReadIn;
 use crate::semantics::c::HasOut;
 use crate::semantics::context::HasIn;
 
-pub trait Layer: Convert + HasIn + HasOut {}
+pub trait Layer: Convert<(), (), false,> + HasIn + HasOut {}
 
 pub trait LayerBuilder: HasIn + HasOut + ReadIn {}
@@ -0,0 +1,287 @@
+//! code generation targets
+//!
+//! a `Triple` such as `aarch64-linux-gnu` names what to compile for, and
+//! `Triple::target` hands out the backend implementing `Target` for it.
+//! everything that differs between machines goes through the trait, so the
+//! host only decides which tools can run without a cross prefix
+
+use crate::asm::Arch;
+use crate::err::B::X;
+use crate::err::LlccB;
+use crate::err::LlccError;
+use crate::ir::Module;
+use crate::ir::Type;
+use crate::obj::Object;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Linux system calls used by generated programs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::Display,)]
+#[strum(serialize_all = "lowercase")]
+pub enum Syscall {
+	Read,
+	Write,
+	Exit,
+	Brk,
+	Mmap,
+}
+
+impl Syscall {
+	/// number in the generic table used by aarch64 and riscv64
+	pub const fn generic(self,) -> u16 {
+		match self {
+			Self::Read => 63,
+			Self::Write => 64,
+			Self::Exit => 93,
+			Self::Brk => 214,
+			Self::Mmap => 222,
+		}
+	}
+
+	/// number in the x86_64 table
+	pub const fn x86_64(self,) -> u16 {
+		match self {
+			Self::Read => 0,
+			Self::Write => 1,
+			Self::Exit => 60,
+			Self::Brk => 12,
+			Self::Mmap => 9,
+		}
+	}
+}
+
+/// registers by their assembler names
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub struct RegisterFile {
+	pub general:       Vec<String,>,
+	pub float:         Vec<String,>,
+	pub stack_pointer: String,
+	pub frame_pointer: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub struct CallingConvention {
+	pub int_args:     Vec<String,>,
+	pub float_args:   Vec<String,>,
+	pub int_ret:      String,
+	pub float_ret:    String,
+	/// registers a callee has to restore before returning
+	pub callee_saved: Vec<String,>,
+	/// registers without a role in calls, which any call may clobber
+	pub temporaries:  Vec<String,>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub struct DataLayout {
+	pub pointer_bytes: u32,
+	/// alignment of the stack pointer at calls
+	pub stack_align:   u32,
+	pub little_endian: bool,
+}
+
+impl Default for DataLayout {
+	/// LP64 as all supported targets use it
+	fn default() -> Self {
+		Self { pointer_bytes: 8, stack_align: 16, little_endian: true, }
+	}
+}
+
+impl DataLayout {
+	pub fn size_of(&self, ty: Type,) -> u32 {
+		ty.bytes()
+	}
+
+	/// scalars are aligned to their size
+	pub fn align_of(&self, ty: Type,) -> u32 {
+		ty.bytes()
+	}
+}
+
+pub trait Target {
+	fn triple(&self,) -> &Triple;
+
+	fn registers(&self,) -> RegisterFile;
+
+	fn calling_convention(&self,) -> CallingConvention;
+
+	fn data_layout(&self,) -> DataLayout {
+		DataLayout::default()
+	}
+
+	fn syscall(&self, call: Syscall,) -> u16;
+
+	/// selects instructions for `module` and prints them as GNU assembly
+	fn print(&self, module: &Module,) -> LlccB<String,>;
+
+	/// selects instructions for `module` and encodes them into an object.
+	/// `None` if the target has no integrated assembler, in which case
+	/// objects come from `assembler`
+	fn object(&self, module: &Module,) -> Option<LlccB<Object,>,> {
+		let _ = module;
+		None
+	}
+
+	/// program assembling the output of `print`
+	fn assembler(&self,) -> String {
+		format!("{}as", self.triple().tool_prefix())
+	}
+
+	/// program linking the objects of the assembler
+	fn linker(&self,) -> String {
+		format!("{}ld", self.triple().tool_prefix())
+	}
+}
+
+/// `<arch>[-<vendor>]-linux[-<env>]`, or a bare `<arch>` meaning Linux.
+/// `Default` is the host
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub struct Triple {
+	pub arch:   Arch,
+	pub vendor: Option<String,>,
+	pub env:    String,
+}
+
+impl Default for Triple {
+	fn default() -> Self {
+		Self {
+			arch:   Arch::default(),
+			vendor: None,
+			env:    "gnu".to_string(),
+		}
+	}
+}
+
+impl FromStr for Triple {
+	type Err = LlccError;
+
+	fn from_str(s: &str,) -> Result<Self, Self::Err,> {
+		let parts: Vec<&str,> = s.split('-',).collect();
+		let arch = parts[0].parse()?;
+		let (vendor, env,) = match parts[1..] {
+			[] | ["linux",] => (None, "gnu",),
+			["linux", env,] => (None, env,),
+			[vendor, "linux",] => (Some(vendor,), "gnu",),
+			[vendor, "linux", env,] => (Some(vendor,), env,),
+			_ => {
+				return Err(LlccError::unsupported(format!(
+					"target `{s}`, only Linux triples are supported"
+				),),);
+			},
+		};
+		Ok(Self {
+			arch,
+			vendor: vendor.map(str::to_string,),
+			env: env.to_string(),
+		},)
+	}
+}
+
+impl Display for Triple {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_,>,) -> std::fmt::Result {
+		write!(f, "{}", self.arch)?;
+		if let Some(vendor,) = &self.vendor {
+			write!(f, "-{vendor}")?;
+		}
+		write!(f, "-linux-{}", self.env)
+	}
+}
+
+impl Triple {
+	/// the backend generating code for this triple
+	pub fn target(&self,) -> Box<dyn Target,> {
+		let triple = self.clone();
+		match self.arch {
+			Arch::Aarch64 => Box::new(crate::asm::Aarch64 { triple, },),
+			Arch::X86_64 => Box::new(crate::asm::X86_64 { triple, },),
+			Arch::Riscv64 => Box::new(crate::asm::Riscv64 { triple, },),
+		}
+	}
+
+	/// prefix of binutils built for this triple, empty if the host can run
+	/// the output natively
+	pub fn tool_prefix(&self,) -> String {
+		if self.arch == Arch::default() {
+			String::new()
+		} else {
+			format!("{}-linux-{}-", self.arch, self.env)
+		}
+	}
+}
+
+/// parses `s` as a triple and returns its backend
+pub fn from_triple(s: &str,) -> LlccB<Box<dyn Target,>,> {
+	let triple: Triple = s.parse()?;
+	X(triple.target(),)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::err::Container;
+
+	#[test]
+	fn triples_parse() {
+		let cases = [
+			("aarch64-linux-gnu", Arch::Aarch64, None, "gnu",),
+			("x86_64-unknown-linux-gnu", Arch::X86_64, Some("unknown",), "gnu",),
+			(
+				"riscv64gc-unknown-linux-musl",
+				Arch::Riscv64,
+				Some("unknown",),
+				"musl",
+			),
+			("arm64", Arch::Aarch64, None, "gnu",),
+		];
+		for (s, arch, vendor, env,) in cases {
+			let triple: Triple = s.parse().unwrap();
+			assert_eq!(triple.arch, arch, "{s}");
+			assert_eq!(triple.vendor.as_deref(), vendor, "{s}");
+			assert_eq!(triple.env, env, "{s}");
+			// printing canonicalizes the arch and fills in the os
+			let again: Triple = triple.to_string().parse().unwrap();
+			assert_eq!(again, triple);
+		}
+		assert!("mips-linux-gnu".parse::<Triple>().is_err());
+		assert!("aarch64-apple-darwin".parse::<Triple>().is_err());
+	}
+
+	#[test]
+	fn targets_are_consistent() {
+		for arch in ["aarch64", "x86_64", "riscv64",] {
+			let target = from_triple(arch,).unwrap();
+			let regs = target.registers();
+			let cc = target.calling_convention();
+			for r in cc.int_args.iter().chain(&cc.callee_saved,) {
+				assert!(
+					regs.general.contains(r,) || regs.float.contains(r,),
+					"{arch}: {r}"
+				);
+			}
+			assert!(cc.float_args.iter().all(|r| regs.float.contains(r,),));
+			assert!(regs.general.contains(&cc.int_ret,), "{arch}");
+			assert!(regs.float.contains(&cc.float_ret,), "{arch}");
+			assert!(!cc.callee_saved.contains(&cc.int_ret,), "{arch}");
+			for r in &cc.temporaries {
+				assert!(
+					regs.general.contains(r,) || regs.float.contains(r,),
+					"{arch}: {r}"
+				);
+				assert!(!cc.callee_saved.contains(r,), "{arch}: {r}");
+				assert!(!cc.int_args.contains(r,), "{arch}: {r}");
+				assert!(!cc.float_args.contains(r,), "{arch}: {r}");
+				assert_ne!(*r, cc.int_ret, "{arch}");
+			}
+			assert_eq!(target.data_layout().pointer_bytes, 8);
+
+			let exit = target.syscall(Syscall::Exit,);
+			let expected = if arch == "x86_64" { 60 } else { 93 };
+			assert_eq!(exit, expected);
+
+			let native = target.triple().arch == Arch::default();
+			assert_eq!(target.assembler() == "as", native, "{arch}");
+			if !native {
+				assert_eq!(target.linker(), format!("{arch}-linux-gnu-ld"));
+			}
+		}
+	}
+}
@@ -0,0 +1,341 @@
+//! external programs llcc runs
+//!
+//! the assembler and linker are binutils named after the target, such as
+//! `aarch64-linux-gnu-as` when cross compiling. `LLCC_AS` and `LLCC_LD`
+//! replace them with another name, a path or a whole command line.
+//! executables the host cannot run go through `LLCC_RUNNER`, through
+//! `qemu-<arch>` if it is installed or, for aarch64, through the built-in
+//! emulator. `Toolchain::check` tells up front what is missing rather than
+//! failing after compiling
+
+use crate::asm::Arch;
+use crate::asm::run_cmd;
+use crate::err::B::X;
+use crate::err::B::Y;
+use crate::err::LlccB;
+use crate::err::LlccError;
+use crate::target::Triple;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+
+/// command line of the assembler
+pub const AS_VAR: &str = "LLCC_AS";
+/// command line of the linker
+pub const LD_VAR: &str = "LLCC_LD";
+/// command line running executables, which are passed last
+pub const RUNNER_VAR: &str = "LLCC_RUNNER";
+
+/// a program together with the arguments it always takes
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub struct Tool {
+	pub program: String,
+	pub args:    Vec<String,>,
+}
+
+impl Tool {
+	pub fn new(program: impl Into<String,>,) -> Self {
+		Self { program: program.into(), args: vec![], }
+	}
+
+	/// splits `line` at whitespace. `None` if it is blank
+	pub fn parse(line: &str,) -> Option<Self,> {
+		let mut words = line.split_whitespace().map(str::to_string,);
+		Some(Self { program: words.next()?, args: words.collect(), },)
+	}
+
+	/// the program itself if it is given as a path, the first match in
+	/// `search_path` otherwise
+	pub fn find(&self, search_path: Option<&OsStr,>,) -> Option<PathBuf,> {
+		let is_file = |p: &Path| p.is_file();
+		if self.program.contains('/',) {
+			let path = PathBuf::from(&self.program,);
+			return is_file(&path,).then_some(path,);
+		}
+		std::env::split_paths(search_path?,)
+			.map(|dir| dir.join(&self.program,),)
+			.find(|p| is_file(p,),)
+	}
+
+	/// runs the program with its own arguments followed by `args`
+	pub fn run<I, S,>(&self, args: I,) -> LlccB<ExitStatus,>
+	where
+		I: IntoIterator<Item = S,>,
+		S: AsRef<OsStr,>,
+	{
+		let own = self.args.iter().map(OsStr::new,);
+		let args: Vec<_,> = args.into_iter().collect();
+		run_cmd(&self.program, own.chain(args.iter().map(AsRef::as_ref,),),)
+	}
+
+	/// `run` for tools such as the assembler, failing unless the program
+	/// succeeds
+	pub fn run_ok<I, S,>(&self, args: I,) -> LlccB<(),>
+	where
+		I: IntoIterator<Item = S,>,
+		S: AsRef<OsStr,>,
+	{
+		let problem = match self.run(args,) {
+			X(status,) if status.success() => return X((),),
+			X(status,) => format!("failed with {status}"),
+			Y(e,) => format!("could not be run: {e}"),
+		};
+		Y(LlccError::toolchain(format!("`{}` {problem}", self.program),),)
+	}
+}
+
+/// how executables are started
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub enum Runner {
+	/// the host runs them
+	Native,
+	/// a command such as `qemu-aarch64 -L /usr/aarch64-linux-gnu`, given
+	/// the executable as its last argument
+	Command(Tool,),
+	/// `asm::emulate` runs them in process
+	Emulator,
+}
+
+/// what a compilation is going to run
+#[derive(Debug, Clone, Copy, PartialEq, Eq,)]
+pub enum Need {
+	Assembler,
+	Linker,
+	Runner,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq,)]
+pub struct Toolchain {
+	pub assembler:   Tool,
+	pub linker:      Tool,
+	/// `None` if nothing can run executables of the target
+	pub runner:      Option<Runner,>,
+	/// where programs without a path are looked up
+	pub search_path: Option<String,>,
+}
+
+impl Default for Toolchain {
+	/// the tools for the host, from the environment of this process
+	fn default() -> Self {
+		Self::discover(&Triple::default(), |var| std::env::var(var,).ok(),)
+	}
+}
+
+impl Toolchain {
+	/// the tools for `triple`, reading variables such as `LLCC_AS` and
+	/// `PATH` with `env`
+	pub fn discover(
+		triple: &Triple,
+		env: impl Fn(&str,) -> Option<String,>,
+	) -> Self {
+		let target = triple.target();
+		let tool = |var: &str, default: String| {
+			env(var,)
+				.as_deref()
+				.and_then(Tool::parse,)
+				.unwrap_or_else(|| Tool::new(default,),)
+		};
+		let search_path = env("PATH",);
+		let qemu = Tool {
+			program: format!("qemu-{}", triple.arch),
+			args:    vec![
+				"-L".to_string(),
+				format!("/usr/{}-linux-{}", triple.arch, triple.env),
+			],
+		};
+		let runner = match env(RUNNER_VAR,).as_deref().and_then(Tool::parse,) {
+			Some(runner,) => Some(Runner::Command(runner,),),
+			None if triple.arch == Arch::default() => Some(Runner::Native,),
+			None if qemu
+				.find(search_path.as_deref().map(OsStr::new,),)
+				.is_some() =>
+			{
+				Some(Runner::Command(qemu,),)
+			},
+			None if triple.arch == Arch::Aarch64 => Some(Runner::Emulator,),
+			None => None,
+		};
+		Self {
+			assembler: tool(AS_VAR, target.assembler(),),
+			linker: tool(LD_VAR, target.linker(),),
+			runner,
+			search_path,
+		}
+	}
+
+	/// # Return
+	///
+	/// returns an error naming every one of `needs` that is missing, and
+	/// how to provide it
+	pub fn check(&self, needs: &[Need],) -> LlccB<(),> {
+		let search_path = self.search_path.as_deref().map(OsStr::new,);
+		let missing = |tool: &Tool, what: &str, var: &str| {
+			tool.find(search_path,).is_none().then(|| {
+				format!(
+					"{what} `{}` was not found, install it or set {var}",
+					tool.program
+				)
+			},)
+		};
+		let problems: Vec<String,> = needs
+			.iter()
+			.filter_map(|need| match need {
+				Need::Assembler => {
+					missing(&self.assembler, "assembler", AS_VAR,)
+				},
+				Need::Linker => missing(&self.linker, "linker", LD_VAR,),
+				Need::Runner => match &self.runner {
+					None => Some(no_runner(),),
+					Some(Runner::Command(tool,),) => {
+						missing(tool, "runner", RUNNER_VAR,)
+					},
+					Some(Runner::Native | Runner::Emulator,) => None,
+				},
+			},)
+			.collect();
+		if problems.is_empty() {
+			X((),)
+		} else {
+			Y(LlccError::toolchain(problems.join("; ",),),)
+		}
+	}
+
+	pub fn runner(&self,) -> LlccB<&Runner,> {
+		match &self.runner {
+			Some(runner,) => X(runner,),
+			None => Y(LlccError::toolchain(no_runner(),),),
+		}
+	}
+}
+
+fn no_runner() -> String {
+	format!(
+		"nothing runs executables of the target, set {RUNNER_VAR} to a \
+		 command such as `qemu-<arch> -L <sysroot>`"
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::err::Container;
+	use std::collections::HashMap;
+	use std::fs;
+
+	/// an empty directory to put fake programs in
+	fn bin_dir() -> PathBuf {
+		let dir = std::env::temp_dir()
+			.join(format!("llcc-toolchain-{}", uuid::Uuid::new_v4()),);
+		fs::create_dir_all(&dir,).unwrap();
+		dir
+	}
+
+	fn discover(triple: &str, vars: &[(&str, &str,)],) -> Toolchain {
+		let vars: HashMap<_, _,> = vars.iter().copied().collect();
+		Toolchain::discover(&triple.parse().unwrap(), |var| {
+			vars.get(var,).map(|v| v.to_string(),)
+		},)
+	}
+
+	#[test]
+	fn names_binutils_after_the_target() {
+		for triple in ["aarch64-linux-gnu", "x86_64-linux-gnu", "riscv64",] {
+			let prefix = triple.parse::<Triple>().unwrap().tool_prefix();
+			let tools = discover(triple, &[],);
+			assert_eq!(tools.assembler, Tool::new(format!("{prefix}as"),));
+			assert_eq!(tools.linker, Tool::new(format!("{prefix}ld"),));
+		}
+
+		let tools = discover(
+			"aarch64",
+			&[
+				(AS_VAR, "/opt/cross/bin/as --fatal-warnings",),
+				(LD_VAR, "mold",),
+			],
+		);
+		assert_eq!(tools.assembler.program, "/opt/cross/bin/as");
+		assert_eq!(tools.assembler.args, ["--fatal-warnings"]);
+		assert_eq!(tools.linker, Tool::new("mold",));
+	}
+
+	#[test]
+	fn picks_a_runner() {
+		let dir = bin_dir();
+		let path = dir.to_str().unwrap();
+		let runner = |triple: &str, vars: &[(&str, &str,)]| {
+			discover(triple, vars,).runner
+		};
+
+		let qemu = Tool::parse("qemu-user -L /sysroot",).unwrap();
+		assert_eq!(
+			runner("riscv64", &[(RUNNER_VAR, "qemu-user -L /sysroot",)],),
+			Some(Runner::Command(qemu,),)
+		);
+		let host = Arch::default().to_string();
+		assert_eq!(runner(&host, &[],), Some(Runner::Native,));
+
+		let foreign = [Arch::Aarch64, Arch::X86_64, Arch::Riscv64,]
+			.into_iter()
+			.filter(|arch| *arch != Arch::default(),);
+		for arch in foreign {
+			let arch = arch.to_string();
+			let expected = (arch == "aarch64").then_some(Runner::Emulator,);
+			assert_eq!(runner(&arch, &[("PATH", path,)],), expected);
+
+			fs::write(dir.join(format!("qemu-{arch}"),), "",).unwrap();
+			let Some(Runner::Command(qemu,),) =
+				runner(&arch, &[("PATH", path,),],)
+			else {
+				panic!("no qemu for {arch}");
+			};
+			assert_eq!(
+				qemu.args,
+				["-L".to_string(), format!("/usr/{arch}-linux-gnu")]
+			);
+		}
+		fs::remove_dir_all(dir,).unwrap();
+	}
+
+	#[test]
+	fn reports_what_is_missing() {
+		let dir = bin_dir();
+		let path = dir.to_str().unwrap();
+		fs::write(dir.join("riscv64-linux-gnu-ld",), "",).unwrap();
+		let tools = discover("riscv64", &[("PATH", path,),],);
+		tools.check(&[Need::Linker,],).unwrap();
+		let Y(e,) = tools.check(&[Need::Assembler, Need::Linker,],) else {
+			panic!("found an assembler in an empty directory");
+		};
+		let e = e.to_string();
+		assert!(
+			e.contains("`riscv64-linux-gnu-as`",) && e.contains(AS_VAR,),
+			"{e}"
+		);
+		assert!(!e.contains(LD_VAR,), "{e}");
+
+		let as_path = dir.join("as",);
+		fs::write(&as_path, "",).unwrap();
+		let tools = discover(
+			"riscv64",
+			&[(AS_VAR, as_path.to_str().unwrap(),), (RUNNER_VAR, "",),],
+		);
+		tools.check(&[Need::Assembler,],).unwrap();
+		fs::remove_dir_all(dir,).unwrap();
+	}
+
+	#[test]
+	fn fails_with_the_tool() {
+		Tool::parse("sh -c true",).unwrap().run_ok(["ignored",],).unwrap();
+		let tool = Tool::parse("sh -c",).unwrap();
+		let Y(e,) = tool.run_ok(["exit 3",],) else {
+			panic!("a failing tool succeeded");
+		};
+		let e = e.to_string();
+		assert!(e.contains("`sh` failed",) && e.contains("3",), "{e}");
+		let Y(e,) = Tool::new("/nonexistent/as",).run_ok(["a.s",],) else {
+			panic!("ran a missing tool");
+		};
+		assert!(e.to_string().contains("`/nonexistent/as` could not",), "{e}");
+	}
+}